@@ -70,6 +70,7 @@ impl AuthHandler for LocalAuthHandler {
                 internal_caller: None,      // TODO
                 start: tokio::time::Instant::now(),
                 start_time: std::time::SystemTime::now(),
+                deadline: meta.deadline,
                 data: RequestData::Auth(AuthRequestData {
                     auth_handler: this.name().clone(),
                     parsed_payload: AuthPayload { query, header },
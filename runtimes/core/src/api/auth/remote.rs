@@ -83,10 +83,12 @@ impl RemoteAuthHandler {
             caller: &caller,
             parent_span: meta.parent_span_id.map(|sp| meta.trace_id.with_span(sp)),
             parent_event_id: None,
+            ext_parent_span: false,
             ext_correlation_id: meta
                 .ext_correlation_id
                 .as_ref()
                 .map(|s| Cow::Borrowed(s.as_str())),
+            deadline: meta.deadline,
             auth_user_id: None,
             auth_data: None,
             svc_auth_method: self.svc_auth_method.as_ref(),
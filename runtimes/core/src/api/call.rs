@@ -2,17 +2,22 @@ use std::borrow::{Borrow, Cow};
 use std::collections::HashMap;
 use std::future::Future;
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 use anyhow::Context;
+use tokio::time::Instant;
 use tokio_tungstenite::tungstenite::client::IntoClientRequest;
 use url::Url;
 
 use encore::runtime::v1 as pb;
 
+use crate::api::circuitbreaker::CircuitBreaker;
+use crate::api::deadline;
+use crate::api::lb;
 use crate::api::reqauth::caller::Caller;
 use crate::api::reqauth::meta::MetaKey;
 use crate::api::reqauth::{service_auth_method, svcauth};
+use crate::api::retry::{self, RetryPolicy};
 use crate::api::schema::{JSONPayload, ToOutgoingRequest};
 use crate::api::{schema, APIResult, Endpoint, EndpointMap};
 use crate::model::{SpanKey, TraceEventId};
@@ -25,16 +30,43 @@ use super::websocket_client::WebSocketClient;
 use super::HandshakeSchema;
 use super::ResponsePayload;
 
+/// Options for an individual API call.
+#[derive(Debug, Clone, Default)]
+pub struct CallOpts {
+    /// The maximum time to wait for the call to complete.
+    /// The call is also bounded by the deadline of the calling request, if any.
+    pub timeout: Option<Duration>,
+}
+
 /// Tracks where services are located and how to call them.
 pub struct ServiceRegistry {
     endpoints: Arc<EndpointMap>,
     base_urls: HashMap<EncoreName, String>,
+    upstreams: HashMap<EncoreName, Arc<lb::Upstream>>,
     http_client: reqwest::Client,
     tracer: Tracer,
     service_auth: HashMap<EncoreName, Arc<dyn svcauth::ServiceAuthMethod>>,
+    call_policies: HashMap<EncoreName, Arc<CallPolicy>>,
+    default_call_policy: Arc<CallPolicy>,
     deploy_id: String,
 }
 
+/// How calls to a service are retried and circuit broken.
+#[derive(Debug)]
+struct CallPolicy {
+    retry: RetryPolicy,
+    breaker: Option<CircuitBreaker>,
+}
+
+impl CallPolicy {
+    fn new(loc: &pb::service_discovery::Location) -> Self {
+        Self {
+            retry: RetryPolicy::from_config(loc.retry_policy.clone()),
+            breaker: CircuitBreaker::from_config(loc.circuit_breaker.clone()),
+        }
+    }
+}
+
 impl ServiceRegistry {
     #[allow(clippy::too_many_arguments)]
     pub fn new(
@@ -51,8 +83,23 @@ impl ServiceRegistry {
     ) -> anyhow::Result<Self> {
         let mut base_urls = HashMap::with_capacity(sd.services.len());
         let mut service_auth = HashMap::with_capacity(sd.services.len());
+        let mut call_policies = HashMap::with_capacity(sd.services.len());
+        let mut upstreams = HashMap::new();
         for (svc, mut loc) in sd.services {
             let svc = EncoreName::from(svc);
+            call_policies.insert(svc.clone(), Arc::new(CallPolicy::new(&loc)));
+
+            if !loc.additional_base_urls.is_empty() {
+                let mut urls = vec![loc.base_url.clone()];
+                urls.append(&mut loc.additional_base_urls);
+                let upstream = lb::Upstream::new(
+                    svc.clone(),
+                    urls,
+                    loc.load_balancing.take().unwrap_or_default(),
+                )
+                .with_context(|| format!("load balancer for service {}", svc))?;
+                upstreams.insert(svc.clone(), Arc::new(upstream));
+            }
             base_urls.insert(svc.clone(), loc.base_url);
 
             let auth_method = if loc.auth_methods.is_empty() {
@@ -89,9 +136,12 @@ impl ServiceRegistry {
         Ok(Self {
             endpoints,
             base_urls,
+            upstreams,
             http_client,
             tracer,
             service_auth,
+            call_policies,
+            default_call_policy: Arc::new(CallPolicy::new(&Default::default())),
             deploy_id,
         })
     }
@@ -104,6 +154,38 @@ impl ServiceRegistry {
         self.base_urls.get(service_name)
     }
 
+    /// Returns the load balancer for a service with multiple instances.
+    pub fn service_upstream<Q>(&self, service_name: &Q) -> Option<&Arc<lb::Upstream>>
+    where
+        EncoreName: Borrow<Q>,
+        Q: Eq + std::hash::Hash + ?Sized,
+    {
+        self.upstreams.get(service_name)
+    }
+
+    pub fn upstreams(&self) -> impl Iterator<Item = &Arc<lb::Upstream>> {
+        self.upstreams.values()
+    }
+
+    /// Selects the base URL to call a service at,
+    /// picking an instance if the service has multiple instances.
+    ///
+    /// The selected instance must be kept until the request completes,
+    /// so that it's counted as in flight.
+    fn select_base_url(&self, service_name: &str) -> Option<(Cow<'_, str>, Option<lb::Selection>)> {
+        match self.upstreams.get(service_name) {
+            Some(upstream) => {
+                let instance = upstream.select();
+                let base_url = Cow::Owned(instance.base_url().to_string());
+                Some((base_url, Some(instance)))
+            }
+            None => self
+                .base_urls
+                .get(service_name)
+                .map(|url| (Cow::Borrowed(url.as_str()), None)),
+        }
+    }
+
     pub fn service_auth_method<Q>(
         &self,
         service_name: &Q,
@@ -120,12 +202,17 @@ impl ServiceRegistry {
         target: EndpointName,
         data: JSONPayload,
         source: Option<Arc<model::Request>>,
+        opts: CallOpts,
     ) -> impl Future<Output = APIResult<ResponsePayload>> + 'static {
         let tracer = self.tracer.clone();
         let call = model::APICall { source, target };
         let start_event_id = tracer.rpc_call_start(&call);
 
-        let fut = self.do_api_call(&call.target, data, call.source.as_deref(), start_event_id);
+        let deadline = deadline::earliest(
+            call.source.as_ref().and_then(|s| s.deadline),
+            deadline::from_timeout(opts.timeout),
+        );
+        let fut = self.do_api_call(&call, data, start_event_id, deadline);
         async move {
             let result = fut.await;
             if let Some(start_event_id) = start_event_id {
@@ -159,28 +246,106 @@ impl ServiceRegistry {
 
     fn do_api_call(
         &self,
-        target: &EndpointName,
+        call: &model::APICall,
         data: JSONPayload,
-        source: Option<&model::Request>,
         start_event_id: Option<TraceEventId>,
+        deadline: Option<Instant>,
     ) -> impl Future<Output = APIResult<ResponsePayload>> + 'static {
         let http_client = self.http_client.clone();
-        let req = self.prepare_api_call_request(target, data, source, start_event_id);
+        let tracer = self.tracer.clone();
+        let source = call.source.clone();
+        let target = call.target.clone();
+        let policy = self
+            .call_policies
+            .get(target.service())
+            .unwrap_or(&self.default_call_policy)
+            .clone();
+        let upstream = self.upstreams.get(target.service()).cloned();
+        let req = self.prepare_api_call_request(
+            &call.target,
+            data,
+            call.source.as_deref(),
+            start_event_id,
+            deadline,
+        );
+
         async move {
-            match req {
-                Ok((req, resp_schema)) => {
-                    let fut = http_client.execute(req);
-                    match fut.await {
-                        Ok(resp) => {
-                            if !resp.status().is_success() {
-                                return Err(extract_error(resp).await);
-                            }
-                            resp_schema.extract(resp).await
+            // Don't make the call if there's no time left to complete it.
+            if deadline.is_some_and(|d| d <= Instant::now()) {
+                return Err(api::Error::deadline_exceeded());
+            }
+
+            let (req, resp_schema, mut instance) = req?;
+            let method = req.method().clone();
+            let attempts = async {
+                let mut next_req = Some(req);
+                let mut attempt = 1;
+                while let Some(req) = next_req.take() {
+                    if let Some(breaker) = &policy.breaker {
+                        if !breaker.allow() {
+                            return Err(circuit_open_error(&target));
+                        }
+                    }
+
+                    // Keep a copy of the request around to retry it, unless it has a
+                    // streaming body. The call metadata, including the remaining deadline,
+                    // is signed so it's sent unchanged on retries.
+                    next_req = req.try_clone();
+                    let (result, failure) =
+                        execute_call(&http_client, req, &resp_schema, instance.as_ref()).await;
+
+                    if let Some(breaker) = &policy.breaker {
+                        match &failure {
+                            Some(failure) if failure.is_unhealthy() => breaker.record_failure(),
+                            _ => breaker.record_success(),
+                        }
+                    }
+
+                    let (Err(err), Some(failure)) = (&result, failure) else {
+                        return result;
+                    };
+                    if next_req.is_none() || !policy.retry.should_retry(&method, attempt, &failure)
+                    {
+                        return result;
+                    }
+
+                    // Don't retry if the deadline expires before the retry would be made.
+                    let backoff = policy.retry.backoff(attempt);
+                    if deadline.is_some_and(|d| Instant::now() + backoff >= d) {
+                        return result;
+                    }
+
+                    if let (Some(source), Some(start_event_id)) = (&source, start_event_id) {
+                        tracer.rpc_call_retry(
+                            source,
+                            start_event_id,
+                            &target,
+                            attempt,
+                            err,
+                            backoff,
+                        );
+                    }
+                    tokio::time::sleep(backoff).await;
+                    attempt += 1;
+
+                    // Pick the instance to retry against anew, so retries
+                    // fail over to other instances of the service.
+                    if let (Some(upstream), Some(prev), Some(req)) =
+                        (&upstream, &instance, next_req.as_mut())
+                    {
+                        let next = upstream.select();
+                        if let Some(url) = rebase_url(req.url(), prev.url(), next.url()) {
+                            *req.url_mut() = url;
+                            instance = Some(next);
                         }
-                        Err(e) => Err(api::Error::internal(e)),
                     }
                 }
-                Err(e) => Err(e),
+                unreachable!("the api call loop always returns")
+            };
+
+            tokio::select! {
+                result = attempts => result,
+                _ = deadline::expired(deadline) => Err(api::Error::deadline_exceeded()),
             }
         }
     }
@@ -191,20 +356,24 @@ impl ServiceRegistry {
         mut data: JSONPayload,
         source: Option<&model::Request>,
         start_event_id: Option<TraceEventId>,
-    ) -> APIResult<(reqwest::Request, Arc<schema::Response>)> {
-        let base_url = self
-            .base_urls
-            .get(target.service())
-            .ok_or_else(|| api::Error {
-                code: api::ErrCode::NotFound,
-                message: "service not found".into(),
-                internal_message: Some(format!(
-                    "no service discovery configuration found for service {}",
-                    target.service()
-                )),
-                stack: None,
-                details: None,
-            })?;
+        deadline: Option<Instant>,
+    ) -> APIResult<(
+        reqwest::Request,
+        Arc<schema::Response>,
+        Option<lb::Selection>,
+    )> {
+        let (base_url, instance) =
+            self.select_base_url(target.service())
+                .ok_or_else(|| api::Error {
+                    code: api::ErrCode::NotFound,
+                    message: "service not found".into(),
+                    internal_message: Some(format!(
+                        "no service discovery configuration found for service {}",
+                        target.service()
+                    )),
+                    stack: None,
+                    details: None,
+                })?;
 
         let Some(endpoint) = self.endpoints.get(target).cloned() else {
             return Err(api::Error {
@@ -266,12 +435,12 @@ impl ServiceRegistry {
 
         // Add call metadata.
         let headers = req.headers_mut();
-        self.propagate_call_meta(headers, &endpoint, source, start_event_id)
+        self.propagate_call_meta(headers, &endpoint, source, start_event_id, deadline)
             .map_err(api::Error::internal)?;
 
         let resp_schema = endpoint.response.clone();
 
-        Ok((req, resp_schema))
+        Ok((req, resp_schema, instance))
     }
 
     fn do_connect_stream(
@@ -284,7 +453,7 @@ impl ServiceRegistry {
         let req = self.prepare_stream_request(target, data, source, start_event_id);
         async move {
             match req {
-                Ok((req, outgoing, incoming)) => {
+                Ok((req, outgoing, incoming, _instance)) => {
                     let schema = schema::Stream::new(incoming, outgoing);
                     WebSocketClient::connect(req, schema).await
                 }
@@ -303,20 +472,20 @@ impl ServiceRegistry {
         http::Request<()>,
         Arc<schema::Request>,
         Arc<schema::Response>,
+        Option<lb::Selection>,
     )> {
-        let base_url = self
-            .base_urls
-            .get(target.service())
-            .ok_or_else(|| api::Error {
-                code: api::ErrCode::NotFound,
-                message: "service not found".into(),
-                internal_message: Some(format!(
-                    "no service discovery configuration found for service {}",
-                    target.service()
-                )),
-                stack: None,
-                details: None,
-            })?;
+        let (base_url, instance) =
+            self.select_base_url(target.service())
+                .ok_or_else(|| api::Error {
+                    code: api::ErrCode::NotFound,
+                    message: "service not found".into(),
+                    internal_message: Some(format!(
+                        "no service discovery configuration found for service {}",
+                        target.service()
+                    )),
+                    stack: None,
+                    details: None,
+                })?;
 
         let Some(endpoint) = self.endpoints.get(target) else {
             return Err(api::Error {
@@ -375,12 +544,13 @@ impl ServiceRegistry {
             }
         }
 
-        self.propagate_call_meta(req.headers_mut(), endpoint, source, start_event_id)
+        // Streams are long-lived, so they aren't bound by the deadline of the caller.
+        self.propagate_call_meta(req.headers_mut(), endpoint, source, start_event_id, None)
             .map_err(api::Error::internal)?;
 
         let outgoing = endpoint.request[0].clone();
         let incoming = endpoint.response.clone();
-        Ok((req, outgoing, incoming))
+        Ok((req, outgoing, incoming, instance))
     }
 
     fn propagate_call_meta(
@@ -389,6 +559,7 @@ impl ServiceRegistry {
         endpoint: &Endpoint,
         source: Option<&model::Request>,
         parent_event_id: Option<TraceEventId>,
+        deadline: Option<Instant>,
     ) -> anyhow::Result<()> {
         let svc_auth_method = self
             .service_auth_method(endpoint.name.service())
@@ -430,11 +601,13 @@ impl ServiceRegistry {
             svc_auth_method: svc_auth_method.as_ref(),
             parent_span: source.map(|r| r.span),
             parent_event_id,
+            ext_parent_span: false,
             ext_correlation_id: source.and_then(|r| {
                 r.ext_correlation_id
                     .as_ref()
                     .map(|id| Cow::Borrowed(id.as_str()))
             }),
+            deadline,
             auth_user_id: source.and_then(|r| {
                 match &r.data {
                     model::RequestData::RPC(data) => data.auth_user_id.as_ref(),
@@ -463,8 +636,14 @@ pub struct CallDesc<'a, AuthData> {
 
     pub parent_span: Option<SpanKey>,
     pub parent_event_id: Option<TraceEventId>,
+    /// Whether the parent span was propagated from an external tracing system
+    /// via the W3C traceparent header, as opposed to being generated by Encore.
+    pub ext_parent_span: bool,
     pub ext_correlation_id: Option<Cow<'a, str>>,
 
+    /// The deadline by which the call must complete, if any.
+    pub deadline: Option<Instant>,
+
     pub auth_user_id: Option<Cow<'a, str>>,
     pub auth_data: Option<AuthData>,
 
@@ -494,6 +673,9 @@ where
                 trace_state.push_str(",encore/event-id=");
                 trace_state.push_str(event_id.to_string().as_str());
             }
+            if self.ext_parent_span {
+                trace_state.push_str(",encore/ext-parent=1");
+            }
             headers.set(MetaKey::TraceState, trace_state)?;
         }
 
@@ -504,6 +686,10 @@ where
             headers.set(MetaKey::XCorrelationId, corr_id.into_owned())?;
         }
 
+        if let Some(deadline) = self.deadline {
+            headers.set(MetaKey::Deadline, deadline::to_meta(deadline))?;
+        }
+
         // Add auth data.
         if let Some(auth_uid) = self.auth_user_id {
             headers.set(MetaKey::UserId, auth_uid.into_owned())?;
@@ -532,6 +718,61 @@ where
     }
 }
 
+/// Moves a request URL from one instance base URL to another,
+/// keeping the request path relative to the base and the query.
+fn rebase_url(url: &Url, from: &Url, to: &Url) -> Option<Url> {
+    let rel_path = url.path().strip_prefix(from.path().trim_end_matches('/'))?;
+    let mut rebased = to.clone();
+    rebased.set_path(&format!("{}{}", to.path().trim_end_matches('/'), rel_path));
+    rebased.set_query(url.query());
+    Some(rebased)
+}
+
+/// Makes a single attempt at an API call.
+/// On failure, it also returns why the attempt failed.
+async fn execute_call(
+    http_client: &reqwest::Client,
+    req: reqwest::Request,
+    resp_schema: &schema::Response,
+    instance: Option<&lb::Selection>,
+) -> (APIResult<ResponsePayload>, Option<retry::Failure>) {
+    let resp = match http_client.execute(req).await {
+        Ok(resp) => resp,
+        Err(err) => {
+            let failure = if err.is_connect() {
+                if let Some(instance) = instance {
+                    instance.eject();
+                }
+                retry::Failure::Connect
+            } else {
+                retry::Failure::Transport
+            };
+            return (Err(api::Error::internal(err)), Some(failure));
+        }
+    };
+
+    if !resp.status().is_success() {
+        let err = extract_error(resp).await;
+        let failure = retry::Failure::Response(err.code);
+        return (Err(err), Some(failure));
+    }
+
+    (resp_schema.extract(resp).await, None)
+}
+
+fn circuit_open_error(target: &EndpointName) -> api::Error {
+    api::Error {
+        code: api::ErrCode::Unavailable,
+        message: "service unavailable".into(),
+        internal_message: Some(format!(
+            "circuit breaker open for service {}: too many recent calls failed",
+            target.service()
+        )),
+        stack: None,
+        details: None,
+    }
+}
+
 async fn extract_error(resp: reqwest::Response) -> api::Error {
     match resp.bytes().await {
         Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|err| {
@@ -540,3 +781,29 @@ async fn extract_error(resp: reqwest::Response) -> api::Error {
         Err(err) => api::Error::invalid_argument("unable to read response body", err),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rebase_url() {
+        let url: Url = "http://10.0.0.1:4000/prefix/svc.Endpoint?a=1"
+            .parse()
+            .unwrap();
+        let from: Url = "http://10.0.0.1:4000/prefix".parse().unwrap();
+        let to: Url = "https://other:4001/prefix/".parse().unwrap();
+        assert_eq!(
+            rebase_url(&url, &from, &to).unwrap().as_str(),
+            "https://other:4001/prefix/svc.Endpoint?a=1"
+        );
+
+        let url: Url = "http://a:4000/svc.Endpoint".parse().unwrap();
+        let from: Url = "http://a:4000".parse().unwrap();
+        let to: Url = "http://b:4000".parse().unwrap();
+        assert_eq!(
+            rebase_url(&url, &from, &to).unwrap().as_str(),
+            "http://b:4000/svc.Endpoint"
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::encore::runtime::v1 as pb;
+
+/// Fails calls to a service fast while it's unhealthy,
+/// instead of piling up requests waiting on it.
+///
+/// The breaker opens after a number of consecutive failed calls.
+/// Once it has been open for a while it lets a single trial call through,
+/// and closes again if that call succeeds.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    open_duration: Duration,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Closed {
+        failures: u32,
+    },
+    Open {
+        until: Instant,
+    },
+    /// A trial call was let through at the given time.
+    HalfOpen {
+        since: Instant,
+    },
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, open_duration: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            open_duration,
+            state: Mutex::new(State::Closed { failures: 0 }),
+        }
+    }
+
+    /// Returns the circuit breaker to use given its configuration,
+    /// or None if circuit breaking is not configured or disabled.
+    pub fn from_config(cfg: Option<pb::service_discovery::CircuitBreaker>) -> Option<Self> {
+        let cfg = cfg?;
+        if cfg.disabled {
+            return None;
+        }
+        let open_duration = cfg
+            .open_duration
+            .and_then(|d| Duration::try_from(d).ok())
+            .unwrap_or(Duration::from_secs(10));
+        Some(Self::new(cfg.failure_threshold.unwrap_or(5), open_duration))
+    }
+
+    /// Reports whether a call may be made.
+    /// If it returns true, the outcome of the call must be reported
+    /// with `record_success` or `record_failure`.
+    pub fn allow(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let trial_at = match *state {
+            State::Closed { .. } => return true,
+            State::Open { until } => until,
+            // If the trial call never reported its outcome, let another one through after a while.
+            State::HalfOpen { since } => since + self.open_duration,
+        };
+
+        let now = Instant::now();
+        if now >= trial_at {
+            // Let a single trial call through.
+            *state = State::HalfOpen { since: now };
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn record_success(&self) {
+        *self.state.lock().unwrap() = State::Closed { failures: 0 };
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        *state = match *state {
+            State::Closed { failures } if failures + 1 < self.failure_threshold => State::Closed {
+                failures: failures + 1,
+            },
+            State::Closed { .. } | State::HalfOpen { .. } => State::Open {
+                until: Instant::now() + self.open_duration,
+            },
+            open @ State::Open { .. } => open,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set_open_until(cb: &CircuitBreaker, until: Instant) {
+        *cb.state.lock().unwrap() = State::Open { until };
+    }
+
+    #[test]
+    fn test_circuit_breaker() {
+        let cb = CircuitBreaker::new(2, Duration::from_secs(60));
+        assert!(cb.allow());
+        cb.record_failure();
+        assert!(cb.allow());
+
+        // A success resets the failure count.
+        cb.record_success();
+        cb.record_failure();
+        assert!(cb.allow());
+
+        // The second consecutive failure opens the breaker.
+        cb.record_failure();
+        assert!(!cb.allow());
+
+        // Once the open duration has passed, a single trial call is let through.
+        set_open_until(&cb, Instant::now() - Duration::from_secs(1));
+        assert!(cb.allow());
+        assert!(!cb.allow());
+
+        // A failed trial call opens the breaker again.
+        cb.record_failure();
+        assert!(!cb.allow());
+
+        // A successful trial call closes it.
+        set_open_until(&cb, Instant::now() - Duration::from_secs(1));
+        assert!(cb.allow());
+        cb.record_success();
+        assert!(cb.allow());
+        assert!(cb.allow());
+    }
+}
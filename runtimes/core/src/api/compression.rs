@@ -0,0 +1,117 @@
+use tower_http::compression::predicate::{Predicate, SizeAbove};
+use tower_http::compression::CompressionLayer;
+use tower_http::decompression::RequestDecompressionLayer;
+
+/// Responses smaller than this are not compressed,
+/// as the savings don't make up for the overhead.
+pub const MIN_SIZE: u16 = 1024;
+
+/// Adds response compression and request decompression to a router.
+///
+/// Responses are compressed with gzip, brotli or zstd depending on the
+/// request's `Accept-Encoding` header. Request bodies are decompressed
+/// according to their `Content-Encoding` header.
+pub fn apply(router: axum::Router) -> axum::Router {
+    let compression = CompressionLayer::new()
+        .compress_when(SizeAbove::new(MIN_SIZE).and(KnownSize).and(Compressible));
+    router
+        .layer(compression)
+        .layer(RequestDecompressionLayer::new())
+}
+
+/// Reports whether responses with the given content type benefit from compression.
+///
+/// Only text-based formats are compressed. Other formats, like images and archives,
+/// are typically compressed already. Server-sent events are not compressed since
+/// compression buffers the output.
+pub fn is_compressible_content_type(content_type: &str) -> bool {
+    let Ok(mime) = content_type.parse::<mime::Mime>() else {
+        return false;
+    };
+    let (ty, sub) = (mime.type_(), mime.subtype());
+    if ty == mime::TEXT {
+        sub != mime::EVENT_STREAM
+    } else if ty == mime::IMAGE {
+        sub == mime::SVG
+    } else if ty == mime::APPLICATION {
+        matches!(sub.as_str(), "javascript" | "json" | "xml" | "wasm")
+            || mime
+                .suffix()
+                .is_some_and(|suffix| suffix == mime::JSON || suffix == mime::XML)
+    } else {
+        false
+    }
+}
+
+/// Only compresses responses whose size is known up front.
+///
+/// Responses without a known size are typically streamed, and compressing
+/// them would hold back output until the encoder's buffer fills up.
+#[derive(Clone, Copy)]
+struct KnownSize;
+
+impl Predicate for KnownSize {
+    fn should_compress<B>(&self, response: &http::Response<B>) -> bool
+    where
+        B: axum::body::HttpBody,
+    {
+        response
+            .headers()
+            .contains_key(http::header::CONTENT_LENGTH)
+            || response.body().size_hint().exact().is_some()
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Compressible;
+
+impl Predicate for Compressible {
+    fn should_compress<B>(&self, response: &http::Response<B>) -> bool
+    where
+        B: axum::body::HttpBody,
+    {
+        response
+            .headers()
+            .get(http::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(is_compressible_content_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_compressible_content_type() {
+        assert!(is_compressible_content_type("application/json"));
+        assert!(is_compressible_content_type(
+            "application/json; charset=utf-8"
+        ));
+        assert!(is_compressible_content_type("application/problem+json"));
+        assert!(is_compressible_content_type("text/html; charset=utf-8"));
+        assert!(is_compressible_content_type("image/svg+xml"));
+        assert!(!is_compressible_content_type("text/event-stream"));
+        assert!(!is_compressible_content_type("image/png"));
+        assert!(!is_compressible_content_type("application/octet-stream"));
+        assert!(!is_compressible_content_type("application/grpc"));
+        assert!(!is_compressible_content_type("not a content type"));
+    }
+
+    #[test]
+    fn test_known_size() {
+        let sized = http::Response::new(axum::body::Body::from("hello"));
+        assert!(KnownSize.should_compress(&sized));
+
+        let stream = futures::stream::iter([Ok::<_, std::io::Error>("hello")]);
+        let streamed = http::Response::new(axum::body::Body::from_stream(stream));
+        assert!(!KnownSize.should_compress(&streamed));
+
+        let mut streamed = streamed;
+        streamed.headers_mut().insert(
+            http::header::CONTENT_LENGTH,
+            http::HeaderValue::from_static("5"),
+        );
+        assert!(KnownSize.should_compress(&streamed));
+    }
+}
@@ -0,0 +1,84 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use tokio::time::Instant;
+
+/// Returns the earliest of two optional deadlines.
+pub fn earliest(a: Option<Instant>, b: Option<Instant>) -> Option<Instant> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Computes the deadline for something starting now that must complete within the given timeout.
+/// Timeouts too large to be represented are treated as no timeout.
+pub fn from_timeout(timeout: Option<Duration>) -> Option<Instant> {
+    timeout.and_then(|timeout| Instant::now().checked_add(timeout))
+}
+
+/// Completes when the deadline expires, or never if there is no deadline.
+pub async fn expired(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Serializes a deadline for propagation in call metadata.
+///
+/// The deadline is sent as the number of milliseconds remaining rather than
+/// as a point in time, so that it's unaffected by clock skew between hosts.
+pub(super) fn to_meta(deadline: Instant) -> String {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    remaining.as_millis().to_string()
+}
+
+/// Parses a deadline propagated in call metadata.
+pub(super) fn parse_meta(value: &str) -> anyhow::Result<Instant> {
+    let remaining_ms: u64 = value.parse().context("invalid deadline")?;
+    Instant::now()
+        .checked_add(Duration::from_millis(remaining_ms))
+        .context("deadline out of range")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_earliest() {
+        let now = Instant::now();
+        let later = now + Duration::from_secs(1);
+        assert_eq!(earliest(None, None), None);
+        assert_eq!(earliest(Some(now), None), Some(now));
+        assert_eq!(earliest(None, Some(later)), Some(later));
+        assert_eq!(earliest(Some(later), Some(now)), Some(now));
+    }
+
+    #[test]
+    fn test_meta_roundtrip() {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let parsed = parse_meta(&to_meta(deadline)).unwrap();
+
+        // Allow for time passing between serializing and parsing.
+        let diff = if parsed > deadline {
+            parsed - deadline
+        } else {
+            deadline - parsed
+        };
+        assert!(diff < Duration::from_millis(100), "{diff:?}");
+
+        // Expired deadlines are sent as no time remaining.
+        let expired = Instant::now() - Duration::from_secs(1);
+        assert_eq!(to_meta(expired), "0");
+
+        assert!(parse_meta("soon").is_err());
+    }
+
+    #[test]
+    fn test_out_of_range() {
+        assert!(parse_meta(&u64::MAX.to_string()).is_err());
+        assert_eq!(from_timeout(Some(Duration::MAX)), None);
+    }
+}
@@ -0,0 +1,136 @@
+use std::sync::Arc;
+
+use axum::extract::Request;
+use axum::http::{header, HeaderMap, Method};
+use axum::response::{IntoResponse, Json};
+use subtle::ConstantTimeEq;
+
+use crate::api::reqauth::platform;
+use crate::api::{self, ToResponse};
+use crate::secrets;
+
+/// The maximum size of an admin request body.
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// Authenticates requests to the admin API. Requests are accepted
+/// if they carry one of the configured bearer tokens, or are signed
+/// by the Encore Platform.
+#[derive(Clone)]
+pub struct Auth {
+    pub tokens: Arc<[secrets::Secret]>,
+    pub platform_validator: Arc<platform::RequestValidator>,
+}
+
+impl Auth {
+    /// Authenticates a request to the given path with the given headers.
+    pub fn authenticate(&self, path: &str, headers: &HeaderMap) -> Result<(), api::Error> {
+        if let Some(token) = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+        {
+            for secret in self.tokens.iter() {
+                match secret.get() {
+                    // An empty token is treated as no token being configured.
+                    Ok(expected) if expected.is_empty() => {}
+                    Ok(expected) if bool::from(expected.ct_eq(token.as_bytes())) => return Ok(()),
+                    Ok(_) => {}
+                    Err(err) => log::warn!("unable to resolve admin api token: {}", err),
+                }
+            }
+        }
+
+        match self
+            .platform_validator
+            .validate_request_headers(path, headers)
+        {
+            Ok(Some(_)) => Ok(()),
+            Ok(None) => Err(api::Error::unauthenticated()),
+            Err(err) => {
+                log::debug!("invalid platform signature for {}: {}", path, err);
+                Err(api::Error::unauthenticated())
+            }
+        }
+    }
+}
+
+/// Reports and changes the log levels of the process.
+///
+/// `GET` returns the current levels, and `PUT` replaces them with the levels
+/// in the request body, returning the new levels. Changes only apply to
+/// this process and are not persisted across restarts.
+#[derive(Clone)]
+pub struct LogLevelsHandler(pub Auth);
+
+impl LogLevelsHandler {
+    async fn handle(self, req: Request) -> Result<crate::log::Levels, api::Error> {
+        self.0.authenticate(req.uri().path(), req.headers())?;
+
+        if req.method() == Method::PUT {
+            let body = axum::body::to_bytes(req.into_body(), MAX_BODY_SIZE)
+                .await
+                .map_err(|err| api::Error::invalid_argument("unable to read body", err))?;
+            let levels: crate::log::Levels = serde_json::from_slice(&body)
+                .map_err(|err| api::Error::invalid_argument("invalid log levels", err))?;
+
+            log::info!("updating log levels: {:?}", levels);
+            crate::log::set_levels(levels);
+        }
+
+        Ok(crate::log::root().levels())
+    }
+}
+
+impl axum::handler::Handler<(), ()> for LogLevelsHandler {
+    type Future = std::pin::Pin<
+        Box<dyn std::future::Future<Output = axum::response::Response<axum::body::Body>> + Send>,
+    >;
+
+    fn call(self, req: Request, _state: ()) -> Self::Future {
+        Box::pin(async move {
+            match self.handle(req).await {
+                Ok(levels) => Json(levels).into_response(),
+                Err(err) => err.to_response(None),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auth(tokens: &[&'static str]) -> Auth {
+        Auth {
+            tokens: tokens
+                .iter()
+                .map(|t| secrets::Secret::new_for_test(t))
+                .collect(),
+            platform_validator: Arc::new(platform::RequestValidator::new(
+                &secrets::Manager::new(vec![]),
+                vec![],
+            )),
+        }
+    }
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            format!("Bearer {}", token).parse().unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn test_authenticate() {
+        let auth = auth(&["", "secret"]);
+        let path = "/__encore/admin/log-levels";
+        assert!(auth.authenticate(path, &bearer("secret")).is_ok());
+        assert!(auth.authenticate(path, &bearer("other")).is_err());
+        assert!(auth.authenticate(path, &HeaderMap::new()).is_err());
+
+        // Empty tokens never match.
+        assert!(auth.authenticate(path, &bearer("")).is_err());
+    }
+}
@@ -1,11 +1,17 @@
 use axum::extract::Request;
+use axum::http::StatusCode;
 use axum::response::{IntoResponse, Json};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
+use crate::api::encore_routes::admin;
+use crate::health;
+
+/// Handles liveness checks, which report whether the process is up and running.
+#[derive(Clone)]
 pub struct Handler {
     pub app_revision: String,
     pub deploy_id: String,
+    pub readiness: health::Readiness,
 }
 
 impl Handler {
@@ -23,6 +29,76 @@ impl Handler {
             },
         }
     }
+
+    /// Checks whether the resources the app depends on are reachable.
+    ///
+    /// The result of each check is only reported if `detailed` is set,
+    /// as the errors can reveal internal details of the app.
+    pub async fn readiness_check(self, detailed: bool) -> Response {
+        let results = self.readiness.check().await;
+        let ready = results.iter().all(|r| r.passed());
+        log::trace!(ready = ready; "handling incoming readiness check request");
+
+        let (code, message) = if ready {
+            ("ok", "Your Encore app is ready to serve traffic.")
+        } else {
+            ("unavailable", "One or more readiness checks failed.")
+        };
+        Response {
+            code: code.into(),
+            message: message.into(),
+            details: Details {
+                app_revision: self.app_revision,
+                encore_compiler: "".into(),
+                deploy_id: self.deploy_id,
+                checks: if detailed {
+                    results
+                        .iter()
+                        .map(|r| CheckResult {
+                            name: r.name.clone(),
+                            passed: r.passed(),
+                            error: r.error.clone(),
+                        })
+                        .collect()
+                } else {
+                    vec![]
+                },
+                enabled_experiments: vec![],
+            },
+        }
+    }
+}
+
+impl Response {
+    pub fn status_code(&self) -> StatusCode {
+        if self.code == "ok" {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+    }
+}
+
+/// Handles readiness checks.
+///
+/// Anyone can check whether the app is ready, but the results of the
+/// individual checks are only included for requests that are
+/// authenticated the same way as requests to the admin API.
+#[derive(Clone)]
+pub struct ReadinessHandler(pub Handler, pub admin::Auth);
+
+impl axum::handler::Handler<(), ()> for ReadinessHandler {
+    type Future = std::pin::Pin<
+        Box<dyn std::future::Future<Output = axum::response::Response<axum::body::Body>> + Send>,
+    >;
+
+    fn call(self, req: Request, _state: ()) -> Self::Future {
+        let detailed = self.1.authenticate(req.uri().path(), req.headers()).is_ok();
+        Box::pin(async move {
+            let resp = self.0.readiness_check(detailed).await;
+            (resp.status_code(), Json(resp)).into_response()
+        })
+    }
 }
 
 impl axum::handler::Handler<(), ()> for Handler {
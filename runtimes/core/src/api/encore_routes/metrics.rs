@@ -0,0 +1,39 @@
+use axum::extract::Request;
+use axum::http::header;
+use axum::response::IntoResponse;
+
+use crate::api::encore_routes::admin;
+use crate::api::ToResponse;
+use crate::metrics;
+
+/// Serves all runtime metrics in the Prometheus text exposition format,
+/// allowing the process to be scraped directly.
+///
+/// Metrics can reveal internal details of the app, so requests are
+/// authenticated the same way as requests to the admin API.
+#[derive(Clone)]
+pub struct Handler(pub admin::Auth);
+
+impl Handler {
+    pub fn render(&self) -> String {
+        metrics::render_text(&metrics::registry().snapshot())
+    }
+}
+
+impl axum::handler::Handler<(), ()> for Handler {
+    type Future = std::pin::Pin<
+        Box<dyn std::future::Future<Output = axum::response::Response<axum::body::Body>> + Send>,
+    >;
+
+    fn call(self, req: Request, _state: ()) -> Self::Future {
+        log::trace!("handling incoming metrics request");
+        if let Err(err) = self.0.authenticate(req.uri().path(), req.headers()) {
+            return Box::pin(async move { err.to_response(None) });
+        }
+
+        let body = self.render();
+        Box::pin(async move {
+            ([(header::CONTENT_TYPE, metrics::TEXT_CONTENT_TYPE)], body).into_response()
+        })
+    }
+}
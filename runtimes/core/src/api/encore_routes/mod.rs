@@ -2,17 +2,34 @@ use axum::routing;
 
 use crate::pubsub;
 
+pub mod admin;
 pub mod healthz;
+pub mod metrics;
 
 pub struct Desc {
     pub healthz: healthz::Handler,
+    pub admin: admin::Auth,
     pub push_registry: pubsub::PushHandlerRegistry,
 }
 
 impl Desc {
     pub fn router(self) -> axum::Router<()> {
         axum::Router::new()
-            .route("/__encore/healthz", routing::any(self.healthz))
+            .route("/__encore/healthz", routing::any(self.healthz.clone()))
+            .route("/__encore/livez", routing::any(self.healthz.clone()))
+            .route(
+                "/__encore/readyz",
+                routing::any(healthz::ReadinessHandler(self.healthz, self.admin.clone())),
+            )
+            .route(
+                "/__encore/metrics",
+                routing::get(metrics::Handler(self.admin.clone())),
+            )
+            .route(
+                "/__encore/admin/log-levels",
+                routing::get(admin::LogLevelsHandler(self.admin.clone()))
+                    .put(admin::LogLevelsHandler(self.admin)),
+            )
             .route(
                 "/__encore/pubsub/push/:subscription_id",
                 routing::any(self.push_registry),
@@ -2,6 +2,7 @@ use std::collections::HashMap;
 use std::future::Future;
 use std::pin::Pin;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use anyhow::Context;
 use axum::extract::{FromRequestParts, WebSocketUpgrade};
@@ -10,9 +11,10 @@ use axum::response::IntoResponse;
 use bytes::{BufMut, BytesMut};
 use http::HeaderMap;
 use indexmap::IndexMap;
-use percent_encoding::percent_decode_str;
 use serde::Serialize;
 
+use crate::api::deadline;
+use crate::api::ratelimit::{self, RateLimiter};
 use crate::api::reqauth::{platform, svcauth, CallMeta};
 use crate::api::schema::encoding::{
     handshake_encoding, request_encoding, response_encoding, HandshakeSchemaUnderConstruction,
@@ -25,8 +27,7 @@ use crate::encore::parser::meta::v1::{self as meta, selector};
 use crate::log::LogFromRust;
 use crate::model::StreamDirection;
 use crate::names::EndpointName;
-use crate::trace;
-use crate::{model, Hosted};
+use crate::{model, shutdown, trace, Hosted};
 
 use super::pvalue::{PValue, PValues};
 use super::reqauth::caller::Caller;
@@ -173,6 +174,10 @@ pub struct Endpoint {
     /// If None, no limits are applied.
     pub body_limit: Option<u64>,
 
+    /// The maximum time a request may take before it's canceled.
+    /// If None, no timeout is applied.
+    pub timeout: Option<Duration>,
+
     /// The static assets to serve from this endpoint.
     /// Set only for static asset endpoints.
     pub static_assets: Option<meta::rpc::StaticAssets>,
@@ -367,6 +372,11 @@ pub fn endpoints_from_meta(
             exposed,
             requires_auth: !ep.ep.allow_unauthenticated,
             body_limit: ep.ep.body_limit,
+            timeout: ep
+                .ep
+                .timeout
+                .and_then(|nanos| u64::try_from(nanos).ok())
+                .map(Duration::from_nanos),
             static_assets: ep.ep.static_assets.clone(),
             tags,
         };
@@ -397,6 +407,12 @@ pub(super) struct SharedEndpointData {
     /// When we support multiple this needs to be made into a map, and the
     /// correct schema looked up based on the gateway being used.
     pub auth_data_schemas: HashMap<String, Option<jsonschema::JSONSchema>>,
+
+    /// The rate limits to enforce on incoming requests.
+    pub rate_limiter: Arc<RateLimiter>,
+
+    /// Tracks outstanding requests during graceful shutdown.
+    pub shutdown: shutdown::Tracker,
 }
 
 impl Clone for EndpointHandler {
@@ -518,6 +534,13 @@ impl EndpointHandler {
             })
         };
 
+        // Streams are long-lived, so deadlines only apply to regular requests.
+        let deadline = if stream_direction.is_none() {
+            deadline::earliest(meta.deadline, deadline::from_timeout(self.endpoint.timeout))
+        } else {
+            None
+        };
+
         let request = Arc::new(model::Request {
             span,
             parent_trace: None,
@@ -526,6 +549,7 @@ impl EndpointHandler {
             ext_correlation_id: meta.ext_correlation_id,
             start: tokio::time::Instant::now(),
             start_time: std::time::SystemTime::now(),
+            deadline,
             is_platform_request: platform_seal_of_approval.is_some(),
             internal_caller,
             data,
@@ -540,6 +564,7 @@ impl EndpointHandler {
     ) -> Pin<Box<dyn Future<Output = axum::http::Response<axum::body::Body>> + Send + 'static>>
     {
         Box::pin(async move {
+            let _task = self.shared.shutdown.track_handler();
             let request = match self.parse_request(axum_req).await {
                 Ok(req) => req,
                 Err(err) => return err.to_response(None),
@@ -568,15 +593,41 @@ impl EndpointHandler {
                 .to_response(internal_caller);
             }
 
+            if let Err(limited) = self.check_rate_limits(&request) {
+                let mut resp = limited.to_error().to_response(internal_caller);
+                resp.headers_mut().insert(
+                    axum::http::header::RETRY_AFTER,
+                    HeaderValue::from(limited.retry_after_secs()),
+                );
+                return resp;
+            }
+
             let logger = crate::log::root();
             logger.info(Some(&request), "starting request", None);
 
             self.shared.tracer.request_span_start(&request);
 
-            let resp: ResponseData = self.handler.call(request.clone()).await;
+            let canceled = self.shared.shutdown.handlers_canceled();
+            let resp: ResponseData = tokio::select! {
+                resp = self.handler.call(request.clone()) => resp,
+                _ = canceled.cancelled() => ResponseData::Typed(Err(Error::shutting_down())),
+                _ = deadline::expired(request.deadline) => ResponseData::Typed(Err(Error::deadline_exceeded())),
+            };
 
             let duration = tokio::time::Instant::now().duration_since(request.start);
 
+            let code = match &resp {
+                ResponseData::Typed(Ok(_)) => "ok".to_string(),
+                ResponseData::Typed(Err(err)) => err.code.to_string(),
+                ResponseData::Raw(resp) => ErrCode::from(resp.status()).to_string(),
+            };
+            crate::metrics::record_request(
+                self.endpoint.name.service(),
+                self.endpoint.name.endpoint(),
+                &code,
+                duration,
+            );
+
             // If we had a request failure, log that separately.
 
             if let ResponseData::Typed(Err(err)) = &resp {
@@ -605,12 +656,6 @@ impl EndpointHandler {
                     )),
                 );
 
-                let code = match &resp {
-                    ResponseData::Typed(Ok(_)) => "ok".to_string(),
-                    ResponseData::Typed(Err(err)) => err.code.to_string(),
-                    ResponseData::Raw(resp) => ErrCode::from(resp.status()).to_string(),
-                };
-
                 fields.insert("code".into(), serde_json::Value::String(code));
                 Some(fields)
             });
@@ -660,35 +705,19 @@ impl EndpointHandler {
         })
     }
 
+    fn check_rate_limits(&self, request: &model::Request) -> Result<(), ratelimit::RateLimited> {
+        let keys =
+            ratelimit::Key::for_request(request.internal_caller.as_ref(), request.auth_user_id());
+        self.shared.rate_limiter.check(&self.endpoint.name, &keys)
+    }
+
     fn authenticate_platform(
         &self,
         req: &axum::http::request::Parts,
     ) -> Result<Option<platform::SealOfApproval>, platform::ValidationError> {
-        let Some(x_encore_auth_header) = req.headers.get("x-encore-auth") else {
-            return Ok(None);
-        };
-        let x_encore_auth_header = x_encore_auth_header
-            .to_str()
-            .map_err(|_| platform::ValidationError::InvalidMac)?;
-
-        let Some(date_header) = req.headers.get("Date") else {
-            return Err(platform::ValidationError::InvalidDateHeader);
-        };
-        let date_header = date_header
-            .to_str()
-            .map_err(|_| platform::ValidationError::InvalidDateHeader)?;
-
-        let request_path = percent_decode_str(req.uri.path()).decode_utf8_lossy();
-        let req = platform::ValidationData {
-            request_path: &request_path,
-            date_header,
-            x_encore_auth_header,
-        };
-
         self.shared
             .platform_auth
-            .validate_platform_request(&req)
-            .map(Some)
+            .validate_request_headers(req.uri.path(), &req.headers)
     }
 }
 
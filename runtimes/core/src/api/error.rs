@@ -122,6 +122,28 @@ impl Error {
             details: None,
         }
     }
+
+    /// The error returned when a handler is canceled due to a graceful shutdown.
+    pub fn shutting_down() -> Self {
+        Self {
+            code: ErrCode::Unavailable,
+            message: "the server is shutting down".into(),
+            internal_message: Some("handler canceled due to graceful shutdown".into()),
+            stack: None,
+            details: None,
+        }
+    }
+
+    /// The error returned when a request's deadline expires before it completes.
+    pub fn deadline_exceeded() -> Self {
+        Self {
+            code: ErrCode::DeadlineExceeded,
+            message: "deadline exceeded".into(),
+            internal_message: Some("the request did not complete before its deadline".into()),
+            stack: None,
+            details: None,
+        }
+    }
 }
 
 impl From<WebSocketUpgradeRejection> for Error {
@@ -11,6 +11,8 @@ use axum::async_trait;
 use bytes::{BufMut, Bytes, BytesMut};
 use hyper::header;
 use pingora::http::{RequestHeader, ResponseHeader};
+use pingora::modules::http::compression::{ResponseCompression, ResponseCompressionBuilder};
+use pingora::modules::http::HttpModules;
 use pingora::protocols::http::error_resp;
 use pingora::proxy::{http_proxy_service, ProxyHttp, Session};
 use pingora::server::configuration::{Opt, ServerConf};
@@ -19,17 +21,22 @@ use pingora::upstreams::peer::HttpPeer;
 use pingora::{Error, ErrorSource, ErrorType, OkOrErr, OrErr};
 use router::Target;
 use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
 use url::Url;
 
 use crate::api::auth;
 use crate::api::call::{CallDesc, ServiceRegistry};
+use crate::api::compression;
+use crate::api::lb;
 use crate::api::paths::PathSet;
+use crate::api::ratelimit::{self, RateLimiter};
 use crate::api::reqauth::caller::Caller;
+use crate::api::reqauth::meta::MetaKey;
 use crate::api::reqauth::{svcauth, CallMeta};
 use crate::{api, model, EncoreName};
 
 use super::cors::cors_headers_config::CorsHeadersConfig;
-use super::encore_routes::healthz;
+use super::encore_routes::{admin, healthz, metrics};
 
 #[derive(Clone)]
 pub struct Gateway {
@@ -44,6 +51,13 @@ struct Inner {
     healthz: healthz::Handler,
     own_api_address: Option<SocketAddr>,
     proxied_push_subs: HashMap<String, EncoreName>,
+    rate_limiter: Arc<RateLimiter>,
+    admin_auth: admin::Auth,
+
+    /// Whether a traceparent header on incoming requests is kept as the parent
+    /// of the request's span. Only done when exporting traces over OTLP, as
+    /// platforms like Cloud Run add a traceparent header to every request.
+    ext_parent_spans: bool,
 }
 
 pub struct GatewayCtx {
@@ -51,6 +65,11 @@ pub struct GatewayCtx {
     upstream_base_path: String,
     upstream_host: Option<String>,
     upstream_require_auth: bool,
+
+    /// The instance the request is sent to, for services with multiple instances.
+    upstream_instance: Option<lb::Selection>,
+    /// The number of failed attempts at connecting to an instance.
+    connect_failures: usize,
 }
 
 impl GatewayCtx {
@@ -85,6 +104,9 @@ impl Gateway {
         healthz: healthz::Handler,
         own_api_address: Option<SocketAddr>,
         proxied_push_subs: HashMap<String, EncoreName>,
+        rate_limiter: Arc<RateLimiter>,
+        admin_auth: admin::Auth,
+        ext_parent_spans: bool,
     ) -> anyhow::Result<Self> {
         let shared = Arc::new(SharedGatewayData {
             name,
@@ -103,6 +125,9 @@ impl Gateway {
                 healthz,
                 own_api_address,
                 proxied_push_subs,
+                rate_limiter,
+                admin_auth,
+                ext_parent_spans,
             }),
         })
     }
@@ -111,7 +136,41 @@ impl Gateway {
         self.inner.shared.auth.as_ref()
     }
 
-    pub async fn serve(self, listen_addr: &str) -> anyhow::Result<()> {
+    /// Checks the request against the rate limits keyed by client IP.
+    /// Requests that don't route to an endpoint are not limited.
+    fn check_rate_limits(&self, session: &Session) -> Result<(), ratelimit::RateLimited> {
+        let Some(peer) = session
+            .client_addr()
+            .and_then(|addr| addr.as_inet())
+            .map(|addr| addr.ip())
+        else {
+            return Ok(());
+        };
+
+        let limiter = &self.inner.rate_limiter;
+        if !limiter.has_limits(ratelimit::Key::ClientIp(peer)) {
+            return Ok(());
+        }
+
+        let req = session.req_header();
+        let key = ratelimit::Key::ClientIp(limiter.client_ip(peer, &req.headers));
+        let Ok(method) = req.method.as_ref().try_into() else {
+            return Ok(());
+        };
+        let endpoint = match self.inner.router.route_to_service(method, req.uri.path()) {
+            Ok(Target {
+                endpoint_name: Some(endpoint),
+                ..
+            }) => endpoint,
+            _ => return Ok(()),
+        };
+
+        limiter.check(endpoint, &[key])
+    }
+
+    /// Serves the gateway until `shutdown` is canceled, at which point
+    /// it stops accepting new connections.
+    pub async fn serve(self, listen_addr: &str, shutdown: CancellationToken) -> anyhow::Result<()> {
         let conf = Arc::new(
             ServerConf::new_with_opt_override(&Opt {
                 upgrade: false,
@@ -126,7 +185,11 @@ impl Gateway {
 
         proxy.add_tcp(listen_addr);
 
-        let (_tx, rx) = watch::channel(false);
+        let (tx, rx) = watch::channel(false);
+        tokio::spawn(async move {
+            shutdown.cancelled().await;
+            _ = tx.send(true);
+        });
         proxy
             .start_service(
                 #[cfg(unix)]
@@ -139,6 +202,23 @@ impl Gateway {
     }
 }
 
+/// The compression level used for responses, which balances
+/// compression ratio against CPU usage.
+const COMPRESSION_LEVEL: u32 = 5;
+
+/// Reports whether a response should be compressed,
+/// using the same rules as the API server.
+fn should_compress(resp: &ResponseHeader) -> bool {
+    let header = |name| resp.headers.get(name).and_then(|v| v.to_str().ok());
+
+    let compressible =
+        header(header::CONTENT_TYPE).is_some_and(compression::is_compressible_content_type);
+    let too_small = header(header::CONTENT_LENGTH)
+        .and_then(|len| len.parse::<u64>().ok())
+        .is_some_and(|len| len < compression::MIN_SIZE as u64);
+    compressible && !too_small
+}
+
 #[async_trait]
 impl ProxyHttp for Gateway {
     type CTX = Option<GatewayCtx>;
@@ -150,6 +230,12 @@ impl ProxyHttp for Gateway {
     // see https://github.com/cloudflare/pingora/blob/main/docs/user_guide/internals.md for
     // details on when different filters are called.
 
+    fn init_downstream_modules(&self, modules: &mut HttpModules) {
+        // Compress responses the upstream didn't already compress,
+        // with the encoding negotiated from the Accept-Encoding header.
+        modules.add_module(ResponseCompressionBuilder::enable(COMPRESSION_LEVEL));
+    }
+
     async fn request_filter(
         &self,
         session: &mut Session,
@@ -158,12 +244,28 @@ impl ProxyHttp for Gateway {
     where
         Self::CTX: Send + Sync,
     {
-        if session.req_header().uri.path() == "/__encore/healthz" {
-            let healthz_resp = self.inner.healthz.clone().health_check();
+        let path = session.req_header().uri.path();
+        if matches!(
+            path,
+            "/__encore/healthz" | "/__encore/livez" | "/__encore/readyz"
+        ) {
+            let healthz_resp = if path == "/__encore/readyz" {
+                // Only include the results of the individual checks for
+                // authenticated requests, as they reveal internal details.
+                let req = session.req_header();
+                let detailed = self
+                    .inner
+                    .admin_auth
+                    .authenticate(path, &req.headers)
+                    .is_ok();
+                self.inner.healthz.clone().readiness_check(detailed).await
+            } else {
+                self.inner.healthz.clone().health_check()
+            };
             let healthz_bytes: Vec<u8> = serde_json::to_vec(&healthz_resp)
                 .or_err(ErrorType::HTTPStatus(500), "could not encode response")?;
 
-            let mut header = ResponseHeader::build(200, None)?;
+            let mut header = ResponseHeader::build(healthz_resp.status_code(), None)?;
             header.insert_header(header::CONTENT_LENGTH, healthz_bytes.len())?;
             header.insert_header(header::CONTENT_TYPE, "application/json")?;
             session
@@ -176,6 +278,31 @@ impl ProxyHttp for Gateway {
             return Ok(true);
         }
 
+        if session.req_header().uri.path() == "/__encore/metrics" {
+            let handler = metrics::Handler(self.inner.admin_auth.clone());
+            let req = session.req_header();
+            if let Err(err) = handler.0.authenticate(req.uri.path(), &req.headers) {
+                let (resp, body) = api_error_response(&err);
+                session.write_response_header(Box::new(resp), false).await?;
+                session.write_response_body(Some(body), true).await?;
+                return Ok(true);
+            }
+
+            let body = handler.render();
+
+            let mut header = ResponseHeader::build(200, None)?;
+            header.insert_header(header::CONTENT_LENGTH, body.len())?;
+            header.insert_header(header::CONTENT_TYPE, crate::metrics::TEXT_CONTENT_TYPE)?;
+            session
+                .write_response_header(Box::new(header), false)
+                .await?;
+            session
+                .write_response_body(Some(Bytes::from(body)), true)
+                .await?;
+
+            return Ok(true);
+        }
+
         // preflight request, return early with cors headers
         if axum::http::Method::OPTIONS == session.req_header().method {
             let mut resp = ResponseHeader::build(200, None)?;
@@ -188,6 +315,18 @@ impl ProxyHttp for Gateway {
             return Ok(true);
         }
 
+        if let Err(limited) = self.check_rate_limits(session) {
+            let (mut resp, body) = api_error_response(&limited.to_error());
+            resp.insert_header(header::RETRY_AFTER, limited.retry_after_secs())?;
+            self.inner
+                .cors_config
+                .apply(session.req_header(), &mut resp)?;
+            session.write_response_header(Box::new(resp), false).await?;
+            session.write_response_body(Some(body), true).await?;
+
+            return Ok(true);
+        }
+
         Ok(false)
     }
 
@@ -204,6 +343,7 @@ impl ProxyHttp for Gateway {
             .and_then(|sub_id| self.inner.proxied_push_subs.get(sub_id))
             .map(|svc| Target {
                 service_name: svc.clone(),
+                endpoint_name: None,
                 requires_auth: false,
             });
 
@@ -233,31 +373,53 @@ impl ProxyHttp for Gateway {
             Ok,
         )?;
 
-        let upstream = self
+        let connect_failures = ctx.as_ref().map_or(0, |ctx| ctx.connect_failures);
+
+        let (upstream_url, upstream_addr, upstream_instance) = match self
             .inner
             .service_registry
-            .service_base_url(&target.service_name)
-            .or_err(ErrorType::InternalError, "couldn't find upstream")?;
-
-        let upstream_url: Url = upstream
-            .parse()
-            .or_err(ErrorType::InternalError, "upstream not a valid url")?;
-
-        let upstream_addrs = upstream_url
-            .socket_addrs(|| match upstream_url.scheme() {
-                "https" => Some(443),
-                "http" => Some(80),
-                _ => None,
-            })
-            .or_err(
-                ErrorType::InternalError,
-                "couldn't lookup upstream ip address",
-            )?;
+            .service_upstream(&target.service_name)
+        {
+            Some(upstream) => {
+                let instance = upstream.select();
+                (instance.url().clone(), instance.addr(), Some(instance))
+            }
+            None => {
+                let upstream = self
+                    .inner
+                    .service_registry
+                    .service_base_url(&target.service_name)
+                    .or_err(ErrorType::InternalError, "couldn't find upstream")?;
+
+                let upstream_url: Url = upstream
+                    .parse()
+                    .or_err(ErrorType::InternalError, "upstream not a valid url")?;
+
+                (upstream_url, None, None)
+            }
+        };
 
-        let upstream_addr = upstream_addrs.first().or_err(
-            ErrorType::InternalError,
-            "didn't find any upstream ip addresses",
-        )?;
+        // Look up the address unless the instance's address is already known.
+        let upstream_addr = match upstream_addr {
+            Some(addr) => addr,
+            None => {
+                let upstream_addrs = upstream_url
+                    .socket_addrs(|| match upstream_url.scheme() {
+                        "https" => Some(443),
+                        "http" => Some(80),
+                        _ => None,
+                    })
+                    .or_err(
+                        ErrorType::InternalError,
+                        "couldn't lookup upstream ip address",
+                    )?;
+
+                *upstream_addrs.first().or_err(
+                    ErrorType::InternalError,
+                    "didn't find any upstream ip addresses",
+                )?
+            }
+        };
 
         let tls = upstream_url.scheme() == "https";
         let host = upstream_url.host().map(|h| h.to_string());
@@ -268,11 +430,42 @@ impl ProxyHttp for Gateway {
             upstream_host: host,
             upstream_service_name: target.service_name.clone(),
             upstream_require_auth: target.requires_auth,
+            upstream_instance,
+            connect_failures,
         });
 
         Ok(Box::new(peer))
     }
 
+    fn fail_to_connect(
+        &self,
+        _session: &mut Session,
+        _peer: &HttpPeer,
+        ctx: &mut Self::CTX,
+        mut e: Box<Error>,
+    ) -> Box<Error> {
+        let Some(gateway_ctx) = ctx.as_mut() else {
+            return e;
+        };
+        let Some(instance) = gateway_ctx.upstream_instance.take() else {
+            return e;
+        };
+
+        // Skip the instance for a while, and try another one since
+        // nothing was sent to this one.
+        instance.eject();
+        gateway_ctx.connect_failures += 1;
+        let num_instances = self
+            .inner
+            .service_registry
+            .service_upstream(&gateway_ctx.upstream_service_name)
+            .map_or(0, |upstream| upstream.num_instances());
+        if gateway_ctx.connect_failures < num_instances {
+            e.set_retry(true);
+        }
+        e
+    }
+
     async fn response_filter(
         &self,
         session: &mut Session,
@@ -288,6 +481,15 @@ impl ProxyHttp for Gateway {
                 .apply(session.req_header(), upstream_response)?;
         }
 
+        if !should_compress(upstream_response) {
+            if let Some(compression) = session
+                .downstream_modules_ctx
+                .get_mut::<ResponseCompression>()
+            {
+                compression.adjust_level(0);
+            }
+        }
+
         Ok(())
     }
 
@@ -330,12 +532,19 @@ impl ProxyHttp for Gateway {
                 .service_auth_method(&gateway_ctx.upstream_service_name)
                 .unwrap_or_else(|| Arc::new(svcauth::Noop));
 
+            // Deadlines are only propagated between Encore services, and the
+            // request is forwarded as an internal call from the gateway.
+            upstream_request.remove_header(MetaKey::Deadline.header_key());
+
             let headers = &upstream_request.headers;
 
             let mut call_meta = CallMeta::parse_without_caller(headers).or_err(
                 ErrorType::InternalError,
                 "couldn't parse CallMeta from request",
             )?;
+            // A parent span from the traceparent header was created by an external tracing
+            // system, which the service needs to know to keep it as the parent of the request.
+            let ext_parent_span = self.inner.ext_parent_spans && call_meta.parent_span_id.is_some();
             if call_meta.parent_span_id.is_none() {
                 call_meta.parent_span_id = Some(model::SpanId::generate());
             }
@@ -349,10 +558,12 @@ impl ProxyHttp for Gateway {
                     .parent_span_id
                     .map(|sp| call_meta.trace_id.with_span(sp)),
                 parent_event_id: None,
+                ext_parent_span,
                 ext_correlation_id: call_meta
                     .ext_correlation_id
                     .as_ref()
                     .map(|s| Cow::Borrowed(s.as_str())),
+                deadline: None,
                 auth_user_id: None,
                 auth_data: None,
                 svc_auth_method: svc_auth_method.as_ref(),
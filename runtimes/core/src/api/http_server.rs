@@ -9,6 +9,8 @@ use axum::serve::IncomingStream;
 use axum::Router;
 use tower_service::Service;
 
+use super::compression;
+
 #[derive(Clone)]
 pub struct HttpServer {
     encore_routes: Router,
@@ -19,9 +21,9 @@ pub struct HttpServer {
 impl HttpServer {
     pub fn new(encore_routes: Router, api: Option<Router>, fallback: Router) -> Self {
         Self {
-            encore_routes,
-            api,
-            fallback,
+            encore_routes: compression::apply(encore_routes),
+            api: api.map(compression::apply),
+            fallback: compression::apply(fallback),
         }
     }
 }
@@ -0,0 +1,420 @@
+use std::collections::{BTreeSet, HashMap};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Context;
+use axum::async_trait;
+use pingora::http::RequestHeader;
+use pingora::lb::discovery::ServiceDiscovery;
+use pingora::lb::health_check::{HealthCheck, HttpHealthCheck};
+use pingora::lb::selection::RoundRobin;
+use pingora::lb::{Backend, Backends, LoadBalancer};
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use url::{Host, Url};
+
+use crate::api::httputil::join_url_path;
+use crate::encore::runtime::v1 as pb;
+use crate::EncoreName;
+
+use pb::service_discovery::load_balancing::Strategy;
+
+/// Balances calls to a service across multiple instances of it.
+///
+/// Instances are skipped while they fail active health checks against
+/// their `/__encore/healthz` endpoint, and for a while after a connection
+/// to them fails. Their addresses are resolved in the background, so an
+/// instance that can't be resolved yet doesn't prevent startup.
+pub struct Upstream {
+    service: EncoreName,
+    instances: Arc<[Instance]>,
+    balancer: LoadBalancer<RoundRobin>,
+    strategy: Strategy,
+    health_checks: bool,
+    refresh_interval: Duration,
+    ejection_duration: Duration,
+}
+
+struct Instance {
+    base_url: String,
+    url: Url,
+    /// The most recently resolved address, if any.
+    addr: Mutex<Option<SocketAddr>>,
+    in_flight: AtomicUsize,
+    ejected_until: Mutex<Option<Instant>>,
+}
+
+impl Instance {
+    fn addr(&self) -> Option<SocketAddr> {
+        *self.addr.lock().unwrap()
+    }
+
+    async fn resolve(&self) -> anyhow::Result<SocketAddr> {
+        let port = self.url.port_or_known_default().context("unknown port")?;
+        let addr = match self.url.host().context("missing host")? {
+            Host::Ipv4(ip) => SocketAddr::new(ip.into(), port),
+            Host::Ipv6(ip) => SocketAddr::new(ip.into(), port),
+            Host::Domain(domain) => tokio::net::lookup_host((domain, port))
+                .await?
+                .next()
+                .context("didn't find any ip addresses")?,
+        };
+        *self.addr.lock().unwrap() = Some(addr);
+        Ok(addr)
+    }
+
+    fn is_ejected(&self, now: Instant) -> bool {
+        self.ejected_until
+            .lock()
+            .unwrap()
+            .is_some_and(|until| now < until)
+    }
+
+    fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+}
+
+impl Upstream {
+    pub fn new(
+        service: EncoreName,
+        base_urls: Vec<String>,
+        cfg: pb::service_discovery::LoadBalancing,
+    ) -> anyhow::Result<Self> {
+        let mut instances = Vec::with_capacity(base_urls.len());
+        for base_url in base_urls {
+            let url: Url = base_url
+                .parse()
+                .with_context(|| format!("invalid base url {}", base_url))?;
+            if url.host().is_none() || url.port_or_known_default().is_none() {
+                anyhow::bail!("invalid base url {}: missing host or port", base_url);
+            }
+
+            instances.push(Instance {
+                base_url,
+                url,
+                addr: Mutex::new(None),
+                in_flight: AtomicUsize::new(0),
+                ejected_until: Mutex::new(None),
+            });
+        }
+
+        if instances.is_empty() {
+            anyhow::bail!("no instances of service {}", service);
+        }
+        let instances: Arc<[Instance]> = instances.into();
+
+        let discovery = Discovery {
+            service: service.clone(),
+            instances: instances.clone(),
+        };
+        let mut balancer =
+            LoadBalancer::<RoundRobin>::from_backends(Backends::new(Box::new(discovery)));
+
+        let health_checks = !cfg.disable_health_checks;
+        if health_checks {
+            let checks = instances
+                .iter()
+                .map(http_health_check)
+                .collect::<anyhow::Result<_>>()?;
+            balancer.set_health_check(Box::new(InstanceHealthCheck {
+                instances: instances.clone(),
+                checks,
+            }));
+        }
+
+        let refresh_interval = cfg
+            .health_check_interval
+            .and_then(|d| Duration::try_from(d).ok())
+            .unwrap_or(Duration::from_secs(10));
+
+        let ejection_duration = cfg
+            .ejection_duration
+            .and_then(|d| Duration::try_from(d).ok())
+            .unwrap_or(Duration::from_secs(30));
+
+        Ok(Self {
+            service,
+            instances,
+            balancer,
+            strategy: cfg.strategy(),
+            health_checks,
+            refresh_interval,
+            ejection_duration,
+        })
+    }
+
+    /// Selects the instance to send a request to.
+    ///
+    /// The instance is considered to have the request in flight
+    /// until the returned selection is dropped.
+    pub fn select(self: &Arc<Self>) -> Selection {
+        let now = Instant::now();
+        let available = |inst: &Instance| !inst.is_ejected(now);
+
+        let selected = match self.strategy {
+            Strategy::LeastConnections => {
+                let backends = self.balancer.backends();
+                backends
+                    .get_backend()
+                    .iter()
+                    .filter(|backend| backends.ready(backend))
+                    .filter_map(|backend| self.instance_idx(backend))
+                    .filter(|idx| available(&self.instances[*idx]))
+                    .min_by_key(|idx| self.instances[*idx].in_flight())
+            }
+            Strategy::RoundRobin | Strategy::Unspecified => self
+                .balancer
+                .select_with(b"", self.instances.len(), |backend, healthy| {
+                    healthy
+                        && self
+                            .instance_idx(backend)
+                            .is_some_and(|idx| available(&self.instances[idx]))
+                })
+                .and_then(|backend| self.instance_idx(&backend)),
+        };
+
+        // If no instance is healthy or resolved yet, try the least
+        // loaded one rather than failing the request outright.
+        let idx = selected.unwrap_or_else(|| {
+            (0..self.instances.len())
+                .min_by_key(|idx| self.instances[*idx].in_flight())
+                .expect("upstream has instances")
+        });
+
+        self.instances[idx]
+            .in_flight
+            .fetch_add(1, Ordering::Relaxed);
+        Selection {
+            upstream: self.clone(),
+            idx,
+        }
+    }
+
+    pub fn num_instances(&self) -> usize {
+        self.instances.len()
+    }
+
+    /// Keeps the instance addresses up to date and runs the active
+    /// health checks, if enabled, until `shutdown` is canceled.
+    pub async fn run(self: Arc<Self>, shutdown: CancellationToken) {
+        loop {
+            if let Err(err) = self.balancer.update().await {
+                log::warn!(
+                    "couldn't update instances of service {}: {}",
+                    self.service,
+                    err
+                );
+            }
+            if self.health_checks {
+                self.balancer.backends().run_health_check(true).await;
+            }
+            tokio::select! {
+                _ = shutdown.cancelled() => return,
+                _ = tokio::time::sleep(self.refresh_interval) => {}
+            }
+        }
+    }
+
+    fn instance_idx(&self, backend: &Backend) -> Option<usize> {
+        instance_idx(&self.instances, backend)
+    }
+}
+
+/// Finds the instance a backend's address was resolved for.
+fn instance_idx(instances: &[Instance], backend: &Backend) -> Option<usize> {
+    let addr = backend.addr.as_inet()?;
+    instances.iter().position(|inst| inst.addr() == Some(*addr))
+}
+
+/// Builds the health check for an instance, using its
+/// own scheme and host for the Host header and SNI.
+fn http_health_check(inst: &Instance) -> anyhow::Result<HttpHealthCheck> {
+    let host = inst.url.host_str().unwrap_or_default();
+    let path =
+        join_url_path(inst.url.path(), "/__encore/healthz").context("invalid health check path")?;
+
+    let mut hc = HttpHealthCheck::new(host, inst.url.scheme() == "https");
+    hc.req = RequestHeader::build("GET", path.as_bytes(), None)
+        .context("couldn't build health check request")?;
+    hc.req
+        .insert_header(http::header::HOST, host)
+        .context("couldn't build health check request")?;
+    Ok(hc)
+}
+
+/// Checks the health of each instance with the health check built for it.
+struct InstanceHealthCheck {
+    instances: Arc<[Instance]>,
+    checks: Vec<HttpHealthCheck>,
+}
+
+#[async_trait]
+impl HealthCheck for InstanceHealthCheck {
+    async fn check(&self, target: &Backend) -> pingora::Result<()> {
+        match instance_idx(&self.instances, target) {
+            Some(idx) => self.checks[idx].check(target).await,
+            None => pingora::Error::e_explain(
+                pingora::ErrorType::InternalError,
+                "health check target is not an instance of the service",
+            ),
+        }
+    }
+
+    fn health_threshold(&self, success: bool) -> usize {
+        self.checks[0].health_threshold(success)
+    }
+}
+
+/// Resolves the addresses of the instances of a service.
+struct Discovery {
+    service: EncoreName,
+    instances: Arc<[Instance]>,
+}
+
+#[async_trait]
+impl ServiceDiscovery for Discovery {
+    async fn discover(&self) -> pingora::Result<(BTreeSet<Backend>, HashMap<u64, bool>)> {
+        let mut backends = BTreeSet::new();
+        for inst in self.instances.iter() {
+            // Keep using the last known address if the lookup fails.
+            let addr = match inst.resolve().await {
+                Ok(addr) => addr,
+                Err(err) => {
+                    log::warn!(
+                        "couldn't lookup ip address for service {} instance {}: {:#}",
+                        self.service,
+                        inst.base_url,
+                        err
+                    );
+                    match inst.addr() {
+                        Some(addr) => addr,
+                        None => continue,
+                    }
+                }
+            };
+            backends.insert(Backend::new(&addr.to_string())?);
+        }
+        Ok((backends, HashMap::new()))
+    }
+}
+
+/// An instance selected to send a request to.
+pub struct Selection {
+    upstream: Arc<Upstream>,
+    idx: usize,
+}
+
+impl Selection {
+    /// The base URL of the instance.
+    pub fn base_url(&self) -> &str {
+        &self.instance().base_url
+    }
+
+    pub fn url(&self) -> &Url {
+        &self.instance().url
+    }
+
+    /// The address of the instance, if it has been resolved.
+    pub fn addr(&self) -> Option<SocketAddr> {
+        self.instance().addr()
+    }
+
+    /// Takes the instance out of rotation, after a connection to it failed.
+    pub fn eject(&self) {
+        let until = Instant::now() + self.upstream.ejection_duration;
+        *self.instance().ejected_until.lock().unwrap() = Some(until);
+        log::warn!(
+            "service {} instance {} is unreachable, skipping it for {:?}",
+            self.upstream.service,
+            self.instance().base_url,
+            self.upstream.ejection_duration,
+        );
+    }
+
+    fn instance(&self) -> &Instance {
+        &self.upstream.instances[self.idx]
+    }
+}
+
+impl Drop for Selection {
+    fn drop(&mut self) {
+        self.instance().in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn upstream(strategy: Strategy) -> Arc<Upstream> {
+        let cfg = pb::service_discovery::LoadBalancing {
+            strategy: strategy as i32,
+            disable_health_checks: true,
+            ..Default::default()
+        };
+        let urls = vec![
+            "http://127.0.0.1:4001".to_string(),
+            "http://127.0.0.1:4002".to_string(),
+        ];
+        let upstream = Arc::new(Upstream::new("svc".into(), urls, cfg).unwrap());
+        upstream.balancer.update().await.unwrap();
+        upstream
+    }
+
+    #[test]
+    fn test_unresolved() {
+        let cfg = pb::service_discovery::LoadBalancing {
+            disable_health_checks: true,
+            ..Default::default()
+        };
+        let urls = vec!["http://svc.invalid:4001".to_string()];
+        let upstream = Arc::new(Upstream::new("svc".into(), urls, cfg).unwrap());
+
+        // Requests can still be attempted before the address is resolved.
+        let selection = upstream.select();
+        assert_eq!(selection.base_url(), "http://svc.invalid:4001");
+        assert_eq!(selection.addr(), None);
+    }
+
+    #[tokio::test]
+    async fn test_round_robin() {
+        let upstream = upstream(Strategy::RoundRobin).await;
+        let first = upstream.select().base_url().to_string();
+        let second = upstream.select().base_url().to_string();
+        assert_ne!(first, second);
+        assert_eq!(upstream.select().base_url(), first);
+    }
+
+    #[tokio::test]
+    async fn test_least_connections() {
+        let upstream = upstream(Strategy::LeastConnections).await;
+        let a = upstream.select();
+        let b = upstream.select();
+        assert_ne!(a.base_url(), b.base_url());
+
+        // Completing a request frees up its instance.
+        let freed = b.base_url().to_string();
+        drop(b);
+        assert_eq!(upstream.select().base_url(), freed);
+    }
+
+    #[tokio::test]
+    async fn test_ejection() {
+        let upstream = upstream(Strategy::RoundRobin).await;
+        let ejected = upstream.select();
+        ejected.eject();
+        for _ in 0..4 {
+            assert_ne!(upstream.select().base_url(), ejected.base_url());
+        }
+
+        // If all instances are ejected, requests are still sent somewhere.
+        let other = upstream.select();
+        other.eject();
+        drop(ejected);
+        drop(other);
+        upstream.select();
+    }
+}
@@ -5,10 +5,11 @@ use std::sync::{Arc, Mutex};
 use anyhow::Context;
 
 use crate::api::auth::{LocalAuthHandler, RemoteAuthHandler};
-use crate::api::call::ServiceRegistry;
+use crate::api::call::{CallOpts, ServiceRegistry};
 use crate::api::gateway::Gateway;
 use crate::api::http_server::HttpServer;
 use crate::api::paths::Pather;
+use crate::api::ratelimit::RateLimiter;
 use crate::api::reqauth::platform;
 use crate::api::schema::encoding::EncodingConfig;
 use crate::api::schema::JSONPayload;
@@ -19,9 +20,9 @@ use crate::api::{
 use crate::encore::parser::meta::v1 as meta;
 use crate::encore::runtime::v1 as runtime;
 use crate::trace::Tracer;
-use crate::{api, model, pubsub, secrets, EncoreName, EndpointName, Hosted};
+use crate::{api, health, model, pubsub, secrets, shutdown, EncoreName, EndpointName, Hosted};
 
-use super::encore_routes::healthz;
+use super::encore_routes::{admin, healthz};
 use super::websocket_client::WebSocketClient;
 use super::ResponsePayload;
 
@@ -43,6 +44,11 @@ pub struct ManagerConfig<'a> {
     pub runtime: tokio::runtime::Handle,
     pub testing: bool,
     pub proxied_push_subs: HashMap<String, EncoreName>,
+    pub rate_limits: Vec<runtime::RateLimit>,
+    pub trusted_proxies: Vec<String>,
+    pub shutdown: shutdown::Tracker,
+    pub readiness: health::Readiness,
+    pub admin_api: Option<runtime::AdminApi>,
 }
 
 pub struct Manager {
@@ -50,6 +56,7 @@ pub struct Manager {
     api_listener: Mutex<Option<std::net::TcpListener>>,
     service_registry: Arc<ServiceRegistry>,
     healthz: healthz::Handler,
+    admin_auth: admin::Auth,
     pubsub_push_registry: pubsub::PushHandlerRegistry,
 
     api_server: Option<server::Server>,
@@ -57,6 +64,7 @@ pub struct Manager {
 
     gateways: HashMap<EncoreName, Gateway>,
     testing: bool,
+    shutdown: shutdown::Tracker,
 }
 
 impl ManagerConfig<'_> {
@@ -102,6 +110,7 @@ impl ManagerConfig<'_> {
                 .strip_prefix("roll_")
                 .unwrap_or(&self.deploy_id)
                 .to_string(),
+            readiness: self.readiness,
         };
 
         let hosted_services = Hosted::from_iter(self.hosted_services.into_iter().map(|s| s.name));
@@ -137,6 +146,10 @@ impl ManagerConfig<'_> {
         .context("unable to create service registry")?;
         let service_registry = Arc::new(service_registry);
 
+        let rate_limiter = RateLimiter::from_config(self.rate_limits, self.trusted_proxies)
+            .context("invalid rate limit configuration")?;
+        let rate_limiter = Arc::new(rate_limiter);
+
         let gateways_by_rid: HashMap<String, runtime::Gateway> =
             HashMap::from_iter(self.gateways.drain(..).map(|gw| (gw.rid.clone(), gw)));
 
@@ -146,6 +159,17 @@ impl ManagerConfig<'_> {
                     .get(rid)
                     .map(|gw| (gw.encore_name.as_str(), gw))
             }));
+        let admin_auth = admin::Auth {
+            tokens: self
+                .admin_api
+                .map(|cfg| cfg.tokens)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|data| self.secrets.load(data))
+                .collect(),
+            platform_validator: self.platform_validator.clone(),
+        };
+
         let mut gateways = HashMap::new();
         let routes = paths::compute(
             endpoints
@@ -191,6 +215,9 @@ impl ManagerConfig<'_> {
                     healthz_handler.clone(),
                     own_api_address,
                     self.proxied_push_subs.clone(),
+                    rate_limiter.clone(),
+                    admin_auth.clone(),
+                    self.tracer.otlp_enabled(),
                 )
                 .context("couldn't create gateway")?,
             );
@@ -204,6 +231,8 @@ impl ManagerConfig<'_> {
                 inbound_svc_auth,
                 self.tracer.clone(),
                 auth_data_schemas,
+                rate_limiter,
+                self.shutdown.clone(),
             )
             .context("unable to create API server")?;
             Some(server)
@@ -220,7 +249,9 @@ impl ManagerConfig<'_> {
             pubsub_push_registry: self.pubsub_push_registry,
             runtime: self.runtime,
             healthz: healthz_handler,
+            admin_auth,
             testing: self.testing,
+            shutdown: self.shutdown,
         })
     }
 }
@@ -324,8 +355,9 @@ impl Manager {
         target: EndpointName,
         data: JSONPayload,
         source: Option<Arc<model::Request>>,
+        opts: CallOpts,
     ) -> impl Future<Output = APIResult<ResponsePayload>> + 'static {
-        self.service_registry.api_call(target, data, source)
+        self.service_registry.api_call(target, data, source, opts)
     }
 
     pub fn stream(
@@ -357,6 +389,7 @@ impl Manager {
 
         let encore_routes = encore_routes::Desc {
             healthz: self.healthz.clone(),
+            admin: self.admin_auth.clone(),
             push_registry: self.pubsub_push_registry.clone(),
         }
         .router();
@@ -370,6 +403,12 @@ impl Manager {
         // TODO handle multiple gateways
         let gateway = self.gateways.values().next().cloned();
         let testing = self.testing;
+        let shutdown = self.shutdown.clone();
+
+        for upstream in self.service_registry.upstreams() {
+            self.runtime
+                .spawn(upstream.clone().run(shutdown.initiated()));
+        }
 
         self.runtime.spawn(async move {
             let gateway_parts = (gateway, gateway_listener);
@@ -377,7 +416,7 @@ impl Manager {
                 (Some(gw), Some(ref ln)) => {
                     if !testing {
                         log::debug!(addr=ln; "gateway listening for incoming requests");
-                        Some(gw.serve(ln))
+                        Some(gw.serve(ln, shutdown.initiated()))
                     } else {
                         // No need running the gateway in tests
                         None
@@ -404,24 +443,43 @@ impl Manager {
                         .context("unable to set nonblocking")?;
                     let axum_listener = tokio::net::TcpListener::from_std(ln)
                         .context("unable to convert listener to tokio")?;
-                    let fut = axum::serve(axum_listener, server).into_future();
+                    // Stop accepting new requests when the runtime shuts down,
+                    // and wait for outstanding requests to complete.
+                    let fut = axum::serve(axum_listener, server)
+                        .with_graceful_shutdown(shutdown.initiated().cancelled_owned())
+                        .into_future();
                     Some(fut)
                 }
                 None => None,
             };
 
-            tokio::select! {
-                res = async { gateway_fut.unwrap().await }, if gateway_fut.is_some() => {
-                    res.context("serve gateway").inspect_err(|err| log::error!("api gateway failed: {:?}", err))?;
-                },
-                res = async { api_fut.unwrap().await }, if api_fut.is_some() => {
-                    res.context("serve api").inspect_err(|err| log::error!("api server failed: {:?}", err))?;
-                },
-                else => {
-                    // Nothing to serve.
-                    ::log::debug!("no api server or gateway to serve");
+            if gateway_fut.is_none() && api_fut.is_none() {
+                // Nothing to serve.
+                ::log::debug!("no api server or gateway to serve");
+                return Ok(());
+            }
+
+            // Run both to completion, so that a graceful shutdown
+            // of one doesn't abort the other's outstanding requests.
+            let gateway_fut = async {
+                match gateway_fut {
+                    Some(fut) => fut
+                        .await
+                        .context("serve gateway")
+                        .inspect_err(|err| log::error!("api gateway failed: {:?}", err)),
+                    None => Ok(()),
+                }
+            };
+            let api_fut = async {
+                match api_fut {
+                    Some(fut) => fut
+                        .await
+                        .context("serve api")
+                        .inspect_err(|err| log::error!("api server failed: {:?}", err)),
+                    None => Ok(()),
                 }
             };
+            tokio::try_join!(gateway_fut, api_fut)?;
             Ok(())
         })
     }
@@ -1,6 +1,9 @@
 pub mod auth;
 pub mod call;
+mod circuitbreaker;
+mod compression;
 mod cors;
+pub mod deadline;
 mod encore_routes;
 mod endpoint;
 mod error;
@@ -8,10 +11,13 @@ pub mod gateway;
 mod http_server;
 mod httputil;
 pub mod jsonschema;
+pub mod lb;
 mod manager;
 mod paths;
 mod pvalue;
+mod ratelimit;
 pub mod reqauth;
+mod retry;
 pub mod schema;
 mod server;
 mod static_assets;
@@ -0,0 +1,570 @@
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+
+use crate::api::reqauth::caller::Caller;
+use crate::api::{ErrCode, Error};
+use crate::encore::runtime::v1 as pb;
+use crate::names::EndpointName;
+
+/// The number of tracked keys above which a limit prunes
+/// the buckets that have refilled completely.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// What a rate limit is keyed by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyKind {
+    Endpoint,
+    ClientIp,
+    AuthUid,
+}
+
+/// The key to check a request against.
+#[derive(Debug, Clone, Copy)]
+pub enum Key<'a> {
+    /// Check the per-endpoint limits.
+    Endpoint,
+    /// Check the limits keyed by the client's IP address.
+    ClientIp(IpAddr),
+    /// Check the limits keyed by the authenticated user id.
+    AuthUid(&'a str),
+}
+
+impl<'a> Key<'a> {
+    /// The keys to check a request handled by an endpoint against.
+    ///
+    /// Calls from other services don't count against the endpoint limits,
+    /// which are meant to protect the endpoint from external traffic.
+    pub fn for_request(internal_caller: Option<&Caller>, auth_uid: Option<&'a str>) -> Vec<Self> {
+        let from_service = internal_caller.is_some_and(|c| !c.is_gateway());
+        let mut keys = Vec::with_capacity(2);
+        if !from_service {
+            keys.push(Key::Endpoint);
+        }
+        if let Some(uid) = auth_uid {
+            keys.push(Key::AuthUid(uid));
+        }
+        keys
+    }
+
+    fn kind(&self) -> KeyKind {
+        match self {
+            Key::Endpoint => KeyKind::Endpoint,
+            Key::ClientIp(_) => KeyKind::ClientIp,
+            Key::AuthUid(_) => KeyKind::AuthUid,
+        }
+    }
+
+    fn bucket_key(&self, endpoint: &EndpointName) -> String {
+        match self {
+            Key::Endpoint => endpoint.to_string(),
+            Key::ClientIp(ip) => ip.to_string(),
+            Key::AuthUid(uid) => uid.to_string(),
+        }
+    }
+}
+
+/// Returned when a request exceeds a rate limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimited {
+    /// How long until the request would be allowed.
+    pub retry_after: Duration,
+}
+
+impl RateLimited {
+    /// The value to use for the Retry-After header, in whole seconds.
+    pub fn retry_after_secs(&self) -> u64 {
+        let secs = self.retry_after.as_secs();
+        if self.retry_after.subsec_nanos() > 0 || secs == 0 {
+            secs + 1
+        } else {
+            secs
+        }
+    }
+
+    pub fn to_error(&self) -> Error {
+        Error {
+            code: ErrCode::ResourceExhausted,
+            message: "rate limit exceeded".into(),
+            internal_message: Some(format!(
+                "rate limit exceeded, retry after {}s",
+                self.retry_after_secs()
+            )),
+            stack: None,
+            details: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Debug)]
+struct Limit {
+    kind: KeyKind,
+
+    /// The number of tokens added per second.
+    rate: f64,
+
+    /// The maximum number of tokens in a bucket.
+    burst: f64,
+
+    /// The endpoints the limit applies to.
+    /// If empty it applies to all endpoints.
+    endpoints: HashSet<EndpointName>,
+
+    buckets: Mutex<HashMap<String, TokenBucket>>,
+}
+
+impl Limit {
+    fn applies_to(&self, endpoint: &EndpointName) -> bool {
+        self.endpoints.is_empty() || self.endpoints.contains(endpoint)
+    }
+
+    /// Returns the bucket for the given key, refilled up to the given time.
+    fn bucket<'a>(
+        &self,
+        buckets: &'a mut HashMap<String, TokenBucket>,
+        key: &str,
+        now: Instant,
+    ) -> &'a mut TokenBucket {
+        if buckets.len() >= PRUNE_THRESHOLD && !buckets.contains_key(key) {
+            self.prune(buckets, now);
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(TokenBucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        bucket.tokens = self.refill(bucket, now);
+        bucket.updated = now;
+        bucket
+    }
+
+    fn refill(&self, bucket: &TokenBucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * self.rate).min(self.burst)
+    }
+
+    /// Removes the buckets that are full, since they
+    /// behave the same as a newly created bucket.
+    fn prune(&self, buckets: &mut HashMap<String, TokenBucket>, now: Instant) {
+        buckets.retain(|_, bucket| self.refill(bucket, now) < self.burst);
+    }
+}
+
+/// An IP address range, in CIDR notation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct IpRange {
+    addr: IpAddr,
+    prefix_len: u32,
+}
+
+impl IpRange {
+    /// Parses a CIDR range, or a single IP address.
+    fn parse(s: &str) -> anyhow::Result<Self> {
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, len)) => (addr, Some(len)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr
+            .parse()
+            .with_context(|| format!("invalid IP address {:?}", s))?;
+        let max_len = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(len) => len
+                .parse()
+                .ok()
+                .filter(|len| *len <= max_len)
+                .with_context(|| format!("invalid CIDR prefix length in {:?}", s))?,
+            None => max_len,
+        };
+        Ok(Self { addr, prefix_len })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix_len).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix_len).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Enforces the configured rate limits on incoming requests.
+///
+/// Limits are tracked in memory, so they apply per process.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    limits: Vec<Limit>,
+
+    /// The reverse proxies that are trusted to report
+    /// the client IP address in the X-Forwarded-For header.
+    trusted_proxies: Vec<IpRange>,
+}
+
+impl RateLimiter {
+    pub fn from_config(
+        cfg: Vec<pb::RateLimit>,
+        trusted_proxies: Vec<String>,
+    ) -> anyhow::Result<Self> {
+        let trusted_proxies = trusted_proxies
+            .iter()
+            .map(|s| IpRange::parse(s).context("invalid trusted proxy"))
+            .collect::<anyhow::Result<_>>()?;
+
+        let mut limits = Vec::with_capacity(cfg.len());
+        for limit in cfg {
+            let kind = match limit.key() {
+                pb::rate_limit::Key::Endpoint => KeyKind::Endpoint,
+                pb::rate_limit::Key::ClientIp => KeyKind::ClientIp,
+                pb::rate_limit::Key::AuthUid => KeyKind::AuthUid,
+                pb::rate_limit::Key::Unspecified => anyhow::bail!("rate limit key not set"),
+            };
+
+            let limiter = limit.limiter.context("rate limiter not set")?;
+            let (rate, burst) = match limiter.kind {
+                Some(pb::rate_limiter::Kind::TokenBucket(tb)) => (tb.rate, tb.burst),
+                None => anyhow::bail!("rate limiter kind not set"),
+            };
+            if !rate.is_finite() || rate <= 0.0 {
+                anyhow::bail!("invalid rate limit rate {}: must be positive", rate);
+            } else if burst == 0 {
+                anyhow::bail!("invalid rate limit burst: must be at least 1");
+            }
+
+            let endpoints = limit
+                .endpoints
+                .into_iter()
+                .map(|ep| {
+                    EndpointName::try_from(ep.clone())
+                        .with_context(|| format!("invalid endpoint name {:?}", ep))
+                })
+                .collect::<anyhow::Result<_>>()?;
+
+            limits.push(Limit {
+                kind,
+                rate,
+                burst: burst as f64,
+                endpoints,
+                buckets: Mutex::new(HashMap::new()),
+            });
+        }
+        Ok(Self {
+            limits,
+            trusted_proxies,
+        })
+    }
+
+    /// Returns the IP address of the client that made a request
+    /// received from the given peer.
+    ///
+    /// If the peer is a trusted proxy the X-Forwarded-For header is read
+    /// from right to left, skipping the trusted proxies, so clients
+    /// can't pick their own address by sending the header themselves.
+    pub fn client_ip(&self, peer: IpAddr, headers: &http::HeaderMap) -> IpAddr {
+        let is_trusted = |ip: IpAddr| self.trusted_proxies.iter().any(|r| r.contains(ip));
+        if !is_trusted(peer) {
+            return peer;
+        }
+
+        let forwarded: Vec<&str> = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(str::trim)
+            .collect();
+
+        let mut client = peer;
+        for addr in forwarded.into_iter().rev() {
+            let Some(ip) = parse_forwarded_addr(addr) else {
+                break;
+            };
+            client = ip;
+            if !is_trusted(ip) {
+                break;
+            }
+        }
+        client
+    }
+
+    /// Reports whether the limiter has any limits keyed by the given key.
+    pub fn has_limits(&self, key: Key<'_>) -> bool {
+        let kind = key.kind();
+        self.limits.iter().any(|l| l.kind == kind)
+    }
+
+    /// Checks a request to the given endpoint against the limits
+    /// matching any of the given keys.
+    ///
+    /// Tokens are only taken if every limit allows the request.
+    pub fn check(&self, endpoint: &EndpointName, keys: &[Key<'_>]) -> Result<(), RateLimited> {
+        self.check_at(endpoint, keys, Instant::now())
+    }
+
+    fn check_at(
+        &self,
+        endpoint: &EndpointName,
+        keys: &[Key<'_>],
+        now: Instant,
+    ) -> Result<(), RateLimited> {
+        // Lock all the applicable limits before taking any tokens,
+        // so a request rejected by one limit doesn't consume tokens from the others.
+        // The locks are always acquired in the same order, so this can't deadlock.
+        let mut locked = Vec::new();
+        for limit in &self.limits {
+            if !limit.applies_to(endpoint) {
+                continue;
+            }
+            if let Some(key) = keys.iter().find(|k| k.kind() == limit.kind) {
+                locked.push((
+                    limit,
+                    key.bucket_key(endpoint),
+                    limit.buckets.lock().unwrap(),
+                ));
+            }
+        }
+
+        let mut retry_after: Option<Duration> = None;
+        for (limit, bucket_key, buckets) in &mut locked {
+            let bucket = limit.bucket(buckets, bucket_key, now);
+            if bucket.tokens < 1.0 {
+                let wait = Duration::from_secs_f64((1.0 - bucket.tokens) / limit.rate);
+                retry_after = Some(retry_after.map_or(wait, |d| d.max(wait)));
+            }
+        }
+        if let Some(retry_after) = retry_after {
+            return Err(RateLimited { retry_after });
+        }
+
+        for (limit, bucket_key, buckets) in &mut locked {
+            limit.bucket(buckets, bucket_key, now).tokens -= 1.0;
+        }
+        Ok(())
+    }
+}
+
+/// Parses an address from the X-Forwarded-For header,
+/// which some proxies include the port in.
+fn parse_forwarded_addr(addr: &str) -> Option<IpAddr> {
+    addr.parse::<IpAddr>()
+        .ok()
+        .or_else(|| addr.parse::<SocketAddr>().ok().map(|a| a.ip()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limit(key: pb::rate_limit::Key, rate: f64, burst: u32, endpoints: &[&str]) -> pb::RateLimit {
+        pb::RateLimit {
+            limiter: Some(pb::RateLimiter {
+                kind: Some(pb::rate_limiter::Kind::TokenBucket(
+                    pb::rate_limiter::TokenBucket { rate, burst },
+                )),
+            }),
+            key: key as i32,
+            endpoints: endpoints.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let limiter = RateLimiter::from_config(
+            vec![limit(pb::rate_limit::Key::Endpoint, 2.0, 3, &[])],
+            vec![],
+        )
+        .unwrap();
+        let ep = EndpointName::new("svc", "foo");
+        let now = Instant::now();
+
+        // The burst is allowed immediately.
+        for _ in 0..3 {
+            assert_eq!(limiter.check_at(&ep, &[Key::Endpoint], now), Ok(()));
+        }
+        let err = limiter.check_at(&ep, &[Key::Endpoint], now).unwrap_err();
+        assert_eq!(err.retry_after, Duration::from_millis(500));
+        assert_eq!(err.retry_after_secs(), 1);
+
+        // Tokens are refilled at the configured rate.
+        let later = now + Duration::from_millis(500);
+        assert_eq!(limiter.check_at(&ep, &[Key::Endpoint], later), Ok(()));
+        assert!(limiter.check_at(&ep, &[Key::Endpoint], later).is_err());
+
+        // Other endpoints have their own buckets.
+        let other = EndpointName::new("svc", "bar");
+        assert_eq!(limiter.check_at(&other, &[Key::Endpoint], now), Ok(()));
+    }
+
+    #[test]
+    fn test_keys() {
+        let limiter = RateLimiter::from_config(
+            vec![
+                limit(pb::rate_limit::Key::ClientIp, 1.0, 1, &["svc.foo"]),
+                limit(pb::rate_limit::Key::AuthUid, 1.0, 1, &[]),
+            ],
+            vec![],
+        )
+        .unwrap();
+        let foo = EndpointName::new("svc", "foo");
+        let bar = EndpointName::new("svc", "bar");
+        let ip1: IpAddr = "10.0.0.1".parse().unwrap();
+        let ip2: IpAddr = "10.0.0.2".parse().unwrap();
+        let now = Instant::now();
+
+        assert!(limiter.has_limits(Key::ClientIp(ip1)));
+        assert!(!limiter.has_limits(Key::Endpoint));
+
+        assert_eq!(limiter.check_at(&foo, &[Key::ClientIp(ip1)], now), Ok(()));
+        assert!(limiter.check_at(&foo, &[Key::ClientIp(ip1)], now).is_err());
+        assert_eq!(limiter.check_at(&foo, &[Key::ClientIp(ip2)], now), Ok(()));
+        // The IP limit only applies to svc.foo.
+        assert_eq!(limiter.check_at(&bar, &[Key::ClientIp(ip1)], now), Ok(()));
+        assert_eq!(limiter.check_at(&bar, &[Key::ClientIp(ip1)], now), Ok(()));
+
+        assert_eq!(
+            limiter.check_at(&foo, &[Key::AuthUid("alice")], now),
+            Ok(())
+        );
+        assert!(limiter
+            .check_at(&bar, &[Key::AuthUid("alice")], now)
+            .is_err());
+        assert_eq!(limiter.check_at(&bar, &[Key::AuthUid("bob")], now), Ok(()));
+    }
+
+    #[test]
+    fn test_keys_for_request() {
+        let kinds = |caller: Option<Caller>, uid| {
+            Key::for_request(caller.as_ref(), uid)
+                .iter()
+                .map(Key::kind)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(kinds(None, None), vec![KeyKind::Endpoint]);
+        let gateway = Caller::Gateway {
+            gateway: "api-gateway".into(),
+        };
+        assert_eq!(
+            kinds(Some(gateway), Some("alice")),
+            vec![KeyKind::Endpoint, KeyKind::AuthUid]
+        );
+
+        // Service-to-service calls skip the endpoint limits.
+        let endpoint = Caller::APIEndpoint(EndpointName::new("svc", "foo"));
+        assert_eq!(kinds(Some(endpoint), None), vec![]);
+        let endpoint = Caller::APIEndpoint(EndpointName::new("svc", "foo"));
+        assert_eq!(kinds(Some(endpoint), Some("alice")), vec![KeyKind::AuthUid]);
+    }
+
+    #[test]
+    fn test_rejected_request_takes_no_tokens() {
+        let limiter = RateLimiter::from_config(
+            vec![
+                limit(pb::rate_limit::Key::Endpoint, 1.0, 3, &[]),
+                limit(pb::rate_limit::Key::Endpoint, 1.0, 1, &["svc.foo"]),
+            ],
+            vec![],
+        )
+        .unwrap();
+        let foo = EndpointName::new("svc", "foo");
+        let bar = EndpointName::new("svc", "bar");
+        let now = Instant::now();
+
+        assert_eq!(limiter.check_at(&foo, &[Key::Endpoint], now), Ok(()));
+        // Rejected by the stricter limit, so the shared limit keeps its tokens.
+        for _ in 0..5 {
+            assert!(limiter.check_at(&foo, &[Key::Endpoint], now).is_err());
+        }
+        let buckets = limiter.limits[0].buckets.lock().unwrap();
+        assert_eq!(buckets["svc.foo"].tokens, 2.0);
+        drop(buckets);
+
+        assert_eq!(limiter.check_at(&bar, &[Key::Endpoint], now), Ok(()));
+
+        // The same applies across keys.
+        let limiter = RateLimiter::from_config(
+            vec![
+                limit(pb::rate_limit::Key::Endpoint, 1.0, 1, &[]),
+                limit(pb::rate_limit::Key::AuthUid, 1.0, 1, &[]),
+            ],
+            vec![],
+        )
+        .unwrap();
+        let keys = [Key::Endpoint, Key::AuthUid("alice")];
+        assert_eq!(
+            limiter.check_at(&foo, &[Key::AuthUid("alice")], now),
+            Ok(())
+        );
+        assert!(limiter.check_at(&foo, &keys, now).is_err());
+        assert_eq!(limiter.check_at(&foo, &[Key::Endpoint], now), Ok(()));
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let limiter = RateLimiter::from_config(
+            vec![],
+            vec!["10.0.0.0/8".to_string(), "fd00::1".to_string()],
+        )
+        .unwrap();
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        let headers = |vals: &[&str]| {
+            let mut headers = http::HeaderMap::new();
+            for val in vals {
+                headers.append("x-forwarded-for", val.parse().unwrap());
+            }
+            headers
+        };
+
+        // The header is ignored for untrusted peers.
+        let spoofed = headers(&["1.1.1.1"]);
+        assert_eq!(limiter.client_ip(ip("2.2.2.2"), &spoofed), ip("2.2.2.2"));
+
+        // Trusted proxies are skipped, right to left.
+        let chain = headers(&["1.1.1.1, 3.3.3.3", "10.0.0.2"]);
+        assert_eq!(limiter.client_ip(ip("10.0.0.1"), &chain), ip("3.3.3.3"));
+        let chain = headers(&["[2001:db8::1]:1234, fd00::1"]);
+        assert_eq!(limiter.client_ip(ip("10.0.0.1"), &chain), ip("2001:db8::1"));
+        assert_eq!(
+            limiter.client_ip(ip("::ffff:10.0.0.1"), &chain),
+            ip("2001:db8::1")
+        );
+
+        // Without a usable header the peer is the client.
+        assert_eq!(
+            limiter.client_ip(ip("10.0.0.1"), &headers(&[])),
+            ip("10.0.0.1")
+        );
+        let garbage = headers(&["1.1.1.1, unknown"]);
+        assert_eq!(limiter.client_ip(ip("10.0.0.1"), &garbage), ip("10.0.0.1"));
+
+        for cfg in ["10.0.0.0/33", "10.0.0", "fd00::/129", "10.0.0.0/"] {
+            assert!(RateLimiter::from_config(vec![], vec![cfg.to_string()]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        for cfg in [
+            limit(pb::rate_limit::Key::Unspecified, 1.0, 1, &[]),
+            limit(pb::rate_limit::Key::Endpoint, 0.0, 1, &[]),
+            limit(pb::rate_limit::Key::Endpoint, 1.0, 0, &[]),
+            limit(pb::rate_limit::Key::Endpoint, 1.0, 1, &["foo"]),
+        ] {
+            assert!(RateLimiter::from_config(vec![cfg], vec![]).is_err());
+        }
+    }
+}
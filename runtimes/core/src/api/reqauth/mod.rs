@@ -72,6 +72,9 @@ pub struct CallMeta {
     /// Correlation id to use.
     pub ext_correlation_id: Option<String>,
 
+    /// The deadline by which the caller expects the request to complete, if any.
+    pub deadline: Option<tokio::time::Instant>,
+
     /// Information about an internal call, if any.
     /// If set it can be trusted as it has been authenticated.
     pub internal: Option<InternalCallMeta>,
@@ -124,6 +127,7 @@ impl CallMeta {
                 this_span_id: None,
                 parent_event_id: None,
                 ext_correlation_id: None,
+                deadline: None,
                 internal: None,
             };
 
@@ -185,12 +189,8 @@ impl CallMeta {
                 };
             }
 
-            // For now we only read the traceparent for internal-to-internal calls, this is because CloudRun
-            // is adding a traceparent header to all requests, which is causing our trace system to get confused
-            // and think that the initial request is a child of another already traced request
-            //
-            // In the future we should be able to remove this check and read the traceparent header for all requests
-            // to interopt with other tracing systems.
+            // Read the W3C traceparent header, which is set both by Encore and by external
+            // tracing systems, so that the request joins the caller's trace.
             if let Some(traceparent) = headers.get_meta(MetaKey::TraceParent) {
                 // Parse the traceparent.
                 if let Ok((trace_id, parent_span_id)) = parse_traceparent(traceparent) {
@@ -199,28 +199,38 @@ impl CallMeta {
                     meta.parent_span_id = Some(parent_span_id);
                 };
 
+                // Parse the trace state.
+                let state = parse_tracestate(headers.meta_values(MetaKey::TraceState));
+
                 // If the caller is a gateway, ignore the parent span id as gateways don't currently record a span.
                 // If we include it the root request won't be tagged as such.
+                // The exception is a parent span the gateway received from an external tracing system.
                 if let Some(internal) = &meta.internal {
-                    if matches!(internal.caller, Caller::Gateway { .. }) {
+                    if matches!(internal.caller, Caller::Gateway { .. }) && !state.ext_parent {
                         meta.parent_span_id = None;
                     }
                 }
 
-                // Parse the trace state.
-                if let (Some(event_id), parent_span) =
-                    parse_tracestate(headers.meta_values(MetaKey::TraceState))
-                {
+                if let Some(event_id) = state.event_id {
                     meta.parent_event_id = Some(event_id);
                     // If we where given a parent span ID, use that instead of the one from the traceparent header
                     // This is because GCP Cloud Run will add it's own spans in before the application code is run
                     // and thus we lose the parent span ID from the traceparent header
-                    if let Some(parent_span) = parent_span {
+                    if let Some(parent_span) = state.span_id {
                         meta.parent_span_id = Some(parent_span);
                     }
                 }
             }
 
+            // Deadlines are only propagated between Encore services, so external
+            // callers can't shorten the time a request is allowed to take.
+            // An invalid deadline is ignored rather than failing the request.
+            if meta.internal.is_some() {
+                meta.deadline = headers
+                    .get_meta(MetaKey::Deadline)
+                    .and_then(|deadline| api::deadline::parse_meta(deadline).ok());
+            }
+
             meta.ext_correlation_id = headers.get_meta(MetaKey::XCorrelationId).map(|s| {
                 // Limit the maximum length the correlation id can have.
                 s[..s.len().min(64)].to_string()
@@ -276,12 +286,21 @@ fn parse_traceparent(s: &str) -> anyhow::Result<(model::TraceId, model::SpanId)>
     Ok((trace_id, span_id))
 }
 
-fn parse_tracestate<'a>(
-    vals: impl Iterator<Item = &'a str>,
-) -> (Option<model::TraceEventId>, Option<model::SpanId>) {
+/// The Encore-specific entries of the W3C tracestate header.
+#[derive(Debug, Default, PartialEq)]
+struct TraceState {
+    event_id: Option<model::TraceEventId>,
+    span_id: Option<model::SpanId>,
+
+    /// Whether the parent span was propagated from an external tracing system.
+    ext_parent: bool,
+}
+
+fn parse_tracestate<'a>(vals: impl Iterator<Item = &'a str>) -> TraceState {
     enum Data {
         EventId(model::TraceEventId),
         SpanId(model::SpanId),
+        ExtParent,
     }
 
     let parse_entry = |val: &str| -> Option<Data> {
@@ -290,22 +309,22 @@ fn parse_tracestate<'a>(
         match key {
             "encore/event-id" => Some(Data::EventId(val.parse().ok()?)),
             "encore/span-id" => Some(Data::SpanId(model::SpanId::parse_std(val).ok()?)),
+            "encore/ext-parent" if val == "1" => Some(Data::ExtParent),
             _ => None,
         }
     };
 
-    let mut event_id = None;
-    let mut span_id = None;
-
+    let mut state = TraceState::default();
     for val in vals {
         for field in val.split(',') {
             match parse_entry(field) {
-                Some(Data::EventId(id)) => event_id = Some(id),
-                Some(Data::SpanId(id)) => span_id = Some(id),
+                Some(Data::EventId(id)) => state.event_id = Some(id),
+                Some(Data::SpanId(id)) => state.span_id = Some(id),
+                Some(Data::ExtParent) => state.ext_parent = true,
                 None => (),
             }
         }
     }
 
-    (event_id, span_id)
+    state
 }
@@ -69,6 +69,36 @@ impl RequestValidator {
         Err(ValidationError::UnknownMacKey)
     }
 
+    /// Validates that a request to the given path was signed by the Encore Platform,
+    /// based on its headers. It returns `Ok(None)` if the request isn't signed.
+    pub fn validate_request_headers(
+        &self,
+        path: &str,
+        headers: &axum::http::HeaderMap,
+    ) -> Result<Option<SealOfApproval>, ValidationError> {
+        let Some(x_encore_auth_header) = headers.get("x-encore-auth") else {
+            return Ok(None);
+        };
+        let x_encore_auth_header = x_encore_auth_header
+            .to_str()
+            .map_err(|_| ValidationError::InvalidMac)?;
+
+        let Some(date_header) = headers.get("Date") else {
+            return Err(ValidationError::InvalidDateHeader);
+        };
+        let date_header = date_header
+            .to_str()
+            .map_err(|_| ValidationError::InvalidDateHeader)?;
+
+        let request_path = percent_decode_str(path).decode_utf8_lossy();
+        let req = ValidationData {
+            request_path: &request_path,
+            date_header,
+            x_encore_auth_header,
+        };
+        self.validate_platform_request(&req).map(Some)
+    }
+
     pub fn sign_outgoing_request(&self, req: &mut reqwest::Request) -> anyhow::Result<()> {
         let path = percent_decode_str(req.url().path())
             .decode_utf8_lossy()
@@ -207,7 +207,7 @@ impl EncoreAuth {
                     // by things like load balancers.
                 }
 
-                XCorrelationId | Version | UserId | UserData | Caller | Callee => {
+                XCorrelationId | Version | UserId | UserData | Caller | Callee | Deadline => {
                     // Read all values for this key, and sort them.
                     let mut values = req.meta_values(key).collect::<Vec<_>>();
                     values.sort();
@@ -0,0 +1,169 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+use crate::api;
+use crate::encore::runtime::v1 as pb;
+
+/// Determines when and how failed API calls are retried.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts, including the first one.
+    pub max_attempts: u32,
+
+    /// The backoff before the first retry.
+    /// It doubles for each subsequent retry, up to `max_backoff`.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+/// Why an attempt at making an API call failed.
+pub enum Failure {
+    /// The connection to the service could not be established,
+    /// so the request was never sent.
+    Connect,
+
+    /// The request failed after it may have been sent,
+    /// for example due to the connection being reset.
+    Transport,
+
+    /// The service responded with an error.
+    Response(api::ErrCode),
+}
+
+impl Failure {
+    /// Reports whether the failure indicates the service is unhealthy,
+    /// as opposed to the call itself being invalid.
+    pub fn is_unhealthy(&self) -> bool {
+        match self {
+            Failure::Connect | Failure::Transport => true,
+            Failure::Response(code) => *code == api::ErrCode::Unavailable,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries.
+    pub fn never() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// Returns the retry policy to use given its configuration.
+    /// Calls are only retried if a policy is configured.
+    pub fn from_config(cfg: Option<pb::service_discovery::RetryPolicy>) -> Self {
+        let default = Self::default();
+        let Some(cfg) = cfg else {
+            return Self::never();
+        };
+        Self {
+            max_attempts: cfg.max_attempts.unwrap_or(default.max_attempts).max(1),
+            initial_backoff: cfg
+                .initial_backoff
+                .and_then(|d| Duration::try_from(d).ok())
+                .unwrap_or(default.initial_backoff),
+            max_backoff: cfg
+                .max_backoff
+                .and_then(|d| Duration::try_from(d).ok())
+                .unwrap_or(default.max_backoff),
+        }
+    }
+
+    /// Reports whether a call that failed on the given attempt (starting at 1) should be retried.
+    ///
+    /// Calls to idempotent methods are retried for any failure caused by the service
+    /// being unhealthy. Other calls are only retried when the request is known not to
+    /// have been processed: when the connection failed or the service responded `Unavailable`.
+    pub fn should_retry(&self, method: &reqwest::Method, attempt: u32, failure: &Failure) -> bool {
+        if attempt >= self.max_attempts {
+            return false;
+        }
+        match failure {
+            Failure::Connect => true,
+            Failure::Response(code) => *code == api::ErrCode::Unavailable,
+            Failure::Transport => method.is_idempotent(),
+        }
+    }
+
+    /// Computes the backoff before retrying a call that failed on the given attempt,
+    /// using exponential backoff with full jitter.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let max = self.max_backoff_for(attempt);
+        if max.is_zero() {
+            return max;
+        }
+        rand::thread_rng().gen_range(Duration::ZERO..=max)
+    }
+
+    fn max_backoff_for(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Method;
+
+    use super::*;
+
+    #[test]
+    fn test_should_retry() {
+        let policy = RetryPolicy::default();
+        let unavailable = Failure::Response(api::ErrCode::Unavailable);
+        let internal = Failure::Response(api::ErrCode::Internal);
+
+        assert!(policy.should_retry(&Method::POST, 1, &Failure::Connect));
+        assert!(policy.should_retry(&Method::POST, 1, &unavailable));
+        assert!(!policy.should_retry(&Method::POST, 1, &Failure::Transport));
+        assert!(policy.should_retry(&Method::GET, 1, &Failure::Transport));
+        assert!(!policy.should_retry(&Method::GET, 1, &internal));
+
+        // The attempts are bounded.
+        assert!(policy.should_retry(&Method::GET, 2, &Failure::Connect));
+        assert!(!policy.should_retry(&Method::GET, 3, &Failure::Connect));
+    }
+
+    #[test]
+    fn test_from_config() {
+        // Retries are opt-in.
+        let policy = RetryPolicy::from_config(None);
+        assert!(!policy.should_retry(&Method::GET, 1, &Failure::Connect));
+
+        let policy = RetryPolicy::from_config(Some(Default::default()));
+        assert_eq!(policy.max_attempts, 3);
+        assert!(policy.should_retry(&Method::GET, 1, &Failure::Connect));
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+        };
+        assert_eq!(policy.max_backoff_for(1), Duration::from_millis(100));
+        assert_eq!(policy.max_backoff_for(2), Duration::from_millis(200));
+        assert_eq!(policy.max_backoff_for(3), Duration::from_millis(400));
+        assert_eq!(policy.max_backoff_for(4), Duration::from_millis(500));
+        assert_eq!(policy.max_backoff_for(40), Duration::from_millis(500));
+
+        for attempt in 1..10 {
+            assert!(policy.backoff(attempt) <= policy.max_backoff_for(attempt));
+        }
+    }
+}
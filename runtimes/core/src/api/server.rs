@@ -7,13 +7,14 @@ use std::sync::{Arc, Mutex, RwLock};
 
 use crate::api::endpoint::{EndpointHandler, SharedEndpointData};
 use crate::api::paths::Pather;
+use crate::api::ratelimit::RateLimiter;
 use crate::api::reqauth::svcauth;
 use crate::api::static_assets::StaticAssetsHandler;
 use crate::api::{self, ToResponse};
 use crate::api::{paths, reqauth, schema, BoxedHandler, EndpointMap};
 use crate::encore::parser::meta::v1 as meta;
 use crate::names::EndpointName;
-use crate::trace;
+use crate::{shutdown, trace};
 
 use super::jsonschema::JSONSchema;
 
@@ -47,6 +48,8 @@ impl Server {
         inbound_svc_auth: Vec<Arc<dyn svcauth::ServiceAuthMethod>>,
         tracer: trace::Tracer,
         auth_data_schemas: HashMap<String, Option<JSONSchema>>,
+        rate_limiter: Arc<RateLimiter>,
+        shutdown: shutdown::Tracker,
     ) -> anyhow::Result<Self> {
         // Register the routes, and track the handlers in a map so we can easily
         // set the request handler when registered.
@@ -78,6 +81,8 @@ impl Server {
             platform_auth,
             inbound_svc_auth,
             auth_data_schemas,
+            rate_limiter,
+            shutdown,
         });
 
         let mut register = |paths: &[(Arc<api::Endpoint>, Vec<String>)],
@@ -20,17 +20,22 @@ pub struct StaticAssetsHandler {
 
 impl StaticAssetsHandler {
     pub fn new(cfg: &meta::rpc::StaticAssets) -> Self {
-        let service = ServeDir::new(PathBuf::from(&cfg.dir_rel_path));
+        // Serve precompressed siblings of files (like `app.js.br` for `app.js`)
+        // when they exist and the client accepts the encoding.
+        let service = ServeDir::new(PathBuf::from(&cfg.dir_rel_path))
+            .precompressed_br()
+            .precompressed_gzip();
 
         let not_found_status = cfg
             .not_found_status
             .and_then(|c| StatusCode::from_u16(c as u16).ok())
             .unwrap_or(StatusCode::NOT_FOUND);
 
-        let not_found = cfg
-            .not_found_rel_path
-            .as_ref()
-            .map(|p| ServeFile::new(PathBuf::from(p)));
+        let not_found = cfg.not_found_rel_path.as_ref().map(|p| {
+            ServeFile::new(PathBuf::from(p))
+                .precompressed_br()
+                .precompressed_gzip()
+        });
         let not_found_handler = not_found.is_some();
         let service: Arc<dyn FileServer> = match not_found {
             Some(not_found) => Arc::new(service.not_found_service(not_found)),
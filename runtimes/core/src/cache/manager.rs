@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use anyhow::Context;
+
+use crate::cache::{noop, redis, Cluster, ClusterImpl};
+use crate::encore::runtime::v1 as pb;
+use crate::names::EncoreName;
+use crate::secrets;
+use crate::trace::Tracer;
+
+pub struct Manager {
+    tracer: Tracer,
+    cluster_cfg: HashMap<EncoreName, Arc<dyn ClusterImpl>>,
+
+    clusters: Arc<RwLock<HashMap<EncoreName, Arc<dyn ClusterImpl>>>>,
+}
+
+pub struct ManagerConfig<'a> {
+    pub clusters: Vec<pb::RedisCluster>,
+    pub creds: &'a pb::infrastructure::Credentials,
+    pub secrets: &'a secrets::Manager,
+    pub tracer: Tracer,
+}
+
+impl ManagerConfig<'_> {
+    pub fn build(self) -> anyhow::Result<Manager> {
+        let cluster_cfg = clusters_from_cfg(self.clusters, self.creds, self.secrets)
+            .context("failed to parse Redis clusters")?;
+
+        Ok(Manager {
+            tracer: self.tracer,
+            cluster_cfg,
+            clusters: Arc::default(),
+        })
+    }
+}
+
+impl Manager {
+    /// Returns the cache cluster with the given name.
+    /// If the cluster is not configured, a cluster is returned
+    /// that fails all operations.
+    pub fn cluster(&self, name: EncoreName) -> Cluster {
+        Cluster {
+            imp: self.cluster_impl(name),
+            tracer: self.tracer.clone(),
+        }
+    }
+
+    fn cluster_impl(&self, name: EncoreName) -> Arc<dyn ClusterImpl> {
+        if let Some(cluster) = self.clusters.read().unwrap().get(&name) {
+            return cluster.clone();
+        }
+
+        let cluster = match self.cluster_cfg.get(&name) {
+            Some(cluster) => cluster.clone(),
+            None => Arc::new(noop::Cluster::new(name.clone())),
+        };
+
+        self.clusters.write().unwrap().insert(name, cluster.clone());
+        cluster
+    }
+}
+
+fn clusters_from_cfg(
+    clusters: Vec<pb::RedisCluster>,
+    creds: &pb::infrastructure::Credentials,
+    secrets: &secrets::Manager,
+) -> anyhow::Result<HashMap<EncoreName, Arc<dyn ClusterImpl>>> {
+    let mut map: HashMap<EncoreName, Arc<dyn ClusterImpl>> = HashMap::new();
+    for c in clusters {
+        // Get the primary server.
+        let server = c
+            .servers
+            .iter()
+            .find(|s| s.kind() == pb::ServerKind::Primary);
+        let Some(server) = server else {
+            log::warn!(
+                "no primary server found for redis cluster {}, skipping",
+                c.rid
+            );
+            continue;
+        };
+
+        for db in &c.databases {
+            // Get the read-write pool for this db.
+            let pool = db.conn_pools.iter().find(|p| !p.is_readonly);
+            let Some(pool) = pool else {
+                log::warn!(
+                    "no read-write pool found for cache cluster {}, skipping",
+                    db.encore_name
+                );
+                continue;
+            };
+
+            // Get the role to authenticate with.
+            let role = creds
+                .redis_roles
+                .iter()
+                .find(|r| r.rid == pool.role_rid)
+                .with_context(|| {
+                    format!(
+                        "no role found with rid {} for cache cluster {}",
+                        pool.role_rid, db.encore_name
+                    )
+                })?;
+
+            let cluster = redis::Cluster::new(server, db, role, creds, secrets)
+                .with_context(|| format!("invalid config for cache cluster {}", db.encore_name))?;
+            map.insert(db.encore_name.clone().into(), Arc::new(cluster));
+        }
+    }
+
+    Ok(map)
+}
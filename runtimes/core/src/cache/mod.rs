@@ -0,0 +1,586 @@
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+pub use manager::{Manager, ManagerConfig};
+
+use crate::trace::{protocol, Tracer};
+use crate::{model, EncoreName};
+
+mod manager;
+mod noop;
+mod redis;
+
+trait ClusterImpl: Debug + Send + Sync {
+    fn name(&self) -> &EncoreName;
+
+    /// Executes a single command against the cluster.
+    /// Implementations are responsible for adding any configured key prefix.
+    fn query(
+        self: Arc<Self>,
+        cmd: Command,
+    ) -> Pin<Box<dyn Future<Output = Result<::redis::Value, Error>> + Send + 'static>>;
+}
+
+/// A command to execute against a cache cluster.
+#[derive(Debug)]
+struct Command {
+    name: &'static str,
+
+    /// The keys the command operates on. Written immediately after the command name.
+    keys: Vec<String>,
+
+    /// Additional arguments, written after the keys.
+    args: Vec<Arg>,
+}
+
+#[derive(Debug)]
+enum Arg {
+    Str(&'static str),
+    Bytes(Vec<u8>),
+    Int(i64),
+    Float(f64),
+}
+
+impl Command {
+    fn new(name: &'static str, keys: Vec<String>) -> Self {
+        Self {
+            name,
+            keys,
+            args: Vec::new(),
+        }
+    }
+
+    fn arg(mut self, arg: Arg) -> Self {
+        self.args.push(arg);
+        self
+    }
+
+    fn args<I: IntoIterator<Item = Arg>>(mut self, args: I) -> Self {
+        self.args.extend(args);
+        self
+    }
+
+    /// Adds the arguments to set the given expiry as part of a SET command.
+    fn set_expiry(self, expiry: Expiry) -> Self {
+        match expiry {
+            Expiry::Never => self,
+            Expiry::KeepTtl => self.arg(Arg::Str("KEEPTTL")),
+            Expiry::In(dur) => self
+                .arg(Arg::Str("PX"))
+                .arg(Arg::Int(dur.as_millis().max(1) as i64)),
+            Expiry::At(time) => self.arg(Arg::Str("PXAT")).arg(Arg::Int(unix_millis(time))),
+        }
+    }
+}
+
+/// A cache cluster, as defined by `CacheCluster` in the application.
+#[derive(Debug, Clone)]
+pub struct Cluster {
+    tracer: Tracer,
+    imp: Arc<dyn ClusterImpl>,
+}
+
+/// When a cache entry should expire.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum Expiry {
+    /// The entry never expires.
+    #[default]
+    Never,
+    /// Keep the existing expiry of the entry, if any.
+    KeepTtl,
+    /// The entry expires after the given duration.
+    In(Duration),
+    /// The entry expires at the given time.
+    At(SystemTime),
+}
+
+/// Controls the behavior of set operations when the key already exists, or doesn't.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum SetMode {
+    /// Always set the value.
+    #[default]
+    Always,
+    /// Only set the value if the key does not already exist.
+    IfNotExists,
+    /// Only set the value if the key already exists.
+    IfExists,
+}
+
+#[derive(Debug, Default)]
+pub struct SetOptions {
+    pub mode: SetMode,
+    pub expiry: Expiry,
+}
+
+/// The end of a list to operate on.
+#[derive(Debug, Clone, Copy)]
+pub enum ListEnd {
+    Left,
+    Right,
+}
+
+impl Cluster {
+    pub fn name(&self) -> &EncoreName {
+        self.imp.name()
+    }
+
+    /// Gets the value stored at key.
+    /// Returns [Error::Miss] if the key does not exist.
+    pub async fn get(
+        &self,
+        key: String,
+        source: Option<&model::Request>,
+    ) -> Result<Vec<u8>, Error> {
+        let cmd = Command::new("GET", vec![key]);
+        self.traced_with("get", false, cmd, source, non_nil).await
+    }
+
+    /// Gets the values stored at multiple keys.
+    /// Missing keys are reported as `None`.
+    pub async fn get_multi(
+        &self,
+        keys: Vec<String>,
+        source: Option<&model::Request>,
+    ) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        let cmd = Command::new("MGET", keys);
+        let val = self.traced("get multi", false, cmd, source).await?;
+        Ok(::redis::from_owned_redis_value(val)?)
+    }
+
+    /// Sets the value stored at key.
+    ///
+    /// Depending on the set mode, returns [Error::KeyExists] if the key
+    /// already exists or [Error::Miss] if the key does not exist.
+    pub async fn set(
+        &self,
+        key: String,
+        value: Vec<u8>,
+        opts: SetOptions,
+        source: Option<&model::Request>,
+    ) -> Result<(), Error> {
+        let op = match opts.mode {
+            SetMode::Always => "set",
+            SetMode::IfNotExists => "set if not exists",
+            SetMode::IfExists => "replace",
+        };
+
+        let mut cmd = Command::new("SET", vec![key]).arg(Arg::Bytes(value));
+        match opts.mode {
+            SetMode::Always => {}
+            SetMode::IfNotExists => cmd = cmd.arg(Arg::Str("NX")),
+            SetMode::IfExists => cmd = cmd.arg(Arg::Str("XX")),
+        }
+        let cmd = cmd.set_expiry(opts.expiry);
+
+        // With NX/XX a nil reply means the condition did not hold.
+        let mode = opts.mode;
+        self.traced_with(op, true, cmd, source, move |val| match (val, mode) {
+            (::redis::Value::Nil, SetMode::IfNotExists) => Err(Error::KeyExists),
+            (::redis::Value::Nil, SetMode::IfExists) => Err(Error::Miss),
+            _ => Ok(()),
+        })
+        .await
+    }
+
+    /// Sets the value stored at key, returning the previous value (if any).
+    pub async fn get_and_set(
+        &self,
+        key: String,
+        value: Vec<u8>,
+        expiry: Expiry,
+        source: Option<&model::Request>,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let cmd = Command::new("SET", vec![key])
+            .arg(Arg::Bytes(value))
+            .arg(Arg::Str("GET"))
+            .set_expiry(expiry);
+        let val = self.traced("get and set", true, cmd, source).await?;
+        Ok(::redis::from_owned_redis_value(val)?)
+    }
+
+    /// Deletes the value stored at key, returning it.
+    /// Returns [Error::Miss] if the key does not exist.
+    pub async fn get_and_delete(
+        &self,
+        key: String,
+        source: Option<&model::Request>,
+    ) -> Result<Vec<u8>, Error> {
+        let cmd = Command::new("GETDEL", vec![key]);
+        self.traced_with("get and delete", true, cmd, source, non_nil)
+            .await
+    }
+
+    /// Deletes the given keys, returning the number of keys that were deleted.
+    pub async fn delete(
+        &self,
+        keys: Vec<String>,
+        source: Option<&model::Request>,
+    ) -> Result<u64, Error> {
+        let cmd = Command::new("DEL", keys);
+        let val = self.traced("delete", true, cmd, source).await?;
+        Ok(::redis::from_owned_redis_value(val)?)
+    }
+
+    /// Increments the integer stored at key by delta, returning the new value.
+    /// If the key does not exist it is treated as zero.
+    pub async fn incr_by(
+        &self,
+        key: String,
+        delta: i64,
+        source: Option<&model::Request>,
+    ) -> Result<i64, Error> {
+        let cmd = Command::new("INCRBY", vec![key]).arg(Arg::Int(delta));
+        let val = self.traced("increment", true, cmd, source).await?;
+        Ok(::redis::from_owned_redis_value(val)?)
+    }
+
+    /// Increments the float stored at key by delta, returning the new value.
+    /// If the key does not exist it is treated as zero.
+    pub async fn incr_by_float(
+        &self,
+        key: String,
+        delta: f64,
+        source: Option<&model::Request>,
+    ) -> Result<f64, Error> {
+        let cmd = Command::new("INCRBYFLOAT", vec![key]).arg(Arg::Float(delta));
+        let val = self.traced("increment", true, cmd, source).await?;
+        Ok(::redis::from_owned_redis_value(val)?)
+    }
+
+    /// Updates the expiry of the given key.
+    /// Returns [Error::Miss] if the key does not exist.
+    pub async fn expire(
+        &self,
+        key: String,
+        expiry: Expiry,
+        source: Option<&model::Request>,
+    ) -> Result<(), Error> {
+        let cmd = match expiry {
+            Expiry::Never => Command::new("PERSIST", vec![key]),
+            Expiry::In(dur) => {
+                Command::new("PEXPIRE", vec![key]).arg(Arg::Int(dur.as_millis().max(1) as i64))
+            }
+            Expiry::At(time) => {
+                Command::new("PEXPIREAT", vec![key]).arg(Arg::Int(unix_millis(time)))
+            }
+            // Keeping the existing TTL is a no-op beyond checking the key exists.
+            Expiry::KeepTtl => Command::new("EXISTS", vec![key]),
+        };
+
+        // PERSIST returns 0 both when the key is missing and when it has no expiry,
+        // so don't report a miss in that case.
+        let report_miss = !matches!(expiry, Expiry::Never);
+        self.traced_with("expire", true, cmd, source, move |val| {
+            let updated: i64 = ::redis::from_owned_redis_value(val)?;
+            if updated == 0 && report_miss {
+                Err(Error::Miss)
+            } else {
+                Ok(())
+            }
+        })
+        .await
+    }
+
+    /// Pushes the given values onto the list stored at key,
+    /// returning the new length of the list.
+    pub async fn push(
+        &self,
+        key: String,
+        end: ListEnd,
+        values: Vec<Vec<u8>>,
+        source: Option<&model::Request>,
+    ) -> Result<u64, Error> {
+        let (name, op) = match end {
+            ListEnd::Left => ("LPUSH", "push left"),
+            ListEnd::Right => ("RPUSH", "push right"),
+        };
+        let cmd = Command::new(name, vec![key]).args(values.into_iter().map(Arg::Bytes));
+        let val = self.traced(op, true, cmd, source).await?;
+        Ok(::redis::from_owned_redis_value(val)?)
+    }
+
+    /// Pops a value off the list stored at key.
+    /// Returns [Error::Miss] if the list is empty or does not exist.
+    pub async fn pop(
+        &self,
+        key: String,
+        end: ListEnd,
+        source: Option<&model::Request>,
+    ) -> Result<Vec<u8>, Error> {
+        let (name, op) = match end {
+            ListEnd::Left => ("LPOP", "pop left"),
+            ListEnd::Right => ("RPOP", "pop right"),
+        };
+        let cmd = Command::new(name, vec![key]);
+        self.traced_with(op, true, cmd, source, non_nil).await
+    }
+
+    /// Returns the elements of the list stored at key between start and stop (inclusive).
+    /// Negative indices are offsets from the end of the list.
+    pub async fn list_range(
+        &self,
+        key: String,
+        start: i64,
+        stop: i64,
+        source: Option<&model::Request>,
+    ) -> Result<Vec<Vec<u8>>, Error> {
+        let cmd = Command::new("LRANGE", vec![key])
+            .arg(Arg::Int(start))
+            .arg(Arg::Int(stop));
+        let val = self.traced("list range", false, cmd, source).await?;
+        Ok(::redis::from_owned_redis_value(val)?)
+    }
+
+    /// Returns the length of the list stored at key.
+    pub async fn list_len(
+        &self,
+        key: String,
+        source: Option<&model::Request>,
+    ) -> Result<u64, Error> {
+        let cmd = Command::new("LLEN", vec![key]);
+        let val = self.traced("list len", false, cmd, source).await?;
+        Ok(::redis::from_owned_redis_value(val)?)
+    }
+
+    /// Returns the element at the given index of the list stored at key.
+    /// Returns [Error::Miss] if the index is out of range.
+    pub async fn list_get(
+        &self,
+        key: String,
+        index: i64,
+        source: Option<&model::Request>,
+    ) -> Result<Vec<u8>, Error> {
+        let cmd = Command::new("LINDEX", vec![key]).arg(Arg::Int(index));
+        self.traced_with("list get", false, cmd, source, non_nil)
+            .await
+    }
+
+    /// Sets the element at the given index of the list stored at key.
+    pub async fn list_set(
+        &self,
+        key: String,
+        index: i64,
+        value: Vec<u8>,
+        source: Option<&model::Request>,
+    ) -> Result<(), Error> {
+        let cmd = Command::new("LSET", vec![key])
+            .arg(Arg::Int(index))
+            .arg(Arg::Bytes(value));
+        self.traced("list set", true, cmd, source).await?;
+        Ok(())
+    }
+
+    /// Trims the list stored at key to the elements between start and stop (inclusive).
+    pub async fn list_trim(
+        &self,
+        key: String,
+        start: i64,
+        stop: i64,
+        source: Option<&model::Request>,
+    ) -> Result<(), Error> {
+        let cmd = Command::new("LTRIM", vec![key])
+            .arg(Arg::Int(start))
+            .arg(Arg::Int(stop));
+        self.traced("list trim", true, cmd, source).await?;
+        Ok(())
+    }
+
+    /// Removes elements equal to value from the list stored at key,
+    /// returning the number of removed elements.
+    ///
+    /// A positive count removes up to count elements starting from the left,
+    /// a negative count removes up to -count elements starting from the right,
+    /// and zero removes all matching elements.
+    pub async fn list_remove(
+        &self,
+        key: String,
+        count: i64,
+        value: Vec<u8>,
+        source: Option<&model::Request>,
+    ) -> Result<u64, Error> {
+        let cmd = Command::new("LREM", vec![key])
+            .arg(Arg::Int(count))
+            .arg(Arg::Bytes(value));
+        let val = self.traced("list remove", true, cmd, source).await?;
+        Ok(::redis::from_owned_redis_value(val)?)
+    }
+
+    async fn traced(
+        &self,
+        op: &'static str,
+        is_write: bool,
+        cmd: Command,
+        source: Option<&model::Request>,
+    ) -> Result<::redis::Value, Error> {
+        self.traced_with(op, is_write, cmd, source, Ok).await
+    }
+
+    /// Executes the command, tracing it if there is a source request.
+    /// The map function converts the raw response into the operation result,
+    /// so that misses and conflicts are reflected in the trace.
+    async fn traced_with<T, F>(
+        &self,
+        op: &'static str,
+        is_write: bool,
+        cmd: Command,
+        source: Option<&model::Request>,
+        map: F,
+    ) -> Result<T, Error>
+    where
+        F: FnOnce(::redis::Value) -> Result<T, Error>,
+    {
+        let Some(source) = source else {
+            return self.imp.clone().query(cmd).await.and_then(map);
+        };
+
+        let start_id = self.tracer.cache_call_start(protocol::CacheCallStartData {
+            source,
+            operation: op,
+            is_write,
+            keys: cmd.keys.iter().map(String::as_str),
+        });
+
+        let res = self.imp.clone().query(cmd).await.and_then(map);
+
+        self.tracer.cache_call_end(protocol::CacheCallEndData {
+            start_id,
+            source,
+            result: match &res {
+                Ok(_) => protocol::CacheCallResult::Ok,
+                Err(Error::Miss) => protocol::CacheCallResult::NoSuchKey,
+                Err(Error::KeyExists) => protocol::CacheCallResult::Conflict,
+                Err(err) => protocol::CacheCallResult::Err(err),
+            },
+        });
+
+        res
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("cache miss")]
+    Miss,
+
+    #[error("key already exists")]
+    KeyExists,
+
+    #[error("cache cluster not configured")]
+    Unconfigured,
+
+    #[error("redis error: {0}")]
+    Redis(#[from] ::redis::RedisError),
+}
+
+/// Decodes a bulk string reply, treating nil as a cache miss.
+fn non_nil(val: ::redis::Value) -> Result<Vec<u8>, Error> {
+    match ::redis::from_owned_redis_value::<Option<Vec<u8>>>(val)? {
+        Some(val) => Ok(val),
+        None => Err(Error::Miss),
+    }
+}
+
+fn unix_millis(time: SystemTime) -> i64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A cluster that replies to every command with the same value.
+    #[derive(Debug)]
+    struct StaticCluster {
+        name: EncoreName,
+        reply: ::redis::Value,
+    }
+
+    impl ClusterImpl for StaticCluster {
+        fn name(&self) -> &EncoreName {
+            &self.name
+        }
+
+        fn query(
+            self: Arc<Self>,
+            _cmd: Command,
+        ) -> Pin<Box<dyn Future<Output = Result<::redis::Value, Error>> + Send + 'static>> {
+            let reply = self.reply.clone();
+            Box::pin(async move { Ok(reply) })
+        }
+    }
+
+    fn cluster(reply: ::redis::Value) -> Cluster {
+        Cluster {
+            tracer: Tracer::noop(),
+            imp: Arc::new(StaticCluster {
+                name: "test".into(),
+                reply,
+            }),
+        }
+    }
+
+    fn arg_strs(cmd: &Command) -> Vec<String> {
+        cmd.args
+            .iter()
+            .map(|arg| match arg {
+                Arg::Str(s) => s.to_string(),
+                Arg::Bytes(b) => String::from_utf8_lossy(b).into_owned(),
+                Arg::Int(i) => i.to_string(),
+                Arg::Float(f) => f.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_set_expiry() {
+        let cmd = |expiry| Command::new("SET", vec!["k".into()]).set_expiry(expiry);
+
+        assert!(arg_strs(&cmd(Expiry::Never)).is_empty());
+        assert_eq!(arg_strs(&cmd(Expiry::KeepTtl)), vec!["KEEPTTL"]);
+        assert_eq!(
+            arg_strs(&cmd(Expiry::In(Duration::from_secs(2)))),
+            vec!["PX", "2000"]
+        );
+        // Sub-millisecond expiries are rounded up rather than expiring immediately.
+        assert_eq!(
+            arg_strs(&cmd(Expiry::In(Duration::from_micros(10)))),
+            vec!["PX", "1"]
+        );
+        let at = SystemTime::UNIX_EPOCH + Duration::from_secs(5);
+        assert_eq!(arg_strs(&cmd(Expiry::At(at))), vec!["PXAT", "5000"]);
+    }
+
+    #[tokio::test]
+    async fn test_set_conditional_nil_reply() {
+        let set = |mode| {
+            let opts = SetOptions {
+                mode,
+                expiry: Expiry::Never,
+            };
+            async move {
+                cluster(::redis::Value::Nil)
+                    .set("k".into(), b"v".to_vec(), opts, None)
+                    .await
+            }
+        };
+
+        assert!(matches!(
+            set(SetMode::IfNotExists).await,
+            Err(Error::KeyExists)
+        ));
+        assert!(matches!(set(SetMode::IfExists).await, Err(Error::Miss)));
+        assert!(set(SetMode::Always).await.is_ok());
+
+        let ok = cluster(::redis::Value::Okay);
+        let opts = SetOptions {
+            mode: SetMode::IfNotExists,
+            expiry: Expiry::Never,
+        };
+        assert!(ok.set("k".into(), b"v".to_vec(), opts, None).await.is_ok());
+    }
+}
@@ -0,0 +1,34 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use futures::future;
+
+use crate::cache::{self, Command};
+use crate::names::EncoreName;
+
+/// A cache cluster that is not configured for this runtime.
+/// All operations fail with [cache::Error::Unconfigured].
+#[derive(Debug)]
+pub struct Cluster {
+    name: EncoreName,
+}
+
+impl Cluster {
+    pub fn new(name: EncoreName) -> Self {
+        Self { name }
+    }
+}
+
+impl cache::ClusterImpl for Cluster {
+    fn name(&self) -> &EncoreName {
+        &self.name
+    }
+
+    fn query(
+        self: Arc<Self>,
+        _cmd: Command,
+    ) -> Pin<Box<dyn Future<Output = Result<redis::Value, cache::Error>> + Send + 'static>> {
+        Box::pin(future::ready(Err(cache::Error::Unconfigured)))
+    }
+}
@@ -0,0 +1,240 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use anyhow::Context;
+use redis::aio::ConnectionManager;
+use tokio::sync::OnceCell;
+
+use crate::cache::{self, Arg, Command};
+use crate::encore::runtime::v1 as pb;
+use crate::names::EncoreName;
+use crate::secrets;
+
+/// A Redis database backing a cache cluster.
+///
+/// Commands are multiplexed over a single connection which is
+/// established lazily on first use and re-established on failure.
+pub struct Cluster {
+    name: EncoreName,
+    key_prefix: Option<String>,
+    client: redis::Client,
+    conn: OnceCell<ConnectionManager>,
+}
+
+impl std::fmt::Debug for Cluster {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Cluster")
+            .field("name", &self.name)
+            .field("key_prefix", &self.key_prefix)
+            .finish()
+    }
+}
+
+impl Cluster {
+    pub fn new(
+        server: &pb::RedisServer,
+        db: &pb::RedisDatabase,
+        role: &pb::RedisRole,
+        creds: &pb::infrastructure::Credentials,
+        secrets: &secrets::Manager,
+    ) -> anyhow::Result<Self> {
+        let (username, password) = match &role.auth {
+            None => (None, None),
+            Some(pb::redis_role::Auth::Acl(acl)) => {
+                let password = match &acl.password {
+                    Some(password) => Some(resolve_secret(secrets, password)?),
+                    None => None,
+                };
+                (Some(acl.username.clone()), password)
+            }
+            Some(pb::redis_role::Auth::AuthString(auth)) => {
+                (None, Some(resolve_secret(secrets, auth)?))
+            }
+        };
+
+        let use_tls = server.tls_config.is_some();
+        let insecure = server
+            .tls_config
+            .as_ref()
+            .is_some_and(|c| c.disable_ca_validation || c.disable_tls_hostname_verification);
+
+        let addr = if server.host.starts_with('/') {
+            redis::ConnectionAddr::Unix(server.host.clone().into())
+        } else {
+            let (host, port) = match server.host.split_once(':') {
+                Some((host, port)) => (
+                    host.to_string(),
+                    port.parse::<u16>().context("invalid port")?,
+                ),
+                None => (server.host.clone(), 6379),
+            };
+            if use_tls {
+                redis::ConnectionAddr::TcpTls {
+                    host,
+                    port,
+                    insecure,
+                    tls_params: None,
+                }
+            } else {
+                redis::ConnectionAddr::Tcp(host, port)
+            }
+        };
+
+        let info = redis::ConnectionInfo {
+            addr,
+            redis: redis::RedisConnectionInfo {
+                db: db.database_idx as i64,
+                username,
+                password,
+            },
+        };
+
+        let root_cert = server
+            .tls_config
+            .as_ref()
+            .and_then(|c| c.server_ca_cert.as_ref())
+            .map(|cert| cert.as_bytes().to_vec());
+
+        let client_tls = match &role.client_cert_rid {
+            Some(rid) => {
+                let client_cert = creds
+                    .client_certs
+                    .iter()
+                    .find(|c| c.rid == *rid)
+                    .with_context(|| {
+                        format!(
+                            "no client certificate found with rid {} for cache cluster {}",
+                            rid, db.encore_name
+                        )
+                    })?;
+                let key = client_cert
+                    .key
+                    .as_ref()
+                    .context("client certificate has no key")?;
+                let key = secrets.load(key.clone());
+                let key = key.get().context("failed to resolve client key")?;
+                Some(redis::ClientTlsConfig {
+                    client_cert: client_cert.cert.as_bytes().to_vec(),
+                    client_key: key.to_vec(),
+                })
+            }
+            None => None,
+        };
+
+        let client = if use_tls && (root_cert.is_some() || client_tls.is_some()) {
+            redis::Client::build_with_tls(
+                info,
+                redis::TlsCertificates {
+                    client_tls,
+                    root_cert,
+                },
+            )
+        } else {
+            redis::Client::open(info)
+        }
+        .context("invalid redis configuration")?;
+
+        Ok(Self {
+            name: db.encore_name.clone().into(),
+            key_prefix: db.key_prefix.clone().filter(|p| !p.is_empty()),
+            client,
+            conn: OnceCell::new(),
+        })
+    }
+
+    async fn conn(&self) -> Result<ConnectionManager, cache::Error> {
+        let conn = self
+            .conn
+            .get_or_try_init(|| self.client.get_connection_manager())
+            .await?;
+        Ok(conn.clone())
+    }
+
+    fn to_cmd(&self, cmd: Command) -> redis::Cmd {
+        let mut c = redis::cmd(cmd.name);
+        for key in cmd.keys {
+            match &self.key_prefix {
+                Some(prefix) => c.arg(format!("{}{}", prefix, key)),
+                None => c.arg(key),
+            };
+        }
+        for arg in cmd.args {
+            match arg {
+                Arg::Str(s) => c.arg(s),
+                Arg::Bytes(b) => c.arg(b),
+                Arg::Int(i) => c.arg(i),
+                Arg::Float(f) => c.arg(f),
+            };
+        }
+        c
+    }
+}
+
+impl cache::ClusterImpl for Cluster {
+    fn name(&self) -> &EncoreName {
+        &self.name
+    }
+
+    fn query(
+        self: Arc<Self>,
+        cmd: Command,
+    ) -> Pin<Box<dyn Future<Output = Result<redis::Value, cache::Error>> + Send + 'static>> {
+        Box::pin(async move {
+            let cmd = self.to_cmd(cmd);
+            let mut conn = self.conn().await?;
+            let val = cmd.query_async(&mut conn).await?;
+            Ok(val)
+        })
+    }
+}
+
+fn resolve_secret(secrets: &secrets::Manager, data: &pb::SecretData) -> anyhow::Result<String> {
+    let secret = secrets.load(data.clone());
+    let bytes = secret
+        .get()
+        .context("failed to resolve redis credentials")?;
+    String::from_utf8(bytes.to_vec()).context("redis credentials are not valid utf-8")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cluster(key_prefix: Option<&str>) -> Cluster {
+        Cluster {
+            name: "test".into(),
+            key_prefix: key_prefix.map(String::from),
+            client: redis::Client::open("redis://localhost").unwrap(),
+            conn: OnceCell::new(),
+        }
+    }
+
+    #[test]
+    fn test_to_cmd_key_prefix() {
+        let cmd = || {
+            Command::new("MSET", vec!["a".into(), "b".into()])
+                .arg(Arg::Str("a"))
+                .arg(Arg::Int(1))
+        };
+
+        // Only the keys are prefixed, not the arguments.
+        let prefixed = cluster(Some("svc:")).to_cmd(cmd());
+        let expected = redis::cmd("MSET")
+            .arg("svc:a")
+            .arg("svc:b")
+            .arg("a")
+            .arg(1)
+            .get_packed_command();
+        assert_eq!(prefixed.get_packed_command(), expected);
+
+        let unprefixed = cluster(None).to_cmd(cmd());
+        let expected = redis::cmd("MSET")
+            .arg("a")
+            .arg("b")
+            .arg("a")
+            .arg(1)
+            .get_packed_command();
+        assert_eq!(unprefixed.get_packed_command(), expected);
+    }
+}
@@ -0,0 +1,161 @@
+//! Readiness checks of the resources the app depends on,
+//! such as databases, Pub/Sub topics and buckets.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// How long a single check may take before it's considered failed.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long check results are reused for, to avoid probing
+/// the resources on every readiness request.
+const CACHE_TTL: Duration = Duration::from_secs(5);
+
+pub type CheckFuture = Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send + 'static>>;
+
+/// A check of whether a resource is reachable.
+pub struct Check {
+    name: String,
+    probe: Box<dyn Fn() -> CheckFuture + Send + Sync>,
+}
+
+impl Check {
+    pub fn new<F>(name: String, probe: F) -> Self
+    where
+        F: Fn() -> CheckFuture + Send + Sync + 'static,
+    {
+        Self {
+            name,
+            probe: Box::new(probe),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CheckResult {
+    pub name: String,
+    pub error: Option<String>,
+}
+
+impl CheckResult {
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Runs the readiness checks.
+///
+/// The checks run concurrently, each with a timeout, and the results are cached
+/// for a short while. Concurrent readiness requests share a single run of the checks.
+#[derive(Clone)]
+pub struct Readiness {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    checks: Vec<Check>,
+    timeout: Duration,
+    cache_ttl: Duration,
+    cached: tokio::sync::Mutex<Option<(Instant, Arc<Vec<CheckResult>>)>>,
+}
+
+impl Readiness {
+    pub fn new(checks: Vec<Check>) -> Self {
+        Self::with_timings(checks, CHECK_TIMEOUT, CACHE_TTL)
+    }
+
+    fn with_timings(checks: Vec<Check>, timeout: Duration, cache_ttl: Duration) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                checks,
+                timeout,
+                cache_ttl,
+                cached: tokio::sync::Mutex::new(None),
+            }),
+        }
+    }
+
+    /// Runs the checks, or returns the cached results if they're recent enough.
+    pub async fn check(&self) -> Arc<Vec<CheckResult>> {
+        let mut cached = self.inner.cached.lock().await;
+        if let Some((checked_at, results)) = cached.as_ref() {
+            if checked_at.elapsed() < self.inner.cache_ttl {
+                return results.clone();
+            }
+        }
+
+        let timeout = self.inner.timeout;
+        let results = futures::future::join_all(self.inner.checks.iter().map(|check| {
+            let probe = (check.probe)();
+            async move {
+                let error = match tokio::time::timeout(timeout, probe).await {
+                    Ok(Ok(())) => None,
+                    Ok(Err(err)) => Some(format!("{:#}", err)),
+                    Err(_) => Some(format!("timed out after {:?}", timeout)),
+                };
+                if let Some(error) = &error {
+                    log::warn!("readiness check {} failed: {}", check.name, error);
+                }
+                CheckResult {
+                    name: check.name.clone(),
+                    error,
+                }
+            }
+        }))
+        .await;
+
+        let results = Arc::new(results);
+        *cached = Some((Instant::now(), results.clone()));
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_readiness() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = calls.clone();
+        let checks = vec![
+            Check::new("ok".into(), move || {
+                counted.fetch_add(1, Ordering::SeqCst);
+                Box::pin(async { Ok(()) })
+            }),
+            Check::new("failing".into(), || {
+                Box::pin(async { Err(anyhow::anyhow!("connection refused")) })
+            }),
+            Check::new("slow".into(), || {
+                Box::pin(async {
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                    Ok(())
+                })
+            }),
+        ];
+        let readiness = Readiness::with_timings(
+            checks,
+            Duration::from_millis(50),
+            Duration::from_millis(200),
+        );
+
+        let results = readiness.check().await;
+        assert!(results[0].passed());
+        assert_eq!(results[1].error.as_deref(), Some("connection refused"));
+        assert_eq!(results[2].error.as_deref(), Some("timed out after 50ms"));
+
+        // The results are cached for a while.
+        readiness.check().await;
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        readiness.check().await;
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+}
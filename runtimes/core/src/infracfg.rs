@@ -1,11 +1,11 @@
 use crate::encore::runtime::v1::infrastructure::{Credentials, Resources};
 use crate::encore::runtime::v1::{
-    self as pbruntime, environment, gateway, metrics_provider, pub_sub_cluster,
+    self as pbruntime, environment, gateway, logs_provider, metrics_provider, pub_sub_cluster,
     pub_sub_subscription, pub_sub_topic, redis_role, secret_data, service_auth, service_discovery,
-    AppSecret, Deployment, Environment, Infrastructure, MetricsProvider, Observability,
-    PubSubCluster, PubSubSubscription, PubSubTopic, RedisCluster, RedisConnectionPool,
-    RedisDatabase, RedisRole, RedisServer, RuntimeConfig, SqlCluster, SqlConnectionPool,
-    SqlDatabase, SqlRole, SqlServer, TlsConfig,
+    tracing_provider, AppSecret, Deployment, Environment, Infrastructure, LogsProvider,
+    MetricsProvider, Observability, PubSubCluster, PubSubSubscription, PubSubTopic, RedisCluster,
+    RedisConnectionPool, RedisDatabase, RedisRole, RedisServer, RuntimeConfig, SqlCluster,
+    SqlConnectionPool, SqlDatabase, SqlRole, SqlServer, TlsConfig, TracingProvider,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -17,6 +17,10 @@ pub struct InfraConfig {
     pub auth: Option<Vec<Auth>>,
     pub service_discovery: Option<HashMap<String, ServiceDiscovery>>,
     pub metrics: Option<Metrics>,
+    pub tracing: Option<Tracing>,
+    pub logs: Option<Vec<Logs>>,
+    pub log_levels: Option<LogLevels>,
+    pub admin: Option<Admin>,
     pub sql_servers: Option<Vec<SQLServer>>,
     pub redis: Option<HashMap<String, Redis>>,
     pub pubsub: Option<Vec<PubSub>>,
@@ -27,6 +31,35 @@ pub struct InfraConfig {
     pub object_storage: Option<Vec<ObjectStorage>>,
     pub worker_threads: Option<i32>,
     pub log_config: Option<String>,
+    pub sql_migrations: Option<SQLMigrations>,
+    pub rate_limits: Option<Vec<RateLimit>>,
+    pub trusted_proxies: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RateLimit {
+    pub key: RateLimitKey,
+    pub rate: f64,
+    pub burst: u32,
+    #[serde(default)]
+    pub endpoints: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum RateLimitKey {
+    #[serde(rename = "endpoint")]
+    Endpoint,
+    #[serde(rename = "client_ip")]
+    ClientIp,
+    #[serde(rename = "auth_uid")]
+    AuthUid,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SQLMigrations {
+    #[serde(default)]
+    pub apply_on_startup: bool,
+    pub app_root: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -105,6 +138,46 @@ pub struct ServiceDiscovery {
     pub base_url: String,
 
     pub auth: Option<Vec<Auth>>,
+    pub retry_policy: Option<RetryPolicy>,
+    pub circuit_breaker: Option<CircuitBreaker>,
+
+    #[serde(default)]
+    pub additional_base_urls: Vec<String>,
+    pub load_balancing: Option<LoadBalancing>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoadBalancing {
+    #[serde(default)]
+    pub strategy: LoadBalancingStrategy,
+    #[serde(default)]
+    pub disable_health_checks: bool,
+    pub health_check_interval_secs: Option<u64>,
+    pub ejection_duration_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub enum LoadBalancingStrategy {
+    #[default]
+    #[serde(rename = "round_robin")]
+    RoundRobin,
+    #[serde(rename = "least_connections")]
+    LeastConnections,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    pub max_attempts: Option<u32>,
+    pub initial_backoff_ms: Option<u64>,
+    pub max_backoff_ms: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CircuitBreaker {
+    #[serde(default)]
+    pub disabled: bool,
+    pub failure_threshold: Option<u32>,
+    pub open_duration_secs: Option<u64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -148,6 +221,73 @@ pub struct AWSCloudWatchMetrics {
     pub namespace: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Tracing {
+    #[serde(rename = "otlp")]
+    OTLP(OTLPTracing),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OTLPTracing {
+    pub endpoint: String,
+    pub headers: Option<HashMap<String, String>>,
+    pub sampling_rate: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Logs {
+    #[serde(flatten)]
+    pub sink: LogSink,
+    pub buffer_size: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum LogSink {
+    #[serde(rename = "stderr")]
+    Stderr,
+    #[serde(rename = "file")]
+    File(FileLogs),
+    #[serde(rename = "otlp")]
+    OTLP(OTLPLogs),
+    #[serde(rename = "syslog")]
+    Syslog(SyslogLogs),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileLogs {
+    pub path: String,
+    pub max_size_bytes: Option<u64>,
+    pub max_files: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OTLPLogs {
+    pub endpoint: String,
+    pub headers: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SyslogLogs {
+    pub address: Option<String>,
+    pub facility: Option<u32>,
+    pub app_name: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LogLevels {
+    pub default: Option<String>,
+    pub services: Option<HashMap<String, String>>,
+    pub endpoints: Option<HashMap<String, String>>,
+    pub loggers: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Admin {
+    pub tokens: Vec<EnvString>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum Secrets {
@@ -173,6 +313,10 @@ pub struct SQLServer {
     pub host: String,
     pub tls_config: Option<TLSConfig>,
     pub databases: HashMap<String, SQLDatabase>,
+    /// Hosts of read replicas of the server.
+    /// They use the same TLS configuration and credentials as the primary.
+    #[serde(default)]
+    pub read_replicas: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -195,6 +339,8 @@ pub struct SQLDatabase {
     pub username: String,
     pub password: EnvString,
     pub client_cert: Option<ClientCert>,
+    #[serde(default)]
+    pub route_reads_to_replicas: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -232,6 +378,27 @@ pub struct ClientCert {
 
 // PubSub-related structures
 
+/// Buffers published messages and publishes them in batches.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PublishBuffer {
+    pub max_latency_ms: Option<u64>,
+    pub max_batch_size: Option<u32>,
+}
+
+/// Limits on the messages a subscription processes at once.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FlowControl {
+    pub max_in_flight_bytes: Option<u64>,
+    pub adaptive: Option<AdaptiveConcurrency>,
+}
+
+/// Lowers a subscription's concurrency when its handlers slow down or are overloaded.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdaptiveConcurrency {
+    pub min_concurrency: Option<u32>,
+    pub target_latency_ms: Option<u64>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum PubSub {
@@ -241,6 +408,10 @@ pub enum PubSub {
     AWSSnsSqs(AWSSnsSqs),
     #[serde(rename = "nsq")]
     NSQ(NSQPubsub),
+    #[serde(rename = "azure_service_bus")]
+    AzureServiceBus(AzureServiceBus),
+    #[serde(rename = "memory")]
+    Memory(MemoryPubsub),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -255,6 +426,8 @@ pub struct GCPTopic {
     pub project_id: Option<String>,
     #[serde(skip_serializing_if = "HashMap::is_empty", default)]
     pub subscriptions: HashMap<String, GCPSub>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub publish_buffer: Option<PublishBuffer>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -264,6 +437,9 @@ pub struct GCPSub {
     pub project_id: Option<String>,
 
     pub push_config: Option<PushConfig>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flow_control: Option<FlowControl>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -283,29 +459,114 @@ pub struct AWSTopic {
     pub arn: String,
     #[serde(skip_serializing_if = "HashMap::is_empty", default)]
     pub subscriptions: HashMap<String, AWSSub>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub publish_buffer: Option<PublishBuffer>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AWSSub {
     pub arn: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flow_control: Option<FlowControl>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct NSQPubsub {
-    pub hosts: String,
+    #[serde(default)]
+    pub hosts: NSQHosts,
+    /// HTTP addresses of nsqlookupd instances to discover
+    /// the nsqd hosts to consume messages from.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub lookupd_hosts: Vec<String>,
     pub topics: HashMap<String, NSQTopic>,
 }
 
+/// One or more nsqd hosts, either as a list or a comma-separated string.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum NSQHosts {
+    String(String),
+    List(Vec<String>),
+}
+
+impl Default for NSQHosts {
+    fn default() -> Self {
+        NSQHosts::List(vec![])
+    }
+}
+
+impl NSQHosts {
+    pub fn to_vec(&self) -> Vec<String> {
+        match self {
+            NSQHosts::String(s) => s
+                .split(',')
+                .map(str::trim)
+                .filter(|h| !h.is_empty())
+                .map(String::from)
+                .collect(),
+            NSQHosts::List(hosts) => hosts.clone(),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct NSQTopic {
     pub name: String,
     #[serde(skip_serializing_if = "HashMap::is_empty", default)]
     pub subscriptions: HashMap<String, NSQSub>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub publish_buffer: Option<PublishBuffer>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct NSQSub {
     pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flow_control: Option<FlowControl>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AzureServiceBus {
+    pub namespace: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connection_string: Option<EnvString>,
+    pub topics: HashMap<String, AzureTopic>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AzureTopic {
+    pub name: String,
+    /// The message attribute to order messages by, using sessions.
+    /// The topic's subscriptions must have sessions enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ordering_attr: Option<String>,
+    #[serde(skip_serializing_if = "HashMap::is_empty", default)]
+    pub subscriptions: HashMap<String, AzureSub>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub publish_buffer: Option<PublishBuffer>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AzureSub {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flow_control: Option<FlowControl>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MemoryPubsub {
+    pub topics: HashMap<String, MemoryTopic>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MemoryTopic {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ordering_attr: Option<String>,
+    /// The names of the topic's subscriptions.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub subscriptions: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub publish_buffer: Option<PublishBuffer>,
 }
 
 pub fn map_infra_to_runtime(infra: InfraConfig) -> RuntimeConfig {
@@ -428,6 +689,37 @@ pub fn map_infra_to_runtime(infra: InfraConfig) -> RuntimeConfig {
                     service_discovery::Location {
                         base_url: sd.base_url,
                         auth_methods: svc_auth_methods,
+                        retry_policy: sd.retry_policy.map(|rp| service_discovery::RetryPolicy {
+                            max_attempts: rp.max_attempts,
+                            initial_backoff: rp.initial_backoff_ms.map(millis_to_duration),
+                            max_backoff: rp.max_backoff_ms.map(millis_to_duration),
+                        }),
+                        circuit_breaker: sd.circuit_breaker.map(|cb| {
+                            service_discovery::CircuitBreaker {
+                                disabled: cb.disabled,
+                                failure_threshold: cb.failure_threshold,
+                                open_duration: cb.open_duration_secs.map(secs_to_duration),
+                            }
+                        }),
+                        additional_base_urls: sd.additional_base_urls,
+                        load_balancing: sd.load_balancing.map(|lb| {
+                            let strategy = match lb.strategy {
+                                LoadBalancingStrategy::RoundRobin => {
+                                    service_discovery::load_balancing::Strategy::RoundRobin
+                                }
+                                LoadBalancingStrategy::LeastConnections => {
+                                    service_discovery::load_balancing::Strategy::LeastConnections
+                                }
+                            };
+                            service_discovery::LoadBalancing {
+                                strategy: strategy as i32,
+                                disable_health_checks: lb.disable_health_checks,
+                                health_check_interval: lb
+                                    .health_check_interval_secs
+                                    .map(secs_to_duration),
+                                ejection_duration: lb.ejection_duration_secs.map(secs_to_duration),
+                            }
+                        }),
                     },
                 )
             })
@@ -538,11 +830,73 @@ pub fn map_infra_to_runtime(infra: InfraConfig) -> RuntimeConfig {
         }]
     });
 
+    // Map Tracing
+    let tracing = infra.tracing.map(|tracing| {
+        let provider = match tracing {
+            Tracing::OTLP(otlp) => {
+                tracing_provider::Provider::Otlp(tracing_provider::OtlpTracingProvider {
+                    endpoint: otlp.endpoint,
+                    headers: otlp.headers.unwrap_or_default(),
+                    sampling_rate: otlp.sampling_rate,
+                })
+            }
+        };
+
+        vec![TracingProvider {
+            rid: get_next_rid(),
+            provider: Some(provider),
+        }]
+    });
+
+    // Map Logs
+    let logs = infra.logs.map(|logs| {
+        logs.into_iter()
+            .map(|logs| {
+                let provider = match logs.sink {
+                    LogSink::Stderr => {
+                        logs_provider::Provider::Stderr(logs_provider::StderrLogsProvider {})
+                    }
+                    LogSink::File(file) => {
+                        logs_provider::Provider::File(logs_provider::FileLogsProvider {
+                            path: file.path,
+                            max_size_bytes: file.max_size_bytes,
+                            max_files: file.max_files,
+                        })
+                    }
+                    LogSink::OTLP(otlp) => {
+                        logs_provider::Provider::Otlp(logs_provider::OtlpLogsProvider {
+                            endpoint: otlp.endpoint,
+                            headers: otlp.headers.unwrap_or_default(),
+                        })
+                    }
+                    LogSink::Syslog(syslog) => {
+                        logs_provider::Provider::Syslog(logs_provider::SyslogLogsProvider {
+                            address: syslog.address,
+                            facility: syslog.facility,
+                            app_name: syslog.app_name,
+                        })
+                    }
+                };
+                LogsProvider {
+                    rid: get_next_rid(),
+                    buffer_size: logs.buffer_size,
+                    provider: Some(provider),
+                }
+            })
+            .collect::<Vec<_>>()
+    });
+
     // Map Observability
     let observability = Some(Observability {
         metrics: metrics.unwrap_or_default(),
-        tracing: Vec::new(),
-        logs: Vec::new(),
+        tracing: tracing.unwrap_or_default(),
+        logs: logs.unwrap_or_default(),
+        log_levels: infra.log_levels.map(|levels| pbruntime::LogLevels {
+            default_level: levels.default,
+            services: levels.services.unwrap_or_default(),
+            endpoints: levels.endpoints.unwrap_or_default(),
+            loggers: levels.loggers.unwrap_or_default(),
+        }),
     });
 
     let cors = infra.cors.map(|cors| gateway::Cors {
@@ -600,6 +954,39 @@ pub fn map_infra_to_runtime(infra: InfraConfig) -> RuntimeConfig {
         observability,
         service_discovery,
         graceful_shutdown,
+        rate_limits: infra
+            .rate_limits
+            .unwrap_or_default()
+            .into_iter()
+            .map(|limit| pbruntime::RateLimit {
+                limiter: Some(pbruntime::RateLimiter {
+                    kind: Some(pbruntime::rate_limiter::Kind::TokenBucket(
+                        pbruntime::rate_limiter::TokenBucket {
+                            rate: limit.rate,
+                            burst: limit.burst,
+                        },
+                    )),
+                }),
+                key: match limit.key {
+                    RateLimitKey::Endpoint => pbruntime::rate_limit::Key::Endpoint,
+                    RateLimitKey::ClientIp => pbruntime::rate_limit::Key::ClientIp,
+                    RateLimitKey::AuthUid => pbruntime::rate_limit::Key::AuthUid,
+                } as i32,
+                endpoints: limit.endpoints,
+            })
+            .collect(),
+        trusted_proxies: infra.trusted_proxies.unwrap_or_default(),
+        sql_migrations: infra.sql_migrations.map(|m| pbruntime::SqlMigrations {
+            apply_on_startup: m.apply_on_startup,
+            app_root: m.app_root,
+        }),
+        admin_api: infra.admin.map(|admin| pbruntime::AdminApi {
+            tokens: admin
+                .tokens
+                .iter()
+                .map(map_env_string_to_secret_data)
+                .collect(),
+        }),
     });
 
     let mut credentials = Credentials {
@@ -663,29 +1050,40 @@ pub fn map_infra_to_runtime(infra: InfraConfig) -> RuntimeConfig {
                                 min_connections: db.min_connections.unwrap_or(0),
                                 max_connections: db.max_connections.unwrap_or(100),
                             }],
+                            route_reads_to_replicas: db.route_reads_to_replicas,
                         }
                     })
                     .collect();
 
+                let tls_config = server.tls_config.map_or_else(
+                    || Some(TlsConfig::default()),
+                    |tls| match tls.disabled {
+                        true => None,
+                        false => Some(TlsConfig {
+                            server_ca_cert: tls.ca,
+                            disable_tls_hostname_verification: tls
+                                .disable_tls_hostname_verification,
+                            disable_ca_validation: tls.disable_ca_validation,
+                        }),
+                    },
+                );
+
+                let mut servers = vec![SqlServer {
+                    rid: get_next_rid(),
+                    host: server.host,
+                    kind: pbruntime::ServerKind::Primary as i32,
+                    tls_config: tls_config.clone(),
+                }];
+                servers.extend(server.read_replicas.into_iter().map(|host| SqlServer {
+                    rid: get_next_rid(),
+                    host,
+                    kind: pbruntime::ServerKind::ReadReplica as i32,
+                    tls_config: tls_config.clone(),
+                }));
+
                 SqlCluster {
                     rid: get_next_rid(),
-                    servers: vec![SqlServer {
-                        rid: get_next_rid(),
-                        host: server.host,
-                        kind: pbruntime::ServerKind::Primary as i32,
-                        tls_config: server.tls_config.map_or_else(
-                            || Some(TlsConfig::default()),
-                            |tls| match tls.disabled {
-                                true => None,
-                                false => Some(TlsConfig {
-                                    server_ca_cert: tls.ca,
-                                    disable_tls_hostname_verification: tls
-                                        .disable_tls_hostname_verification,
-                                    disable_ca_validation: tls.disable_ca_validation,
-                                }),
-                            },
-                        ),
-                    }],
+                    servers,
                     databases,
                 }
             })
@@ -789,6 +1187,10 @@ pub fn map_infra_to_runtime(infra: InfraConfig) -> RuntimeConfig {
                                 delivery_guarantee: pub_sub_topic::DeliveryGuarantee::AtLeastOnce
                                     as i32,
                                 ordering_attr: None,
+                                publish_buffer: topic
+                                    .publish_buffer
+                                    .as_ref()
+                                    .map(map_publish_buffer),
                                 provider_config: Some(pub_sub_topic::ProviderConfig::GcpConfig(
                                     pub_sub_topic::GcpConfig {
                                         project_id: topic
@@ -811,6 +1213,10 @@ pub fn map_infra_to_runtime(infra: InfraConfig) -> RuntimeConfig {
                                         topic_cloud_name: topic.name.clone(),
                                         subscription_cloud_name: sub.name.clone(),
                                         push_only: sub.push_config.is_some(),
+                                        flow_control: sub
+                                            .flow_control
+                                            .as_ref()
+                                            .map(map_flow_control),
                                         provider_config: Some(
                                             pub_sub_subscription::ProviderConfig::GcpConfig(
                                                 pub_sub_subscription::GcpConfig {
@@ -849,6 +1255,10 @@ pub fn map_infra_to_runtime(infra: InfraConfig) -> RuntimeConfig {
                                 delivery_guarantee: pub_sub_topic::DeliveryGuarantee::AtLeastOnce
                                     as i32, // AWS typically provides at-least-once delivery
                                 ordering_attr: None, // Add ordering if necessary
+                                publish_buffer: topic
+                                    .publish_buffer
+                                    .as_ref()
+                                    .map(map_publish_buffer),
                                 provider_config: None, // AWS doesn't need additional provider config here
                             })
                             .collect();
@@ -865,6 +1275,10 @@ pub fn map_infra_to_runtime(infra: InfraConfig) -> RuntimeConfig {
                                         topic_cloud_name: topic.arn.clone(),
                                         subscription_cloud_name: sub.arn.clone(),
                                         push_only: false, // AWS SQS doesn't typically use push config
+                                        flow_control: sub
+                                            .flow_control
+                                            .as_ref()
+                                            .map(map_flow_control),
                                         provider_config: None, // AWS doesn't need additional provider config
                                     }
                                 })
@@ -887,6 +1301,10 @@ pub fn map_infra_to_runtime(infra: InfraConfig) -> RuntimeConfig {
                                 delivery_guarantee: pub_sub_topic::DeliveryGuarantee::AtLeastOnce
                                     as i32, // NSQ typically guarantees at-least-once delivery
                                 ordering_attr: None, // NSQ doesn't handle message ordering natively
+                                publish_buffer: topic
+                                    .publish_buffer
+                                    .as_ref()
+                                    .map(map_publish_buffer),
                                 provider_config: None, // No additional provider config for NSQ
                             })
                             .collect();
@@ -903,6 +1321,10 @@ pub fn map_infra_to_runtime(infra: InfraConfig) -> RuntimeConfig {
                                         topic_cloud_name: topic.name.clone(), // Using topic name for simplicity
                                         subscription_cloud_name: sub.name.clone(),
                                         push_only: false, // NSQ is pull-based, no push config
+                                        flow_control: sub
+                                            .flow_control
+                                            .as_ref()
+                                            .map(map_flow_control),
                                         provider_config: None, // No additional provider config for NSQ
                                     }
                                 })
@@ -910,9 +1332,106 @@ pub fn map_infra_to_runtime(infra: InfraConfig) -> RuntimeConfig {
                             .collect();
 
                         let provider = pub_sub_cluster::Provider::Nsq(pub_sub_cluster::Nsq {
-                            hosts: vec![nsq.hosts.clone()], // Mapping NSQ hosts
+                            hosts: nsq.hosts.to_vec(),
+                            lookupd_hosts: nsq.lookupd_hosts.clone(),
                         });
 
+                        (Some(provider), topics, subscriptions)
+                    }
+                    PubSub::AzureServiceBus(azure) => {
+                        let topics = azure
+                            .topics
+                            .iter()
+                            .map(|(name, topic)| PubSubTopic {
+                                rid: String::new(),
+                                encore_name: name.clone(),
+                                cloud_name: topic.name.clone(),
+                                delivery_guarantee: pub_sub_topic::DeliveryGuarantee::AtLeastOnce
+                                    as i32,
+                                ordering_attr: topic.ordering_attr.clone(),
+                                publish_buffer: topic
+                                    .publish_buffer
+                                    .as_ref()
+                                    .map(map_publish_buffer),
+                                provider_config: None,
+                            })
+                            .collect();
+
+                        let subscriptions = azure
+                            .topics
+                            .iter()
+                            .flat_map(|(topic_name, topic)| {
+                                topic.subscriptions.iter().map(|(sub_name, sub)| {
+                                    PubSubSubscription {
+                                        rid: String::new(),
+                                        topic_encore_name: topic_name.clone(),
+                                        subscription_encore_name: sub_name.clone(),
+                                        topic_cloud_name: topic.name.clone(),
+                                        subscription_cloud_name: sub.name.clone(),
+                                        push_only: false,
+                                        flow_control: sub
+                                            .flow_control
+                                            .as_ref()
+                                            .map(map_flow_control),
+                                        provider_config: None,
+                                    }
+                                })
+                            })
+                            .collect();
+
+                        let provider =
+                            pub_sub_cluster::Provider::Azure(pub_sub_cluster::AzureServiceBus {
+                                namespace: azure.namespace.clone(),
+                                connection_string: azure
+                                    .connection_string
+                                    .as_ref()
+                                    .map(map_env_string_to_secret_data),
+                            });
+
+                        (Some(provider), topics, subscriptions)
+                    }
+                    PubSub::Memory(memory) => {
+                        let topics = memory
+                            .topics
+                            .iter()
+                            .map(|(name, topic)| PubSubTopic {
+                                rid: String::new(),
+                                encore_name: name.clone(),
+                                cloud_name: name.clone(),
+                                delivery_guarantee: pub_sub_topic::DeliveryGuarantee::AtLeastOnce
+                                    as i32,
+                                ordering_attr: topic.ordering_attr.clone(),
+                                publish_buffer: topic
+                                    .publish_buffer
+                                    .as_ref()
+                                    .map(map_publish_buffer),
+                                provider_config: None,
+                            })
+                            .collect();
+
+                        let subscriptions = memory
+                            .topics
+                            .iter()
+                            .flat_map(|(topic_name, topic)| {
+                                topic
+                                    .subscriptions
+                                    .iter()
+                                    .map(|sub_name| PubSubSubscription {
+                                        rid: String::new(),
+                                        topic_encore_name: topic_name.clone(),
+                                        subscription_encore_name: sub_name.clone(),
+                                        topic_cloud_name: topic_name.clone(),
+                                        subscription_cloud_name: sub_name.clone(),
+                                        push_only: false,
+                                        flow_control: None,
+                                        provider_config: None,
+                                    })
+                            })
+                            .collect();
+
+                        let provider =
+                            pub_sub_cluster::Provider::Memory(pub_sub_cluster::Memory {});
+
                         (Some(provider), topics, subscriptions)
                     }
                 };
@@ -996,6 +1515,40 @@ pub fn map_infra_to_runtime(infra: InfraConfig) -> RuntimeConfig {
     }
 }
 
+fn secs_to_duration(secs: u64) -> prost_types::Duration {
+    prost_types::Duration {
+        seconds: secs as i64,
+        nanos: 0,
+    }
+}
+
+fn map_publish_buffer(buf: &PublishBuffer) -> pub_sub_topic::PublishBuffer {
+    pub_sub_topic::PublishBuffer {
+        max_latency: buf.max_latency_ms.map(millis_to_duration),
+        max_batch_size: buf.max_batch_size,
+    }
+}
+
+fn map_flow_control(fc: &FlowControl) -> pub_sub_subscription::FlowControl {
+    pub_sub_subscription::FlowControl {
+        max_in_flight_bytes: fc.max_in_flight_bytes,
+        adaptive: fc
+            .adaptive
+            .as_ref()
+            .map(|a| pub_sub_subscription::AdaptiveConcurrency {
+                min_concurrency: a.min_concurrency,
+                target_latency: a.target_latency_ms.map(millis_to_duration),
+            }),
+    }
+}
+
+fn millis_to_duration(millis: u64) -> prost_types::Duration {
+    prost_types::Duration {
+        seconds: (millis / 1000) as i64,
+        nanos: ((millis % 1000) * 1_000_000) as i32,
+    }
+}
+
 // Helper function to map EnvString to SecretData
 fn map_env_string_to_secret_data(env_string: &EnvString) -> pbruntime::SecretData {
     match env_string {
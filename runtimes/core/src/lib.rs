@@ -20,16 +20,20 @@ use crate::encore::runtime::v1 as runtimepb;
 
 pub mod api;
 mod base32;
+pub mod cache;
 pub mod error;
+pub mod health;
 pub mod infracfg;
 pub mod log;
 pub mod meta;
+pub mod metrics;
 pub mod model;
 mod names;
 pub mod objects;
 pub mod proccfg;
 pub mod pubsub;
 pub mod secrets;
+pub mod shutdown;
 pub mod sqldb;
 mod trace;
 
@@ -197,6 +201,55 @@ impl RuntimeBuilder {
         }
         Runtime::new(cfg, md, self.test_mode)
     }
+
+    /// Applies the pending database migrations without starting the runtime,
+    /// as done by the supervisor's migrate subcommand.
+    pub fn migrate(self) -> anyhow::Result<()> {
+        if let Some(err) = self.err {
+            return Err(err);
+        }
+        let mut cfg = self.cfg.context("runtime config not provided")?;
+        let md = self.md.context("metadata not provided")?;
+        if let Some(proc_config) = self.proc_cfg {
+            proc_config.apply(&mut cfg)?;
+        }
+
+        openssl_probe::init_ssl_cert_env_vars();
+        let tokio_rt = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .context("failed to build tokio runtime")?;
+
+        let mut infra = cfg.infra.take().unwrap_or_default();
+        let resources = infra.resources.take().unwrap_or_default();
+        let creds = infra.credentials.take().unwrap_or_default();
+        let app_root = cfg
+            .deployment
+            .and_then(|d| d.sql_migrations)
+            .and_then(|m| m.app_root)
+            .unwrap_or_else(|| ".".to_string());
+
+        let secrets = secrets::Manager::new(resources.app_secrets);
+        let sqldb = sqldb::ManagerConfig {
+            clusters: resources.sql_clusters,
+            creds: &creds,
+            secrets: &secrets,
+            tracer: trace::Tracer::noop(),
+            runtime: tokio_rt.handle().clone(),
+            shutdown: shutdown::Tracker::new(shutdown::Timings::default()),
+        }
+        .build()
+        .context("unable to initialize sqldb proxy")?;
+
+        tokio_rt.block_on(sqldb.migrate(&md, Path::new(&app_root), sqldb::MigrateMode::Apply))
+    }
+}
+
+/// Reports whether the process was started only to apply the database
+/// migrations, in which case [`RuntimeBuilder::migrate`] should be used
+/// instead of starting the runtime.
+pub fn migrate_only() -> bool {
+    std::env::var("ENCORE_SQLDB_MIGRATE_ONLY").is_ok_and(|v| v == "1")
 }
 
 pub struct Runtime {
@@ -205,7 +258,10 @@ pub struct Runtime {
     secrets: secrets::Manager,
     sqldb: sqldb::Manager,
     objects: objects::Manager,
+    cache: cache::Manager,
+    metrics: metrics::Manager,
     api: api::Manager,
+    shutdown: shutdown::Tracker,
     app_meta: meta::AppMeta,
     compute: ComputeConfig,
     runtime: tokio::runtime::Runtime,
@@ -251,16 +307,16 @@ impl Runtime {
         let platform_validator = Arc::new(platform_validator);
 
         // Set up observability.
+        let observability = deployment.observability.take().unwrap_or_default();
         let disable_tracing =
             testing || std::env::var("ENCORE_NOTRACE").is_ok_and(|v| !v.is_empty());
         let tracer = if !disable_tracing {
-            let observability = deployment.observability.take().unwrap_or_default();
             let trace_endpoint = observability
                 .tracing
-                .into_iter()
+                .iter()
                 .find_map(|p| match p.provider {
                     Some(runtimepb::tracing_provider::Provider::Encore(encore)) => {
-                        Some(encore.trace_endpoint)
+                        Some(encore.trace_endpoint.clone())
                     }
                     _ => None,
                 })
@@ -293,8 +349,85 @@ impl Runtime {
             trace::Tracer::noop()
         };
 
+        let otlp_config = if !disable_tracing {
+            observability
+                .tracing
+                .iter()
+                .find_map(|p| match &p.provider {
+                    Some(runtimepb::tracing_provider::Provider::Otlp(otlp)) => Some(otlp),
+                    _ => None,
+                })
+                .and_then(
+                    |otlp| match trace::OtlpExporterConfig::from_provider(otlp) {
+                        Ok(config) => Some(config),
+                        Err(err) => {
+                            ::log::warn!("disabling otlp tracing: {:?}", err);
+                            None
+                        }
+                    },
+                )
+        } else {
+            None
+        };
+
+        let otlp_resource_attrs = vec![
+            trace::OtlpAttr::str("encore.app_id", &environment.app_id),
+            trace::OtlpAttr::str("encore.env_id", &environment.env_id),
+            trace::OtlpAttr::str("deployment.environment", &environment.env_name),
+            trace::OtlpAttr::str("service.version", &md.app_revision),
+        ];
+
+        let tracer = match otlp_config {
+            Some(mut config) => {
+                config.resource_attrs = otlp_resource_attrs.clone();
+                let (recorder, exporter) = trace::otlp_exporter(http_client.clone(), config);
+                tokio_rt.spawn(exporter.start_exporting());
+                tracer.with_otlp(recorder)
+            }
+            None => tracer,
+        };
+
         log::set_tracer(tracer.clone());
 
+        let log_writer = log::WriterConfig {
+            providers: observability.logs,
+            fields: log::root().field_config(),
+            app_name: &environment.app_slug,
+            resource_attrs: otlp_resource_attrs,
+            http_client: http_client.clone(),
+            runtime: tokio_rt.handle().clone(),
+        }
+        .build()
+        .context("unable to initialize log providers")?;
+        if let Some(writer) = log_writer {
+            log::set_writer(writer);
+        }
+
+        // Apply the configured log levels. A hosted service's log config
+        // sets its level, unless the log levels configure one explicitly.
+        let mut log_levels = match observability.log_levels {
+            Some(cfg) => log::Levels::from_config(cfg).context("invalid log levels")?,
+            None => log::Levels::default(),
+        };
+        for svc in deployment.hosted_services.iter() {
+            let Some(log_config) = &svc.log_config else {
+                continue;
+            };
+            match log_config.parse::<log::Level>() {
+                Ok(level) => {
+                    log_levels.services.entry(svc.name.clone()).or_insert(level);
+                }
+                Err(_) => {
+                    ::log::warn!(
+                        "ignoring invalid log level {} for service {}",
+                        log_config,
+                        svc.name
+                    );
+                }
+            }
+        }
+        log::set_levels(log_levels);
+
         // Find push subscriptions which should be proxied to the subscribing service by the gateway
         let proxied_push_subs = resources
             .pubsub_clusters
@@ -330,7 +463,17 @@ impl Runtime {
             .collect::<Result<HashMap<_, _>, anyhow::Error>>()
             .context("failed to resolve gateway push subscriptions")?;
 
-        let pubsub = pubsub::Manager::new(tracer.clone(), resources.pubsub_clusters, &md)?;
+        let shutdown = shutdown::Tracker::new(shutdown::Timings::from_config(
+            deployment.graceful_shutdown.as_ref(),
+        ));
+        let pubsub = pubsub::Manager::new(
+            &secrets,
+            tracer.clone(),
+            resources.pubsub_clusters,
+            &md,
+            shutdown.clone(),
+            testing,
+        )?;
         let objects =
             objects::Manager::new(&secrets, tracer.clone(), resources.bucket_clusters, &md);
         let sqldb = sqldb::ManagerConfig {
@@ -339,10 +482,40 @@ impl Runtime {
             secrets: &secrets,
             tracer: tracer.clone(),
             runtime: tokio_rt.handle().clone(),
+            shutdown: shutdown.clone(),
         }
         .build()
         .context("unable to initialize sqldb proxy")?;
 
+        // Apply or verify the database migrations.
+        if let Some(cfg) = deployment.sql_migrations.take() {
+            let mode = if cfg.apply_on_startup {
+                sqldb::MigrateMode::Apply
+            } else {
+                sqldb::MigrateMode::Verify
+            };
+            let app_root = Path::new(cfg.app_root.as_deref().unwrap_or("."));
+            tokio_rt
+                .block_on(sqldb.migrate(&md, app_root, mode))
+                .context("unable to migrate databases")?;
+        }
+        let metrics = metrics::ManagerConfig {
+            providers: observability.metrics,
+            secrets: &secrets,
+            http_client: http_client.clone(),
+            runtime: tokio_rt.handle().clone(),
+        }
+        .build()
+        .context("unable to initialize metrics manager")?;
+        let cache = cache::ManagerConfig {
+            clusters: resources.redis_clusters,
+            creds: &creds,
+            secrets: &secrets,
+            tracer: tracer.clone(),
+        }
+        .build()
+        .context("unable to initialize cache manager")?;
+
         // Determine the compute configuration.
         let compute = {
             let mut cfg = ComputeConfig::default();
@@ -370,6 +543,13 @@ impl Runtime {
             cfg
         };
 
+        let readiness = {
+            let mut checks = sqldb.readiness_checks();
+            checks.extend(pubsub.readiness_checks());
+            checks.extend(objects.readiness_checks());
+            health::Readiness::new(checks)
+        };
+
         let api = api::ManagerConfig {
             meta: &md,
             environment: &environment,
@@ -388,6 +568,11 @@ impl Runtime {
             runtime: tokio_rt.handle().clone(),
             testing,
             proxied_push_subs,
+            rate_limits: deployment.rate_limits,
+            trusted_proxies: deployment.trusted_proxies,
+            shutdown: shutdown.clone(),
+            readiness,
+            admin_api: deployment.admin_api,
         }
         .build()
         .context("unable to initialize api manager")?;
@@ -399,6 +584,11 @@ impl Runtime {
             }
         });
 
+        if !testing {
+            metrics.start_exporting();
+            shutdown.watch_signals(tokio_rt.handle());
+        }
+
         ::log::debug!("encore runtime successfully initialized");
 
         Ok(Self {
@@ -407,7 +597,10 @@ impl Runtime {
             secrets,
             sqldb,
             objects,
+            cache,
+            metrics,
             api,
+            shutdown,
             app_meta,
             compute,
             runtime: tokio_rt,
@@ -434,6 +627,21 @@ impl Runtime {
         &self.objects
     }
 
+    #[inline]
+    pub fn cache(&self) -> &cache::Manager {
+        &self.cache
+    }
+
+    #[inline]
+    pub fn metrics(&self) -> &metrics::Manager {
+        &self.metrics
+    }
+
+    #[inline]
+    pub fn shutdown(&self) -> &shutdown::Tracker {
+        &self.shutdown
+    }
+
     #[inline]
     pub fn metadata(&self) -> &metapb::Data {
         &self.md
@@ -0,0 +1,249 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+use crate::log::fields::FieldConfig;
+use crate::log::logger::iso8601_now;
+use crate::log::writers::Writer;
+
+/// The default maximum number of entries waiting to be written.
+pub const DEFAULT_BUFFER_SIZE: usize = 8192;
+
+/// How often sinks are flushed, and dropped entries reported.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long `flush` waits for the queued entries to be written.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A log entry waiting to be written to a sink.
+#[derive(Debug)]
+pub struct Entry {
+    pub level: log::Level,
+    pub values: BTreeMap<String, Value>,
+}
+
+/// A destination that log entries are written to from a background thread,
+/// so that writing to it never blocks the code doing the logging.
+pub trait Sink: Send + 'static {
+    /// A short name identifying the kind of sink, used in metrics.
+    fn name(&self) -> &'static str;
+
+    fn write(&mut self, entry: Entry) -> anyhow::Result<()>;
+
+    /// Writes out any entries buffered by the sink.
+    fn flush(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+enum Msg {
+    Entry(Entry),
+    Flush(SyncSender<()>),
+}
+
+/// A writer that queues entries in a bounded buffer and writes them
+/// to a sink from a background thread.
+///
+/// Entries logged while the buffer is full are dropped and counted,
+/// rather than blocking the caller. The number of dropped entries is
+/// periodically written to the sink itself and recorded as a metric.
+pub struct BufferedWriter {
+    tx: SyncSender<Msg>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl BufferedWriter {
+    pub fn new(
+        sink: Box<dyn Sink>,
+        fields: &'static FieldConfig,
+        buffer_size: usize,
+    ) -> anyhow::Result<Self> {
+        let (tx, rx) = std::sync::mpsc::sync_channel(buffer_size.max(1));
+        let dropped = Arc::new(AtomicU64::new(0));
+        let worker = Worker {
+            sink,
+            fields,
+            rx,
+            dropped: dropped.clone(),
+        };
+        std::thread::Builder::new()
+            .name(format!("encore-log-{}", worker.sink.name()))
+            .spawn(move || worker.run())?;
+        Ok(Self { tx, dropped })
+    }
+
+    /// The number of entries dropped since the last time drops were reported.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl Writer for BufferedWriter {
+    fn write(&self, level: log::Level, values: &BTreeMap<String, Value>) -> anyhow::Result<()> {
+        let entry = Entry {
+            level,
+            values: values.clone(),
+        };
+        match self.tx.try_send(Msg::Entry(entry)) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                Err(anyhow::anyhow!("log writer thread has exited"))
+            }
+        }
+    }
+
+    fn flush(&self) -> anyhow::Result<()> {
+        let (done_tx, done_rx) = std::sync::mpsc::sync_channel(1);
+        self.tx
+            .send_timeout(Msg::Flush(done_tx), FLUSH_TIMEOUT)
+            .map_err(|_| anyhow::anyhow!("timed out flushing logs"))?;
+        done_rx
+            .recv_timeout(FLUSH_TIMEOUT)
+            .map_err(|_| anyhow::anyhow!("timed out flushing logs"))
+    }
+}
+
+struct Worker {
+    sink: Box<dyn Sink>,
+    fields: &'static FieldConfig,
+    rx: Receiver<Msg>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl Worker {
+    fn run(mut self) {
+        let mut last_flush = Instant::now();
+        loop {
+            let timeout = FLUSH_INTERVAL.saturating_sub(last_flush.elapsed());
+            match self.rx.recv_timeout(timeout) {
+                Ok(Msg::Entry(entry)) => {
+                    if let Err(err) = self.sink.write(entry) {
+                        eprintln!("failed to write log entry: {:#}", err);
+                    }
+                }
+                Ok(Msg::Flush(done)) => {
+                    self.flush();
+                    last_flush = Instant::now();
+                    let _ = done.send(());
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.flush();
+                    return;
+                }
+            }
+
+            if last_flush.elapsed() >= FLUSH_INTERVAL {
+                self.flush();
+                last_flush = Instant::now();
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        let dropped = self.dropped.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            crate::metrics::record_dropped_logs(self.sink.name(), dropped);
+            let fields = self.fields;
+            let values = BTreeMap::from([
+                (
+                    fields.message_field_name.to_string(),
+                    Value::from("dropped log entries: the log buffer is full"),
+                ),
+                (
+                    fields.level_field_name.to_string(),
+                    Value::from(fields.level_warn_value),
+                ),
+                (fields.timestamp_field_name.to_string(), iso8601_now()),
+                ("dropped".to_string(), Value::from(dropped)),
+            ]);
+            let entry = Entry {
+                level: log::Level::Warn,
+                values,
+            };
+            if let Err(err) = self.sink.write(entry) {
+                eprintln!("failed to write log entry: {:#}", err);
+            }
+        }
+
+        if let Err(err) = self.sink.flush() {
+            eprintln!("failed to flush logs: {:#}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::log::fields::DEFAULT_FIELDS;
+
+    struct RecordingSink {
+        entries: Arc<Mutex<Vec<Entry>>>,
+        block: Arc<Mutex<()>>,
+    }
+
+    impl Sink for RecordingSink {
+        fn name(&self) -> &'static str {
+            "test"
+        }
+
+        fn write(&mut self, entry: Entry) -> anyhow::Result<()> {
+            let _guard = self.block.lock().unwrap();
+            self.entries.lock().unwrap().push(entry);
+            Ok(())
+        }
+    }
+
+    fn values(msg: &str) -> BTreeMap<String, Value> {
+        BTreeMap::from([("message".to_string(), Value::from(msg))])
+    }
+
+    #[test]
+    fn test_buffered_writer() {
+        let entries = Arc::new(Mutex::new(Vec::new()));
+        let block = Arc::new(Mutex::new(()));
+        let sink = RecordingSink {
+            entries: entries.clone(),
+            block: block.clone(),
+        };
+        let w = BufferedWriter::new(Box::new(sink), &DEFAULT_FIELDS, 2).unwrap();
+
+        w.write(log::Level::Info, &values("one")).unwrap();
+        w.flush().unwrap();
+        assert_eq!(entries.lock().unwrap().len(), 1);
+
+        // While the sink is blocked, writes beyond the buffer size are dropped.
+        let guard = block.lock().unwrap();
+        for _ in 0..10 {
+            w.write(log::Level::Info, &values("more")).unwrap();
+        }
+        assert!(w.dropped() > 0);
+        drop(guard);
+
+        // The drops are reported to the sink.
+        w.flush().unwrap();
+        let entries = entries.lock().unwrap();
+        let reported: u64 = entries
+            .iter()
+            .filter_map(|e| e.values.get("dropped"))
+            .filter_map(|v| v.as_u64())
+            .sum();
+        let written = entries
+            .iter()
+            .filter(|e| e.values["message"] == "more")
+            .count() as u64;
+        assert!(reported > 0);
+        assert_eq!(written + reported, 10);
+        assert_eq!(w.dropped(), 0);
+    }
+}
@@ -0,0 +1,161 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use crate::encore::runtime::v1 as pb;
+use crate::log::buffered::{Entry, Sink};
+
+const DEFAULT_MAX_SIZE: u64 = 100 * 1024 * 1024;
+const DEFAULT_MAX_FILES: u32 = 5;
+
+/// A sink that writes JSON lines to a file, rotating it when it grows too large.
+///
+/// When the file would exceed the maximum size it is renamed to `<path>.1`,
+/// shifting previously rotated files up by one and removing the oldest.
+pub struct FileSink {
+    path: PathBuf,
+    max_size: u64,
+    max_files: u32,
+    file: BufWriter<File>,
+    size: u64,
+}
+
+impl FileSink {
+    pub fn new(cfg: &pb::logs_provider::FileLogsProvider) -> anyhow::Result<Self> {
+        Self::open(
+            PathBuf::from(&cfg.path),
+            cfg.max_size_bytes.unwrap_or(DEFAULT_MAX_SIZE),
+            cfg.max_files.unwrap_or(DEFAULT_MAX_FILES),
+        )
+    }
+
+    fn open(path: PathBuf, max_size: u64, max_files: u32) -> anyhow::Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("unable to create log directory {}", dir.display()))?;
+        }
+        let (file, size) = open_append(&path)?;
+        Ok(Self {
+            path,
+            max_size,
+            max_files,
+            file: BufWriter::new(file),
+            size,
+        })
+    }
+
+    fn rotate(&mut self) -> anyhow::Result<()> {
+        self.file.flush()?;
+
+        if self.max_files == 0 {
+            // No rotated files are kept, so start over with an empty file.
+            let file = File::create(&self.path)
+                .with_context(|| format!("unable to truncate {}", self.path.display()))?;
+            self.file = BufWriter::new(file);
+            self.size = 0;
+            return Ok(());
+        }
+
+        for n in (1..self.max_files).rev() {
+            let from = rotated_path(&self.path, n);
+            if from.exists() {
+                std::fs::rename(&from, rotated_path(&self.path, n + 1))
+                    .with_context(|| format!("unable to rotate {}", from.display()))?;
+            }
+        }
+        std::fs::rename(&self.path, rotated_path(&self.path, 1))
+            .with_context(|| format!("unable to rotate {}", self.path.display()))?;
+
+        let (file, size) = open_append(&self.path)?;
+        self.file = BufWriter::new(file);
+        self.size = size;
+        Ok(())
+    }
+}
+
+impl Sink for FileSink {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn write(&mut self, entry: Entry) -> anyhow::Result<()> {
+        let mut buf = serde_json::to_vec(&entry.values).context("unable to encode log entry")?;
+        buf.push(b'\n');
+
+        let len = buf.len() as u64;
+        if self.size > 0 && self.size + len > self.max_size {
+            self.rotate()?;
+        }
+        self.file
+            .write_all(&buf)
+            .context("unable to write log file")?;
+        self.size += len;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.file.flush().context("unable to flush log file")
+    }
+}
+
+fn open_append(path: &Path) -> anyhow::Result<(File, u64)> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("unable to open log file {}", path.display()))?;
+    let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+    Ok((file, size))
+}
+
+fn rotated_path(path: &Path, n: u32) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn entry(msg: &str) -> Entry {
+        Entry {
+            level: log::Level::Info,
+            values: BTreeMap::from([("message".to_string(), msg.into())]),
+        }
+    }
+
+    #[test]
+    fn test_rotation() {
+        let dir = std::env::temp_dir().join(format!("encore-log-test-{}", std::process::id()));
+        let path = dir.join("app.log");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        // Each entry is 20 bytes, so two entries fit in a file.
+        let mut sink = FileSink::open(path.clone(), 40, 2).unwrap();
+        for msg in [
+            "aaaaa", "bbbbb", "ccccc", "ddddd", "eeeee", "fffff", "ggggg",
+        ] {
+            sink.write(entry(msg)).unwrap();
+        }
+        sink.flush().unwrap();
+
+        let read = |p: PathBuf| std::fs::read_to_string(p).unwrap();
+        assert_eq!(read(path.clone()), "{\"message\":\"ggggg\"}\n");
+        assert_eq!(
+            read(rotated_path(&path, 1)),
+            "{\"message\":\"eeeee\"}\n{\"message\":\"fffff\"}\n"
+        );
+        assert_eq!(
+            read(rotated_path(&path, 2)),
+            "{\"message\":\"ccccc\"}\n{\"message\":\"ddddd\"}\n"
+        );
+        assert!(!rotated_path(&path, 3).exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use anyhow::Context;
+use log::LevelFilter;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::encore::runtime::v1 as pb;
+
+/// Log levels by service, endpoint and logger name,
+/// overriding the levels loggers would otherwise use.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Levels {
+    /// The level of application logs without a more specific level.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<Level>,
+
+    /// Levels of application logs by service name.
+    #[serde(default)]
+    pub services: BTreeMap<String, Level>,
+
+    /// Levels of application logs by endpoint, keyed by "service.endpoint".
+    #[serde(default)]
+    pub endpoints: BTreeMap<String, Level>,
+
+    /// Levels of runtime logs by logger name.
+    #[serde(default)]
+    pub loggers: BTreeMap<String, Level>,
+}
+
+impl Levels {
+    pub fn from_config(cfg: pb::LogLevels) -> anyhow::Result<Self> {
+        fn parse_map(
+            map: impl IntoIterator<Item = (String, String)>,
+        ) -> anyhow::Result<BTreeMap<String, Level>> {
+            map.into_iter()
+                .map(|(name, level)| {
+                    let level = level
+                        .parse()
+                        .with_context(|| format!("invalid log level for {}", name))?;
+                    Ok((name, level))
+                })
+                .collect()
+        }
+
+        Ok(Self {
+            default: cfg
+                .default_level
+                .map(|level| level.parse())
+                .transpose()
+                .context("invalid default log level")?,
+            services: parse_map(cfg.services)?,
+            endpoints: parse_map(cfg.endpoints)?,
+            loggers: parse_map(cfg.loggers)?,
+        })
+    }
+
+    /// The level of application logs emitted while handling a request to the given endpoint,
+    /// if the levels override it.
+    pub fn app_level(&self, service: &str, endpoint: Option<&str>) -> Option<LevelFilter> {
+        if let Some(endpoint) = endpoint {
+            if !self.endpoints.is_empty() {
+                let key = format!("{}.{}", service, endpoint);
+                if let Some(level) = self.endpoints.get(&key) {
+                    return Some(level.0);
+                }
+            }
+        }
+        self.services.get(service).map(|level| level.0)
+    }
+
+    /// The level of the runtime logger with the given name, if the levels override it.
+    /// The most specific matching logger name takes precedence.
+    pub fn logger_level(&self, target: &str) -> Option<LevelFilter> {
+        self.loggers
+            .iter()
+            .filter(|(name, _)| {
+                target
+                    .strip_prefix(name.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .max_by_key(|(name, _)| name.len())
+            .map(|(_, level)| level.0)
+    }
+}
+
+/// A log level, serialized in lowercase.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Level(pub LevelFilter);
+
+impl FromStr for Level {
+    type Err = log::ParseLevelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(Level)
+    }
+}
+
+impl Serialize for Level {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0.as_str().to_lowercase())
+    }
+}
+
+impl<'de> Deserialize<'de> for Level {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels() {
+        let cfg = pb::LogLevels {
+            default_level: Some("info".into()),
+            services: [("svc".to_string(), "debug".to_string())].into(),
+            endpoints: [("svc.Foo".to_string(), "trace".to_string())].into(),
+            loggers: [
+                ("encore_runtime_core".to_string(), "warn".to_string()),
+                (
+                    "encore_runtime_core::pubsub".to_string(),
+                    "debug".to_string(),
+                ),
+            ]
+            .into(),
+        };
+        let levels = Levels::from_config(cfg).unwrap();
+        assert_eq!(levels.default, Some(Level(LevelFilter::Info)));
+
+        assert_eq!(
+            levels.app_level("svc", Some("Foo")),
+            Some(LevelFilter::Trace)
+        );
+        assert_eq!(
+            levels.app_level("svc", Some("Bar")),
+            Some(LevelFilter::Debug)
+        );
+        assert_eq!(levels.app_level("svc", None), Some(LevelFilter::Debug));
+        assert_eq!(levels.app_level("other", Some("Foo")), None);
+
+        assert_eq!(
+            levels.logger_level("encore_runtime_core::pubsub::manager"),
+            Some(LevelFilter::Debug)
+        );
+        assert_eq!(
+            levels.logger_level("encore_runtime_core::api"),
+            Some(LevelFilter::Warn)
+        );
+        assert_eq!(levels.logger_level("encore_runtime_core_other"), None);
+        assert_eq!(levels.logger_level("pingora_core"), None);
+
+        let json = serde_json::to_value(&levels).unwrap();
+        assert_eq!(json["default"], "info");
+        assert_eq!(json["services"]["svc"], "debug");
+        let parsed: Levels = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, levels);
+
+        assert!(Levels::from_config(pb::LogLevels {
+            default_level: Some("loud".into()),
+            ..Default::default()
+        })
+        .is_err());
+    }
+}
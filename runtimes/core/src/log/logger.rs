@@ -1,5 +1,6 @@
 use crate::error::AppError;
 use crate::log::fields::FieldConfig;
+use crate::log::levels::Levels;
 use crate::log::writers::{default_writer, Writer};
 use crate::model::{self, LogField};
 use crate::trace::protocol::LogMessageData;
@@ -17,9 +18,11 @@ pub type Fields = BTreeMap<String, serde_json::Value>;
 #[derive(Debug, Clone)]
 pub struct Logger {
     filter: Arc<Filter>,
-    app_level: log::LevelFilter,
+    /// The level set in code using `with_level`, if any.
+    app_level: Option<log::LevelFilter>,
+    levels: Arc<RwLock<LevelState>>,
     field_config: &'static FieldConfig,
-    writer: Arc<dyn Writer>,
+    writer: Arc<RwLock<Arc<dyn Writer>>>,
     extra_fields: Fields,
     tracer: Arc<RwLock<Tracer>>,
 }
@@ -33,9 +36,13 @@ impl Logger {
     ) -> Self {
         Self {
             filter: Arc::new(filter),
-            app_level,
+            app_level: None,
+            levels: Arc::new(RwLock::new(LevelState {
+                base: app_level,
+                levels: Levels::default(),
+            })),
             field_config,
-            writer: default_writer(field_config),
+            writer: Arc::new(RwLock::new(default_writer(field_config))),
             extra_fields: Fields::new(),
             tracer: Arc::new(RwLock::new(Tracer::noop())),
         }
@@ -47,18 +54,78 @@ impl Logger {
         *t = tracer;
     }
 
+    /// Returns the names of the fields used in the log output.
+    pub fn field_config(&self) -> &'static FieldConfig {
+        self.field_config
+    }
+
+    /// Sets the loggers writer
+    pub fn set_writer(&self, writer: Arc<dyn Writer>) {
+        let mut w = self.writer.write().expect("writer lock poisoned");
+        *w = writer;
+    }
+
+    /// Sets the configured log levels, replacing any previously set levels.
+    /// The levels apply to this logger and all loggers derived from it.
+    pub fn set_levels(&self, levels: Levels) {
+        let mut state = self.levels.write().expect("levels lock poisoned");
+        state.levels = levels;
+    }
+
+    /// Returns the configured log levels.
+    pub fn levels(&self) -> Levels {
+        self.levels
+            .read()
+            .expect("levels lock poisoned")
+            .levels
+            .clone()
+    }
+
     /// Returns a new logger with the given log level.
+    ///
+    /// Levels configured for a service or endpoint take precedence
+    /// over this level for logs emitted while handling its requests.
     pub fn with_level(&self, level: log::LevelFilter) -> Self {
         Self {
-            app_level: level,
+            app_level: Some(level),
             ..self.clone()
         }
     }
 
+    /// Reports whether application logs at the given level should be emitted,
+    /// given the request being handled.
+    fn app_enabled(&self, request: Option<&model::Request>, level: log::Level) -> bool {
+        let state = self.levels.read().expect("levels lock poisoned");
+        let configured = request.and_then(|req| {
+            let (service, endpoint) = match &req.data {
+                model::RequestData::RPC(rpc) => (
+                    rpc.endpoint.name.service(),
+                    Some(rpc.endpoint.name.endpoint()),
+                ),
+                model::RequestData::Auth(auth) => (
+                    auth.auth_handler.service(),
+                    Some(auth.auth_handler.endpoint()),
+                ),
+                model::RequestData::PubSub(msg) => (msg.service.as_ref(), None),
+                model::RequestData::Stream(data) => (
+                    data.endpoint.name.service(),
+                    Some(data.endpoint.name.endpoint()),
+                ),
+            };
+            state.levels.app_level(service, endpoint)
+        });
+
+        let app_level = configured
+            .or(self.app_level)
+            .or(state.levels.default.map(|level| level.0))
+            .unwrap_or(state.base);
+        level <= app_level
+    }
+
     /// Returns a new logger with the given writer.
     pub fn with_writer(&self, writer: Arc<dyn Writer>) -> Self {
         Self {
-            writer,
+            writer: Arc::new(RwLock::new(writer)),
             ..self.clone()
         }
     }
@@ -226,9 +293,8 @@ impl Logger {
         }
 
         // Now write the log to the configured writer.
-        self.writer
-            .write(level, &values)
-            .context("unable to write")?;
+        let writer = self.writer.read().expect("writer lock poisoned").clone();
+        writer.write(level, &values).context("unable to write")?;
 
         Ok(())
     }
@@ -317,6 +383,16 @@ impl Logger {
     }
 }
 
+/// The log levels shared between a logger and the loggers derived from it,
+/// so that changing them takes effect everywhere.
+#[derive(Debug)]
+struct LevelState {
+    /// The application log level from the environment,
+    /// used when no other level applies.
+    base: log::LevelFilter,
+    levels: Levels,
+}
+
 /// This trait defines the logging functions that are available on the `Logger` type.
 ///
 /// It is used to allow Rust code to emit structured logs via our `Logger` implementation
@@ -374,7 +450,7 @@ where
         caller: Option<String>,
         fields: Option<Fields>,
     ) -> anyhow::Result<()> {
-        if level > self.app_level {
+        if !self.app_enabled(request, level) {
             return Ok(());
         }
 
@@ -395,7 +471,7 @@ where
 {
     #[track_caller]
     fn trace(&self, req: Option<&model::Request>, msg: T, fields: Option<Fields>) {
-        if log::Level::Trace > self.app_level {
+        if !self.app_enabled(req, log::Level::Trace) {
             return;
         }
 
@@ -405,7 +481,7 @@ where
 
     #[track_caller]
     fn debug(&self, req: Option<&model::Request>, msg: T, fields: Option<Fields>) {
-        if log::Level::Debug > self.app_level {
+        if !self.app_enabled(req, log::Level::Debug) {
             return;
         }
 
@@ -415,7 +491,7 @@ where
 
     #[track_caller]
     fn info(&self, req: Option<&model::Request>, msg: T, fields: Option<Fields>) {
-        if log::Level::Info > self.app_level {
+        if !self.app_enabled(req, log::Level::Info) {
             return;
         }
 
@@ -431,7 +507,7 @@ where
         error: Option<Err>,
         fields: Option<Fields>,
     ) {
-        if log::Level::Warn > self.app_level {
+        if !self.app_enabled(req, log::Level::Warn) {
             return;
         }
 
@@ -454,7 +530,7 @@ where
         error: Option<Err>,
         fields: Option<Fields>,
     ) {
-        if log::Level::Error > self.app_level {
+        if !self.app_enabled(req, log::Level::Error) {
             return;
         }
 
@@ -483,7 +559,16 @@ pub fn iso8601_now() -> serde_json::Value {
 /// crate to emit structured logs via our `Logger` implementation.
 impl Log for Logger {
     fn enabled(&self, metadata: &Metadata) -> bool {
-        self.filter.enabled(metadata)
+        let configured = self
+            .levels
+            .read()
+            .expect("levels lock poisoned")
+            .levels
+            .logger_level(metadata.target());
+        match configured {
+            Some(level) => metadata.level() <= level,
+            None => self.filter.enabled(metadata),
+        }
     }
 
     fn log(&self, record: &Record) {
@@ -494,7 +579,12 @@ impl Log for Logger {
         }
     }
 
-    fn flush(&self) {}
+    fn flush(&self) {
+        let writer = self.writer.read().expect("writer lock poisoned").clone();
+        if let Err(err) = writer.flush() {
+            eprintln!("failed to flush logs: {:#}", err);
+        }
+    }
 }
 
 /// A visitor that can be used to visit key-value pairs and insert them into a `BTreeMap`.
@@ -1,12 +1,21 @@
+use std::sync::Arc;
+
 use once_cell::sync::OnceCell;
 
+mod buffered;
 mod consolewriter;
 mod fields;
+mod file;
+mod levels;
 mod logger;
+mod otlp;
+mod syslog;
 mod writers;
 
 use crate::log::fields::FieldConfig;
+pub use levels::{Level, Levels};
 pub use logger::{Fields, LogFromExternalRuntime, LogFromRust, Logger};
+pub use writers::{Writer, WriterConfig};
 
 use crate::trace::Tracer;
 
@@ -27,6 +36,16 @@ pub fn set_tracer(tracer: Tracer) {
     root().set_tracer(tracer);
 }
 
+/// Set the writer on the global logger
+pub fn set_writer(writer: Arc<dyn Writer>) {
+    root().set_writer(writer);
+}
+
+/// Set the log levels on the global logger
+pub fn set_levels(levels: Levels) {
+    root().set_levels(levels);
+}
+
 /// Returns a reference to the global root logger instance.
 pub fn root() -> &'static Logger {
     ROOT.get_or_init(|| {
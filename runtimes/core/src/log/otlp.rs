@@ -0,0 +1,291 @@
+//! Exports logs as OpenTelemetry log records over OTLP/HTTP, using the JSON encoding.
+
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use serde::Serialize;
+
+use crate::encore::runtime::v1 as pb;
+use crate::log::buffered::{Entry, Sink};
+use crate::log::fields::FieldConfig;
+use crate::model::{SpanId, TraceId};
+use crate::trace::{parse_otlp_headers, OtlpAttr, OtlpValue};
+
+/// The maximum number of log records sent in a single export request.
+const MAX_BATCH_SIZE: usize = 512;
+
+/// A sink that exports log entries to an OTLP logs endpoint.
+///
+/// Entries are exported in batches, when the batch is full or the sink is flushed.
+pub struct OtlpSink {
+    http_client: reqwest::Client,
+    runtime: tokio::runtime::Handle,
+    endpoint: reqwest::Url,
+    headers: reqwest::header::HeaderMap,
+    fields: &'static FieldConfig,
+    resource_attrs: Vec<OtlpAttr>,
+    batch: Vec<(Option<String>, LogRecord)>,
+}
+
+impl OtlpSink {
+    pub fn new(
+        cfg: &pb::logs_provider::OtlpLogsProvider,
+        fields: &'static FieldConfig,
+        http_client: reqwest::Client,
+        runtime: tokio::runtime::Handle,
+        resource_attrs: Vec<OtlpAttr>,
+    ) -> anyhow::Result<Self> {
+        let endpoint = reqwest::Url::parse(&cfg.endpoint)
+            .with_context(|| format!("invalid otlp endpoint {}", cfg.endpoint))?;
+        Ok(Self {
+            http_client,
+            runtime,
+            endpoint,
+            headers: parse_otlp_headers(&cfg.headers)?,
+            fields,
+            resource_attrs,
+            batch: Vec::with_capacity(MAX_BATCH_SIZE),
+        })
+    }
+
+    fn export(&mut self) -> anyhow::Result<()> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        let batch = std::mem::take(&mut self.batch);
+        let req = export_request(&self.resource_attrs, batch);
+
+        let send = self
+            .http_client
+            .post(self.endpoint.clone())
+            .headers(self.headers.clone())
+            .json(&req)
+            .send();
+        // Sinks run on a dedicated thread, so it's fine to block on the request.
+        let resp = self
+            .runtime
+            .block_on(send)
+            .context("failed to export logs")?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let body = self.runtime.block_on(resp.text()).unwrap_or_default();
+            anyhow::bail!("failed to export logs: HTTP {}: {}", status, body);
+        }
+        Ok(())
+    }
+}
+
+impl Sink for OtlpSink {
+    fn name(&self) -> &'static str {
+        "otlp"
+    }
+
+    fn write(&mut self, entry: Entry) -> anyhow::Result<()> {
+        let service = entry
+            .values
+            .get("service")
+            .and_then(|v| v.as_str())
+            .map(str::to_string);
+        self.batch.push((service, log_record(self.fields, entry)));
+        if self.batch.len() >= MAX_BATCH_SIZE {
+            self.export()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.export()
+    }
+}
+
+fn log_record(fields: &FieldConfig, entry: Entry) -> LogRecord {
+    let (severity_number, severity_text) = match entry.level {
+        log::Level::Trace => (1, "TRACE"),
+        log::Level::Debug => (5, "DEBUG"),
+        log::Level::Info => (9, "INFO"),
+        log::Level::Warn => (13, "WARN"),
+        log::Level::Error => (17, "ERROR"),
+    };
+    let observed = unix_nanos(SystemTime::now());
+
+    let mut record = LogRecord {
+        time_unix_nano: observed.clone(),
+        observed_time_unix_nano: observed,
+        severity_number,
+        severity_text,
+        body: None,
+        attributes: Vec::new(),
+        trace_id: String::new(),
+        span_id: String::new(),
+    };
+
+    for (key, value) in entry.values {
+        if key == fields.message_field_name {
+            record.body = Some(OtlpValue::json(&value));
+        } else if key == fields.timestamp_field_name {
+            if let Some(time) = value
+                .as_str()
+                .and_then(|s| chrono::DateTime::parse_from_rfc3339(s).ok())
+            {
+                record.time_unix_nano = unix_nanos(time.into());
+            }
+        } else if key == fields.level_field_name {
+            // Already captured by the severity.
+        } else if key == "trace_id" {
+            // Logs use the Encore format for ids, while OpenTelemetry uses hex.
+            record.trace_id = value
+                .as_str()
+                .and_then(|s| TraceId::parse_encore(s).ok())
+                .map(|id| id.serialize_std())
+                .unwrap_or_default();
+        } else if key == "span_id" {
+            record.span_id = value
+                .as_str()
+                .and_then(|s| SpanId::parse_encore(s).ok())
+                .map(|id| id.serialize_std())
+                .unwrap_or_default();
+        } else {
+            record.attributes.push(OtlpAttr::json(&key, &value));
+        }
+    }
+    record
+}
+
+/// Groups the records by service, as each service is a separate OpenTelemetry resource.
+fn export_request(
+    resource_attrs: &[OtlpAttr],
+    batch: Vec<(Option<String>, LogRecord)>,
+) -> ExportRequest {
+    let mut by_service: HashMap<Option<String>, Vec<LogRecord>> = HashMap::new();
+    for (service, record) in batch {
+        by_service.entry(service).or_default().push(record);
+    }
+
+    let resource_logs = by_service
+        .into_iter()
+        .map(|(service, log_records)| {
+            let mut attributes = Vec::new();
+            if let Some(service) = service {
+                attributes.push(OtlpAttr::str("service.name", &service));
+            }
+            attributes.extend(resource_attrs.iter().cloned());
+            ResourceLogs {
+                resource: Resource { attributes },
+                scope_logs: vec![ScopeLogs {
+                    scope: Scope {
+                        name: "encore",
+                        version: env!("CARGO_PKG_VERSION"),
+                    },
+                    log_records,
+                }],
+            }
+        })
+        .collect();
+    ExportRequest { resource_logs }
+}
+
+fn unix_nanos(t: SystemTime) -> String {
+    t.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default()
+        .to_string()
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportRequest {
+    resource_logs: Vec<ResourceLogs>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ResourceLogs {
+    resource: Resource,
+    scope_logs: Vec<ScopeLogs>,
+}
+
+#[derive(Debug, Serialize)]
+struct Resource {
+    attributes: Vec<OtlpAttr>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ScopeLogs {
+    scope: Scope,
+    log_records: Vec<LogRecord>,
+}
+
+#[derive(Debug, Serialize)]
+struct Scope {
+    name: &'static str,
+    version: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LogRecord {
+    time_unix_nano: String,
+    observed_time_unix_nano: String,
+    severity_number: i32,
+    severity_text: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body: Option<OtlpValue>,
+    attributes: Vec<OtlpAttr>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    trace_id: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    span_id: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde_json::Value;
+
+    use super::*;
+    use crate::log::fields::DEFAULT_FIELDS;
+
+    #[test]
+    fn test_export_request_encoding() {
+        let trace_id = TraceId([1; 16]);
+        let entry = Entry {
+            level: log::Level::Warn,
+            values: BTreeMap::from([
+                ("message".to_string(), Value::from("hello")),
+                ("level".to_string(), Value::from("warn")),
+                ("time".to_string(), Value::from("1970-01-01T00:00:01.000Z")),
+                ("service".to_string(), Value::from("svc")),
+                (
+                    "trace_id".to_string(),
+                    Value::from(trace_id.serialize_encore()),
+                ),
+                ("count".to_string(), Value::from(3)),
+            ]),
+        };
+        let record = log_record(&DEFAULT_FIELDS, entry);
+        let req = export_request(&[], vec![(Some("svc".to_string()), record)]);
+
+        let json = serde_json::to_value(&req).unwrap();
+        let resource_logs = &json["resourceLogs"][0];
+        assert_eq!(
+            resource_logs["resource"]["attributes"][0],
+            serde_json::json!({"key": "service.name", "value": {"stringValue": "svc"}})
+        );
+        let record = &resource_logs["scopeLogs"][0]["logRecords"][0];
+        assert_eq!(record["timeUnixNano"], "1000000000");
+        assert_eq!(record["severityNumber"], 13);
+        assert_eq!(record["body"], serde_json::json!({"stringValue": "hello"}));
+        assert_eq!(record["traceId"], trace_id.serialize_std());
+        assert!(record.get("spanId").is_none());
+        assert_eq!(
+            record["attributes"],
+            serde_json::json!([
+                {"key": "count", "value": {"intValue": "3"}},
+                {"key": "service", "value": {"stringValue": "svc"}},
+            ])
+        );
+    }
+}
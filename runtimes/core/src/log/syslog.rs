@@ -0,0 +1,189 @@
+use std::io::Write;
+use std::net::{TcpStream, UdpSocket};
+
+use anyhow::Context;
+
+use crate::encore::runtime::v1 as pb;
+use crate::log::buffered::{Entry, Sink};
+
+const DEFAULT_ADDRESS: &str = "unix:///dev/log";
+
+/// The local0 facility.
+const DEFAULT_FACILITY: u32 = 16;
+
+/// A sink that sends log entries to syslog, formatted according to RFC 5424
+/// with the JSON-encoded entry as the message.
+pub struct SyslogSink {
+    transport: Transport,
+    facility: u32,
+    hostname: String,
+    app_name: String,
+    pid: u32,
+}
+
+enum Transport {
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixDatagram, String),
+    Udp(UdpSocket),
+    /// A TCP connection, reconnected on the next write if it fails.
+    Tcp(String, Option<TcpStream>),
+}
+
+impl SyslogSink {
+    pub fn new(
+        cfg: &pb::logs_provider::SyslogLogsProvider,
+        default_app_name: &str,
+    ) -> anyhow::Result<Self> {
+        let address = cfg.address.as_deref().unwrap_or(DEFAULT_ADDRESS);
+        let facility = cfg.facility.unwrap_or(DEFAULT_FACILITY);
+        if facility > 23 {
+            anyhow::bail!("invalid syslog facility {}", facility);
+        }
+
+        let transport = if let Some(path) = address.strip_prefix("unix://") {
+            unix_transport(path)?
+        } else if let Some(addr) = address.strip_prefix("udp://") {
+            let sock = UdpSocket::bind("0.0.0.0:0").context("unable to bind udp socket")?;
+            sock.connect(addr)
+                .with_context(|| format!("unable to resolve syslog address {}", addr))?;
+            Transport::Udp(sock)
+        } else if let Some(addr) = address.strip_prefix("tcp://") {
+            Transport::Tcp(addr.to_string(), None)
+        } else {
+            anyhow::bail!("invalid syslog address {}", address);
+        };
+
+        Ok(Self {
+            transport,
+            facility,
+            hostname: hostname(),
+            app_name: cfg
+                .app_name
+                .clone()
+                .unwrap_or_else(|| default_app_name.to_string()),
+            pid: std::process::id(),
+        })
+    }
+
+    fn format(&self, entry: &Entry) -> anyhow::Result<Vec<u8>> {
+        let severity = match entry.level {
+            log::Level::Error => 3,
+            log::Level::Warn => 4,
+            log::Level::Info => 6,
+            log::Level::Debug | log::Level::Trace => 7,
+        };
+        let timestamp = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Micros, true);
+
+        let mut buf = format!(
+            "<{}>1 {} {} {} {} - - ",
+            self.facility * 8 + severity,
+            timestamp,
+            nil_if_empty(&self.hostname),
+            nil_if_empty(&self.app_name),
+            self.pid,
+        )
+        .into_bytes();
+        serde_json::to_writer(&mut buf, &entry.values).context("unable to encode log entry")?;
+        Ok(buf)
+    }
+}
+
+impl Sink for SyslogSink {
+    fn name(&self) -> &'static str {
+        "syslog"
+    }
+
+    fn write(&mut self, entry: Entry) -> anyhow::Result<()> {
+        let msg = self.format(&entry)?;
+        match &mut self.transport {
+            #[cfg(unix)]
+            Transport::Unix(sock, path) => {
+                sock.send_to(&msg, &*path)
+                    .with_context(|| format!("unable to send to syslog at {}", path))?;
+            }
+            Transport::Udp(sock) => {
+                sock.send(&msg).context("unable to send to syslog")?;
+            }
+            Transport::Tcp(addr, conn) => {
+                // Messages are framed using octet counting, as described in RFC 6587.
+                let mut framed = format!("{} ", msg.len()).into_bytes();
+                framed.extend_from_slice(&msg);
+
+                let stream = match conn {
+                    Some(stream) => stream,
+                    None => conn.insert(
+                        TcpStream::connect(&*addr)
+                            .with_context(|| format!("unable to connect to syslog at {}", addr))?,
+                    ),
+                };
+                if let Err(err) = stream.write_all(&framed) {
+                    *conn = None;
+                    return Err(err).context("unable to send to syslog");
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn unix_transport(path: &str) -> anyhow::Result<Transport> {
+    let sock =
+        std::os::unix::net::UnixDatagram::unbound().context("unable to create unix socket")?;
+    Ok(Transport::Unix(sock, path.to_string()))
+}
+
+#[cfg(not(unix))]
+fn unix_transport(path: &str) -> anyhow::Result<Transport> {
+    anyhow::bail!(
+        "unix syslog sockets are not supported on this platform: {}",
+        path
+    )
+}
+
+fn nil_if_empty(s: &str) -> &str {
+    if s.is_empty() {
+        "-"
+    } else {
+        s
+    }
+}
+
+fn hostname() -> String {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|h| h.trim().replace(' ', "_"))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let cfg = pb::logs_provider::SyslogLogsProvider {
+            address: Some("udp://127.0.0.1:514".into()),
+            facility: Some(1),
+            app_name: None,
+        };
+        let mut sink = SyslogSink::new(&cfg, "my-app").unwrap();
+        sink.hostname = "host".into();
+        sink.pid = 42;
+
+        let entry = Entry {
+            level: log::Level::Warn,
+            values: BTreeMap::from([("message".to_string(), "hello".into())]),
+        };
+        let msg = String::from_utf8(sink.format(&entry).unwrap()).unwrap();
+        assert!(msg.starts_with("<12>1 "), "{}", msg);
+        assert!(
+            msg.ends_with(" host my-app 42 - - {\"message\":\"hello\"}"),
+            "{}",
+            msg
+        );
+    }
+}
@@ -1,20 +1,30 @@
+use crate::encore::runtime::v1 as pb;
+use crate::log::buffered::{self, BufferedWriter, Entry, Sink};
 use crate::log::consolewriter::ConsoleWriter;
 use crate::log::fields::FieldConfig;
+use crate::log::file::FileSink;
+use crate::log::otlp::OtlpSink;
+use crate::log::syslog::SyslogSink;
+use crate::trace::OtlpAttr;
 use anyhow::Context;
+use pb::logs_provider::Provider;
 use serde_json::Value;
 use std::cell::RefCell;
 use std::collections::BTreeMap;
 use std::env;
 use std::fmt::Debug;
 use std::io::Write;
-use std::pin::Pin;
 use std::sync::{Arc, Mutex};
-use tokio::io::AsyncWrite;
 
 /// A log writer.
 pub trait Writer: Send + Sync + 'static {
     /// Write the given key-value pairs to the log.
     fn write(&self, level: log::Level, values: &BTreeMap<String, Value>) -> anyhow::Result<()>;
+
+    /// Waits for previously written logs to be written out.
+    fn flush(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
 }
 
 impl Debug for dyn Writer {
@@ -27,11 +37,7 @@ impl Debug for dyn Writer {
 ///
 /// If the `ENCORE_LOG_FORMAT` environment variable is set to `console` then
 /// the pretty console writer will be used to write logs to stderr, otherwise
-/// JSONL logs will be written to stderr.
-///
-/// For JSONL logs, if a tokio runtime is detected then the async writer
-/// will be used, otherwise a blocking writer will be used, resulting
-/// in blocking writes to stderr.
+/// JSONL logs will be written to stderr. Writes to stderr are blocking.
 pub fn default_writer(fields: &'static FieldConfig) -> Arc<dyn Writer> {
     // Check if the user has set the `ENCORE_LOG_FORMAT` environment variable to `console`.
     // if so we'll use the pretty console writer.
@@ -43,13 +49,106 @@ pub fn default_writer(fields: &'static FieldConfig) -> Arc<dyn Writer> {
         }
     }
 
-    // if tokio::runtime::Handle::try_current().is_ok() {
-    // If we're running in a tokio runtime we'll use the async writer.
-    // Arc::new(AsyncWriter::default())
-    // } else {
-    // Otherwise we'll use the blocking writer.
     Arc::new(BlockingWriter::default())
-    // }
+}
+
+/// Configures the log writer based on the logs providers in the runtime config.
+pub struct WriterConfig<'a> {
+    pub providers: Vec<pb::LogsProvider>,
+    pub fields: &'static FieldConfig,
+
+    /// The app name reported to syslog, unless the provider overrides it.
+    pub app_name: &'a str,
+
+    /// Attributes describing the deployment, added to logs exported over OTLP.
+    pub resource_attrs: Vec<OtlpAttr>,
+
+    pub http_client: reqwest::Client,
+    pub runtime: tokio::runtime::Handle,
+}
+
+impl WriterConfig<'_> {
+    /// Builds the writer, or returns None if no providers are configured
+    /// and the default writer should be used.
+    ///
+    /// Each provider gets a bounded buffer that is written out from a background
+    /// thread, so logging never blocks on a slow or unavailable destination.
+    pub fn build(self) -> anyhow::Result<Option<Arc<dyn Writer>>> {
+        let mut writers: Vec<Arc<dyn Writer>> = Vec::with_capacity(self.providers.len());
+        for provider in &self.providers {
+            let buffer_size = provider
+                .buffer_size
+                .map(|n| n as usize)
+                .unwrap_or(buffered::DEFAULT_BUFFER_SIZE);
+            let buffered =
+                |sink: Box<dyn Sink>| BufferedWriter::new(sink, self.fields, buffer_size);
+
+            let writer = match &provider.provider {
+                Some(Provider::Stderr(_)) => {
+                    buffered(Box::new(WriterSink(default_writer(self.fields))))
+                }
+                Some(Provider::File(cfg)) => buffered(Box::new(FileSink::new(cfg)?)),
+                Some(Provider::Otlp(cfg)) => buffered(Box::new(OtlpSink::new(
+                    cfg,
+                    self.fields,
+                    self.http_client.clone(),
+                    self.runtime.clone(),
+                    self.resource_attrs.clone(),
+                )?)),
+                Some(Provider::Syslog(cfg)) => buffered(Box::new(
+                    SyslogSink::new(cfg, self.app_name).context("unable to set up syslog")?,
+                )),
+                None => anyhow::bail!("logs provider {} has no provider set", provider.rid),
+            }
+            .with_context(|| format!("unable to start log writer for {}", provider.rid))?;
+            writers.push(Arc::new(writer));
+        }
+
+        Ok(match writers.len() {
+            0 => None,
+            1 => writers.pop(),
+            _ => Some(Arc::new(MultiWriter(writers))),
+        })
+    }
+}
+
+/// A sink that writes to another writer, to buffer writes to it.
+struct WriterSink(Arc<dyn Writer>);
+
+impl Sink for WriterSink {
+    fn name(&self) -> &'static str {
+        "stderr"
+    }
+
+    fn write(&mut self, entry: Entry) -> anyhow::Result<()> {
+        self.0.write(entry.level, &entry.values)
+    }
+}
+
+/// A writer that writes each entry to multiple writers.
+struct MultiWriter(Vec<Arc<dyn Writer>>);
+
+impl Writer for MultiWriter {
+    fn write(&self, level: log::Level, values: &BTreeMap<String, Value>) -> anyhow::Result<()> {
+        let mut result = Ok(());
+        for w in &self.0 {
+            // Keep writing to the other writers if one of them fails.
+            if let Err(err) = w.write(level, values) {
+                result = Err(err);
+            }
+        }
+        result
+    }
+
+    fn flush(&self) -> anyhow::Result<()> {
+        let mut result = Ok(());
+        for w in &self.0 {
+            if let Err(err) = w.flush() {
+                result = Err(err);
+            }
+        }
+        result
+    }
 }
 
 /// A log writer that synchronizes writes to stderr blocking
@@ -92,41 +191,3 @@ impl<W: Write + Sync + Send + 'static> Writer for BlockingWriter<W> {
         }
     }
 }
-
-pub struct AsyncWriter<W: AsyncWrite + Sync + Send + 'static> {
-    mu: Arc<tokio::sync::Mutex<Pin<Box<W>>>>,
-}
-
-impl<W: AsyncWrite + Sync + Send + 'static> AsyncWriter<W> {
-    pub fn new(w: W) -> Self {
-        Self {
-            mu: Arc::new(tokio::sync::Mutex::new(Box::pin(w))),
-        }
-    }
-}
-
-impl Default for AsyncWriter<tokio::io::Stderr> {
-    fn default() -> Self {
-        Self::new(tokio::io::stderr())
-    }
-}
-
-impl<W: AsyncWrite + Sync + Send + 'static> Writer for AsyncWriter<W> {
-    fn write(&self, _: log::Level, values: &BTreeMap<String, Value>) -> anyhow::Result<()> {
-        let mut buf = Vec::with_capacity(256);
-        serde_json::to_writer(&mut buf, values)
-            .map_err(std::io::Error::from)
-            .context("serde_writer")?;
-        buf.write_all(b"\n").context("new line")?;
-
-        let mu = self.mu.clone();
-        tokio::spawn(async move {
-            use tokio::io::AsyncWriteExt;
-
-            let mut w = mu.lock().await;
-            w.write_all(&buf).await
-        });
-
-        Ok(())
-    }
-}
@@ -0,0 +1,232 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use once_cell::sync::Lazy;
+
+use crate::encore::runtime::v1 as pb;
+use crate::secrets;
+
+mod prometheus;
+mod registry;
+mod text;
+
+pub use registry::{
+    Counter, Family, Gauge, Histogram, HistogramValue, Labels, MetricKind, Registry, Sample, Value,
+};
+
+/// The global registry that all runtime metrics are recorded in.
+static REGISTRY: Lazy<Registry> = Lazy::new(|| {
+    let reg = Registry::new();
+    reg.describe(
+        REQUESTS_TOTAL,
+        MetricKind::Counter,
+        "Number of API requests handled, by endpoint and status code.",
+    );
+    reg.describe(
+        REQUEST_DURATION,
+        MetricKind::Histogram,
+        "API request latency in seconds, by endpoint.",
+    );
+    reg.describe(
+        PUBSUB_MESSAGES_TOTAL,
+        MetricKind::Counter,
+        "Number of Pub/Sub messages processed, by subscription and status code.",
+    );
+    reg.describe(
+        PUBSUB_MESSAGE_DURATION,
+        MetricKind::Histogram,
+        "Pub/Sub message processing latency in seconds, by subscription.",
+    );
+    reg.describe(
+        DB_QUERIES_TOTAL,
+        MetricKind::Counter,
+        "Number of database queries executed, by database and status.",
+    );
+    reg.describe(
+        DB_QUERY_DURATION,
+        MetricKind::Histogram,
+        "Database query latency in seconds, by database.",
+    );
+    reg.describe(
+        LOG_ENTRIES_DROPPED_TOTAL,
+        MetricKind::Counter,
+        "Number of log entries dropped because the log buffer was full, by sink.",
+    );
+    reg
+});
+
+const REQUESTS_TOTAL: &str = "e_requests_total";
+const REQUEST_DURATION: &str = "e_request_duration_seconds";
+const PUBSUB_MESSAGES_TOTAL: &str = "e_pubsub_messages_total";
+const PUBSUB_MESSAGE_DURATION: &str = "e_pubsub_message_duration_seconds";
+const DB_QUERIES_TOTAL: &str = "e_sqldb_queries_total";
+const DB_QUERY_DURATION: &str = "e_sqldb_query_duration_seconds";
+const LOG_ENTRIES_DROPPED_TOTAL: &str = "e_log_entries_dropped_total";
+
+/// Returns the global metrics registry.
+pub fn registry() -> &'static Registry {
+    &REGISTRY
+}
+
+/// Records a completed API request.
+pub fn record_request(service: &str, endpoint: &str, code: &str, duration: Duration) {
+    let reg = registry();
+    reg.counter(
+        REQUESTS_TOTAL,
+        Labels::new([("service", service), ("endpoint", endpoint), ("code", code)]),
+    )
+    .increment();
+    reg.histogram(
+        REQUEST_DURATION,
+        Labels::new([("service", service), ("endpoint", endpoint)]),
+    )
+    .observe_duration(duration);
+}
+
+/// Records a processed Pub/Sub message.
+pub fn record_pubsub_message(topic: &str, subscription: &str, code: &str, duration: Duration) {
+    let reg = registry();
+    reg.counter(
+        PUBSUB_MESSAGES_TOTAL,
+        Labels::new([
+            ("topic", topic),
+            ("subscription", subscription),
+            ("code", code),
+        ]),
+    )
+    .increment();
+    reg.histogram(
+        PUBSUB_MESSAGE_DURATION,
+        Labels::new([("topic", topic), ("subscription", subscription)]),
+    )
+    .observe_duration(duration);
+}
+
+/// Records an executed database query.
+pub fn record_db_query(database: &str, ok: bool, duration: Duration) {
+    let reg = registry();
+    let status = if ok { "ok" } else { "error" };
+    reg.counter(
+        DB_QUERIES_TOTAL,
+        Labels::new([("database", database), ("status", status)]),
+    )
+    .increment();
+    reg.histogram(DB_QUERY_DURATION, Labels::new([("database", database)]))
+        .observe_duration(duration);
+}
+
+/// Records log entries dropped by a log sink.
+pub fn record_dropped_logs(sink: &str, count: u64) {
+    registry()
+        .counter(LOG_ENTRIES_DROPPED_TOTAL, Labels::new([("sink", sink)]))
+        .add(count as f64);
+}
+
+/// Exports a snapshot of metrics to an external system.
+trait Exporter: Send + Sync + 'static {
+    fn export(
+        &self,
+        families: Vec<Family>,
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send + '_>>;
+}
+
+/// The default interval between metric exports,
+/// used when the provider does not specify one.
+const DEFAULT_COLLECTION_INTERVAL: Duration = Duration::from_secs(60);
+
+pub struct ManagerConfig<'a> {
+    pub providers: Vec<pb::MetricsProvider>,
+    pub secrets: &'a secrets::Manager,
+    pub http_client: reqwest::Client,
+    pub runtime: tokio::runtime::Handle,
+}
+
+impl ManagerConfig<'_> {
+    pub fn build(self) -> anyhow::Result<Manager> {
+        let mut exporters = Vec::new();
+        for provider in self.providers {
+            let interval = provider
+                .collection_interval
+                .and_then(|d| Duration::try_from(d).ok())
+                .filter(|d| !d.is_zero())
+                .unwrap_or(DEFAULT_COLLECTION_INTERVAL);
+
+            use pb::metrics_provider::Provider;
+            let exporter: Arc<dyn Exporter> = match provider.provider {
+                Some(Provider::PromRemoteWrite(prom)) => {
+                    let url = prom
+                        .remote_write_url
+                        .context("prometheus remote write url not set")?;
+                    let url = self.secrets.load(url);
+                    let url = url
+                        .get()
+                        .context("unable to resolve prometheus remote write url")?;
+                    let url = std::str::from_utf8(url)
+                        .context("prometheus remote write url is not valid utf-8")?;
+                    let url =
+                        reqwest::Url::parse(url).context("invalid prometheus remote write url")?;
+                    Arc::new(prometheus::RemoteWrite::new(url, self.http_client.clone()))
+                }
+                Some(other) => {
+                    let kind = match other {
+                        Provider::EncoreCloud(_) => "encore cloud",
+                        Provider::Gcp(_) => "gcp cloud monitoring",
+                        Provider::Aws(_) => "aws cloudwatch",
+                        Provider::Datadog(_) => "datadog",
+                        Provider::PromRemoteWrite(_) => "prometheus remote write",
+                    };
+                    ::log::warn!(
+                        "metrics provider {} ({}) is not yet supported, skipping",
+                        provider.rid,
+                        kind
+                    );
+                    continue;
+                }
+                None => continue,
+            };
+
+            exporters.push((exporter, interval));
+        }
+
+        Ok(Manager {
+            exporters,
+            runtime: self.runtime,
+        })
+    }
+}
+
+pub struct Manager {
+    exporters: Vec<(Arc<dyn Exporter>, Duration)>,
+    runtime: tokio::runtime::Handle,
+}
+
+impl Manager {
+    /// Returns the registry metrics are recorded in.
+    pub fn registry(&self) -> &'static Registry {
+        registry()
+    }
+
+    /// Starts periodically exporting metrics to the configured providers.
+    pub fn start_exporting(&self) {
+        for (exporter, interval) in &self.exporters {
+            let exporter = exporter.clone();
+            let interval = *interval;
+            self.runtime.spawn(async move {
+                let mut ticker = tokio::time::interval(interval);
+                ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+                // The first tick completes immediately; skip it
+                // so we don't export an empty snapshot on startup.
+                ticker.tick().await;
+                loop {
+                    ticker.tick().await;
+                    if let Err(err) = exporter.export(registry().snapshot()).await {
+                        ::log::error!("unable to export metrics: {:?}", err);
+                    }
+                }
+            });
+        }
+    }
+}
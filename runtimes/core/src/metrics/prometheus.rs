@@ -0,0 +1,261 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::time::SystemTime;
+
+use anyhow::Context;
+use prost::Message;
+
+use crate::metrics::registry::{Family, Value};
+use crate::metrics::Exporter;
+
+/// Exports metrics to a Prometheus remote-write endpoint.
+///
+/// See https://prometheus.io/docs/concepts/remote_write_spec/ for the protocol.
+#[derive(Debug)]
+pub struct RemoteWrite {
+    url: reqwest::Url,
+    http_client: reqwest::Client,
+}
+
+impl RemoteWrite {
+    pub fn new(url: reqwest::Url, http_client: reqwest::Client) -> Self {
+        Self { url, http_client }
+    }
+
+    async fn write(&self, families: Vec<Family>) -> anyhow::Result<()> {
+        let req = write_request(&families, SystemTime::now());
+        if req.timeseries.is_empty() {
+            return Ok(());
+        }
+
+        let body = snap::raw::Encoder::new()
+            .compress_vec(&req.encode_to_vec())
+            .context("unable to compress write request")?;
+
+        let resp = self
+            .http_client
+            .post(self.url.clone())
+            .header(reqwest::header::CONTENT_TYPE, "application/x-protobuf")
+            .header(reqwest::header::CONTENT_ENCODING, "snappy")
+            .header("X-Prometheus-Remote-Write-Version", "0.1.0")
+            .body(body)
+            .send()
+            .await
+            .context("unable to send write request")?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            let body = resp.text().await.unwrap_or_default();
+            anyhow::bail!("remote write failed with status {}: {}", status, body);
+        }
+        Ok(())
+    }
+}
+
+impl Exporter for RemoteWrite {
+    fn export(
+        &self,
+        families: Vec<Family>,
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send + '_>> {
+        Box::pin(self.write(families))
+    }
+}
+
+/// Converts a metrics snapshot into a remote-write request,
+/// expanding histograms into their `_bucket`, `_sum` and `_count` series.
+fn write_request(families: &[Family], now: SystemTime) -> WriteRequest {
+    let timestamp = now
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64;
+
+    let mut timeseries = Vec::new();
+    let mut push =
+        |name: &str, labels: &[(&str, &str)], extra: Option<(&str, String)>, value: f64| {
+            let mut labels: Vec<Label> = labels
+                .iter()
+                .map(|(k, v)| Label {
+                    name: k.to_string(),
+                    value: v.to_string(),
+                })
+                .chain(extra.map(|(k, v)| Label {
+                    name: k.to_string(),
+                    value: v,
+                }))
+                .chain(std::iter::once(Label {
+                    name: "__name__".to_string(),
+                    value: name.to_string(),
+                }))
+                .collect();
+            // The spec requires labels to be sorted by name.
+            labels.sort_by(|a, b| a.name.cmp(&b.name));
+            timeseries.push(TimeSeries {
+                labels,
+                samples: vec![Sample { value, timestamp }],
+            });
+        };
+
+    for family in families {
+        for sample in &family.samples {
+            let labels: Vec<(&str, &str)> = sample.labels.iter().collect();
+            match &sample.value {
+                Value::Counter(v) | Value::Gauge(v) => push(&family.name, &labels, None, *v),
+                Value::Histogram(h) => {
+                    let bucket = format!("{}_bucket", family.name);
+                    for (bound, count) in &h.buckets {
+                        push(
+                            &bucket,
+                            &labels,
+                            Some(("le", bound.to_string())),
+                            *count as f64,
+                        );
+                    }
+                    push(
+                        &bucket,
+                        &labels,
+                        Some(("le", "+Inf".to_string())),
+                        h.count as f64,
+                    );
+                    push(&format!("{}_sum", family.name), &labels, None, h.sum);
+                    push(
+                        &format!("{}_count", family.name),
+                        &labels,
+                        None,
+                        h.count as f64,
+                    );
+                }
+            }
+        }
+    }
+
+    WriteRequest { timeseries }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct WriteRequest {
+    #[prost(message, repeated, tag = "1")]
+    timeseries: Vec<TimeSeries>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct TimeSeries {
+    #[prost(message, repeated, tag = "1")]
+    labels: Vec<Label>,
+    #[prost(message, repeated, tag = "2")]
+    samples: Vec<Sample>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct Label {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(string, tag = "2")]
+    value: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct Sample {
+    #[prost(double, tag = "1")]
+    value: f64,
+    #[prost(int64, tag = "2")]
+    timestamp: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{HeaderMap, StatusCode};
+
+    use super::*;
+    use crate::metrics::registry::{Labels, Registry};
+
+    fn label<'a>(ts: &'a TimeSeries, name: &str) -> Option<&'a str> {
+        ts.labels
+            .iter()
+            .find(|l| l.name == name)
+            .map(|l| l.value.as_str())
+    }
+
+    #[tokio::test]
+    async fn test_remote_write() {
+        // Run a local stand-in for a remote-write receiver.
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let app = axum::Router::new().route(
+            "/api/v1/write",
+            axum::routing::post(move |headers: HeaderMap, body: bytes::Bytes| {
+                let tx = tx.clone();
+                async move {
+                    tx.send((headers, body)).unwrap();
+                    StatusCode::NO_CONTENT
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let reg = Registry::new();
+        reg.counter("e_requests_total", Labels::new([("endpoint", "foo")]))
+            .add(3.0);
+        reg.histogram("e_request_duration_seconds", Labels::default())
+            .observe(0.02);
+
+        let url = format!("http://{}/api/v1/write", addr).parse().unwrap();
+        let exporter = RemoteWrite::new(url, reqwest::Client::new());
+        exporter.export(reg.snapshot()).await.unwrap();
+
+        let (headers, body) = rx.recv().await.unwrap();
+        assert_eq!(headers.get("content-encoding").unwrap(), "snappy");
+        assert_eq!(
+            headers.get("content-type").unwrap(),
+            "application/x-protobuf"
+        );
+
+        let body = snap::raw::Decoder::new().decompress_vec(&body).unwrap();
+        let req = WriteRequest::decode(body.as_slice()).unwrap();
+
+        let counter = req
+            .timeseries
+            .iter()
+            .find(|ts| label(ts, "__name__") == Some("e_requests_total"))
+            .unwrap();
+        assert_eq!(label(counter, "endpoint"), Some("foo"));
+        assert_eq!(counter.samples[0].value, 3.0);
+        assert_eq!(counter.labels[0].name, "__name__");
+
+        let inf = req
+            .timeseries
+            .iter()
+            .find(|ts| {
+                label(ts, "__name__") == Some("e_request_duration_seconds_bucket")
+                    && label(ts, "le") == Some("+Inf")
+            })
+            .unwrap();
+        assert_eq!(inf.samples[0].value, 1.0);
+
+        let count = req
+            .timeseries
+            .iter()
+            .find(|ts| label(ts, "__name__") == Some("e_request_duration_seconds_count"))
+            .unwrap();
+        assert_eq!(count.samples[0].value, 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_remote_write_error() {
+        let app = axum::Router::new().route(
+            "/api/v1/write",
+            axum::routing::post(|| async { (StatusCode::BAD_REQUEST, "out of order sample") }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let reg = Registry::new();
+        reg.gauge("g", Labels::default()).set(1.0);
+
+        let url = format!("http://{}/api/v1/write", addr).parse().unwrap();
+        let exporter = RemoteWrite::new(url, reqwest::Client::new());
+        let err = exporter.export(reg.snapshot()).await.unwrap_err();
+        assert!(err.to_string().contains("out of order sample"));
+    }
+}
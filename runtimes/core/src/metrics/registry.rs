@@ -0,0 +1,398 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// The default histogram buckets, in seconds, used for latency histograms.
+pub const DEFAULT_LATENCY_BUCKETS: &[f64] = &[
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0,
+];
+
+/// A set of label key-value pairs, kept sorted by key.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Labels(Vec<(String, String)>);
+
+impl Labels {
+    pub fn new<K, V, I>(labels: I) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let mut labels: Vec<(String, String)> = labels
+            .into_iter()
+            .map(|(k, v)| (k.into(), v.into()))
+            .collect();
+        labels.sort();
+        labels.dedup_by(|a, b| a.0 == b.0);
+        Self(labels)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// A float stored as bits in an atomic, to allow lock-free updates.
+#[derive(Debug, Default)]
+struct AtomicF64(AtomicU64);
+
+impl AtomicF64 {
+    fn load(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    fn store(&self, val: f64) {
+        self.0.store(val.to_bits(), Ordering::Relaxed);
+    }
+
+    fn add(&self, delta: f64) {
+        let mut cur = self.0.load(Ordering::Relaxed);
+        loop {
+            let new = (f64::from_bits(cur) + delta).to_bits();
+            match self
+                .0
+                .compare_exchange_weak(cur, new, Ordering::Relaxed, Ordering::Relaxed)
+            {
+                Ok(_) => return,
+                Err(actual) => cur = actual,
+            }
+        }
+    }
+}
+
+/// A monotonically increasing counter.
+#[derive(Debug, Clone)]
+pub struct Counter(Arc<AtomicF64>);
+
+impl Counter {
+    pub fn increment(&self) {
+        self.0.add(1.0);
+    }
+
+    /// Adds the given value to the counter.
+    /// Negative values are ignored, since counters only ever increase.
+    pub fn add(&self, value: f64) {
+        if value > 0.0 {
+            self.0.add(value);
+        }
+    }
+
+    pub fn get(&self) -> f64 {
+        self.0.load()
+    }
+}
+
+/// A value that can go up and down.
+#[derive(Debug, Clone)]
+pub struct Gauge(Arc<AtomicF64>);
+
+impl Gauge {
+    pub fn set(&self, value: f64) {
+        self.0.store(value);
+    }
+
+    pub fn add(&self, delta: f64) {
+        self.0.add(delta);
+    }
+
+    pub fn get(&self) -> f64 {
+        self.0.load()
+    }
+}
+
+#[derive(Debug)]
+struct HistogramData {
+    bounds: &'static [f64],
+    /// Per-bucket counts (non-cumulative), with a trailing +Inf bucket.
+    counts: Vec<AtomicU64>,
+    sum: AtomicF64,
+    count: AtomicU64,
+}
+
+/// A histogram tracking the distribution of observed values.
+#[derive(Debug, Clone)]
+pub struct Histogram(Arc<HistogramData>);
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        let counts = (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect();
+        Self(Arc::new(HistogramData {
+            bounds,
+            counts,
+            sum: AtomicF64::default(),
+            count: AtomicU64::new(0),
+        }))
+    }
+
+    pub fn observe(&self, value: f64) {
+        let idx = self
+            .0
+            .bounds
+            .iter()
+            .position(|b| value <= *b)
+            .unwrap_or(self.0.bounds.len());
+        self.0.counts[idx].fetch_add(1, Ordering::Relaxed);
+        self.0.sum.add(value);
+        self.0.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn observe_duration(&self, dur: Duration) {
+        self.observe(dur.as_secs_f64());
+    }
+
+    fn snapshot(&self) -> HistogramValue {
+        let mut cumulative = 0;
+        let buckets = self
+            .0
+            .bounds
+            .iter()
+            .enumerate()
+            .map(|(i, bound)| {
+                cumulative += self.0.counts[i].load(Ordering::Relaxed);
+                (*bound, cumulative)
+            })
+            .collect();
+        HistogramValue {
+            buckets,
+            sum: self.0.sum.load(),
+            count: self.0.count.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricKind {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+/// A point-in-time view of a histogram.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistogramValue {
+    /// Cumulative counts per upper bound, excluding the +Inf bucket
+    /// (which is always equal to `count`).
+    pub buckets: Vec<(f64, u64)>,
+    pub sum: f64,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Counter(f64),
+    Gauge(f64),
+    Histogram(HistogramValue),
+}
+
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub labels: Labels,
+    pub value: Value,
+}
+
+/// All samples for a single metric name.
+#[derive(Debug, Clone)]
+pub struct Family {
+    pub name: String,
+    pub help: String,
+    pub kind: MetricKind,
+    pub samples: Vec<Sample>,
+}
+
+#[derive(Debug)]
+enum Entry {
+    Counter(Counter),
+    Gauge(Gauge),
+    Histogram(Histogram),
+}
+
+#[derive(Debug)]
+struct Metric {
+    help: String,
+    kind: MetricKind,
+    series: HashMap<Labels, Entry>,
+}
+
+/// Keeps track of all metrics recorded by the runtime.
+#[derive(Debug, Default)]
+pub struct Registry {
+    metrics: RwLock<BTreeMap<String, Metric>>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the help text for a metric, used when exporting it.
+    pub fn describe(&self, name: &str, kind: MetricKind, help: &str) {
+        let mut metrics = self.metrics.write().unwrap();
+        let metric = metrics.entry(name.to_string()).or_insert_with(|| Metric {
+            help: String::new(),
+            kind,
+            series: HashMap::new(),
+        });
+        metric.help = help.to_string();
+    }
+
+    pub fn counter(&self, name: &str, labels: Labels) -> Counter {
+        self.get_or_insert(name, MetricKind::Counter, labels, |e| match e {
+            Entry::Counter(c) => Some(c.clone()),
+            _ => None,
+        })
+        .unwrap_or_else(|| Counter(Default::default()))
+    }
+
+    pub fn gauge(&self, name: &str, labels: Labels) -> Gauge {
+        self.get_or_insert(name, MetricKind::Gauge, labels, |e| match e {
+            Entry::Gauge(g) => Some(g.clone()),
+            _ => None,
+        })
+        .unwrap_or_else(|| Gauge(Default::default()))
+    }
+
+    pub fn histogram(&self, name: &str, labels: Labels) -> Histogram {
+        self.get_or_insert(name, MetricKind::Histogram, labels, |e| match e {
+            Entry::Histogram(h) => Some(h.clone()),
+            _ => None,
+        })
+        .unwrap_or_else(|| Histogram::new(DEFAULT_LATENCY_BUCKETS))
+    }
+
+    /// Looks up the series for the given name and labels, creating it if necessary.
+    /// Returns None (and logs an error) if the name is already in use by
+    /// a metric of a different kind.
+    fn get_or_insert<T>(
+        &self,
+        name: &str,
+        kind: MetricKind,
+        labels: Labels,
+        extract: impl Fn(&Entry) -> Option<T>,
+    ) -> Option<T> {
+        {
+            let metrics = self.metrics.read().unwrap();
+            if let Some(metric) = metrics.get(name) {
+                if metric.kind != kind {
+                    log::error!(
+                        "metric {} is already registered as a {:?}, not a {:?}",
+                        name,
+                        metric.kind,
+                        kind
+                    );
+                    return None;
+                }
+                if let Some(entry) = metric.series.get(&labels) {
+                    return extract(entry);
+                }
+            }
+        }
+
+        let mut metrics = self.metrics.write().unwrap();
+        let metric = metrics.entry(name.to_string()).or_insert_with(|| Metric {
+            help: String::new(),
+            kind,
+            series: HashMap::new(),
+        });
+        if metric.kind != kind {
+            return None;
+        }
+        let entry = metric.series.entry(labels).or_insert_with(|| match kind {
+            MetricKind::Counter => Entry::Counter(Counter(Default::default())),
+            MetricKind::Gauge => Entry::Gauge(Gauge(Default::default())),
+            MetricKind::Histogram => Entry::Histogram(Histogram::new(DEFAULT_LATENCY_BUCKETS)),
+        });
+        extract(entry)
+    }
+
+    /// Returns a snapshot of all metrics, sorted by name and labels.
+    pub fn snapshot(&self) -> Vec<Family> {
+        let metrics = self.metrics.read().unwrap();
+        metrics
+            .iter()
+            .filter(|(_, m)| !m.series.is_empty())
+            .map(|(name, m)| {
+                let mut samples: Vec<Sample> = m
+                    .series
+                    .iter()
+                    .map(|(labels, entry)| Sample {
+                        labels: labels.clone(),
+                        value: match entry {
+                            Entry::Counter(c) => Value::Counter(c.get()),
+                            Entry::Gauge(g) => Value::Gauge(g.get()),
+                            Entry::Histogram(h) => Value::Histogram(h.snapshot()),
+                        },
+                    })
+                    .collect();
+                samples.sort_by(|a, b| a.labels.cmp(&b.labels));
+                Family {
+                    name: name.clone(),
+                    help: m.help.clone(),
+                    kind: m.kind,
+                    samples,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counter_and_gauge() {
+        let reg = Registry::new();
+        let c = reg.counter("requests", Labels::new([("code", "ok")]));
+        c.increment();
+        c.add(2.0);
+        c.add(-5.0);
+        reg.counter("requests", Labels::new([("code", "ok")]))
+            .increment();
+
+        let g = reg.gauge("in_flight", Labels::default());
+        g.set(3.0);
+        g.add(-1.0);
+
+        let snap = reg.snapshot();
+        assert_eq!(snap.len(), 2);
+        assert_eq!(snap[0].name, "in_flight");
+        assert_eq!(snap[0].samples[0].value, Value::Gauge(2.0));
+        assert_eq!(snap[1].name, "requests");
+        assert_eq!(snap[1].samples[0].value, Value::Counter(4.0));
+    }
+
+    #[test]
+    fn test_histogram() {
+        let reg = Registry::new();
+        let h = reg.histogram("latency", Labels::default());
+        h.observe(0.003);
+        h.observe(0.2);
+        h.observe(100.0);
+
+        let snap = reg.snapshot();
+        let Value::Histogram(val) = &snap[0].samples[0].value else {
+            panic!("expected histogram");
+        };
+        assert_eq!(val.count, 3);
+        assert_eq!(val.buckets[0], (0.001, 0));
+        assert_eq!(val.buckets[2], (0.005, 1));
+        assert_eq!(val.buckets[7], (0.25, 2));
+        assert_eq!(val.buckets.last().unwrap().1, 2);
+    }
+
+    #[test]
+    fn test_kind_mismatch() {
+        let reg = Registry::new();
+        reg.counter("foo", Labels::default()).increment();
+        // Registering the same name as a gauge returns a detached gauge.
+        reg.gauge("foo", Labels::default()).set(10.0);
+        let snap = reg.snapshot();
+        assert_eq!(snap.len(), 1);
+        assert_eq!(snap[0].kind, MetricKind::Counter);
+    }
+}
@@ -0,0 +1,137 @@
+use std::fmt::Write;
+
+use crate::metrics::registry::{Family, Labels, MetricKind, Value};
+
+/// The content type of the Prometheus text exposition format.
+pub const TEXT_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Renders the metrics in the Prometheus text exposition format.
+///
+/// See https://prometheus.io/docs/instrumenting/exposition_formats/ for the format.
+pub fn render_text(families: &[Family]) -> String {
+    let mut out = String::new();
+    for family in families {
+        let name = &family.name;
+        if !family.help.is_empty() {
+            _ = writeln!(out, "# HELP {} {}", name, escape_help(&family.help));
+        }
+        let kind = match family.kind {
+            MetricKind::Counter => "counter",
+            MetricKind::Gauge => "gauge",
+            MetricKind::Histogram => "histogram",
+        };
+        _ = writeln!(out, "# TYPE {} {}", name, kind);
+
+        for sample in &family.samples {
+            match &sample.value {
+                Value::Counter(v) | Value::Gauge(v) => {
+                    write_sample(&mut out, name, &sample.labels, None, *v);
+                }
+                Value::Histogram(h) => {
+                    let bucket = format!("{}_bucket", name);
+                    for (bound, count) in &h.buckets {
+                        let le = format_float(*bound);
+                        write_sample(&mut out, &bucket, &sample.labels, Some(&le), *count as f64);
+                    }
+                    write_sample(
+                        &mut out,
+                        &bucket,
+                        &sample.labels,
+                        Some("+Inf"),
+                        h.count as f64,
+                    );
+                    write_sample(
+                        &mut out,
+                        &format!("{}_sum", name),
+                        &sample.labels,
+                        None,
+                        h.sum,
+                    );
+                    write_sample(
+                        &mut out,
+                        &format!("{}_count", name),
+                        &sample.labels,
+                        None,
+                        h.count as f64,
+                    );
+                }
+            }
+        }
+    }
+    out
+}
+
+fn write_sample(out: &mut String, name: &str, labels: &Labels, le: Option<&str>, value: f64) {
+    out.push_str(name);
+    if !labels.is_empty() || le.is_some() {
+        out.push('{');
+        let mut first = true;
+        for (k, v) in labels.iter().chain(le.map(|le| ("le", le))) {
+            if !first {
+                out.push(',');
+            }
+            first = false;
+            _ = write!(out, "{}=\"{}\"", k, escape_label_value(v));
+        }
+        out.push('}');
+    }
+    _ = writeln!(out, " {}", format_float(value));
+}
+
+fn format_float(v: f64) -> String {
+    if v.is_nan() {
+        "NaN".to_string()
+    } else if v.is_infinite() {
+        if v > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        v.to_string()
+    }
+}
+
+fn escape_help(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn escape_label_value(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::registry::Registry;
+
+    #[test]
+    fn test_render_text() {
+        let reg = Registry::new();
+        reg.describe("requests_total", MetricKind::Counter, "Total requests.");
+        reg.counter(
+            "requests_total",
+            Labels::new([("endpoint", "foo"), ("code", "ok")]),
+        )
+        .add(2.0);
+        reg.gauge("queue_depth", Labels::new([("queue", "a\"b")]))
+            .set(1.5);
+        reg.histogram("latency", Labels::default()).observe(0.003);
+
+        let text = render_text(&reg.snapshot());
+        let lines: Vec<&str> = text.lines().collect();
+
+        // Families are sorted by name.
+        assert_eq!(lines[0], "# TYPE latency histogram");
+        assert_eq!(lines[1], "latency_bucket{le=\"0.001\"} 0");
+        assert_eq!(lines[3], "latency_bucket{le=\"0.005\"} 1");
+        assert!(lines.contains(&"latency_bucket{le=\"+Inf\"} 1"));
+        assert!(lines.contains(&"latency_sum 0.003"));
+        assert!(lines.contains(&"latency_count 1"));
+
+        assert!(lines.contains(&"# TYPE queue_depth gauge"));
+        assert!(lines.contains(&"queue_depth{queue=\"a\\\"b\"} 1.5"));
+
+        assert!(lines.contains(&"# HELP requests_total Total requests."));
+        assert!(lines.contains(&"# TYPE requests_total counter"));
+        assert!(lines.contains(&"requests_total{code=\"ok\",endpoint=\"foo\"} 2"));
+    }
+}
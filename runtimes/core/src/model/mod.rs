@@ -147,6 +147,9 @@ pub struct Request {
     pub start: Instant,
     pub start_time: SystemTime,
 
+    /// The deadline by which the request must complete, if any.
+    pub deadline: Option<Instant>,
+
     /// Type-specific data.
     pub data: RequestData,
 }
@@ -171,6 +174,15 @@ impl Request {
         }
     }
 
+    pub fn auth_user_id(&self) -> Option<&str> {
+        match &self.data {
+            RequestData::RPC(data) => data.auth_user_id.as_deref(),
+            RequestData::Stream(data) => data.auth_user_id.as_deref(),
+            RequestData::Auth(_) => None,
+            RequestData::PubSub(_) => None,
+        }
+    }
+
     pub fn take_raw_body(&self) -> Option<axum::body::Body> {
         if let RequestData::RPC(data) = &self.data {
             if let Some(data) = data.parsed_payload.as_ref() {
@@ -285,6 +297,28 @@ pub struct PubSubRequestData {
     pub attempt: u32,
     pub payload: Vec<u8>,
     pub parsed_payload: Option<PValues>,
+
+    /// Set if the message was forwarded to a dead letter topic
+    /// after exhausting the retries of another subscription.
+    pub dead_letter: Option<DeadLetterData>,
+}
+
+/// Describes why a message was forwarded to a dead letter topic.
+#[derive(Debug, Clone)]
+pub struct DeadLetterData {
+    /// The topic and subscription the message failed to be processed by.
+    pub topic: EncoreName,
+    pub subscription: EncoreName,
+
+    /// The id of the original message.
+    pub message_id: String,
+
+    /// The number of delivery attempts made before giving up.
+    pub attempts: u32,
+
+    /// The error returned by the final attempt.
+    pub error_code: String,
+    pub error_message: String,
 }
 
 #[derive(Debug)]
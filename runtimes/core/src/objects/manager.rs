@@ -4,12 +4,15 @@ use std::sync::{Arc, RwLock};
 use crate::encore::parser::meta::v1 as meta;
 use crate::encore::runtime::v1 as pb;
 use crate::names::EncoreName;
-use crate::objects::{gcs, noop, s3, BucketImpl, ClusterImpl};
-use crate::secrets;
+use crate::objects::{gcs, noop, s3, BucketImpl, ClusterImpl, ExistsOptions};
 use crate::trace::Tracer;
+use crate::{health, secrets};
 
 use super::Bucket;
 
+/// The key of the object used to check that buckets are reachable.
+const READINESS_PROBE_KEY: &str = "__encore_readiness_probe";
+
 pub struct Manager {
     tracer: Tracer,
     bucket_cfg: HashMap<EncoreName, (Arc<dyn ClusterImpl>, pb::Bucket)>,
@@ -41,6 +44,25 @@ impl Manager {
         })
     }
 
+    /// Returns readiness checks that verify each bucket is reachable.
+    pub fn readiness_checks(&self) -> Vec<health::Check> {
+        self.bucket_cfg
+            .keys()
+            .filter_map(|name| {
+                let bkt = self.bucket_impl(name.clone())?;
+                Some(health::Check::new(format!("bucket.{}", name), move || {
+                    // Check for an object that's not expected to exist,
+                    // which verifies the bucket exists and is accessible.
+                    let obj = bkt.clone().object(READINESS_PROBE_KEY.to_string());
+                    Box::pin(async move {
+                        obj.exists(ExistsOptions::default()).await?;
+                        Ok(())
+                    })
+                }))
+            })
+            .collect()
+    }
+
     fn bucket_impl(&self, name: EncoreName) -> Option<Arc<dyn BucketImpl>> {
         if let Some(bkt) = self.buckets.read().unwrap().get(&name) {
             return Some(bkt.clone());
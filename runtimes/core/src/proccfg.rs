@@ -61,11 +61,19 @@ impl ProcessConfig {
         // Iterate through service_ports and add service_discovery entries
         for (service_name, port) in &self.local_service_ports {
             let base_url = format!("http://127.0.0.1:{}", port);
+            // Keep the call policies configured for the service, if any.
+            // The service is hosted locally, so there are no other instances to balance across.
+            let existing = svc_discovery
+                .services
+                .remove(service_name)
+                .unwrap_or_default();
             svc_discovery.services.insert(
                 service_name.clone(),
                 runtimepb::service_discovery::Location {
                     base_url: base_url.clone(),
                     auth_methods: deployment.auth_methods.clone(),
+                    additional_base_urls: Vec::new(),
+                    ..existing
                 },
             );
         }
@@ -0,0 +1,175 @@
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use anyhow::Context;
+use azservicebus::core::BasicRetryPolicy;
+use azservicebus::{
+    ServiceBusClient, ServiceBusClientOptions, ServiceBusReceiver, ServiceBusReceiverOptions,
+    ServiceBusSender, ServiceBusSenderOptions,
+};
+use tokio::sync::Mutex;
+
+use crate::encore::parser::meta::v1 as meta;
+use crate::encore::runtime::v1 as pb;
+use crate::pubsub;
+use crate::pubsub::azure::sub::Subscription;
+use crate::pubsub::azure::topic::Topic;
+use crate::secrets;
+
+mod sub;
+mod topic;
+
+#[derive(Debug)]
+pub struct Cluster {
+    client: Arc<LazyClient>,
+
+    /// The cloud names of the topics that use an ordering attribute.
+    /// Their messages are published with a session id, and their
+    /// subscriptions receive messages one session at a time.
+    ordered_topics: HashSet<String>,
+}
+
+impl Cluster {
+    pub fn new(
+        cfg: &pb::pub_sub_cluster::AzureServiceBus,
+        topics: &[pb::PubSubTopic],
+        connection_string: Option<secrets::Secret>,
+    ) -> anyhow::Result<Self> {
+        // Authenticating with an Azure identity isn't supported yet.
+        let Some(connection_string) = connection_string else {
+            anyhow::bail!(
+                "no connection string configured for azure service bus namespace {}",
+                cfg.namespace
+            );
+        };
+
+        let ordered_topics = topics
+            .iter()
+            .filter(|t| t.ordering_attr.is_some())
+            .map(|t| t.cloud_name.clone())
+            .collect();
+
+        Ok(Self {
+            client: Arc::new(LazyClient::new(cfg.namespace.clone(), connection_string)),
+            ordered_topics,
+        })
+    }
+}
+
+impl pubsub::Cluster for Cluster {
+    fn topic(
+        &self,
+        cfg: &pb::PubSubTopic,
+        _publisher_id: xid::Id,
+    ) -> Arc<dyn pubsub::Topic + 'static> {
+        Arc::new(Topic::new(self.client.clone(), cfg))
+    }
+
+    fn subscription(
+        &self,
+        cfg: &pb::PubSubSubscription,
+        meta: &meta::pub_sub_topic::Subscription,
+    ) -> Arc<dyn pubsub::Subscription + 'static> {
+        let sessions = self.ordered_topics.contains(&cfg.topic_cloud_name);
+        Arc::new(Subscription::new(self.client.clone(), cfg, meta, sessions))
+    }
+
+    fn check_topic(
+        &self,
+        cfg: &pb::PubSubTopic,
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send + 'static>> {
+        let client = self.client.clone();
+        let topic = cfg.cloud_name.clone();
+        Box::pin(async move {
+            let sender = client.sender(&topic).await?;
+            _ = sender.dispose().await;
+            Ok(())
+        })
+    }
+}
+
+/// A Service Bus client that connects on first use.
+struct LazyClient {
+    namespace: String,
+    connection_string: secrets::Secret,
+    cell: tokio::sync::OnceCell<Mutex<ServiceBusClient<BasicRetryPolicy>>>,
+}
+
+impl Debug for LazyClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LazyClient")
+            .field("namespace", &self.namespace)
+            .finish()
+    }
+}
+
+impl LazyClient {
+    fn new(namespace: String, connection_string: secrets::Secret) -> Self {
+        Self {
+            namespace,
+            connection_string,
+            cell: tokio::sync::OnceCell::new(),
+        }
+    }
+
+    async fn get(&self) -> anyhow::Result<&Mutex<ServiceBusClient<BasicRetryPolicy>>> {
+        self.cell
+            .get_or_try_init(|| async { self.connect().await.map(Mutex::new) })
+            .await
+    }
+
+    /// Opens a new connection to the namespace, separate from the shared one.
+    async fn connect(&self) -> anyhow::Result<ServiceBusClient<BasicRetryPolicy>> {
+        let conn = self
+            .connection_string
+            .get()
+            .context("unable to resolve azure service bus connection string")?;
+        let conn = std::str::from_utf8(conn)
+            .context("azure service bus connection string is not valid utf-8")?;
+
+        ServiceBusClient::new_from_connection_string(conn, ServiceBusClientOptions::default())
+            .await
+            .with_context(|| {
+                format!(
+                    "unable to connect to azure service bus namespace {}",
+                    self.namespace
+                )
+            })
+    }
+
+    async fn sender(&self, topic: &str) -> anyhow::Result<ServiceBusSender> {
+        let client = self.get().await?;
+        client
+            .lock()
+            .await
+            .create_sender(topic, ServiceBusSenderOptions::default())
+            .await
+            .with_context(|| format!("unable to create sender for topic {}", topic))
+    }
+
+    async fn receiver(
+        &self,
+        topic: &str,
+        subscription: &str,
+    ) -> anyhow::Result<ServiceBusReceiver> {
+        let client = self.get().await?;
+        client
+            .lock()
+            .await
+            .create_receiver_for_subscription(
+                topic,
+                subscription,
+                ServiceBusReceiverOptions::default(),
+            )
+            .await
+            .with_context(|| {
+                format!(
+                    "unable to create receiver for subscription {}",
+                    subscription
+                )
+            })
+    }
+}
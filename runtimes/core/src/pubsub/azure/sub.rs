@@ -0,0 +1,531 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use azservicebus::core::BasicRetryPolicy;
+use azservicebus::{ServiceBusClient, ServiceBusReceivedMessage, ServiceBusSessionReceiverOptions};
+use fe2o3_amqp_types::messaging::ApplicationProperties;
+use fe2o3_amqp_types::primitives::SimpleValue;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use crate::api::APIResult;
+use crate::encore::parser::meta::v1 as meta;
+use crate::encore::runtime::v1 as pb;
+use crate::pubsub::azure::LazyClient;
+use crate::pubsub::manager::SubHandler;
+use crate::pubsub::{self};
+
+/// How long to wait for messages before checking for shutdown and settling processed messages.
+const RECEIVE_WAIT: Duration = Duration::from_secs(2);
+
+/// How long a session may go without messages before it's released,
+/// allowing other sessions to be processed.
+const SESSION_IDLE: Duration = Duration::from_secs(5);
+
+/// How long to wait before retrying after the connection fails.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+const DEFAULT_MAX_CONCURRENCY: usize = 100;
+
+/// The default number of sessions to process concurrently for ordered topics.
+const DEFAULT_MAX_SESSIONS: usize = 10;
+
+#[derive(Debug)]
+pub struct Subscription {
+    client: Arc<LazyClient>,
+    topic: String,
+    subscription: String,
+
+    /// Whether the topic is ordered, in which case messages are received
+    /// one session at a time, in order.
+    sessions: bool,
+    max_concurrency: usize,
+    retry: RetryPolicy,
+}
+
+impl Subscription {
+    pub(super) fn new(
+        client: Arc<LazyClient>,
+        cfg: &pb::PubSubSubscription,
+        meta: &meta::pub_sub_topic::Subscription,
+        sessions: bool,
+    ) -> Self {
+        let default_concurrency = if sessions {
+            DEFAULT_MAX_SESSIONS
+        } else {
+            DEFAULT_MAX_CONCURRENCY
+        };
+        let max_concurrency = meta
+            .max_concurrency
+            .map_or(default_concurrency, |v| v.max(1) as usize);
+
+        Self {
+            client,
+            topic: cfg.topic_cloud_name.clone(),
+            subscription: cfg.subscription_cloud_name.clone(),
+            sessions,
+            max_concurrency,
+            retry: RetryPolicy::new(meta),
+        }
+    }
+}
+
+impl pubsub::Subscription for Subscription {
+    fn subscribe(
+        &self,
+        handler: Arc<SubHandler>,
+    ) -> Pin<Box<dyn Future<Output = APIResult<()>> + Send + 'static>> {
+        let worker = Arc::new(Worker {
+            client: self.client.clone(),
+            topic: self.topic.clone(),
+            subscription: self.subscription.clone(),
+            max_concurrency: self.max_concurrency,
+            retry: self.retry.clone(),
+            handler,
+        });
+        let sessions = self.sessions;
+
+        Box::pin(async move {
+            // Stop receiving new messages when the runtime shuts down.
+            let stop = worker.handler.shutdown().initiated();
+            if sessions {
+                worker.receive_sessions(stop).await;
+            } else {
+                loop {
+                    match worker.receive(&stop).await {
+                        Ok(()) => break,
+                        Err(_) if stop.is_cancelled() => break,
+                        Err(err) => {
+                            log::warn!(
+                                "azure service bus subscription {} failed, retrying: {:?}",
+                                worker.subscription,
+                                err
+                            );
+                            tokio::select! {
+                                _ = tokio::time::sleep(RECONNECT_DELAY) => {}
+                                _ = stop.cancelled() => break,
+                            }
+                        }
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+struct Worker {
+    client: Arc<LazyClient>,
+    topic: String,
+    subscription: String,
+    max_concurrency: usize,
+    retry: RetryPolicy,
+    handler: Arc<SubHandler>,
+}
+
+/// How to settle a processed message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    /// Remove the message from the subscription.
+    Complete,
+    /// Release the lock on the message so it's delivered again.
+    Abandon,
+}
+
+impl Worker {
+    /// Receives messages until `stop` is canceled, processing up to `max_concurrency`
+    /// at a time. Messages must be settled using the receiver they were received with,
+    /// so processed messages are sent back to this loop to be settled.
+    async fn receive(self: &Arc<Self>, stop: &CancellationToken) -> Result<()> {
+        let mut receiver = self
+            .client
+            .receiver(&self.topic, &self.subscription)
+            .await?;
+        let (done_tx, mut done_rx) = mpsc::unbounded_channel();
+        let mut in_flight = 0;
+
+        loop {
+            // Settle the messages that have finished processing.
+            while let Ok((msg, outcome)) = done_rx.try_recv() {
+                in_flight -= 1;
+                settle(&mut receiver, &msg, outcome).await;
+            }
+
+            // Once we're shutting down, or at capacity, wait for in-flight messages.
+            if stop.is_cancelled() || in_flight >= self.max_concurrency {
+                if stop.is_cancelled() && in_flight == 0 {
+                    _ = receiver.dispose().await;
+                    return Ok(());
+                }
+                tokio::select! {
+                    Some((msg, outcome)) = done_rx.recv() => {
+                        in_flight -= 1;
+                        settle(&mut receiver, &msg, outcome).await;
+                    }
+                    _ = stop.cancelled(), if !stop.is_cancelled() => {}
+                }
+                continue;
+            }
+
+            // Messages received by a canceled receive are redelivered once their lock expires.
+            let max_messages = (self.max_concurrency - in_flight) as u32;
+            let messages = tokio::select! {
+                res = receiver.receive_messages_with_max_wait_time(max_messages, Some(RECEIVE_WAIT)) => {
+                    res.context("unable to receive messages")?
+                }
+                _ = stop.cancelled() => continue,
+            };
+
+            for msg in messages {
+                in_flight += 1;
+                let worker = self.clone();
+                let done_tx = done_tx.clone();
+                tokio::spawn(async move {
+                    let outcome = worker.process(&msg).await;
+                    _ = done_tx.send((msg, outcome));
+                });
+            }
+        }
+    }
+
+    /// Receives messages from `max_concurrency` sessions at a time until `stop` is canceled.
+    /// The messages of each session are processed one at a time, in order.
+    async fn receive_sessions(self: &Arc<Self>, stop: CancellationToken) {
+        let mut workers = tokio::task::JoinSet::new();
+        for _ in 0..self.max_concurrency {
+            let worker = self.clone();
+            let stop = stop.clone();
+            workers.spawn(async move {
+                // Accepting a session waits until one is available, so each worker uses
+                // its own connection to avoid holding up the others and publishing.
+                let Some(mut client) = worker.connect(&stop).await else {
+                    return;
+                };
+
+                while !stop.is_cancelled() {
+                    let accept = client.accept_next_session_for_subscription(
+                        &worker.topic,
+                        &worker.subscription,
+                        ServiceBusSessionReceiverOptions::default(),
+                    );
+                    let receiver = tokio::select! {
+                        res = accept => res.context("unable to accept session"),
+                        _ = stop.cancelled() => break,
+                    };
+
+                    match receiver {
+                        Ok(mut receiver) => {
+                            worker.receive_session(&mut receiver, &stop).await;
+                            _ = receiver.dispose().await;
+                        }
+                        Err(err) => {
+                            // Accepting times out when there are no sessions with messages.
+                            log::debug!(
+                                "no session accepted for subscription {}: {:?}",
+                                worker.subscription,
+                                err
+                            );
+                            tokio::select! {
+                                _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+                                _ = stop.cancelled() => break,
+                            }
+                        }
+                    }
+                }
+                _ = client.dispose().await;
+            });
+        }
+        while workers.join_next().await.is_some() {}
+    }
+
+    /// Opens a new connection, retrying until it succeeds or `stop` is canceled.
+    async fn connect(
+        &self,
+        stop: &CancellationToken,
+    ) -> Option<ServiceBusClient<BasicRetryPolicy>> {
+        loop {
+            match self.client.connect().await {
+                Ok(client) => return Some(client),
+                Err(err) => {
+                    log::warn!(
+                        "azure service bus subscription {} failed, retrying: {:?}",
+                        self.subscription,
+                        err
+                    );
+                    tokio::select! {
+                        _ = tokio::time::sleep(RECONNECT_DELAY) => {}
+                        _ = stop.cancelled() => return None,
+                    }
+                }
+            }
+        }
+    }
+
+    /// Processes the messages of a session in order, until it's idle or `stop` is canceled.
+    async fn receive_session(
+        &self,
+        receiver: &mut azservicebus::ServiceBusSessionReceiver,
+        stop: &CancellationToken,
+    ) {
+        loop {
+            let messages = tokio::select! {
+                res = receiver.receive_messages_with_max_wait_time(1, Some(SESSION_IDLE)) => res,
+                _ = stop.cancelled() => return,
+            };
+            let messages = match messages {
+                Ok(messages) if messages.is_empty() => return,
+                Ok(messages) => messages,
+                Err(err) => {
+                    log::warn!(
+                        "unable to receive messages for subscription {}: {:?}",
+                        self.subscription,
+                        err
+                    );
+                    return;
+                }
+            };
+
+            for msg in messages {
+                let outcome = self.process(&msg).await;
+                let result = match outcome {
+                    Outcome::Complete => receiver
+                        .complete_message(&msg)
+                        .await
+                        .context("unable to complete message"),
+                    Outcome::Abandon => receiver
+                        .abandon_message(&msg, None)
+                        .await
+                        .context("unable to abandon message"),
+                };
+                if let Err(err) = result {
+                    log::warn!("unable to settle azure service bus message: {:?}", err);
+                }
+            }
+        }
+    }
+
+    async fn process(&self, msg: &ServiceBusReceivedMessage) -> Outcome {
+        // The delivery count starts at 1 for the first delivery.
+        let attempt = msg.delivery_count().unwrap_or(1).max(1);
+
+        // If the attempt exceeds the max retries, drop it.
+        // Messages failing their final attempt are forwarded to the
+        // dead letter topic, if any, by the handler.
+        if self.retry.exhausted(attempt) {
+            return Outcome::Complete;
+        }
+
+        let result = match parse_message(msg, attempt) {
+            Ok(msg) => self
+                .handler
+                .handle_message(msg)
+                .await
+                .map_err(|err| err.into()),
+            Err(err) => {
+                log::error!(
+                    "encore: internal error: failed to parse message from azure service bus: {:#?}",
+                    err
+                );
+                Err(err)
+            }
+        };
+
+        match result {
+            Ok(()) => Outcome::Complete,
+            Err(err) => {
+                log::info!("message handler failed, abandoning message: {:?}", err);
+
+                // Service Bus redelivers abandoned messages immediately,
+                // so hold on to the message for the backoff duration first.
+                tokio::time::sleep(self.retry.backoff(attempt)).await;
+                Outcome::Abandon
+            }
+        }
+    }
+}
+
+async fn settle(
+    receiver: &mut azservicebus::ServiceBusReceiver,
+    msg: &ServiceBusReceivedMessage,
+    outcome: Outcome,
+) {
+    let result = match outcome {
+        Outcome::Complete => receiver
+            .complete_message(msg)
+            .await
+            .context("unable to complete message"),
+        Outcome::Abandon => receiver
+            .abandon_message(msg, None)
+            .await
+            .context("unable to abandon message"),
+    };
+    if let Err(err) = result {
+        log::warn!("unable to settle azure service bus message: {:?}", err);
+    }
+}
+
+fn parse_message(msg: &ServiceBusReceivedMessage, attempt: u32) -> Result<pubsub::Message> {
+    let raw_body = msg.body().context("message has no data body")?.to_vec();
+    let id = msg
+        .message_id()
+        .map(|id| id.to_string())
+        .unwrap_or_default();
+    let publish_time =
+        chrono::DateTime::from_timestamp_nanos(msg.enqueued_time().unix_timestamp_nanos() as i64);
+
+    Ok(pubsub::Message {
+        id,
+        publish_time: Some(publish_time),
+        attempt,
+        data: pubsub::MessageData {
+            attrs: decode_attrs(msg.application_properties()),
+            raw_body,
+        },
+    })
+}
+
+/// Decodes application properties into message attributes.
+/// Properties that aren't strings weren't published by Encore, and are skipped.
+pub(super) fn decode_attrs(props: Option<&ApplicationProperties>) -> HashMap<String, String> {
+    props
+        .map(|props| {
+            props
+                .0
+                .iter()
+                .filter_map(|(k, v)| match v {
+                    SimpleValue::String(v) => Some((k.clone(), v.clone())),
+                    _ => None,
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone)]
+struct RetryPolicy {
+    min_backoff: Duration,
+    max_backoff: Duration,
+    max_retries: i64,
+}
+
+impl RetryPolicy {
+    fn new(meta: &meta::pub_sub_topic::Subscription) -> Self {
+        // Backoff happens while holding the message lock, so keep it well
+        // below the ack deadline to avoid the lock expiring.
+        let ack_deadline = Duration::from_nanos(
+            meta.ack_deadline
+                .clamp(1_000_000_000, 5 * 60 * 1_000_000_000) as u64,
+        );
+        let limit = ack_deadline / 2;
+
+        // Default to 2 retries if we don't have a retry policy, like the other providers.
+        match &meta.retry_policy {
+            Some(retry) => Self {
+                min_backoff: Duration::from_nanos(retry.min_backoff.max(0) as u64).min(limit),
+                max_backoff: Duration::from_nanos(retry.max_backoff.max(0) as u64).min(limit),
+                max_retries: retry.max_retries,
+            },
+            None => Self {
+                min_backoff: Duration::from_secs(1).min(limit),
+                max_backoff: Duration::from_secs(10).min(limit),
+                max_retries: 2,
+            },
+        }
+    }
+
+    /// Reports whether a message on the given attempt has exhausted its retries.
+    /// Attempt starts at 1 for the first delivery, which means the retry count is (attempt-1).
+    fn exhausted(&self, attempt: u32) -> bool {
+        self.max_retries >= 0 && attempt as i64 - 1 > self.max_retries
+    }
+
+    /// The backoff before redelivering a message that failed the given attempt.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exp = attempt.saturating_sub(1).min(31);
+        self.min_backoff
+            .saturating_mul(1 << exp)
+            .min(self.max_backoff.max(self.min_backoff))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_policy() {
+        let meta = meta::pub_sub_topic::Subscription {
+            ack_deadline: 30_000_000_000,
+            retry_policy: Some(meta::pub_sub_topic::RetryPolicy {
+                min_backoff: 1_000_000_000,
+                max_backoff: 60_000_000_000,
+                max_retries: 3,
+            }),
+            ..Default::default()
+        };
+        let policy = RetryPolicy::new(&meta);
+        assert_eq!(policy.backoff(1), Duration::from_secs(1));
+        assert_eq!(policy.backoff(3), Duration::from_secs(4));
+        // Capped to half the ack deadline.
+        assert_eq!(policy.backoff(10), Duration::from_secs(15));
+
+        assert!(!policy.exhausted(1));
+        assert!(!policy.exhausted(4));
+        assert!(policy.exhausted(5));
+    }
+
+    /// Publishes and receives a message using the Service Bus emulator,
+    /// with a topic "encore-test" and subscription "encore-test-sub".
+    /// Skipped unless `AZURE_SERVICEBUS_EMULATOR_CONNECTION_STRING` is set.
+    #[tokio::test]
+    async fn test_emulator_roundtrip() {
+        const ENV: &str = "AZURE_SERVICEBUS_EMULATOR_CONNECTION_STRING";
+        if std::env::var(ENV).is_err() {
+            return;
+        }
+        let secret = crate::secrets::Manager::new(vec![]).load(pb::SecretData {
+            source: Some(pb::secret_data::Source::Env(ENV.into())),
+            ..Default::default()
+        });
+        let client = Arc::new(LazyClient::new("emulator".into(), secret));
+
+        let topic_cfg = pb::PubSubTopic {
+            cloud_name: "encore-test".into(),
+            ..Default::default()
+        };
+        let topic = super::super::topic::Topic::new(client.clone(), &topic_cfg);
+        let id = pubsub::Topic::publish(
+            &topic,
+            pubsub::MessageData {
+                attrs: HashMap::from([("foo".to_string(), "bar".to_string())]),
+                raw_body: b"{}".to_vec(),
+            },
+            None,
+        )
+        .await
+        .unwrap();
+
+        let mut receiver = client
+            .receiver("encore-test", "encore-test-sub")
+            .await
+            .unwrap();
+        let messages = receiver
+            .receive_messages_with_max_wait_time(1, Some(Duration::from_secs(10)))
+            .await
+            .unwrap();
+        let msg = messages.first().expect("no message received");
+        let parsed = parse_message(msg, msg.delivery_count().unwrap_or(1)).unwrap();
+        assert_eq!(parsed.id, id);
+        assert_eq!(parsed.attempt, 1);
+        assert_eq!(parsed.data.raw_body, b"{}");
+        assert_eq!(
+            parsed.data.attrs.get("foo").map(String::as_str),
+            Some("bar")
+        );
+        settle(&mut receiver, msg, Outcome::Complete).await;
+    }
+}
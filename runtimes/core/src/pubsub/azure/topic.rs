@@ -0,0 +1,122 @@
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use azservicebus::{ServiceBusMessage, ServiceBusSender};
+use tokio::sync::Mutex;
+
+use crate::encore::runtime::v1 as pb;
+use crate::names::CloudName;
+use crate::pubsub::azure::LazyClient;
+use crate::pubsub::{self, MessageData, MessageId};
+
+pub struct Topic {
+    client: Arc<LazyClient>,
+    cloud_name: CloudName,
+    sender: tokio::sync::OnceCell<Mutex<ServiceBusSender>>,
+}
+
+impl Debug for Topic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Topic")
+            .field("cloud_name", &self.cloud_name)
+            .finish()
+    }
+}
+
+impl Topic {
+    pub(super) fn new(client: Arc<LazyClient>, cfg: &pb::PubSubTopic) -> Self {
+        Self {
+            client,
+            cloud_name: cfg.cloud_name.clone().into(),
+            sender: tokio::sync::OnceCell::new(),
+        }
+    }
+
+    async fn sender(&self) -> Result<&Mutex<ServiceBusSender>> {
+        self.sender
+            .get_or_try_init(|| async {
+                let sender = self.client.sender(&self.cloud_name).await?;
+                Ok(Mutex::new(sender))
+            })
+            .await
+    }
+}
+
+impl pubsub::Topic for Topic {
+    fn publish(
+        &self,
+        msg: MessageData,
+        ordering_key: Option<String>,
+    ) -> Pin<Box<dyn Future<Output = Result<MessageId>> + Send + '_>> {
+        Box::pin(async move {
+            let id = xid::new().to_string();
+            let message = encode_message(&id, msg, ordering_key)?;
+
+            self.sender()
+                .await?
+                .lock()
+                .await
+                .send_message(message)
+                .await
+                .context("unable to publish message")?;
+            Ok(id)
+        })
+    }
+}
+
+/// Encodes a message for publishing, with its attributes as application properties.
+/// Messages with an ordering key are published to the session of the same name,
+/// which Service Bus delivers in order.
+fn encode_message(
+    id: &str,
+    msg: MessageData,
+    ordering_key: Option<String>,
+) -> Result<ServiceBusMessage> {
+    let mut message = ServiceBusMessage::new(msg.raw_body);
+    message.set_message_id(id).context("invalid message id")?;
+    if let Some(key) = ordering_key {
+        message
+            .set_session_id(Some(key))
+            .context("invalid ordering key")?;
+    }
+
+    let props = message.application_properties_mut();
+    for (key, value) in msg.attrs {
+        props.0.insert(key, value.into());
+    }
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::pubsub::azure::sub::decode_attrs;
+
+    #[test]
+    fn test_encode_message() {
+        let msg = MessageData {
+            attrs: HashMap::from([("foo".to_string(), "bar".to_string())]),
+            raw_body: b"{\"hello\":1}".to_vec(),
+        };
+        let message = encode_message("id", msg, Some("key".into())).unwrap();
+        assert_eq!(message.message_id().as_deref(), Some("id"));
+        assert_eq!(message.session_id(), Some("key"));
+        assert_eq!(message.body().unwrap(), b"{\"hello\":1}");
+        assert_eq!(
+            decode_attrs(message.application_properties()),
+            HashMap::from([("foo".to_string(), "bar".to_string())])
+        );
+
+        let long_key = "k".repeat(200);
+        let msg = MessageData {
+            attrs: HashMap::new(),
+            raw_body: vec![],
+        };
+        assert!(encode_message("id", msg, Some(long_key)).is_err());
+    }
+}
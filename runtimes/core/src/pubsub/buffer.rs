@@ -0,0 +1,197 @@
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use anyhow::Context;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::encore::runtime::v1 as pb;
+use crate::pubsub::{BatchMessage, MessageId, Topic};
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// How long a message may be buffered before its batch is published.
+    pub max_latency: Duration,
+
+    /// The maximum number of messages to publish in a batch.
+    pub max_batch_size: usize,
+}
+
+impl Config {
+    pub fn from_config(cfg: &pb::pub_sub_topic::PublishBuffer) -> Self {
+        Self {
+            max_latency: cfg
+                .max_latency
+                .clone()
+                .and_then(|d| Duration::try_from(d).ok())
+                .unwrap_or(Duration::from_millis(10)),
+            max_batch_size: cfg.max_batch_size.map_or(100, |n| n.max(1) as usize),
+        }
+    }
+}
+
+struct Pending {
+    msg: BatchMessage,
+    resp: oneshot::Sender<anyhow::Result<MessageId>>,
+}
+
+/// Buffers messages published to a topic and publishes them in batches,
+/// once a batch is full or its first message has waited for the max latency.
+///
+/// Batches are published one at a time, so messages with the same
+/// ordering key are published in the order they were buffered.
+#[derive(Debug)]
+pub struct Buffer {
+    topic: Arc<dyn Topic>,
+    cfg: Config,
+
+    /// Sends messages to the task publishing the batches, started on first use.
+    tx: OnceLock<mpsc::Sender<Pending>>,
+}
+
+impl Buffer {
+    pub fn new(topic: Arc<dyn Topic>, cfg: Config) -> Self {
+        Self {
+            topic,
+            cfg,
+            tx: OnceLock::new(),
+        }
+    }
+
+    /// Buffers the message, resolving once the batch it's part of has been published.
+    pub async fn publish(&self, msg: BatchMessage) -> anyhow::Result<MessageId> {
+        let tx = self.tx.get_or_init(|| {
+            let (tx, rx) = mpsc::channel(self.cfg.max_batch_size * 4);
+            tokio::spawn(run(self.topic.clone(), self.cfg.clone(), rx));
+            tx
+        });
+
+        let (resp, resp_rx) = oneshot::channel();
+        tx.send(Pending { msg, resp })
+            .await
+            .map_err(|_| anyhow::anyhow!("publish buffer closed"))?;
+        resp_rx.await.context("publish buffer closed")?
+    }
+}
+
+async fn run(topic: Arc<dyn Topic>, cfg: Config, mut rx: mpsc::Receiver<Pending>) {
+    while let Some(first) = rx.recv().await {
+        let mut batch = vec![first];
+        let deadline = tokio::time::sleep(cfg.max_latency);
+        tokio::pin!(deadline);
+
+        while batch.len() < cfg.max_batch_size {
+            tokio::select! {
+                next = rx.recv() => match next {
+                    Some(pending) => batch.push(pending),
+                    None => break,
+                },
+                _ = &mut deadline => break,
+            }
+        }
+
+        // Messages published meanwhile are buffered in the channel.
+        publish(topic.as_ref(), batch).await;
+    }
+}
+
+async fn publish(topic: &dyn Topic, batch: Vec<Pending>) {
+    let (msgs, resps): (Vec<_>, Vec<_>) = batch.into_iter().map(|p| (p.msg, p.resp)).unzip();
+    let mut results = topic.publish_batch(msgs).await.into_iter();
+    for resp in resps {
+        let result = results
+            .next()
+            .unwrap_or_else(|| Err(anyhow::anyhow!("no publish result returned for message")));
+        _ = resp.send(result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::pubsub::MessageData;
+
+    #[derive(Debug, Default)]
+    struct RecordingTopic {
+        batches: Mutex<Vec<usize>>,
+        in_flight: AtomicBool,
+    }
+
+    impl Topic for RecordingTopic {
+        fn publish(
+            &self,
+            _msg: MessageData,
+            _ordering_key: Option<String>,
+        ) -> Pin<Box<dyn Future<Output = anyhow::Result<MessageId>> + Send + '_>> {
+            unreachable!("messages are published in batches")
+        }
+
+        fn publish_batch(
+            &self,
+            msgs: Vec<BatchMessage>,
+        ) -> Pin<Box<dyn Future<Output = Vec<anyhow::Result<MessageId>>> + Send + '_>> {
+            self.batches.lock().unwrap().push(msgs.len());
+            Box::pin(async move {
+                assert!(
+                    !self.in_flight.swap(true, Ordering::SeqCst),
+                    "batches are published one at a time"
+                );
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                self.in_flight.store(false, Ordering::SeqCst);
+
+                // Messages with an empty body fail to publish.
+                msgs.into_iter()
+                    .map(|m| String::from_utf8(m.data.raw_body).unwrap())
+                    .map(|body| {
+                        if body.is_empty() {
+                            Err(anyhow::anyhow!("empty message"))
+                        } else {
+                            Ok(body)
+                        }
+                    })
+                    .collect()
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_buffer_batches() {
+        let topic = Arc::new(RecordingTopic::default());
+        let buffer = Arc::new(Buffer::new(
+            topic.clone(),
+            Config {
+                max_latency: Duration::from_millis(50),
+                max_batch_size: 3,
+            },
+        ));
+
+        let bodies = ["0", "", "2", "3"];
+        let publishes = bodies.map(|body| {
+            let buffer = buffer.clone();
+            async move {
+                buffer
+                    .publish(BatchMessage {
+                        data: MessageData {
+                            attrs: Default::default(),
+                            raw_body: body.as_bytes().to_vec(),
+                        },
+                        ordering_key: None,
+                    })
+                    .await
+                    .ok()
+            }
+        });
+        let ids = futures::future::join_all(publishes).await;
+
+        // Each message gets its own result.
+        let expected = [Some("0"), None, Some("2"), Some("3")].map(|id| id.map(String::from));
+        assert_eq!(ids, expected);
+
+        // The first batch is full, and the second is published after the max latency.
+        assert_eq!(*topic.batches.lock().unwrap(), vec![3, 1]);
+    }
+}
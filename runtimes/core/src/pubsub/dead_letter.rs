@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+
+use crate::api;
+use crate::model::DeadLetterData;
+use crate::names::EncoreName;
+
+/// Attributes added to messages forwarded to a dead letter topic.
+const ATTR_TOPIC: &str = "encore_dead_letter_topic";
+const ATTR_SUBSCRIPTION: &str = "encore_dead_letter_subscription";
+const ATTR_MESSAGE_ID: &str = "encore_dead_letter_message_id";
+const ATTR_ATTEMPTS: &str = "encore_dead_letter_attempts";
+const ATTR_ERROR_CODE: &str = "encore_dead_letter_error_code";
+const ATTR_ERROR_MESSAGE: &str = "encore_dead_letter_error_message";
+
+/// Set on redriven messages so that only the subscription
+/// the message was dead-lettered from processes it.
+pub(super) const ATTR_REDRIVE_SUBSCRIPTION: &str = "encore_redrive_subscription";
+
+/// Reports whether the given delivery attempt (starting at 1) is the final one
+/// allowed by the retry policy. A negative max_retries means retry forever.
+pub(super) fn is_final_attempt(attempt: u32, max_retries: i64) -> bool {
+    max_retries >= 0 && attempt as i64 > max_retries
+}
+
+/// Computes the attributes of a message forwarded to a dead letter topic,
+/// from the attributes of the original message.
+pub(super) fn forward_attrs(
+    mut attrs: HashMap<String, String>,
+    topic: &EncoreName,
+    subscription: &EncoreName,
+    message_id: &str,
+    attempt: u32,
+    err: &api::Error,
+) -> HashMap<String, String> {
+    // A redriven message that fails again must reach all subscriptions
+    // of the dead letter topic.
+    attrs.remove(ATTR_REDRIVE_SUBSCRIPTION);
+
+    attrs.insert(ATTR_TOPIC.to_string(), topic.to_string());
+    attrs.insert(ATTR_SUBSCRIPTION.to_string(), subscription.to_string());
+    attrs.insert(ATTR_MESSAGE_ID.to_string(), message_id.to_string());
+    attrs.insert(ATTR_ATTEMPTS.to_string(), attempt.to_string());
+    attrs.insert(ATTR_ERROR_CODE.to_string(), err.code.to_string());
+    attrs.insert(ATTR_ERROR_MESSAGE.to_string(), err.to_string());
+    attrs
+}
+
+/// Parses the dead letter information from the attributes of a message,
+/// if it was forwarded to a dead letter topic.
+pub(super) fn parse(attrs: &HashMap<String, String>) -> Option<DeadLetterData> {
+    Some(DeadLetterData {
+        topic: attrs.get(ATTR_TOPIC)?.clone().into(),
+        subscription: attrs.get(ATTR_SUBSCRIPTION)?.clone().into(),
+        message_id: attrs.get(ATTR_MESSAGE_ID).cloned().unwrap_or_default(),
+        attempts: attrs
+            .get(ATTR_ATTEMPTS)
+            .and_then(|s| s.parse().ok())
+            .unwrap_or_default(),
+        error_code: attrs.get(ATTR_ERROR_CODE).cloned().unwrap_or_default(),
+        error_message: attrs.get(ATTR_ERROR_MESSAGE).cloned().unwrap_or_default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_final_attempt() {
+        assert!(!is_final_attempt(1, 2));
+        assert!(!is_final_attempt(2, 2));
+        assert!(is_final_attempt(3, 2));
+        assert!(is_final_attempt(1, 0));
+
+        // Negative max retries means retrying forever.
+        assert!(!is_final_attempt(1000, -2));
+    }
+
+    #[test]
+    fn test_forward_and_parse() {
+        let attrs = HashMap::from([
+            ("user_id".to_string(), "1".to_string()),
+            (ATTR_REDRIVE_SUBSCRIPTION.to_string(), "sub".to_string()),
+        ]);
+        let err = api::Error {
+            code: api::ErrCode::Unavailable,
+            message: "service unavailable".to_string(),
+            internal_message: Some("connection refused".to_string()),
+            stack: None,
+            details: None,
+        };
+        let attrs = forward_attrs(
+            attrs,
+            &"orders".into(),
+            &"process-order".into(),
+            "msg-1",
+            3,
+            &err,
+        );
+
+        assert_eq!(attrs.get("user_id").map(String::as_str), Some("1"));
+        assert!(!attrs.contains_key(ATTR_REDRIVE_SUBSCRIPTION));
+
+        let data = parse(&attrs).unwrap();
+        assert_eq!(data.topic.as_ref(), "orders");
+        assert_eq!(data.subscription.as_ref(), "process-order");
+        assert_eq!(data.message_id, "msg-1");
+        assert_eq!(data.attempts, 3);
+        assert_eq!(data.error_code, "unavailable");
+        assert_eq!(data.error_message, "connection refused");
+
+        assert!(parse(&HashMap::new()).is_none());
+    }
+}
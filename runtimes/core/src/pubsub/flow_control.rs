@@ -0,0 +1,253 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+use crate::api;
+use crate::encore::runtime::v1 as pb;
+
+/// How much the concurrency limit is multiplied by when handlers are overloaded.
+const BACKOFF_FACTOR: f64 = 0.75;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// The maximum number of messages to process at once.
+    pub max_concurrency: usize,
+
+    /// The maximum total payload size of the messages being processed at once.
+    pub max_in_flight_bytes: Option<usize>,
+
+    pub adaptive: Option<AdaptiveConfig>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AdaptiveConfig {
+    pub min_concurrency: usize,
+    pub target_latency: Duration,
+}
+
+impl Config {
+    pub fn from_config(
+        cfg: &pb::pub_sub_subscription::FlowControl,
+        max_concurrency: Option<i32>,
+    ) -> Self {
+        let max_concurrency = max_concurrency.map_or(100, |v| v.max(1) as usize);
+        Self {
+            max_concurrency,
+            max_in_flight_bytes: cfg.max_in_flight_bytes.map(|v| v as usize),
+            adaptive: cfg.adaptive.as_ref().map(|a| AdaptiveConfig {
+                min_concurrency: a
+                    .min_concurrency
+                    .map_or(1, |v| v.max(1) as usize)
+                    .min(max_concurrency),
+                target_latency: a
+                    .target_latency
+                    .clone()
+                    .and_then(|d| Duration::try_from(d).ok())
+                    .unwrap_or(Duration::from_secs(1)),
+            }),
+        }
+    }
+}
+
+/// Limits the number and total size of the messages a subscription processes at once.
+///
+/// In adaptive mode the concurrency limit grows by one for every window of fast,
+/// successful messages, and shrinks multiplicatively when handlers exceed the
+/// target latency or fail with `ResourceExhausted` or `Unavailable`.
+#[derive(Debug)]
+pub struct FlowControl {
+    cfg: Config,
+    state: Mutex<State>,
+    released: Notify,
+}
+
+#[derive(Debug)]
+struct State {
+    /// The current concurrency limit. Fractional so that it can
+    /// grow by one over the course of a full window of messages.
+    limit: f64,
+    in_flight: usize,
+    in_flight_bytes: usize,
+
+    /// When the limit was last lowered, so a burst of slow messages
+    /// that were all in flight at once only lowers it once.
+    last_backoff: Option<Instant>,
+}
+
+impl FlowControl {
+    pub fn new(cfg: Config) -> Self {
+        let limit = cfg.max_concurrency as f64;
+        Self {
+            cfg,
+            state: Mutex::new(State {
+                limit,
+                in_flight: 0,
+                in_flight_bytes: 0,
+                last_backoff: None,
+            }),
+            released: Notify::new(),
+        }
+    }
+
+    /// Waits until a message of the given size can be processed.
+    /// The returned permit must be held while the message is processed.
+    pub async fn acquire(self: &Arc<Self>, bytes: usize) -> Permit {
+        loop {
+            let released = self.released.notified();
+            tokio::pin!(released);
+            released.as_mut().enable();
+
+            if self.try_acquire(bytes) {
+                return Permit {
+                    fc: self.clone(),
+                    bytes,
+                };
+            }
+            released.await;
+        }
+    }
+
+    fn try_acquire(&self, bytes: usize) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.in_flight >= (state.limit as usize).max(1) {
+            return false;
+        }
+        // Always let a message through when nothing else is in flight,
+        // so messages larger than the byte limit are not stuck forever.
+        if let Some(max_bytes) = self.cfg.max_in_flight_bytes {
+            if state.in_flight > 0 && state.in_flight_bytes + bytes > max_bytes {
+                return false;
+            }
+        }
+        state.in_flight += 1;
+        state.in_flight_bytes += bytes;
+        true
+    }
+
+    fn record(&self, latency: Duration, result: &Result<(), api::Error>) {
+        let Some(adaptive) = &self.cfg.adaptive else {
+            return;
+        };
+
+        let overloaded = latency > adaptive.target_latency
+            || matches!(
+                result,
+                Err(err) if matches!(
+                    err.code,
+                    api::ErrCode::ResourceExhausted | api::ErrCode::Unavailable
+                )
+            );
+
+        let mut state = self.state.lock().unwrap();
+        if overloaded {
+            let now = Instant::now();
+            let recently = state
+                .last_backoff
+                .is_some_and(|t| now.duration_since(t) < adaptive.target_latency);
+            if !recently {
+                state.limit = (state.limit * BACKOFF_FACTOR).max(adaptive.min_concurrency as f64);
+                state.last_backoff = Some(now);
+            }
+        } else if result.is_ok() {
+            state.limit = (state.limit + 1.0 / state.limit).min(self.cfg.max_concurrency as f64);
+        }
+    }
+
+    fn release(&self, bytes: usize) {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.in_flight -= 1;
+            state.in_flight_bytes -= bytes;
+        }
+        self.released.notify_waiters();
+    }
+}
+
+/// A message being processed, released when dropped.
+#[derive(Debug)]
+pub struct Permit {
+    fc: Arc<FlowControl>,
+    bytes: usize,
+}
+
+impl Permit {
+    /// Records how processing the message went, to adapt the concurrency limit.
+    pub fn complete(self, latency: Duration, result: &Result<(), api::Error>) {
+        self.fc.record(latency, result);
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.fc.release(self.bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn adaptive(max_concurrency: usize) -> Arc<FlowControl> {
+        Arc::new(FlowControl::new(Config {
+            max_concurrency,
+            max_in_flight_bytes: Some(100),
+            adaptive: Some(AdaptiveConfig {
+                min_concurrency: 2,
+                target_latency: Duration::from_millis(100),
+            }),
+        }))
+    }
+
+    fn limit(fc: &FlowControl) -> usize {
+        fc.state.lock().unwrap().limit as usize
+    }
+
+    #[tokio::test]
+    async fn test_byte_limit() {
+        let fc = adaptive(10);
+
+        let first = fc.acquire(80).await;
+        assert!(!fc.try_acquire(30));
+        drop(first);
+
+        // Oversized messages are let through on their own.
+        let big = fc.acquire(500).await;
+        assert!(!fc.try_acquire(1));
+        drop(big);
+        assert!(fc.try_acquire(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_adaptive_limit() {
+        let fc = adaptive(10);
+
+        fc.acquire(0)
+            .await
+            .complete(Duration::from_millis(10), &Err(api::Error::shutting_down()));
+        assert_eq!(limit(&fc), 7);
+
+        // Further failures within the same window don't lower it again.
+        fc.acquire(0)
+            .await
+            .complete(Duration::from_secs(1), &Ok(()));
+        assert_eq!(limit(&fc), 7);
+
+        for _ in 0..5 {
+            tokio::time::advance(Duration::from_secs(1)).await;
+            fc.acquire(0)
+                .await
+                .complete(Duration::from_secs(1), &Ok(()));
+        }
+        assert_eq!(limit(&fc), 2);
+
+        // Fast, successful messages raise it back up to the max.
+        for _ in 0..200 {
+            fc.acquire(0)
+                .await
+                .complete(Duration::from_millis(10), &Ok(()));
+        }
+        assert_eq!(limit(&fc), 10);
+    }
+}
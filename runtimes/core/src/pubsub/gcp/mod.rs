@@ -1,3 +1,5 @@
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::Arc;
 
 use anyhow::Context;
@@ -50,6 +52,30 @@ impl pubsub::Cluster for Cluster {
 
         Arc::new(Subscription::new(self.client.clone(), cfg, meta))
     }
+
+    fn check_topic(
+        &self,
+        cfg: &pb::PubSubTopic,
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send + 'static>> {
+        let client = self.client.clone();
+        let project_id = match cfg.provider_config.as_ref() {
+            Some(pb::pub_sub_topic::ProviderConfig::GcpConfig(gcp_cfg)) => {
+                gcp_cfg.project_id.clone()
+            }
+            _ => String::new(),
+        };
+        let fqtn = format!("projects/{}/topics/{}", project_id, cfg.cloud_name);
+        Box::pin(async move {
+            let client = match client.get().await {
+                Ok(client) => client,
+                Err(e) => anyhow::bail!("failed to get gcp client: {}", e),
+            };
+            if !client.topic(&fqtn).exists(None).await? {
+                anyhow::bail!("topic {} does not exist", fqtn);
+            }
+            Ok(())
+        })
+    }
 }
 
 #[derive(Debug)]
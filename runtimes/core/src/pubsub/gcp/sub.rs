@@ -1,6 +1,8 @@
+use std::collections::HashMap;
 use std::future::Future;
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
 use google_cloud_pubsub as gcp;
@@ -14,6 +16,13 @@ use crate::pubsub::gcp::LazyGCPClient;
 use crate::pubsub::manager::SubHandler;
 use crate::pubsub::{self, MessageId};
 
+/// The number of tracked messages above which the attempts of
+/// messages that haven't been redelivered recently are forgotten.
+const ATTEMPTS_PRUNE_THRESHOLD: usize = 10_000;
+
+/// How long the attempts of a message are remembered after its last delivery.
+const ATTEMPTS_TTL: Duration = Duration::from_secs(60 * 60);
+
 #[derive(Debug)]
 pub struct Subscription {
     inner: Arc<InnerSubscription>,
@@ -40,11 +49,16 @@ impl pubsub::Subscription for Subscription {
         let inner = self.inner.clone();
         Box::pin(async move {
             let sub = inner.get_sub().await.map_err(api::Error::internal)?;
-            let cancel = CancellationToken::new();
+
+            // Stop receiving new messages when the runtime shuts down.
+            // Outstanding messages are still processed.
+            let cancel = handler.shutdown().initiated();
+            let attempts = inner.attempts.clone();
             sub.receive(
                 move |message, cancel| {
                     let handler = handler.clone();
-                    handle_message(handler, message, cancel)
+                    let attempts = attempts.clone();
+                    handle_message(handler, attempts, message, cancel)
                 },
                 cancel,
                 Some(inner.receive_cfg.clone()),
@@ -62,6 +76,7 @@ struct InnerSubscription {
     project_id: String,
     sub_name: String,
     receive_cfg: gcp::subscription::ReceiveConfig,
+    attempts: Arc<Attempts>,
     cell: tokio::sync::OnceCell<Result<gcp::subscription::Subscription>>,
 }
 
@@ -101,6 +116,7 @@ impl InnerSubscription {
             project_id: gcp_cfg.project_id.clone(),
             sub_name: cfg.subscription_cloud_name.clone(),
             receive_cfg,
+            attempts: Arc::new(Attempts::default()),
             cell: tokio::sync::OnceCell::new(),
         }
     }
@@ -128,12 +144,50 @@ impl InnerSubscription {
     }
 }
 
+/// Counts the delivery attempts of messages for subscriptions without
+/// a dead letter policy, for which GCP doesn't report the delivery attempt.
+///
+/// Attempts are counted per process, so a message redelivered
+/// to another instance starts over from the first attempt there.
+#[derive(Debug, Default)]
+struct Attempts {
+    seen: Mutex<HashMap<String, (u32, Instant)>>,
+}
+
+impl Attempts {
+    /// Records a delivery of the given message and returns
+    /// its attempt number, starting at 1.
+    fn record(&self, msg_id: &str, now: Instant) -> u32 {
+        let mut seen = self.seen.lock().unwrap();
+        if seen.len() >= ATTEMPTS_PRUNE_THRESHOLD && !seen.contains_key(msg_id) {
+            seen.retain(|_, (_, last)| now.saturating_duration_since(*last) < ATTEMPTS_TTL);
+        }
+
+        let entry = seen.entry(msg_id.to_string()).or_insert((0, now));
+        entry.0 += 1;
+        entry.1 = now;
+        entry.0
+    }
+
+    /// Forgets a message once it has been acknowledged.
+    fn forget(&self, msg_id: &str) {
+        self.seen.lock().unwrap().remove(msg_id);
+    }
+}
+
 async fn handle_message(
     handler: Arc<SubHandler>,
+    attempts: Arc<Attempts>,
     mut message: gcp::subscriber::ReceivedMessage,
     _cancel: CancellationToken,
 ) {
-    let attempt = message.delivery_attempt().unwrap_or(1) as u32;
+    // GCP only reports the delivery attempt when the subscription
+    // has a dead letter policy, so count the attempts otherwise.
+    let msg_id = message.message.message_id.clone();
+    let attempt = match message.delivery_attempt() {
+        Some(attempt) => attempt as u32,
+        None => attempts.record(&msg_id, Instant::now()),
+    };
     let publish_time = message
         .message
         .publish_time
@@ -156,6 +210,7 @@ async fn handle_message(
     match handler.handle_message(msg).await {
         Ok(()) => {
             // Acknowledge the message.
+            attempts.forget(&msg_id);
             if let Err(err) = message.ack().await {
                 log::error!("failed to ack message: {:?}", err);
             }
@@ -168,3 +223,44 @@ async fn handle_message(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attempts() {
+        let attempts = Attempts::default();
+        let now = Instant::now();
+
+        assert_eq!(attempts.record("a", now), 1);
+        assert_eq!(attempts.record("a", now), 2);
+        assert_eq!(attempts.record("b", now), 1);
+
+        attempts.forget("a");
+        assert_eq!(attempts.record("a", now), 1);
+    }
+
+    #[test]
+    fn test_attempts_prune() {
+        let attempts = Attempts::default();
+        let now = Instant::now();
+        for i in 0..ATTEMPTS_PRUNE_THRESHOLD {
+            attempts.record(&i.to_string(), now);
+        }
+
+        // Messages that were delivered recently are kept.
+        let later = now + Duration::from_secs(60);
+        assert_eq!(attempts.record("0", later), 2);
+        attempts.record("new", later);
+        assert_eq!(
+            attempts.seen.lock().unwrap().len(),
+            ATTEMPTS_PRUNE_THRESHOLD + 1
+        );
+
+        // And forgotten once they haven't been delivered for a while.
+        let much_later = later + ATTEMPTS_TTL;
+        attempts.record("newer", much_later);
+        assert_eq!(attempts.seen.lock().unwrap().len(), 1);
+    }
+}
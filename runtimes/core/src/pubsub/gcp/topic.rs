@@ -9,7 +9,7 @@ use google_cloud_pubsub as gcp;
 use crate::encore::runtime::v1 as pb;
 use crate::names::CloudName;
 use crate::pubsub::gcp::LazyGCPClient;
-use crate::pubsub::{self, MessageData, MessageId};
+use crate::pubsub::{self, BatchMessage, MessageData, MessageId};
 
 #[derive(Debug)]
 pub struct Topic {
@@ -66,18 +66,44 @@ impl pubsub::Topic for Topic {
     ) -> Pin<Box<dyn Future<Output = Result<MessageId>> + Send + '_>> {
         Box::pin(async move {
             let (_, publisher) = self.get_topic().await?;
-            let awaiter = publisher
-                .publish(PubsubMessage {
-                    data: msg.raw_body,
-                    attributes: msg.attrs.into_iter().collect(),
-                    ordering_key: ordering_key.unwrap_or_default(),
-                    ..Default::default()
-                })
-                .await;
+            let awaiter = publisher.publish(pubsub_message(msg, ordering_key)).await;
             match awaiter.get().await {
                 Ok(id) => Ok(id as MessageId),
                 Err(e) => Err(e.into()),
             }
         })
     }
+
+    fn publish_batch(
+        &self,
+        msgs: Vec<BatchMessage>,
+    ) -> Pin<Box<dyn Future<Output = Vec<Result<MessageId>>> + Send + '_>> {
+        Box::pin(async move {
+            let publisher = match self.get_topic().await {
+                Ok((_, publisher)) => publisher,
+                Err(err) => return pubsub::fail_batch(msgs.len(), &err),
+            };
+            let msgs = msgs
+                .into_iter()
+                .map(|msg| pubsub_message(msg.data, msg.ordering_key))
+                .collect();
+
+            // The publisher sends the messages in bundles.
+            let awaiters = publisher.publish_bulk(msgs).await;
+            let mut results = Vec::with_capacity(awaiters.len());
+            for awaiter in awaiters {
+                results.push(awaiter.get().await.map_err(anyhow::Error::from));
+            }
+            results
+        })
+    }
+}
+
+fn pubsub_message(msg: MessageData, ordering_key: Option<String>) -> PubsubMessage {
+    PubsubMessage {
+        data: msg.raw_body,
+        attributes: msg.attrs.into_iter().collect(),
+        ordering_key: ordering_key.unwrap_or_default(),
+        ..Default::default()
+    }
 }
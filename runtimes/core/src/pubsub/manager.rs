@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::future::Future;
 use std::pin::Pin;
 use std::sync::atomic::AtomicUsize;
@@ -19,16 +19,18 @@ use crate::model::{PubSubRequestData, RequestData, ResponseData, SpanId, SpanKey
 use crate::names::EncoreName;
 use crate::pubsub::noop::NoopCluster;
 use crate::pubsub::{
-    gcp, noop, nsq, sqs_sns, Cluster, Message, MessageData, MessageId, SubName, Subscription,
+    azure, buffer, dead_letter, flow_control, gcp, memory, noop, nsq, sqs_sns, BatchMessage,
+    Cluster, Message, MessageData, MessageId, PendingMessage, SubName, Subscription,
     SubscriptionHandler, Topic,
 };
 use crate::trace::{protocol, Tracer};
-use crate::{api, model};
+use crate::{api, health, model, secrets, shutdown};
 
 use super::push_registry::PushHandlerRegistry;
 
 pub struct Manager {
     tracer: Tracer,
+    shutdown: shutdown::Tracker,
     topic_cfg: HashMap<EncoreName, TopicConfig>,
     sub_cfg: HashMap<SubName, SubConfig>,
     publisher_id: xid::Id,
@@ -36,6 +38,7 @@ pub struct Manager {
     topics: Arc<RwLock<HashMap<EncoreName, Arc<TopicInner>>>>,
     subs: Arc<RwLock<HashMap<SubName, Arc<SubscriptionObj>>>>,
     push_registry: PushHandlerRegistry,
+    memory: Arc<memory::Hub>,
 }
 
 #[derive(Debug)]
@@ -50,6 +53,7 @@ struct TopicInner {
     imp: Arc<dyn Topic>,
     attr_fields: Arc<Vec<String>>,
     ordering_attr: Option<String>,
+    buffer: Option<buffer::Buffer>,
 }
 
 impl TopicObj {
@@ -58,73 +62,168 @@ impl TopicObj {
         payload: PValues,
         source: Option<Arc<model::Request>>,
     ) -> impl Future<Output = anyhow::Result<MessageId>> + 'static {
-        self.inner.publish(payload, source)
+        self.inner.publish(payload, HashMap::new(), source)
+    }
+
+    /// Publishes a batch of messages, returning the result of each in the same order.
+    /// It fails without publishing anything if any of the messages can't be encoded.
+    pub fn publish_batch(
+        &self,
+        payloads: Vec<PValues>,
+        source: Option<Arc<model::Request>>,
+    ) -> impl Future<Output = anyhow::Result<Vec<anyhow::Result<MessageId>>>> + 'static {
+        self.inner.publish_batch(payloads, source)
     }
 }
 
 impl TopicInner {
+    /// Publishes a message with the given payload.
+    /// The attributes are added to the attributes computed from the payload.
     pub fn publish(
-        &self,
+        self: &Arc<Self>,
         payload: PValues,
+        attrs: HashMap<String, String>,
         source: Option<Arc<model::Request>>,
     ) -> impl Future<Output = anyhow::Result<MessageId>> + 'static {
-        let tracer = self.tracer.clone();
-        let inner = self.imp.clone();
-        let name = self.name.clone();
-        let attr_fields = self.attr_fields.clone();
-        let ordering_attr = self.ordering_attr.clone();
+        let this = self.clone();
+        async move {
+            let msg = this.encode(&payload, attrs)?;
+            this.publish_raw(msg, source.as_deref()).await
+        }
+    }
+
+    /// Publishes a batch of messages with the given payloads.
+    /// The batch is published directly, bypassing the publish buffer if any.
+    pub fn publish_batch(
+        self: &Arc<Self>,
+        payloads: Vec<PValues>,
+        source: Option<Arc<model::Request>>,
+    ) -> impl Future<Output = anyhow::Result<Vec<anyhow::Result<MessageId>>>> + 'static {
+        let this = self.clone();
         async move {
-            let raw_body = serde_json::to_vec_pretty(&payload)
-                .context("unable to serialize message payload")?;
-            let mut msg = MessageData {
-                attrs: HashMap::new(),
-                raw_body,
+            let msgs = payloads
+                .iter()
+                .map(|payload| {
+                    let msg = this.encode(payload, HashMap::new())?;
+                    this.prepare(msg, source.as_deref())
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            let Some(source) = source else {
+                return Ok(this.imp.publish_batch(msgs).await);
             };
 
-            for name in attr_fields.iter() {
-                if let Some(val) = payload.get(name) {
-                    msg.attrs.insert(name.clone(), val.to_string());
-                }
+            let start_ids: Vec<_> = msgs
+                .iter()
+                .map(|msg| {
+                    this.tracer
+                        .pubsub_publish_start(protocol::PublishStartData {
+                            source: &source,
+                            topic: &this.name,
+                            payload: &msg.data.raw_body,
+                        })
+                })
+                .collect();
+            let results = this.imp.publish_batch(msgs).await;
+            for (start_id, result) in start_ids.into_iter().zip(&results) {
+                this.tracer.pubsub_publish_end(protocol::PublishEndData {
+                    start_id,
+                    source: &source,
+                    result,
+                });
             }
+            Ok(results)
+        }
+    }
 
-            let ordering_key: Option<String> = if let Some(attr) = ordering_attr {
-                Some(
-                    msg.attrs
-                        .get(&attr)
-                        .with_context(|| format!("ordering attribute {} not found", attr))?
-                        .clone(),
-                )
-            } else {
-                None
-            };
+    /// Encodes the payload as compact JSON, copying the attribute fields
+    /// of the payload into the given attributes.
+    fn encode(
+        &self,
+        payload: &PValues,
+        mut attrs: HashMap<String, String>,
+    ) -> anyhow::Result<MessageData> {
+        let raw_body =
+            serde_json::to_vec(payload).context("unable to serialize message payload")?;
+
+        for name in self.attr_fields.iter() {
+            if let Some(val) = payload.get(name) {
+                attrs.insert(name.clone(), val.to_string());
+            }
+        }
+        Ok(MessageData { attrs, raw_body })
+    }
 
-            if let Some(source) = source.as_deref() {
+    /// Determines the ordering key of an encoded message,
+    /// and adds the tracing attributes of the request publishing it.
+    fn prepare(
+        &self,
+        mut msg: MessageData,
+        source: Option<&model::Request>,
+    ) -> anyhow::Result<BatchMessage> {
+        let ordering_key: Option<String> = if let Some(attr) = &self.ordering_attr {
+            Some(
+                msg.attrs
+                    .get(attr)
+                    .with_context(|| format!("ordering attribute {} not found", attr))?
+                    .clone(),
+            )
+        } else {
+            None
+        };
+
+        if let Some(source) = source {
+            msg.attrs.insert(
+                ATTR_PARENT_TRACE_ID.to_string(),
+                source.span.0.serialize_encore(),
+            );
+            if let Some(ext_correlation_id) = &source.ext_correlation_id {
                 msg.attrs.insert(
-                    ATTR_PARENT_TRACE_ID.to_string(),
-                    source.span.0.serialize_encore(),
+                    ATTR_EXT_CORRELATION_ID.to_string(),
+                    ext_correlation_id.clone(),
                 );
-                if let Some(ext_correlation_id) = &source.ext_correlation_id {
-                    msg.attrs.insert(
-                        ATTR_EXT_CORRELATION_ID.to_string(),
-                        ext_correlation_id.clone(),
-                    );
-                }
+            }
+        }
 
-                let start_id = tracer.pubsub_publish_start(protocol::PublishStartData {
-                    source,
-                    topic: &name,
-                    payload: &msg.raw_body,
-                });
-                let result = inner.publish(msg, ordering_key).await;
-                tracer.pubsub_publish_end(protocol::PublishEndData {
-                    start_id,
+        Ok(BatchMessage {
+            data: msg,
+            ordering_key,
+        })
+    }
+
+    /// Publishes an already encoded message.
+    async fn publish_raw(
+        &self,
+        msg: MessageData,
+        source: Option<&model::Request>,
+    ) -> anyhow::Result<MessageId> {
+        let msg = self.prepare(msg, source)?;
+
+        if let Some(source) = source {
+            let start_id = self
+                .tracer
+                .pubsub_publish_start(protocol::PublishStartData {
                     source,
-                    result: &result,
+                    topic: &self.name,
+                    payload: &msg.data.raw_body,
                 });
-                result
-            } else {
-                inner.publish(msg, ordering_key).await
-            }
+            let result = self.send(msg).await;
+            self.tracer.pubsub_publish_end(protocol::PublishEndData {
+                start_id,
+                source,
+                result: &result,
+            });
+            result
+        } else {
+            self.send(msg).await
+        }
+    }
+
+    /// Sends a message to the topic, through the publish buffer if any.
+    async fn send(&self, msg: BatchMessage) -> anyhow::Result<MessageId> {
+        match &self.buffer {
+            Some(buffer) => buffer.publish(msg).await,
+            None => self.imp.publish(msg.data, msg.ordering_key).await,
         }
     }
 }
@@ -137,6 +236,17 @@ pub struct SubscriptionObj {
     topic: EncoreName,
     subscription: EncoreName,
     schema: JSONSchema,
+    shutdown: shutdown::Tracker,
+
+    /// The maximum number of retries before a message is dead-lettered.
+    /// None or a negative value means messages are never dead-lettered.
+    max_retries: Option<i64>,
+
+    /// The topic to forward messages to once retries are exhausted.
+    dead_letter: Option<Arc<TopicInner>>,
+
+    /// Limits on the messages being processed at once, if configured.
+    flow_control: Option<Arc<flow_control::FlowControl>>,
 
     handler: OnceLock<Arc<SubHandler>>,
     subscribe_fut: OnceLock<Shared<SubscribeFut>>,
@@ -180,11 +290,42 @@ impl SubHandler {
         self.handlers.write().unwrap().push(h);
     }
 
+    /// Returns the shutdown tracker, used by subscription implementations
+    /// to stop pulling new messages when the runtime shuts down.
+    pub(super) fn shutdown(&self) -> &shutdown::Tracker {
+        &self.obj.shutdown
+    }
+
     pub(super) fn handle_message(
         &self,
         msg: Message,
     ) -> Pin<Box<dyn Future<Output = Result<(), api::Error>> + Send + '_>> {
         Box::pin(async move {
+            // Redriven messages are only processed by the subscription
+            // they were dead-lettered from.
+            if let Some(sub) = msg.data.attrs.get(dead_letter::ATTR_REDRIVE_SUBSCRIPTION) {
+                if sub.as_str() != self.obj.subscription.as_ref() {
+                    return Ok(());
+                }
+            }
+
+            // Track the message from the moment it's received, so the shutdown
+            // waits for messages that are still waiting for capacity.
+            let _task = self.obj.shutdown.track_handler();
+
+            // Wait until there's capacity for the message before decoding it,
+            // so bursts of large messages aren't all processed at once.
+            let permit = match &self.obj.flow_control {
+                Some(fc) => {
+                    let canceled = self.obj.shutdown.handlers_canceled();
+                    tokio::select! {
+                        permit = fc.acquire(msg.data.raw_body.len()) => Some(permit),
+                        _ = canceled.cancelled() => return Err(api::Error::shutting_down()),
+                    }
+                }
+                None => None,
+            };
+
             let span = SpanKey(TraceId::generate(), SpanId::generate());
 
             let parent_trace_id: Option<TraceId> = msg
@@ -225,6 +366,7 @@ impl SubHandler {
                 internal_caller: None,
                 start,
                 start_time,
+                deadline: None,
                 data: RequestData::PubSub(PubSubRequestData {
                     service: self.obj.service.clone(),
                     topic: self.obj.topic.clone(),
@@ -234,6 +376,7 @@ impl SubHandler {
                     attempt: msg.attempt,
                     payload: msg.data.raw_body.clone(),
                     parsed_payload,
+                    dead_letter: dead_letter::parse(&msg.data.attrs),
                 }),
             });
 
@@ -248,22 +391,115 @@ impl SubHandler {
                     Err(parse_error)
                 } else {
                     let handler = self.next_handler();
-                    handler.handle_message(req.clone()).await
+                    let canceled = self.obj.shutdown.handlers_canceled();
+                    tokio::select! {
+                        result = handler.handle_message(req.clone()) => result,
+                        _ = canceled.cancelled() => Err(api::Error::shutting_down()),
+                    }
                 }
             };
 
             logger.info(Some(&req), "request completed", None);
 
+            // Forward the message to the dead letter topic if this was the final attempt.
+            let dead_lettered = match &result {
+                Err(err) if self.should_dead_letter(msg.attempt, err) => {
+                    self.forward_dead_letter(&req, msg, err).await
+                }
+                _ => false,
+            };
+
+            let duration = tokio::time::Instant::now().duration_since(start);
+            if let Some(permit) = permit {
+                permit.complete(duration, &result);
+            }
+
+            let code = match &result {
+                Ok(()) => "ok".to_string(),
+                Err(err) => err.code.to_string(),
+            };
+            crate::metrics::record_pubsub_message(
+                &self.obj.topic,
+                &self.obj.subscription,
+                &code,
+                duration,
+            );
+
             let resp = model::Response {
                 request: req,
-                duration: tokio::time::Instant::now().duration_since(start),
+                duration,
                 data: ResponseData::PubSub(result.clone()),
             };
             self.obj.tracer.request_span_end(&resp);
+
+            // The message has been handed off to the dead letter topic,
+            // so acknowledge it.
+            if dead_lettered {
+                return Ok(());
+            }
             result
         })
     }
 
+    fn should_dead_letter(&self, attempt: u32, err: &api::Error) -> bool {
+        // Handlers canceled by a graceful shutdown didn't fail on their own.
+        if self.obj.shutdown.is_initiated() && err.code == api::ErrCode::Unavailable {
+            return false;
+        }
+        match (&self.obj.dead_letter, self.obj.max_retries) {
+            (Some(_), Some(max_retries)) => dead_letter::is_final_attempt(attempt, max_retries),
+            _ => false,
+        }
+    }
+
+    /// Forwards a message that exhausted its retries to the dead letter topic.
+    /// Reports whether the message was forwarded.
+    async fn forward_dead_letter(
+        &self,
+        req: &model::Request,
+        msg: Message,
+        err: &api::Error,
+    ) -> bool {
+        let Some(topic) = &self.obj.dead_letter else {
+            return false;
+        };
+
+        let attrs = dead_letter::forward_attrs(
+            msg.data.attrs,
+            &self.obj.topic,
+            &self.obj.subscription,
+            &msg.id,
+            msg.attempt,
+            err,
+        );
+        let data = MessageData {
+            attrs,
+            raw_body: msg.data.raw_body,
+        };
+
+        let logger = crate::log::root();
+        match topic.publish_raw(data, Some(req)).await {
+            Ok(_) => {
+                logger.warn(
+                    Some(req),
+                    format!("retries exhausted, forwarded message to {}", topic.name),
+                    Some(err),
+                    None,
+                );
+                true
+            }
+            Err(fwd_err) => {
+                logger.error(
+                    Some(req),
+                    format!("unable to forward message to {}", topic.name),
+                    Some(fwd_err),
+                    None,
+                );
+                false
+            }
+        }
+    }
+
     fn next_handler(&self) -> Arc<dyn SubscriptionHandler> {
         let handlers = self.handlers.read().unwrap();
         let n = handlers.len();
@@ -282,20 +518,33 @@ impl SubHandler {
 
 impl Manager {
     pub fn new(
+        secrets: &secrets::Manager,
         tracer: Tracer,
         clusters: Vec<pb::PubSubCluster>,
         md: &meta::Data,
+        shutdown: shutdown::Tracker,
+        testing: bool,
     ) -> anyhow::Result<Self> {
-        let (topic_cfg, sub_cfg) = make_cfg_maps(clusters, md)?;
+        let mut clusters = clusters;
+        if testing {
+            // Deliver messages in-process for the topics that aren't configured,
+            // so tests can exercise subscriptions without external infrastructure.
+            clusters.extend(memory_test_cluster(md, &clusters));
+        }
+
+        let memory = Arc::new(memory::Hub::new());
+        let (topic_cfg, sub_cfg) = make_cfg_maps(secrets, &memory, clusters, md)?;
 
         Ok(Self {
             publisher_id: xid::new(),
             tracer,
+            shutdown,
             topic_cfg,
             sub_cfg,
             topics: Arc::default(),
             subs: Arc::default(),
             push_registry: PushHandlerRegistry::new(),
+            memory,
         })
     }
 
@@ -312,12 +561,17 @@ impl Manager {
         let topic = Arc::new({
             if let Some(cfg) = self.topic_cfg.get(&name) {
                 let imp = cfg.cluster.topic(&cfg.cfg, self.publisher_id);
+                let buffer =
+                    cfg.cfg.publish_buffer.as_ref().map(|buf| {
+                        buffer::Buffer::new(imp.clone(), buffer::Config::from_config(buf))
+                    });
                 TopicInner {
                     name: name.clone(),
                     imp,
                     tracer: self.tracer.clone(),
                     attr_fields: cfg.attr_fields.clone(),
                     ordering_attr: cfg.cfg.ordering_attr.clone(),
+                    buffer,
                 }
             } else {
                 TopicInner {
@@ -326,6 +580,7 @@ impl Manager {
                     tracer: self.tracer.clone(),
                     attr_fields: Arc::new(vec![]),
                     ordering_attr: None,
+                    buffer: None,
                 }
             }
         });
@@ -348,6 +603,12 @@ impl Manager {
                     self.push_registry.register(sub_id, push_handler);
                 }
 
+                let dead_letter = cfg
+                    .meta
+                    .dead_letter_topic
+                    .as_ref()
+                    .and_then(|topic| self.topic_impl(topic.into()));
+
                 Arc::new(SubscriptionObj {
                     inner,
                     tracer: self.tracer.clone(),
@@ -355,6 +616,14 @@ impl Manager {
                     topic: name.topic.clone(),
                     subscription: name.subscription.clone(),
                     schema: cfg.schema.clone(),
+                    shutdown: self.shutdown.clone(),
+                    max_retries: cfg.meta.retry_policy.as_ref().map(|p| p.max_retries),
+                    dead_letter,
+                    flow_control: cfg.cfg.flow_control.as_ref().map(|fc| {
+                        Arc::new(flow_control::FlowControl::new(
+                            flow_control::Config::from_config(fc, cfg.meta.max_concurrency),
+                        ))
+                    }),
                     handler: OnceLock::new(),
                     subscribe_fut: Default::default(),
                 })
@@ -375,6 +644,10 @@ impl Manager {
                     // Use a null schema.
                     schema: JSONSchema::null(),
 
+                    shutdown: self.shutdown.clone(),
+                    max_retries: None,
+                    dead_letter: None,
+                    flow_control: None,
                     handler: OnceLock::new(),
                     subscribe_fut: Default::default(),
                 })
@@ -386,9 +659,71 @@ impl Manager {
         Some(sub)
     }
 
+    /// Republishes the dead-lettered message being processed by the given request
+    /// to the topic it was dead-lettered from. The message is only delivered to
+    /// the subscription whose retries were exhausted, not to other subscriptions of the topic.
+    pub fn redrive(
+        &self,
+        source: Arc<model::Request>,
+    ) -> impl Future<Output = anyhow::Result<MessageId>> + 'static {
+        let publish = self.prepare_redrive(source);
+        async move { publish?.await }
+    }
+
+    fn prepare_redrive(
+        &self,
+        source: Arc<model::Request>,
+    ) -> anyhow::Result<impl Future<Output = anyhow::Result<MessageId>> + 'static> {
+        let RequestData::PubSub(data) = &source.data else {
+            anyhow::bail!("redrive must be called while processing a pubsub message");
+        };
+        let Some(dead_letter) = &data.dead_letter else {
+            anyhow::bail!("message {} is not a dead-lettered message", data.message_id);
+        };
+        let Some(payload) = data.parsed_payload.clone() else {
+            anyhow::bail!("message {} has no valid payload", data.message_id);
+        };
+        let topic = self
+            .topic_impl(dead_letter.topic.clone())
+            .with_context(|| format!("topic {} not found", dead_letter.topic))?;
+
+        let attrs = HashMap::from([(
+            dead_letter::ATTR_REDRIVE_SUBSCRIPTION.to_string(),
+            dead_letter.subscription.to_string(),
+        )]);
+        Ok(topic.publish(payload, attrs, Some(source.clone())))
+    }
+
+    /// Returns readiness checks that verify each topic is reachable.
+    pub fn readiness_checks(&self) -> Vec<health::Check> {
+        self.topic_cfg
+            .iter()
+            .map(|(name, cfg)| {
+                let cluster = cfg.cluster.clone();
+                let topic_cfg = cfg.cfg.clone();
+                health::Check::new(format!("pubsub.topic.{}", name), move || {
+                    cluster.check_topic(&topic_cfg)
+                })
+            })
+            .collect()
+    }
+
     pub fn push_registry(&self) -> PushHandlerRegistry {
         self.push_registry.clone()
     }
+
+    /// Waits until the messages published to in-memory topics have been processed
+    /// by their subscriptions, including any redeliveries.
+    pub fn wait_for_pending_messages(&self) -> impl Future<Output = ()> + 'static {
+        let memory = self.memory.clone();
+        async move { memory.wait_idle().await }
+    }
+
+    /// Removes and returns the messages published to in-memory topics
+    /// that have yet to be delivered to their subscriptions.
+    pub fn drain_pending_messages(&self) -> Vec<PendingMessage> {
+        self.memory.drain()
+    }
 }
 
 #[derive(Debug)]
@@ -410,6 +745,8 @@ struct SubConfig {
 }
 
 fn make_cfg_maps(
+    secrets: &secrets::Manager,
+    memory: &Arc<memory::Hub>,
     clusters: Vec<pb::PubSubCluster>,
     md: &meta::Data,
 ) -> anyhow::Result<(
@@ -447,7 +784,7 @@ fn make_cfg_maps(
 
     let schemas = schema_builder.build();
     for cluster_cfg in clusters {
-        let cluster = new_cluster(&cluster_cfg);
+        let cluster = new_cluster(secrets, memory, &cluster_cfg)?;
 
         for topic_cfg in cluster_cfg.topics {
             let Some(attr_fields) = meta_topics.get(&topic_cfg.encore_name) else {
@@ -490,27 +827,95 @@ fn make_cfg_maps(
     Ok((topic_map, sub_map))
 }
 
-fn new_cluster(cluster: &pb::PubSubCluster) -> Arc<dyn Cluster> {
+fn new_cluster(
+    secrets: &secrets::Manager,
+    memory: &Arc<memory::Hub>,
+    cluster: &pb::PubSubCluster,
+) -> anyhow::Result<Arc<dyn Cluster>> {
     let Some(provider) = &cluster.provider else {
         log::error!("missing PubSub cluster provider: {}", cluster.rid);
-        return Arc::new(NoopCluster);
+        return Ok(Arc::new(NoopCluster));
     };
 
     match provider {
-        pb::pub_sub_cluster::Provider::Gcp(_) => return Arc::new(gcp::Cluster::new()),
+        pb::pub_sub_cluster::Provider::Gcp(_) => return Ok(Arc::new(gcp::Cluster::new())),
         pb::pub_sub_cluster::Provider::Nsq(cfg) => {
-            return Arc::new(nsq::Cluster::new(cfg.hosts[0].clone()));
+            let cluster = nsq::Cluster::new(cfg)
+                .with_context(|| format!("invalid nsq cluster {}", cluster.rid))?;
+            return Ok(Arc::new(cluster));
         }
-        pb::pub_sub_cluster::Provider::Aws(_) => return Arc::new(sqs_sns::Cluster::new()),
+        pb::pub_sub_cluster::Provider::Aws(_) => return Ok(Arc::new(sqs_sns::Cluster::new())),
         pb::pub_sub_cluster::Provider::Encore(_) => {
             log::error!("Encore Cloud Pub/Sub not yet supported: {}", cluster.rid);
         }
-        pb::pub_sub_cluster::Provider::Azure(_) => {
-            log::error!("Azure Pub/Sub not yet supported: {}", cluster.rid);
+        pb::pub_sub_cluster::Provider::Azure(cfg) => {
+            let connection_string = cfg
+                .connection_string
+                .as_ref()
+                .map(|s| secrets.load(s.clone()));
+            let cluster = azure::Cluster::new(cfg, &cluster.topics, connection_string)
+                .with_context(|| format!("invalid azure service bus cluster {}", cluster.rid))?;
+            return Ok(Arc::new(cluster));
+        }
+        pb::pub_sub_cluster::Provider::Memory(_) => {
+            return Ok(Arc::new(memory::Cluster::new(memory.clone(), cluster)));
         }
     }
 
-    Arc::new(NoopCluster)
+    Ok(Arc::new(NoopCluster))
+}
+
+/// Returns an in-memory cluster for the topics in the metadata
+/// that aren't part of any of the given clusters, if any.
+fn memory_test_cluster(
+    md: &meta::Data,
+    clusters: &[pb::PubSubCluster],
+) -> Option<pb::PubSubCluster> {
+    let configured: HashSet<&str> = clusters
+        .iter()
+        .flat_map(|c| c.topics.iter().map(|t| t.encore_name.as_str()))
+        .collect();
+
+    let mut topics = Vec::new();
+    let mut subscriptions = Vec::new();
+    for topic in &md.pubsub_topics {
+        if configured.contains(topic.name.as_str()) {
+            continue;
+        }
+        topics.push(pb::PubSubTopic {
+            rid: format!("memory-topic-{}", topic.name),
+            encore_name: topic.name.clone(),
+            cloud_name: topic.name.clone(),
+            delivery_guarantee: pb::pub_sub_topic::DeliveryGuarantee::AtLeastOnce as i32,
+            ordering_attr: (!topic.ordering_key.is_empty()).then(|| topic.ordering_key.clone()),
+            publish_buffer: None,
+            provider_config: None,
+        });
+        for sub in &topic.subscriptions {
+            subscriptions.push(pb::PubSubSubscription {
+                rid: format!("memory-sub-{}-{}", topic.name, sub.name),
+                topic_encore_name: topic.name.clone(),
+                subscription_encore_name: sub.name.clone(),
+                topic_cloud_name: topic.name.clone(),
+                subscription_cloud_name: sub.name.clone(),
+                push_only: false,
+                flow_control: None,
+                provider_config: None,
+            });
+        }
+    }
+
+    if topics.is_empty() {
+        return None;
+    }
+    Some(pb::PubSubCluster {
+        rid: "memory-test-cluster".to_string(),
+        topics,
+        subscriptions,
+        provider: Some(pb::pub_sub_cluster::Provider::Memory(
+            pb::pub_sub_cluster::Memory {},
+        )),
+    })
 }
 
 fn message_attr_fields(
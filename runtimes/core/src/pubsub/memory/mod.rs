@@ -0,0 +1,542 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+use crate::api::APIResult;
+use crate::encore::parser::meta::v1 as meta;
+use crate::encore::runtime::v1 as pb;
+use crate::pubsub::manager::SubHandler;
+use crate::pubsub::{self, dead_letter, MessageData, MessageId, SubName};
+
+/// Delivers messages to the subscriptions of a topic within the process.
+///
+/// Messages are queued for each subscription of the cluster as they're published,
+/// and delivered once the subscription is subscribed to. Messages that fail
+/// processing are redelivered according to the subscription's retry policy,
+/// and messages with the same ordering key are delivered one at a time, in order.
+#[derive(Debug)]
+pub struct Cluster {
+    hub: Arc<Hub>,
+
+    /// The queues of each subscription, by the cloud name of their topic.
+    queues: HashMap<String, Vec<Arc<Queue>>>,
+}
+
+impl Cluster {
+    pub fn new(hub: Arc<Hub>, cfg: &pb::PubSubCluster) -> Self {
+        let mut queues: HashMap<String, Vec<Arc<Queue>>> = HashMap::new();
+        for sub in &cfg.subscriptions {
+            let queue = Arc::new(Queue::new(
+                SubName {
+                    topic: sub.topic_encore_name.clone().into(),
+                    subscription: sub.subscription_encore_name.clone().into(),
+                },
+                hub.changed.clone(),
+            ));
+            hub.queues.lock().unwrap().push(queue.clone());
+            queues
+                .entry(sub.topic_cloud_name.clone())
+                .or_default()
+                .push(queue);
+        }
+        Self { hub, queues }
+    }
+
+    fn queue(&self, topic: &str, subscription: &SubName) -> Arc<Queue> {
+        if let Some(queue) = self
+            .queues
+            .get(topic)
+            .and_then(|queues| queues.iter().find(|q| &q.name == subscription))
+        {
+            return queue.clone();
+        }
+
+        // The subscription wasn't part of the cluster config, so nothing can publish to it.
+        Arc::new(Queue::new(subscription.clone(), self.hub.changed.clone()))
+    }
+}
+
+impl pubsub::Cluster for Cluster {
+    fn topic(&self, cfg: &pb::PubSubTopic, _publisher_id: xid::Id) -> Arc<dyn pubsub::Topic> {
+        Arc::new(Topic {
+            queues: self
+                .queues
+                .get(&cfg.cloud_name)
+                .cloned()
+                .unwrap_or_default(),
+        })
+    }
+
+    fn subscription(
+        &self,
+        cfg: &pb::PubSubSubscription,
+        meta: &meta::pub_sub_topic::Subscription,
+    ) -> Arc<dyn pubsub::Subscription> {
+        let name = SubName {
+            topic: cfg.topic_encore_name.clone().into(),
+            subscription: cfg.subscription_encore_name.clone().into(),
+        };
+        Arc::new(Subscription {
+            queue: self.queue(&cfg.topic_cloud_name, &name),
+            max_concurrency: meta.max_concurrency.map_or(100, |v| v.max(1) as usize),
+            retry: RetryPolicy::new(meta),
+        })
+    }
+}
+
+/// Tracks the in-memory subscription queues across clusters,
+/// to let tests wait for or inspect the messages that have yet to be processed.
+#[derive(Debug, Default)]
+pub struct Hub {
+    queues: Mutex<Vec<Arc<Queue>>>,
+
+    /// Notified whenever the state of a queue changes.
+    changed: Arc<Notify>,
+}
+
+/// A message that has been published but not yet delivered to a subscription.
+#[derive(Debug)]
+pub struct PendingMessage {
+    pub subscription: SubName,
+    pub id: MessageId,
+    pub attempt: u32,
+    pub data: MessageData,
+}
+
+impl Hub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Waits until the subscriptions that are subscribed to have processed all
+    /// their messages, including redeliveries of failed messages.
+    /// Messages for subscriptions that nobody subscribes to are not waited for.
+    pub async fn wait_idle(&self) {
+        loop {
+            let changed = self.changed.notified();
+            tokio::pin!(changed);
+            changed.as_mut().enable();
+
+            let idle = self
+                .queues
+                .lock()
+                .unwrap()
+                .iter()
+                .all(|q| q.state.lock().unwrap().is_idle());
+            if idle {
+                return;
+            }
+            changed.await;
+        }
+    }
+
+    /// Removes and returns the messages that are queued for delivery,
+    /// in the order they would have been delivered for each subscription.
+    /// Messages currently being processed are not included.
+    pub fn drain(&self) -> Vec<PendingMessage> {
+        let queues = self.queues.lock().unwrap();
+        let mut drained = Vec::new();
+        for queue in queues.iter() {
+            let ready = std::mem::take(&mut queue.state.lock().unwrap().ready);
+            drained.extend(ready.into_iter().map(|d| PendingMessage {
+                subscription: queue.name.clone(),
+                id: d.payload.id.clone(),
+                attempt: d.attempt,
+                data: d.payload.data(),
+            }));
+        }
+        self.changed.notify_waiters();
+        drained
+    }
+}
+
+#[derive(Debug)]
+struct Topic {
+    queues: Vec<Arc<Queue>>,
+}
+
+impl pubsub::Topic for Topic {
+    fn publish(
+        &self,
+        msg: MessageData,
+        ordering_key: Option<String>,
+    ) -> Pin<Box<dyn Future<Output = Result<MessageId>> + Send + '_>> {
+        let payload = Arc::new(Payload {
+            id: xid::new().to_string(),
+            publish_time: chrono::Utc::now(),
+            attrs: msg.attrs,
+            raw_body: msg.raw_body,
+        });
+        for queue in &self.queues {
+            queue.push(Delivery {
+                payload: payload.clone(),
+                attempt: 1,
+                ordering_key: ordering_key.clone(),
+                not_before: None,
+            });
+        }
+        let id = payload.id.clone();
+        Box::pin(async move { Ok(id) })
+    }
+}
+
+#[derive(Debug)]
+struct Subscription {
+    queue: Arc<Queue>,
+    max_concurrency: usize,
+    retry: RetryPolicy,
+}
+
+impl pubsub::Subscription for Subscription {
+    fn subscribe(
+        &self,
+        handler: Arc<SubHandler>,
+    ) -> Pin<Box<dyn Future<Output = APIResult<()>> + Send + 'static>> {
+        let queue = self.queue.clone();
+        let retry = self.retry.clone();
+        let sem = Arc::new(tokio::sync::Semaphore::new(self.max_concurrency));
+
+        Box::pin(async move {
+            queue.set_subscribed(true);
+
+            // Stop delivering messages when the runtime shuts down.
+            let stop = handler.shutdown().initiated();
+            loop {
+                let permit = tokio::select! {
+                    permit = sem.clone().acquire_owned() => permit.expect("semaphore is closed"),
+                    _ = stop.cancelled() => break,
+                };
+                let Some(delivery) = queue.next(&stop).await else {
+                    break;
+                };
+
+                let queue = queue.clone();
+                let handler = handler.clone();
+                let retry = retry.clone();
+                tokio::spawn(async move {
+                    let result = handler.handle_message(delivery.message()).await;
+                    queue.settle(delivery, result.is_ok(), &retry);
+                    drop(permit);
+                });
+            }
+
+            queue.set_subscribed(false);
+            Ok(())
+        })
+    }
+}
+
+/// The messages of a subscription, waiting to be delivered.
+#[derive(Debug)]
+struct Queue {
+    name: SubName,
+    state: Mutex<QueueState>,
+
+    /// Notified when a message may have become ready for delivery.
+    ready: Notify,
+
+    /// Notified whenever the state of the queue changes, shared with the hub.
+    changed: Arc<Notify>,
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    ready: VecDeque<Delivery>,
+    in_flight: usize,
+
+    /// The ordering keys of the messages being processed.
+    active_keys: HashSet<String>,
+
+    /// Whether the subscription is being subscribed to.
+    subscribed: bool,
+}
+
+impl QueueState {
+    fn is_idle(&self) -> bool {
+        !self.subscribed || (self.ready.is_empty() && self.in_flight == 0)
+    }
+
+    /// Takes the next message that can be delivered, or returns when the
+    /// earliest message waiting to be redelivered becomes ready, if any.
+    ///
+    /// A message with an ordering key can only be delivered once the earlier
+    /// messages with the same key have been processed.
+    fn take_next(&mut self, now: Instant) -> Result<Delivery, Option<Instant>> {
+        let mut blocked: HashSet<&str> = HashSet::new();
+        let mut next_ready: Option<Instant> = None;
+        let mut found = None;
+
+        for (idx, d) in self.ready.iter().enumerate() {
+            let key = d.ordering_key.as_deref();
+            if let Some(key) = key {
+                if self.active_keys.contains(key) || !blocked.insert(key) {
+                    continue;
+                }
+            }
+            match d.not_before {
+                Some(t) if t > now => {
+                    next_ready = Some(next_ready.map_or(t, |n| n.min(t)));
+                }
+                _ => {
+                    found = Some(idx);
+                    break;
+                }
+            }
+        }
+
+        let Some(idx) = found else {
+            return Err(next_ready);
+        };
+        let delivery = self.ready.remove(idx).expect("index in bounds");
+        if let Some(key) = &delivery.ordering_key {
+            self.active_keys.insert(key.clone());
+        }
+        self.in_flight += 1;
+        Ok(delivery)
+    }
+}
+
+impl Queue {
+    fn new(name: SubName, changed: Arc<Notify>) -> Self {
+        Self {
+            name,
+            state: Mutex::new(QueueState::default()),
+            ready: Notify::new(),
+            changed,
+        }
+    }
+
+    fn push(&self, delivery: Delivery) {
+        self.state.lock().unwrap().ready.push_back(delivery);
+        self.notify();
+    }
+
+    fn set_subscribed(&self, subscribed: bool) {
+        self.state.lock().unwrap().subscribed = subscribed;
+        self.notify();
+    }
+
+    fn notify(&self) {
+        self.ready.notify_one();
+        self.changed.notify_waiters();
+    }
+
+    /// Waits for the next message to deliver, until `stop` is canceled.
+    async fn next(&self, stop: &tokio_util::sync::CancellationToken) -> Option<Delivery> {
+        loop {
+            let ready = self.ready.notified();
+            tokio::pin!(ready);
+            ready.as_mut().enable();
+
+            let next_ready = match self.state.lock().unwrap().take_next(Instant::now()) {
+                Ok(delivery) => return Some(delivery),
+                Err(next_ready) => next_ready,
+            };
+
+            let retry_wait = async {
+                match next_ready {
+                    Some(t) => tokio::time::sleep_until(t).await,
+                    None => futures::future::pending().await,
+                }
+            };
+            tokio::select! {
+                _ = ready => {}
+                _ = retry_wait => {}
+                _ = stop.cancelled() => return None,
+            }
+        }
+    }
+
+    /// Records the outcome of processing a delivered message,
+    /// scheduling it for redelivery if it failed and has retries left.
+    fn settle(&self, delivery: Delivery, ok: bool, retry: &RetryPolicy) {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.in_flight -= 1;
+            if let Some(key) = &delivery.ordering_key {
+                state.active_keys.remove(key);
+            }
+
+            // Messages failing their final attempt are forwarded to the
+            // dead letter topic, if any, by the handler, and dropped here.
+            if !ok && !dead_letter::is_final_attempt(delivery.attempt, retry.max_retries) {
+                let backoff = retry.backoff(delivery.attempt);
+                let redelivery = Delivery {
+                    attempt: delivery.attempt + 1,
+                    not_before: Some(Instant::now() + backoff),
+                    ..delivery
+                };
+
+                // Keep ordered messages ahead of the later messages with the same key.
+                if redelivery.ordering_key.is_some() {
+                    state.ready.push_front(redelivery);
+                } else {
+                    state.ready.push_back(redelivery);
+                }
+            }
+        }
+        self.notify();
+    }
+}
+
+#[derive(Debug)]
+struct Payload {
+    id: MessageId,
+    publish_time: chrono::DateTime<chrono::Utc>,
+    attrs: HashMap<String, String>,
+    raw_body: Vec<u8>,
+}
+
+impl Payload {
+    fn data(&self) -> MessageData {
+        MessageData {
+            attrs: self.attrs.clone(),
+            raw_body: self.raw_body.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Delivery {
+    payload: Arc<Payload>,
+    /// starts at 1
+    attempt: u32,
+    ordering_key: Option<String>,
+    /// When the message may be redelivered, if it's waiting to be retried.
+    not_before: Option<Instant>,
+}
+
+impl Delivery {
+    fn message(&self) -> pubsub::Message {
+        pubsub::Message {
+            id: self.payload.id.clone(),
+            publish_time: Some(self.payload.publish_time),
+            attempt: self.attempt,
+            data: self.payload.data(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RetryPolicy {
+    min_backoff: Duration,
+    max_backoff: Duration,
+    max_retries: i64,
+}
+
+impl RetryPolicy {
+    fn new(meta: &meta::pub_sub_topic::Subscription) -> Self {
+        match &meta.retry_policy {
+            Some(retry) => Self {
+                min_backoff: Duration::from_nanos(retry.min_backoff.max(0) as u64),
+                max_backoff: Duration::from_nanos(retry.max_backoff.max(0) as u64),
+                max_retries: retry.max_retries,
+            },
+            // Default to 2 retries like the other providers do for local development,
+            // and redeliver immediately since there's no broker to protect.
+            None => Self {
+                min_backoff: Duration::ZERO,
+                max_backoff: Duration::ZERO,
+                max_retries: 2,
+            },
+        }
+    }
+
+    /// The backoff before redelivering a message that failed the given attempt.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exp = attempt.saturating_sub(1).min(31);
+        self.min_backoff
+            .saturating_mul(1 << exp)
+            .min(self.max_backoff.max(self.min_backoff))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delivery(id: &str, key: Option<&str>) -> Delivery {
+        Delivery {
+            payload: Arc::new(Payload {
+                id: id.to_string(),
+                publish_time: chrono::Utc::now(),
+                attrs: HashMap::new(),
+                raw_body: vec![],
+            }),
+            attempt: 1,
+            ordering_key: key.map(String::from),
+            not_before: None,
+        }
+    }
+
+    fn take_id(queue: &Queue) -> Option<String> {
+        let mut state = queue.state.lock().unwrap();
+        state
+            .take_next(Instant::now())
+            .ok()
+            .map(|d| d.payload.id.clone())
+    }
+
+    #[tokio::test]
+    async fn test_queue_ordering_and_redelivery() {
+        let name = SubName {
+            topic: "topic".into(),
+            subscription: "sub".into(),
+        };
+        let queue = Queue::new(name, Arc::new(Notify::new()));
+        queue.push(delivery("a1", Some("a")));
+        queue.push(delivery("a2", Some("a")));
+        queue.push(delivery("b1", Some("b")));
+        queue.push(delivery("c", None));
+
+        // "a2" waits for "a1" to be processed.
+        let a1 = queue
+            .state
+            .lock()
+            .unwrap()
+            .take_next(Instant::now())
+            .unwrap();
+        assert_eq!(a1.payload.id, "a1");
+        assert_eq!(take_id(&queue).as_deref(), Some("b1"));
+        assert_eq!(take_id(&queue).as_deref(), Some("c"));
+        assert_eq!(take_id(&queue), None);
+
+        // A failed ordered message is redelivered before the later messages with its key.
+        let retry = RetryPolicy {
+            min_backoff: Duration::from_secs(60),
+            max_backoff: Duration::from_secs(60),
+            max_retries: 1,
+        };
+        queue.settle(a1, false, &retry);
+        let next_ready = queue
+            .state
+            .lock()
+            .unwrap()
+            .take_next(Instant::now())
+            .unwrap_err();
+        assert!(next_ready.is_some());
+
+        let redelivered = queue
+            .state
+            .lock()
+            .unwrap()
+            .take_next(Instant::now() + Duration::from_secs(61))
+            .unwrap();
+        assert_eq!(redelivered.payload.id, "a1");
+        assert_eq!(redelivered.attempt, 2);
+
+        // The final attempt failing drops the message, unblocking the key.
+        queue.settle(redelivered, false, &retry);
+        assert_eq!(take_id(&queue).as_deref(), Some("a2"));
+
+        let hub = Hub::new();
+        hub.queues.lock().unwrap().push(Arc::new(queue));
+        assert!(hub.drain().is_empty());
+    }
+}
@@ -5,6 +5,7 @@ use std::pin::Pin;
 use std::sync::Arc;
 
 pub use manager::{Manager, SubscriptionObj, TopicObj};
+pub use memory::PendingMessage;
 pub use push_registry::PushHandlerRegistry;
 
 use crate::api::APIResult;
@@ -14,8 +15,13 @@ use crate::names::EncoreName;
 use crate::pubsub::manager::SubHandler;
 use crate::{api, model};
 
+mod azure;
+mod buffer;
+mod dead_letter;
+mod flow_control;
 mod gcp;
 mod manager;
+mod memory;
 mod noop;
 mod nsq;
 mod push_registry;
@@ -28,6 +34,12 @@ pub struct MessageData {
     pub raw_body: Vec<u8>,
 }
 
+/// A message to publish as part of a batch.
+pub struct BatchMessage {
+    pub data: MessageData,
+    pub ordering_key: Option<String>,
+}
+
 pub struct Message {
     pub id: MessageId,
     pub publish_time: Option<chrono::DateTime<chrono::Utc>>,
@@ -43,6 +55,14 @@ trait Cluster: Debug + Send + Sync {
         cfg: &pb::PubSubSubscription,
         meta: &meta::pub_sub_topic::Subscription,
     ) -> Arc<dyn Subscription + 'static>;
+
+    /// Checks that the topic is reachable, for readiness checks.
+    fn check_topic(
+        &self,
+        _cfg: &pb::PubSubTopic,
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send + 'static>> {
+        Box::pin(async { Ok(()) })
+    }
 }
 
 trait Topic: Debug + Send + Sync {
@@ -51,6 +71,44 @@ trait Topic: Debug + Send + Sync {
         msg: MessageData,
         ordering_key: Option<String>,
     ) -> Pin<Box<dyn Future<Output = anyhow::Result<MessageId>> + Send + '_>>;
+
+    /// Publishes a batch of messages, returning the result of each in the same order.
+    /// Providers that support batching send the batch in as few requests as possible.
+    ///
+    /// Once a message fails to publish, the messages after it may not be
+    /// published either, so that messages are never published out of order.
+    fn publish_batch(
+        &self,
+        msgs: Vec<BatchMessage>,
+    ) -> Pin<Box<dyn Future<Output = Vec<anyhow::Result<MessageId>>> + Send + '_>> {
+        Box::pin(async move {
+            let mut results = Vec::with_capacity(msgs.len());
+            let mut failed = false;
+            for msg in msgs {
+                if failed {
+                    results.push(Err(not_published()));
+                    continue;
+                }
+                let result = self.publish(msg.data, msg.ordering_key).await;
+                failed = result.is_err();
+                results.push(result);
+            }
+            results
+        })
+    }
+}
+
+/// The error for a message in a batch that wasn't published
+/// because an earlier message in the batch failed to publish.
+fn not_published() -> anyhow::Error {
+    anyhow::anyhow!("not published, as an earlier message in the batch failed to publish")
+}
+
+/// Fails every message of a batch of the given size with the same error.
+fn fail_batch(len: usize, err: &anyhow::Error) -> Vec<anyhow::Result<MessageId>> {
+    (0..len)
+        .map(|_| Err(anyhow::anyhow!("{:#}", err)))
+        .collect()
 }
 
 trait Subscription: Debug + Send + Sync {
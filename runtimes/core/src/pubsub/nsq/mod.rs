@@ -1,5 +1,9 @@
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::Arc;
 
+use anyhow::Context;
+
 use crate::encore::parser::meta::v1 as meta;
 use crate::encore::runtime::v1 as pb;
 use crate::pubsub;
@@ -11,13 +15,23 @@ mod topic;
 
 #[derive(Debug)]
 pub struct Cluster {
-    /// Address to the NSQ server.
-    address: String,
+    /// Addresses of the nsqd servers.
+    hosts: Arc<[String]>,
+
+    /// HTTP addresses of the nsqlookupd servers used to discover
+    /// the nsqd servers to consume from, if any.
+    lookupd_hosts: Arc<[String]>,
 }
 
 impl Cluster {
-    pub fn new(address: String) -> Self {
-        Self { address }
+    pub fn new(cfg: &pb::pub_sub_cluster::Nsq) -> anyhow::Result<Self> {
+        if cfg.hosts.is_empty() && cfg.lookupd_hosts.is_empty() {
+            anyhow::bail!("no nsqd or nsqlookupd hosts configured");
+        }
+        Ok(Self {
+            hosts: cfg.hosts.clone().into(),
+            lookupd_hosts: cfg.lookupd_hosts.clone().into(),
+        })
     }
 }
 
@@ -27,7 +41,7 @@ impl pubsub::Cluster for Cluster {
         cfg: &pb::PubSubTopic,
         _publisher_id: xid::Id,
     ) -> Arc<dyn pubsub::Topic + 'static> {
-        Arc::new(NsqTopic::new(self.address.clone(), cfg))
+        Arc::new(NsqTopic::new(&self.hosts, cfg))
     }
 
     fn subscription(
@@ -35,6 +49,36 @@ impl pubsub::Cluster for Cluster {
         cfg: &pb::PubSubSubscription,
         meta: &meta::pub_sub_topic::Subscription,
     ) -> Arc<dyn pubsub::Subscription + 'static> {
-        Arc::new(NsqSubscription::new(self.address.clone(), cfg, meta))
+        Arc::new(NsqSubscription::new(
+            &self.hosts,
+            &self.lookupd_hosts,
+            cfg,
+            meta,
+        ))
+    }
+
+    fn check_topic(
+        &self,
+        _cfg: &pb::PubSubTopic,
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send + 'static>> {
+        let hosts = self.hosts.clone();
+        Box::pin(async move {
+            // The topic is usable as long as any of the hosts is reachable,
+            // since publishing fails over to the others.
+            let mut last_err = None;
+            for addr in hosts.iter() {
+                match tokio::net::TcpStream::connect(addr).await {
+                    Ok(_) => return Ok(()),
+                    Err(err) => last_err = Some((addr, err)),
+                }
+            }
+            match last_err {
+                Some((addr, err)) => {
+                    Err(err).with_context(|| format!("unable to connect to nsqd at {}", addr))
+                }
+                // Without any nsqd hosts the topic can only be subscribed to.
+                None => Ok(()),
+            }
+        })
     }
 }
@@ -6,7 +6,8 @@ use std::time::Duration;
 
 use anyhow::{Context, Result};
 use tokio_nsq::{
-    NSQChannel, NSQConsumerConfig, NSQConsumerConfigSources, NSQMessage, NSQRequeueDelay, NSQTopic,
+    NSQChannel, NSQConsumerConfig, NSQConsumerConfigSources, NSQConsumerLookupConfig, NSQMessage,
+    NSQRequeueDelay, NSQTopic,
 };
 
 use crate::api::APIResult;
@@ -18,7 +19,7 @@ use crate::pubsub::nsq::topic::EncodedMessage;
 use crate::pubsub::Subscription;
 
 pub struct NsqSubscription {
-    addr: String,
+    sources: Vec<String>,
     config: NSQConsumerConfig,
     max_retries: i64,
 }
@@ -26,22 +27,39 @@ pub struct NsqSubscription {
 impl Debug for NsqSubscription {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("NsqSubscription")
-            .field("addr", &self.addr)
+            .field("sources", &self.sources)
             .finish()
     }
 }
 
 impl NsqSubscription {
     pub(super) fn new(
-        addr: String,
+        hosts: &[String],
+        lookupd_hosts: &[String],
         cfg: &pb::PubSubSubscription,
         meta: &meta::pub_sub_topic::Subscription,
     ) -> Self {
         let topic = NSQTopic::new(cfg.topic_cloud_name.clone()).unwrap();
         let channel = NSQChannel::new(cfg.subscription_cloud_name.clone()).unwrap();
 
+        // Consume from every nsqd host, either the configured ones or
+        // the ones nsqlookupd knows to have the topic.
+        let (sources, config_sources) = if lookupd_hosts.is_empty() {
+            (
+                hosts.to_vec(),
+                NSQConsumerConfigSources::Daemons(hosts.to_vec()),
+            )
+        } else {
+            let lookup = NSQConsumerLookupConfig::new()
+                .set_addresses(lookupd_hosts.iter().cloned().collect());
+            (
+                lookupd_hosts.to_vec(),
+                NSQConsumerConfigSources::Lookup(lookup),
+            )
+        };
+
         let mut config = NSQConsumerConfig::new(topic, channel)
-            .set_sources(NSQConsumerConfigSources::Daemons(vec![addr.clone()]))
+            .set_sources(config_sources)
             .set_max_in_flight(meta.max_concurrency.map_or(100, |v| v as u32));
 
         // For local development, default to 2 retries if we don't have a retry policy.
@@ -67,7 +85,7 @@ impl NsqSubscription {
         }
 
         NsqSubscription {
-            addr,
+            sources,
             config,
             max_retries,
         }
@@ -83,14 +101,21 @@ impl Subscription for NsqSubscription {
         let max_retries = self.max_retries;
 
         Box::pin(async move {
+            // Stop consuming new messages when the runtime shuts down.
+            let stop = handler.shutdown().initiated();
             loop {
-                let Some(msg) = consumer.consume_filtered().await else {
+                let msg = tokio::select! {
+                    msg = consumer.consume_filtered() => msg,
+                    _ = stop.cancelled() => return Ok(()),
+                };
+                let Some(msg) = msg else {
                     continue;
                 };
 
                 // If the attempt exceeds the max retries, drop it.
                 // Attempt starts at 1 for the first delivery, which means
-                // the retry count is (attempt-1).
+                // the retry count is (attempt-1). Messages failing their final
+                // attempt are forwarded to the dead letter topic, if any, by the handler.
                 let retry = msg.attempt as i64 - 1;
                 if retry > max_retries {
                     msg.finish().await;
@@ -127,7 +152,7 @@ async fn handle_message(
         serde_json::from_slice::<EncodedMessage>(&body).context("failed to decode message")?;
 
     let publish_time = nano_timestamp(timestamp);
-    let raw_body = serde_json::to_vec_pretty(&encoded.body).unwrap_or_default();
+    let raw_body = serde_json::to_vec(&encoded.body).unwrap_or_default();
     let pubsub_msg = pubsub::Message {
         id: encoded.id,
         publish_time,
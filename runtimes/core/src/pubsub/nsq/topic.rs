@@ -1,67 +1,113 @@
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
 use std::future::Future;
+use std::hash::{Hash, Hasher};
 use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::{mpsc, oneshot, watch};
 use tokio_nsq::{NSQEvent, NSQProducerConfig, NSQTopic};
 
 use crate::encore::runtime::v1 as pb;
-use crate::pubsub::{MessageData, MessageId, Topic};
+use crate::pubsub::{self, BatchMessage, MessageData, MessageId, Topic};
+
+/// How long to wait for an nsqd host to connect when no host is healthy.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
 
 struct PublishRequest {
-    msg: MessageData,
-    resp: oneshot::Sender<Result<MessageId>>,
+    /// The encoded messages to publish.
+    bodies: Vec<Vec<u8>>,
+    resp: oneshot::Sender<Result<()>>,
 }
 
+/// Publishes messages across a set of nsqd hosts, failing over
+/// to the next host when publishing to one of them fails.
 #[derive(Debug)]
 pub struct NsqTopic {
+    producers: Vec<Producer>,
+    next: AtomicUsize,
+}
+
+/// A connection to a single nsqd host, running in a background task.
+#[derive(Debug)]
+struct Producer {
+    addr: String,
     tx: mpsc::Sender<PublishRequest>,
+    healthy: watch::Receiver<bool>,
 }
 
 impl NsqTopic {
-    pub(super) fn new(addr: String, cfg: &pb::PubSubTopic) -> Self {
-        let cloud_name = cfg.cloud_name.clone();
+    pub(super) fn new(hosts: &[String], cfg: &pb::PubSubTopic) -> Self {
+        let producers = hosts
+            .iter()
+            .map(|addr| Producer::spawn(addr.clone(), cfg.cloud_name.clone()))
+            .collect();
+        NsqTopic {
+            producers,
+            next: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Producer {
+    fn spawn(addr: String, cloud_name: String) -> Self {
         let (tx, mut rx) = mpsc::channel::<PublishRequest>(32);
+        let (healthy_tx, healthy) = watch::channel(false);
+
+        let producer_addr = addr.clone();
         tokio::spawn(async move {
             let topic = NSQTopic::new(&cloud_name).unwrap();
-            let mut producer = NSQProducerConfig::new(addr).build();
+            let mut producer = NSQProducerConfig::new(producer_addr).build();
 
-            // Wait for the producer to send a Ready event.
             loop {
-                if let Some(NSQEvent::Healthy()) = producer.consume().await {
-                    break;
-                }
-            }
-
-            loop {
-                // Wait for either a publish request or an acknowledgement from the producer.
+                // Wait for either a publish request or an event from the producer.
                 tokio::select! {
                     req = rx.recv() => {
                         let Some(req) = req else {
                             break;
                         };
 
-                        // Serialize the message body.
-                        let encoded = EncodedMessage::new_for_data(req.msg);
-                        let bytes = serde_json::to_vec(&encoded).expect("unable to serialize request");
-
-                        // TODO handle error
-                        producer
-                            .publish(&topic, bytes)
-                            .await
-                            .expect("failed to publish message");
+                        // Batches are published using a single MPUB command.
+                        let mut bodies = req.bodies;
+                        let result = if bodies.len() == 1 {
+                            producer.publish(&topic, bodies.remove(0)).await
+                        } else {
+                            producer.publish_multiple(&topic, bodies).await
+                        };
+                        let result = result
+                            .map_err(|err| anyhow::anyhow!("failed to publish message: {}", err));
 
                         // Ignore error.
-                        _ = req.resp.send(Ok(encoded.id));
+                        _ = req.resp.send(result);
+                    }
+                    event = producer.consume() => match event {
+                        Some(NSQEvent::Healthy()) => _ = healthy_tx.send(true),
+                        Some(NSQEvent::Unhealthy()) => _ = healthy_tx.send(false),
+                        _ => {}
                     }
-                    _ = producer.consume() => {}
                 }
             }
         });
 
-        NsqTopic { tx }
+        Producer { addr, tx, healthy }
+    }
+
+    fn is_healthy(&self) -> bool {
+        *self.healthy.borrow()
+    }
+
+    async fn send(&self, bodies: Vec<Vec<u8>>) -> Result<()> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        let req = PublishRequest {
+            bodies,
+            resp: resp_tx,
+        };
+        self.tx.send(req).await.context("failed to send message")?;
+
+        resp_rx.await.context("failed to receive response")?
     }
 }
 
@@ -69,19 +115,150 @@ impl Topic for NsqTopic {
     fn publish(
         &self,
         msg: MessageData,
-        _ordering_key: Option<String>,
+        ordering_key: Option<String>,
     ) -> Pin<Box<dyn Future<Output = Result<MessageId>> + Send + '_>> {
-        let tx = self.tx.clone();
         Box::pin(async move {
-            let (resp_tx, resp_rx) = oneshot::channel::<Result<MessageId>>();
-            let req = PublishRequest { msg, resp: resp_tx };
-            tx.send(req).await.context("failed to send message")?;
+            let ids = self.send(vec![msg], ordering_key.as_deref()).await?;
+            ids.into_iter().next().context("missing message id")
+        })
+    }
+
+    fn publish_batch(
+        &self,
+        msgs: Vec<BatchMessage>,
+    ) -> Pin<Box<dyn Future<Output = Vec<Result<MessageId>>> + Send + '_>> {
+        Box::pin(async move {
+            let mut results: Vec<Option<Result<MessageId>>> = msgs.iter().map(|_| None).collect();
+
+            // Messages with different ordering keys may be published to different hosts,
+            // so the messages of each key are published as a separate batch.
+            for group in group_by_key(msgs) {
+                // The messages of a batch are published atomically,
+                // so they all succeed or fail together.
+                let len = group.msgs.len();
+                let group_results = match self.send(group.msgs, group.key.as_deref()).await {
+                    Ok(ids) => ids.into_iter().map(Ok).collect(),
+                    Err(err) => pubsub::fail_batch(len, &err),
+                };
+                for (idx, result) in group.indices.into_iter().zip(group_results) {
+                    results[idx] = Some(result);
+                }
+            }
 
-            resp_rx.await.context("failed to receive response")?
+            results
+                .into_iter()
+                .map(|result| result.expect("every message is part of a group"))
+                .collect()
         })
     }
 }
 
+impl NsqTopic {
+    async fn send(
+        &self,
+        msgs: Vec<MessageData>,
+        ordering_key: Option<&str>,
+    ) -> Result<Vec<MessageId>> {
+        if msgs.is_empty() {
+            return Ok(vec![]);
+        }
+        if self.producers.is_empty() {
+            anyhow::bail!("no nsqd hosts configured for publishing");
+        }
+
+        // Serialize the messages once, so they keep their ids when failing over.
+        let encoded: Vec<_> = msgs.into_iter().map(EncodedMessage::new_for_data).collect();
+        let ids: Vec<MessageId> = encoded.iter().map(|msg| msg.id.clone()).collect();
+        let bodies = encoded
+            .iter()
+            .map(serde_json::to_vec)
+            .collect::<Result<Vec<_>, _>>()
+            .context("unable to serialize message")?;
+
+        // Try the healthy hosts first, and only wait for the others
+        // to connect if none of the healthy ones accept the messages.
+        let order = self.host_order(ordering_key);
+        let (healthy, unhealthy): (Vec<_>, Vec<_>) = order
+            .into_iter()
+            .map(|idx| &self.producers[idx])
+            .partition(|p| p.is_healthy());
+
+        let mut last_err = None;
+        for producer in healthy {
+            match producer.send(bodies.clone()).await {
+                Ok(()) => return Ok(ids),
+                Err(err) => {
+                    log::warn!("failed to publish to nsqd at {}: {:#}", producer.addr, err);
+                    last_err = Some(err);
+                }
+            }
+        }
+
+        for producer in unhealthy {
+            let mut healthy = producer.healthy.clone();
+            let connected = tokio::time::timeout(CONNECT_TIMEOUT, healthy.wait_for(|h| *h)).await;
+            if !matches!(connected, Ok(Ok(_))) {
+                last_err = Some(anyhow::anyhow!("nsqd at {} is unavailable", producer.addr));
+                continue;
+            }
+            match producer.send(bodies.clone()).await {
+                Ok(()) => return Ok(ids),
+                Err(err) => {
+                    log::warn!("failed to publish to nsqd at {}: {:#}", producer.addr, err);
+                    last_err = Some(err);
+                }
+            }
+        }
+
+        Err(last_err.unwrap_or_else(|| anyhow::anyhow!("no nsqd hosts available")))
+    }
+
+    /// Returns the order in which to try the hosts.
+    /// Messages with an ordering key prefer the same host so they're consumed
+    /// in the order they were published, while other messages are spread
+    /// across the hosts round-robin.
+    fn host_order(&self, ordering_key: Option<&str>) -> Vec<usize> {
+        let n = self.producers.len();
+        let start = match ordering_key {
+            Some(key) => {
+                let mut hasher = DefaultHasher::new();
+                key.hash(&mut hasher);
+                (hasher.finish() % n as u64) as usize
+            }
+            None => self.next.fetch_add(1, Ordering::Relaxed) % n,
+        };
+        (0..n).map(|i| (start + i) % n).collect()
+    }
+}
+
+/// The messages of a batch sharing an ordering key.
+struct KeyGroup {
+    key: Option<String>,
+    /// The indices of the messages in the batch.
+    indices: Vec<usize>,
+    msgs: Vec<MessageData>,
+}
+
+/// Groups the messages of a batch by their ordering key,
+/// keeping the order of the messages within each group.
+fn group_by_key(msgs: Vec<BatchMessage>) -> Vec<KeyGroup> {
+    let mut groups: Vec<KeyGroup> = Vec::new();
+    let mut by_key: HashMap<Option<String>, usize> = HashMap::new();
+    for (idx, msg) in msgs.into_iter().enumerate() {
+        let group = *by_key.entry(msg.ordering_key.clone()).or_insert_with(|| {
+            groups.push(KeyGroup {
+                key: msg.ordering_key,
+                indices: vec![],
+                msgs: vec![],
+            });
+            groups.len() - 1
+        });
+        groups[group].indices.push(idx);
+        groups[group].msgs.push(msg.data);
+    }
+    groups
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub(super) struct EncodedMessage {
     pub id: MessageId,
@@ -99,3 +276,58 @@ impl EncodedMessage {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_host_order() {
+        let hosts: Vec<String> = (1..=3).map(|i| format!("127.0.0.1:{}", i)).collect();
+        let cfg = pb::PubSubTopic {
+            cloud_name: "test".into(),
+            ..Default::default()
+        };
+        let topic = NsqTopic::new(&hosts, &cfg);
+
+        // Messages without an ordering key rotate across the hosts.
+        assert_eq!(topic.host_order(None), vec![0, 1, 2]);
+        assert_eq!(topic.host_order(None), vec![1, 2, 0]);
+
+        // Messages with the same ordering key keep to the same host.
+        let order = topic.host_order(Some("key"));
+        assert_eq!(topic.host_order(Some("key")), order);
+        assert_eq!(order.len(), 3);
+    }
+
+    #[test]
+    fn test_group_by_key() {
+        let msg = |key: Option<&str>| BatchMessage {
+            data: MessageData {
+                attrs: Default::default(),
+                raw_body: vec![],
+            },
+            ordering_key: key.map(String::from),
+        };
+        let groups = group_by_key(vec![
+            msg(Some("a")),
+            msg(None),
+            msg(Some("b")),
+            msg(Some("a")),
+            msg(None),
+        ]);
+
+        let groups: Vec<_> = groups
+            .iter()
+            .map(|g| (g.key.as_deref(), &g.indices[..]))
+            .collect();
+        assert_eq!(
+            groups,
+            vec![
+                (Some("a"), &[0, 3][..]),
+                (None, &[1, 4][..]),
+                (Some("b"), &[2][..]),
+            ]
+        );
+    }
+}
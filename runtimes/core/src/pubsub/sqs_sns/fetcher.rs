@@ -3,6 +3,8 @@ use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
 
+use tokio_util::sync::CancellationToken;
+
 pub trait Fetcher: Clone + Sync + Send {
     type Item;
     type Error: Debug;
@@ -24,7 +26,9 @@ pub struct Config {
     pub max_batch_size: usize,
 }
 
-pub async fn process_concurrently<F: Fetcher>(cfg: Config, fetcher: F) {
+/// Fetches and processes items until `stop` is canceled.
+/// Items that have already been fetched continue to be processed after that.
+pub async fn process_concurrently<F: Fetcher>(cfg: Config, fetcher: F, stop: CancellationToken) {
     // Semaphore representing work being processed.
     let sem = Arc::new(tokio::sync::Semaphore::new(cfg.max_concurrency));
 
@@ -45,7 +49,10 @@ pub async fn process_concurrently<F: Fetcher>(cfg: Config, fetcher: F) {
         // How many items shall we fetch?
         let (to_fetch, permit) = {
             // Wait for at least one permit.
-            let mut permit = sem.acquire().await.expect("semaphore is closed");
+            let mut permit = tokio::select! {
+                permit = sem.acquire() => permit.expect("semaphore is closed"),
+                _ = stop.cancelled() => return,
+            };
 
             // Do we have any additional available permits and the max batch size allows for it?
             let extra = sem.available_permits().min(max_batch - 1);
@@ -62,7 +69,10 @@ pub async fn process_concurrently<F: Fetcher>(cfg: Config, fetcher: F) {
             (1 + extra, permit)
         };
 
-        let fetch_result = fetcher.clone().fetch(to_fetch).await;
+        let fetch_result = tokio::select! {
+            res = fetcher.clone().fetch(to_fetch) => res,
+            _ = stop.cancelled() => return,
+        };
         match fetch_result {
             Ok(work) => {
                 err_sleep = base_sleep;
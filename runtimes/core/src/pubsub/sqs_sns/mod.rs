@@ -1,5 +1,9 @@
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::Arc;
 
+use anyhow::Context;
+
 use crate::encore::parser::meta::v1 as meta;
 use crate::encore::runtime::v1 as pb;
 use crate::pubsub;
@@ -49,6 +53,25 @@ impl pubsub::Cluster for Cluster {
     ) -> Arc<dyn pubsub::Subscription + 'static> {
         Arc::new(Subscription::new(self.client.clone(), cfg, meta))
     }
+
+    fn check_topic(
+        &self,
+        cfg: &pb::PubSubTopic,
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send + 'static>> {
+        let client = self.client.clone();
+        let topic_arn = cfg.cloud_name.clone();
+        Box::pin(async move {
+            client
+                .get_sns()
+                .await
+                .get_topic_attributes()
+                .topic_arn(topic_arn)
+                .send()
+                .await
+                .context("unable to get topic attributes")?;
+            Ok(())
+        })
+    }
 }
 
 #[derive(Debug)]
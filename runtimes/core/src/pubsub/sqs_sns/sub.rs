@@ -80,6 +80,7 @@ impl pubsub::Subscription for Subscription {
 
         Box::pin(async move {
             let client = client.get_sqs().await.clone();
+            let stop = handler.shutdown().initiated();
 
             let sqs_fetcher = Arc::new(SqsFetcher {
                 handler,
@@ -88,7 +89,7 @@ impl pubsub::Subscription for Subscription {
                 ack_deadline,
                 requeue_policy,
             });
-            fetcher::process_concurrently(fetcher_cfg.clone(), sqs_fetcher).await;
+            fetcher::process_concurrently(fetcher_cfg.clone(), sqs_fetcher, stop).await;
 
             Ok(())
         })
@@ -9,7 +9,7 @@ use crate::encore::runtime::v1 as pb;
 use crate::encore::runtime::v1::pub_sub_topic::DeliveryGuarantee;
 use crate::names::CloudName;
 use crate::pubsub::sqs_sns::LazyClient;
-use crate::pubsub::{self, MessageData, MessageId};
+use crate::pubsub::{self, BatchMessage, MessageData, MessageId};
 
 #[derive(Debug)]
 pub struct Topic {
@@ -34,6 +34,23 @@ impl Topic {
     }
 }
 
+impl Topic {
+    /// Returns the message group id and deduplication id to publish a message with,
+    /// for FIFO topics.
+    fn fifo_ids(&self, ordering_key: Option<String>) -> Option<(String, String)> {
+        if let Some(ordering_key) = ordering_key {
+            Some((ordering_key, format!("msg_{}", xid::new())))
+        } else if self.delivery_guarantee == DeliveryGuarantee::ExactlyOnce {
+            Some((
+                format!("inst_{}", self.publisher_id),
+                format!("msg_{}", xid::new()),
+            ))
+        } else {
+            None
+        }
+    }
+}
+
 impl pubsub::Topic for Topic {
     fn publish(
         &self,
@@ -44,19 +61,7 @@ impl pubsub::Topic for Topic {
             // The raw body is JSON, so it's valid UTF8.
             let data =
                 String::from_utf8(msg.raw_body).context("failed to serialize message body")?;
-
-            let attrs: Result<HashMap<String, aws_sdk_sns::types::MessageAttributeValue>, _> = msg
-                .attrs
-                .into_iter()
-                .map(|(k, v)| {
-                    aws_sdk_sns::types::MessageAttributeValue::builder()
-                        .data_type("String".to_string())
-                        .string_value(v)
-                        .build()
-                        .map(|val| (k, val))
-                })
-                .collect();
-            let attrs = attrs.context("failed to build message attributes")?;
+            let attrs = message_attributes(msg.attrs)?;
 
             let client = self.client.get_sns().await;
             let mut params = client
@@ -64,12 +69,9 @@ impl pubsub::Topic for Topic {
                 .topic_arn(self.cloud_name.to_string())
                 .message(data);
 
-            if let Some(ordering_key) = ordering_key {
-                params = params.message_group_id(ordering_key);
-                params = params.message_deduplication_id(format!("msg_{}", xid::new()));
-            } else if self.delivery_guarantee == DeliveryGuarantee::ExactlyOnce {
-                params = params.message_group_id(format!("inst_{}", self.publisher_id));
-                params = params.message_deduplication_id(format!("msg_{}", xid::new()));
+            if let Some((group_id, dedup_id)) = self.fifo_ids(ordering_key) {
+                params = params.message_group_id(group_id);
+                params = params.message_deduplication_id(dedup_id);
             }
 
             let result = params.set_message_attributes(Some(attrs)).send().await;
@@ -80,4 +82,123 @@ impl pubsub::Topic for Topic {
             }
         })
     }
+
+    fn publish_batch(
+        &self,
+        msgs: Vec<BatchMessage>,
+    ) -> Pin<Box<dyn Future<Output = Vec<Result<MessageId>>> + Send + '_>> {
+        Box::pin(async move {
+            // Encode all the messages before sending any of them.
+            let len = msgs.len();
+            let entries = match self.batch_entries(msgs) {
+                Ok(entries) => entries,
+                Err(err) => return pubsub::fail_batch(len, &err),
+            };
+
+            let client = self.client.get_sns().await;
+            let mut results: Vec<Result<MessageId>> = Vec::with_capacity(len);
+            for chunk in entries.chunks(MAX_BATCH_SIZE) {
+                // Don't publish the rest of the batch once a message failed,
+                // so messages aren't published out of order.
+                if results.iter().any(|r| r.is_err()) {
+                    results.extend(chunk.iter().map(|_| Err(pubsub::not_published())));
+                    continue;
+                }
+
+                let output = client
+                    .publish_batch()
+                    .topic_arn(self.cloud_name.to_string())
+                    .set_publish_batch_request_entries(Some(chunk.to_vec()))
+                    .send()
+                    .await;
+                let output = match output {
+                    Ok(output) => output,
+                    Err(err) => {
+                        let err = anyhow::Error::from(err).context("failed to publish batch");
+                        results.extend(pubsub::fail_batch(chunk.len(), &err));
+                        continue;
+                    }
+                };
+
+                // Return the results in the order of the entries.
+                let mut published: HashMap<String, MessageId> = output
+                    .successful
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|entry| Some((entry.id?, entry.message_id?)))
+                    .collect();
+                let mut failed: HashMap<String, anyhow::Error> = output
+                    .failed
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|entry| {
+                        let err = anyhow::anyhow!(
+                            "failed to publish message: {} ({})",
+                            entry.message().unwrap_or_default(),
+                            entry.code(),
+                        );
+                        (entry.id().to_string(), err)
+                    })
+                    .collect();
+                for entry in chunk {
+                    let result = match published.remove(entry.id()) {
+                        Some(id) => Ok(id),
+                        None => Err(failed.remove(entry.id()).unwrap_or_else(|| {
+                            anyhow::anyhow!("no publish result returned for message")
+                        })),
+                    };
+                    results.push(result);
+                }
+            }
+            results
+        })
+    }
+}
+
+impl Topic {
+    /// Builds the batch entries to publish the messages with,
+    /// identified by their index in the batch.
+    fn batch_entries(
+        &self,
+        msgs: Vec<BatchMessage>,
+    ) -> Result<Vec<aws_sdk_sns::types::PublishBatchRequestEntry>> {
+        let mut entries = Vec::with_capacity(msgs.len());
+        for (idx, msg) in msgs.into_iter().enumerate() {
+            // The raw body is JSON, so it's valid UTF8.
+            let data =
+                String::from_utf8(msg.data.raw_body).context("failed to serialize message body")?;
+            let attrs = message_attributes(msg.data.attrs)?;
+
+            let mut entry = aws_sdk_sns::types::PublishBatchRequestEntry::builder()
+                .id(idx.to_string())
+                .message(data)
+                .set_message_attributes(Some(attrs));
+            if let Some((group_id, dedup_id)) = self.fifo_ids(msg.ordering_key) {
+                entry = entry
+                    .message_group_id(group_id)
+                    .message_deduplication_id(dedup_id);
+            }
+            entries.push(entry.build().context("failed to build batch entry")?);
+        }
+        Ok(entries)
+    }
+}
+
+/// The maximum number of messages SNS accepts in a single batch.
+const MAX_BATCH_SIZE: usize = 10;
+
+fn message_attributes(
+    attrs: HashMap<String, String>,
+) -> Result<HashMap<String, aws_sdk_sns::types::MessageAttributeValue>> {
+    attrs
+        .into_iter()
+        .map(|(k, v)| {
+            aws_sdk_sns::types::MessageAttributeValue::builder()
+                .data_type("String".to_string())
+                .string_value(v)
+                .build()
+                .map(|val| (k, val))
+        })
+        .collect::<Result<_, _>>()
+        .context("failed to build message attributes")
 }
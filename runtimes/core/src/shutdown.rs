@@ -0,0 +1,458 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::{task_tracker::TaskTrackerToken, TaskTracker};
+
+use crate::encore::runtime::v1 as pb;
+
+/// The default total time allowed for a graceful shutdown.
+const DEFAULT_TOTAL: Duration = Duration::from_secs(5);
+
+/// The default time before the total deadline at which handlers are canceled.
+const DEFAULT_HANDLERS: Duration = Duration::from_secs(2);
+
+/// The default time shutdown hooks are given to return.
+const DEFAULT_SHUTDOWN_HOOKS: Duration = Duration::from_secs(1);
+
+/// How long to wait for canceled handlers to return before moving on.
+const CANCELED_HANDLERS_GRACE: Duration = Duration::from_secs(1);
+
+/// The timings of a graceful shutdown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timings {
+    /// The total time the graceful shutdown may take
+    /// before the process exits.
+    pub total: Duration,
+
+    /// How long before `total` runs out that outstanding
+    /// API and Pub/Sub handlers are canceled.
+    pub handlers: Duration,
+
+    /// How long shutdown hooks are given to return, measured from when
+    /// they start running after the handlers have drained.
+    /// Capped by the time left before `total` runs out.
+    pub shutdown_hooks: Duration,
+}
+
+impl Default for Timings {
+    fn default() -> Self {
+        Self {
+            total: DEFAULT_TOTAL,
+            handlers: DEFAULT_HANDLERS,
+            shutdown_hooks: DEFAULT_SHUTDOWN_HOOKS,
+        }
+    }
+}
+
+impl Timings {
+    pub fn from_config(cfg: Option<&pb::GracefulShutdown>) -> Self {
+        let Some(cfg) = cfg else {
+            return Self::default();
+        };
+
+        let dur = |d: Option<prost_types::Duration>| d.and_then(|d| Duration::try_from(d).ok());
+        let total = dur(cfg.total)
+            .filter(|d| !d.is_zero())
+            .unwrap_or(DEFAULT_TOTAL);
+        let handlers = dur(cfg.handlers).unwrap_or(DEFAULT_HANDLERS).min(total);
+        let shutdown_hooks = dur(cfg.shutdown_hooks)
+            .unwrap_or(DEFAULT_SHUTDOWN_HOOKS)
+            .min(total);
+
+        Self {
+            total,
+            handlers,
+            shutdown_hooks,
+        }
+    }
+
+    /// The time after shutdown is initiated at which handlers are canceled.
+    fn cancel_handlers_after(&self) -> Duration {
+        self.total.saturating_sub(self.handlers)
+    }
+}
+
+/// Describes the progress of a graceful shutdown to shutdown hooks.
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+    /// The time at which the hook is expected to have returned.
+    pub deadline: SystemTime,
+}
+
+/// A hook that runs during graceful shutdown,
+/// after outstanding handlers have completed or been canceled.
+pub trait Hook: Send + Sync + 'static {
+    fn run(&self, progress: Progress) -> Pin<Box<dyn Future<Output = ()> + Send + '_>>;
+}
+
+/// Tracks and coordinates the graceful shutdown of the runtime.
+///
+/// On shutdown the runtime, in order:
+/// - stops accepting new requests and pulling new Pub/Sub messages,
+/// - waits for outstanding handlers, canceling them at the handlers deadline,
+/// - runs the registered shutdown hooks,
+/// - exits, at the latest when the total deadline is reached.
+#[derive(Debug, Clone)]
+pub struct Tracker {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    timings: Timings,
+
+    /// Canceled when the shutdown is initiated.
+    initiated: CancellationToken,
+
+    /// Canceled when outstanding handlers should be canceled.
+    cancel_handlers: CancellationToken,
+
+    /// Canceled when the shutdown has completed.
+    completed: CancellationToken,
+
+    /// Tracks outstanding API requests and Pub/Sub messages.
+    tasks: TaskTracker,
+
+    hooks: Mutex<Vec<Arc<dyn Hook>>>,
+}
+
+impl std::fmt::Debug for Inner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Inner")
+            .field("timings", &self.timings)
+            .field("initiated", &self.initiated.is_cancelled())
+            .field("tasks", &self.tasks.len())
+            .finish()
+    }
+}
+
+impl Tracker {
+    pub fn new(timings: Timings) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                timings,
+                initiated: CancellationToken::new(),
+                cancel_handlers: CancellationToken::new(),
+                completed: CancellationToken::new(),
+                tasks: TaskTracker::new(),
+                hooks: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    pub fn timings(&self) -> &Timings {
+        &self.inner.timings
+    }
+
+    /// Returns a token that is canceled when the shutdown is initiated,
+    /// at which point no new requests or messages should be accepted.
+    pub fn initiated(&self) -> CancellationToken {
+        self.inner.initiated.child_token()
+    }
+
+    /// Reports whether the shutdown has been initiated.
+    pub fn is_initiated(&self) -> bool {
+        self.inner.initiated.is_cancelled()
+    }
+
+    /// Returns a token that is canceled when outstanding handlers should be canceled.
+    pub fn handlers_canceled(&self) -> CancellationToken {
+        self.inner.cancel_handlers.child_token()
+    }
+
+    /// Returns a token that is canceled when the shutdown has completed,
+    /// after all shutdown hooks have run.
+    pub fn completed(&self) -> CancellationToken {
+        self.inner.completed.child_token()
+    }
+
+    /// Tracks an outstanding handler for the lifetime of the returned token.
+    /// The shutdown waits for outstanding handlers before running shutdown hooks.
+    pub fn track_handler(&self) -> TaskTrackerToken {
+        self.inner.tasks.token()
+    }
+
+    /// Registers a hook to run during graceful shutdown.
+    pub fn register_hook(&self, hook: Arc<dyn Hook>) {
+        self.inner.hooks.lock().unwrap().push(hook);
+    }
+
+    /// Runs the graceful shutdown. Reports whether it completed cleanly
+    /// within the total deadline.
+    ///
+    /// If the shutdown has already been initiated this waits for it to complete.
+    pub async fn shutdown(&self) -> bool {
+        if self.inner.initiated.is_cancelled() {
+            self.inner.completed.cancelled().await;
+            return true;
+        }
+
+        let start = Instant::now();
+        let timings = &self.inner.timings;
+        let deadline = start + timings.total;
+        let clean = tokio::time::timeout_at(deadline, self.run(start))
+            .await
+            .unwrap_or(false);
+        self.inner.completed.cancel();
+        clean
+    }
+
+    async fn run(&self, start: Instant) -> bool {
+        let inner = &self.inner;
+        let timings = &inner.timings;
+
+        // Stop accepting new requests and pulling new messages.
+        inner.initiated.cancel();
+
+        // Wait for outstanding handlers to complete, and cancel them
+        // if they're still running at the handlers deadline.
+        inner.tasks.close();
+        let cancel_at = start + timings.cancel_handlers_after();
+        let mut clean = true;
+        if tokio::time::timeout_at(cancel_at, inner.tasks.wait())
+            .await
+            .is_err()
+        {
+            ::log::info!(
+                outstanding = inner.tasks.len();
+                "canceling outstanding handlers"
+            );
+            inner.cancel_handlers.cancel();
+            if tokio::time::timeout(CANCELED_HANDLERS_GRACE, inner.tasks.wait())
+                .await
+                .is_err()
+            {
+                ::log::warn!("outstanding handlers did not return after being canceled");
+                clean = false;
+            }
+        }
+
+        // Run the shutdown hooks.
+        let hooks = inner.hooks.lock().unwrap().clone();
+        if !hooks.is_empty() {
+            let hooks_deadline = hooks_deadline(start, Instant::now(), timings);
+            let progress = Progress {
+                deadline: SystemTime::now()
+                    + hooks_deadline.saturating_duration_since(Instant::now()),
+            };
+            ::log::trace!(hooks = hooks.len(); "running shutdown hooks");
+            let finished = AtomicUsize::new(0);
+            let run_hooks = futures::future::join_all(hooks.iter().map(|hook| {
+                let finished = &finished;
+                async move {
+                    hook.run(progress).await;
+                    finished.fetch_add(1, Ordering::Relaxed);
+                }
+            }));
+            if tokio::time::timeout_at(hooks_deadline, run_hooks)
+                .await
+                .is_err()
+            {
+                ::log::warn!(
+                    outstanding = hooks.len() - finished.load(Ordering::Relaxed);
+                    "shutdown hooks did not return before the deadline"
+                );
+                clean = false;
+            }
+        }
+
+        clean
+    }
+
+    /// Spawns a task that initiates the graceful shutdown when
+    /// the process receives SIGTERM or SIGINT, and exits the process
+    /// once the shutdown has completed.
+    pub fn watch_signals(&self, runtime: &tokio::runtime::Handle) {
+        let tracker = self.clone();
+        runtime.spawn(async move {
+            let signal = match wait_for_signal().await {
+                Ok(signal) => signal,
+                Err(err) => {
+                    ::log::error!("unable to listen for shutdown signals: {:?}", err);
+                    return;
+                }
+            };
+
+            ::log::info!(signal = signal; "got shutdown signal, initiating graceful shutdown");
+            let code = if tracker.shutdown().await {
+                ::log::trace!("graceful shutdown completed");
+                0
+            } else {
+                ::log::warn!("graceful shutdown window closed, forcing shutdown");
+                1
+            };
+
+            // Write out any buffered logs before exiting.
+            ::log::logger().flush();
+            std::process::exit(code);
+        });
+    }
+}
+
+/// The time at which shutdown hooks started at `hooks_start`
+/// are expected to have returned.
+fn hooks_deadline(start: Instant, hooks_start: Instant, timings: &Timings) -> Instant {
+    (hooks_start + timings.shutdown_hooks).min(start + timings.total)
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() -> std::io::Result<&'static str> {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut sigterm = signal(SignalKind::terminate())?;
+    let mut sigint = signal(SignalKind::interrupt())?;
+    tokio::select! {
+        _ = sigterm.recv() => Ok("SIGTERM"),
+        _ = sigint.recv() => Ok("SIGINT"),
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() -> std::io::Result<&'static str> {
+    tokio::signal::ctrl_c().await?;
+    Ok("ctrl-c")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+
+    use super::*;
+
+    #[test]
+    fn test_timings_from_config() {
+        assert_eq!(Timings::from_config(None), Timings::default());
+
+        let secs = |s| {
+            Some(prost_types::Duration {
+                seconds: s,
+                nanos: 0,
+            })
+        };
+        let timings = Timings::from_config(Some(&pb::GracefulShutdown {
+            total: secs(10),
+            shutdown_hooks: secs(4),
+            handlers: secs(20),
+        }));
+        assert_eq!(timings.total, Duration::from_secs(10));
+        assert_eq!(timings.shutdown_hooks, Duration::from_secs(4));
+        // The handlers duration is capped at the total.
+        assert_eq!(timings.cancel_handlers_after(), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_hooks_deadline() {
+        let timings = Timings {
+            total: Duration::from_secs(10),
+            handlers: Duration::from_secs(2),
+            shutdown_hooks: Duration::from_secs(3),
+        };
+        let start = Instant::now();
+
+        // Measured from when the hooks start, not from when the shutdown started.
+        let hooks_start = start + Duration::from_secs(4);
+        assert_eq!(
+            hooks_deadline(start, hooks_start, &timings),
+            hooks_start + Duration::from_secs(3)
+        );
+
+        // But never past the total deadline.
+        let hooks_start = start + Duration::from_secs(9);
+        assert_eq!(
+            hooks_deadline(start, hooks_start, &timings),
+            start + Duration::from_secs(10)
+        );
+    }
+
+    #[derive(Default)]
+    struct RecordHook {
+        ran: AtomicBool,
+    }
+
+    impl Hook for RecordHook {
+        fn run(&self, _: Progress) -> Pin<Box<dyn Future<Output = ()> + Send + '_>> {
+            Box::pin(async move {
+                self.ran.store(true, Ordering::SeqCst);
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_waits_for_handlers() {
+        let tracker = Tracker::new(Timings {
+            total: Duration::from_secs(5),
+            handlers: Duration::from_secs(1),
+            shutdown_hooks: Duration::from_secs(1),
+        });
+        let hook = Arc::new(RecordHook::default());
+        tracker.register_hook(hook.clone());
+
+        let token = tracker.track_handler();
+        let handler = tokio::spawn({
+            let initiated = tracker.initiated();
+            async move {
+                initiated.cancelled().await;
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                drop(token);
+            }
+        });
+
+        assert!(tracker.shutdown().await);
+        handler.await.unwrap();
+        assert!(hook.ran.load(Ordering::SeqCst));
+        assert!(tracker.is_initiated());
+        assert!(tracker.completed().is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_cancels_handlers() {
+        let tracker = Tracker::new(Timings {
+            total: Duration::from_millis(300),
+            handlers: Duration::from_millis(200),
+            shutdown_hooks: Duration::ZERO,
+        });
+
+        // A handler that only returns once canceled.
+        let canceled = tracker.handlers_canceled();
+        let token = tracker.track_handler();
+        tokio::spawn(async move {
+            canceled.cancelled().await;
+            drop(token);
+        });
+
+        let start = Instant::now();
+        assert!(tracker.shutdown().await);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert!(tracker.handlers_canceled().is_cancelled());
+    }
+
+    struct StuckHook;
+
+    impl Hook for StuckHook {
+        fn run(&self, _: Progress) -> Pin<Box<dyn Future<Output = ()> + Send + '_>> {
+            Box::pin(futures::future::pending())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_hooks_deadline() {
+        let tracker = Tracker::new(Timings {
+            total: Duration::from_secs(5),
+            handlers: Duration::from_secs(1),
+            shutdown_hooks: Duration::from_millis(100),
+        });
+        let hook = Arc::new(RecordHook::default());
+        tracker.register_hook(hook.clone());
+        tracker.register_hook(Arc::new(StuckHook));
+
+        // The stuck hook is abandoned at the hooks deadline,
+        // well before the total deadline.
+        let start = Instant::now();
+        assert!(!tracker.shutdown().await);
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(hook.ran.load(Ordering::SeqCst));
+    }
+}
@@ -1,6 +1,8 @@
 use std::collections::HashMap;
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
 
 use bb8::{ErrorSink, PooledConnection, RunError};
 use bb8_postgres::PostgresConnectionManager;
@@ -8,43 +10,80 @@ use futures_util::StreamExt;
 
 use tokio_postgres::types::BorrowToSql;
 
+use crate::model::TraceEventId;
+use crate::sqldb::replica::{self, Replicas};
 use crate::sqldb::val::RowValue;
 use crate::trace::{protocol, Tracer};
 use crate::{model, sqldb};
 
-type Mgr = PostgresConnectionManager<postgres_native_tls::MakeTlsConnector>;
+pub(super) type Mgr = PostgresConnectionManager<postgres_native_tls::MakeTlsConnector>;
 
 pub struct Pool {
     pool: bb8::Pool<Mgr>,
     tracer: QueryTracer,
+    replicas: Arc<Replicas>,
+
+    /// Whether read-only queries are routed to the replicas.
+    route_reads: bool,
 }
 
 impl Pool {
     pub fn new<DB: sqldb::Database>(db: &DB, tracer: Tracer) -> anyhow::Result<Self> {
         let tls = db.tls()?.clone();
         let mgr = Mgr::new(db.config()?.clone(), tls);
-
         let pool_cfg = db.pool_config()?;
-        let mut pool = bb8::Pool::builder()
-            .error_sink(Box::new(RustLoggerSink {
-                db_name: db.name().to_string(),
-            }))
-            .max_size(if pool_cfg.max_conns > 0 {
-                pool_cfg.max_conns
-            } else {
-                30
-            });
+        let pool = build_pool(db.name(), mgr, &pool_cfg, None);
 
-        if pool_cfg.min_conns > 0 {
-            pool = pool.min_idle(Some(pool_cfg.min_conns));
-        }
+        let replicas = db
+            .replicas()?
+            .iter()
+            .map(|replica| {
+                let mgr = Mgr::new(replica.config.clone(), replica.tls.clone());
+                build_pool(
+                    db.name(),
+                    mgr,
+                    &replica.pool,
+                    Some(replica::CONNECT_TIMEOUT),
+                )
+            })
+            .collect();
 
-        let pool = pool.build_unchecked(mgr);
+        let db_name: Arc<str> = db.name().to_string().into();
         Ok(Self {
             pool,
-            tracer: QueryTracer(tracer),
+            replicas: Arc::new(Replicas::new(db_name.clone(), replicas)),
+            route_reads: pool_cfg.route_reads_to_replicas,
+            tracer: QueryTracer { tracer, db_name },
         })
     }
+
+    /// Returns a handle for querying the database's read replicas.
+    /// Queries fall back to the primary if no replica is available.
+    pub fn replica(&self) -> ReadReplica<'_> {
+        ReadReplica { pool: self }
+    }
+}
+
+fn build_pool(
+    db_name: &str,
+    mgr: Mgr,
+    cfg: &sqldb::PoolConfig,
+    connect_timeout: Option<std::time::Duration>,
+) -> bb8::Pool<Mgr> {
+    let mut pool = bb8::Pool::builder()
+        .error_sink(Box::new(RustLoggerSink {
+            db_name: db_name.to_string(),
+        }))
+        .max_size(if cfg.max_conns > 0 { cfg.max_conns } else { 30 });
+
+    if cfg.min_conns > 0 {
+        pool = pool.min_idle(Some(cfg.min_conns));
+    }
+    if let Some(timeout) = connect_timeout {
+        pool = pool.connection_timeout(timeout);
+    }
+
+    pool.build_unchecked(mgr)
 }
 
 #[derive(Debug, Clone)]
@@ -73,31 +112,111 @@ impl Pool {
         params: I,
         source: Option<&model::Request>,
     ) -> Result<Cursor, Error>
+    where
+        P: BorrowToSql,
+        I: IntoIterator<Item = P>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let use_replica = self.route_reads && replica::is_read_only(query);
+        self.query(query, params, source, use_replica).await
+    }
+
+    async fn query<P, I>(
+        &self,
+        query: &str,
+        params: I,
+        source: Option<&model::Request>,
+        use_replica: bool,
+    ) -> Result<Cursor, Error>
     where
         P: BorrowToSql,
         I: IntoIterator<Item = P>,
         I::IntoIter: ExactSizeIterator,
     {
         self.tracer
-            .trace(source, query, || async {
-                let conn = self.pool.get().await.map_err(|e| match e {
-                    RunError::User(err) => Error::DB(err),
-                    RunError::TimedOut => Error::ConnectTimeout,
-                })?;
-                conn.query_raw(query, params).await.map_err(Error::from)
+            .trace(source, None, query, || async {
+                if !use_replica {
+                    return self.query_primary(query, params).await;
+                }
+
+                // Keep the params around in case the query needs
+                // to be retried on the primary.
+                let params: Vec<P> = params.into_iter().collect();
+                let params = || params.iter().map(BorrowToSql::borrow_to_sql);
+                match self.replicas.get().await {
+                    Some((idx, conn)) => {
+                        replica::or_primary(
+                            conn.query_raw(query, params()),
+                            |err| self.replicas.report_error(idx, err),
+                            self.query_primary(query, params()),
+                        )
+                        .await
+                    }
+                    None => self.query_primary(query, params()).await,
+                }
             })
             .await
     }
 
+    async fn query_primary<P, I>(
+        &self,
+        query: &str,
+        params: I,
+    ) -> Result<tokio_postgres::RowStream, Error>
+    where
+        P: BorrowToSql,
+        I: IntoIterator<Item = P>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let conn = self.pool.get().await.map_err(|e| match e {
+            RunError::User(err) => Error::DB(err),
+            RunError::TimedOut => Error::ConnectTimeout,
+        })?;
+        conn.query_raw(query, params).await.map_err(Error::from)
+    }
+
     pub async fn acquire(&self) -> Result<Connection, tokio_postgres::Error> {
         let conn = self.pool.get_owned().await.map_err(|e| match e {
             RunError::User(err) => err,
             RunError::TimedOut => tokio_postgres::Error::__private_api_timeout(),
         })?;
-        Ok(Connection {
-            conn: tokio::sync::RwLock::new(Some(conn)),
-            tracer: self.tracer.clone(),
-        })
+        Ok(Connection::new(conn, self.tracer.clone()))
+    }
+
+    /// Begins a transaction on a connection acquired from the pool.
+    /// The connection is returned to the pool once the transaction
+    /// and all its savepoints have been dropped.
+    pub async fn begin(&self, source: Option<Arc<model::Request>>) -> Result<Transaction, Error> {
+        let conn = self.pool.get_owned().await.map_err(|e| match e {
+            RunError::User(err) => Error::DB(err),
+            RunError::TimedOut => Error::ConnectTimeout,
+        })?;
+        Connection::new(conn, self.tracer.clone())
+            .begin(source)
+            .await
+    }
+}
+
+/// A handle for querying the read replicas of a database.
+pub struct ReadReplica<'a> {
+    pool: &'a Pool,
+}
+
+impl ReadReplica<'_> {
+    /// Queries a healthy read replica, falling back to the primary
+    /// if no replica is configured or available.
+    pub async fn query_raw<P, I>(
+        &self,
+        query: &str,
+        params: I,
+        source: Option<&model::Request>,
+    ) -> Result<Cursor, Error>
+    where
+        P: BorrowToSql,
+        I: IntoIterator<Item = P>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        self.pool.query(query, params, source, true).await
     }
 }
 
@@ -138,8 +257,11 @@ impl Row {
 type PooledConn =
     PooledConnection<'static, PostgresConnectionManager<postgres_native_tls::MakeTlsConnector>>;
 
+/// A pooled connection shared between a connection and its transactions.
+type SharedConn = Arc<tokio::sync::RwLock<Option<PooledConn>>>;
+
 pub struct Connection {
-    conn: tokio::sync::RwLock<Option<PooledConn>>,
+    conn: SharedConn,
     tracer: QueryTracer,
 }
 
@@ -148,6 +270,7 @@ pub enum Error {
     DB(tokio_postgres::Error),
     Closed,
     ConnectTimeout,
+    TransactionDone,
 }
 
 impl std::fmt::Display for Error {
@@ -156,6 +279,9 @@ impl std::fmt::Display for Error {
             Error::DB(err) => <tokio_postgres::Error as std::fmt::Display>::fmt(err, f),
             Error::Closed => f.write_str("connection_closed"),
             Error::ConnectTimeout => f.write_str("timeout establishing connection"),
+            Error::TransactionDone => {
+                f.write_str("transaction has already been committed or rolled back")
+            }
         }
     }
 }
@@ -167,6 +293,13 @@ impl From<tokio_postgres::Error> for Error {
 }
 
 impl Connection {
+    fn new(conn: PooledConn, tracer: QueryTracer) -> Self {
+        Self {
+            conn: Arc::new(tokio::sync::RwLock::new(Some(conn))),
+            tracer,
+        }
+    }
+
     pub async fn close(&self) {
         let mut guard = self.conn.write().await;
         if let Some(conn) = guard.take() {
@@ -186,7 +319,7 @@ impl Connection {
         I::IntoIter: ExactSizeIterator,
     {
         self.tracer
-            .trace(source, query, || async {
+            .trace(source, None, query, || async {
                 let guard = self.conn.read().await;
                 let Some(conn) = guard.as_ref() else {
                     return Err(Error::Closed);
@@ -195,15 +328,247 @@ impl Connection {
             })
             .await
     }
+
+    /// Begins a transaction on the connection.
+    ///
+    /// Queries made directly on the connection while the transaction
+    /// is in progress also run as part of the transaction.
+    pub async fn begin(&self, source: Option<Arc<model::Request>>) -> Result<Transaction, Error> {
+        Transaction::begin(
+            self.conn.clone(),
+            self.tracer.clone(),
+            source,
+            Arc::new(AtomicU32::new(0)),
+            None,
+        )
+        .await
+    }
+}
+
+/// Tracks whether a transaction or savepoint has completed.
+#[derive(Debug, Default)]
+struct TxState {
+    done: AtomicBool,
+
+    /// The state of the enclosing transaction, for savepoints.
+    parent: Option<Arc<TxState>>,
+}
+
+impl TxState {
+    /// Reports whether the transaction, or any transaction enclosing it, has completed.
+    fn is_done(&self) -> bool {
+        self.done.load(Ordering::Acquire) || self.parent.as_ref().is_some_and(|p| p.is_done())
+    }
+
+    /// Marks the transaction as completed.
+    /// Reports false if it was already completed.
+    fn complete(&self) -> bool {
+        !self.done.swap(true, Ordering::AcqRel)
+            && !self.parent.as_ref().is_some_and(|p| p.is_done())
+    }
+}
+
+/// A database transaction, or a savepoint within one.
+///
+/// The transaction is rolled back when dropped unless
+/// it has been committed or rolled back explicitly.
+pub struct Transaction {
+    conn: SharedConn,
+    tracer: QueryTracer,
+
+    /// The request that began the transaction, if any.
+    source: Option<Arc<model::Request>>,
+
+    /// The trace event that began the transaction, if traced.
+    start_id: Option<TraceEventId>,
+
+    /// The name of the savepoint, if this is a savepoint.
+    savepoint: Option<String>,
+
+    /// The number of savepoints created on the connection,
+    /// used to give each savepoint a unique name.
+    savepoints: Arc<AtomicU32>,
+
+    state: Arc<TxState>,
+
+    /// The runtime to roll back on when the transaction is dropped.
+    rt: tokio::runtime::Handle,
+}
+
+impl Transaction {
+    async fn begin(
+        conn: SharedConn,
+        tracer: QueryTracer,
+        source: Option<Arc<model::Request>>,
+        savepoints: Arc<AtomicU32>,
+        parent: Option<&Transaction>,
+    ) -> Result<Self, Error> {
+        let (savepoint, parent_state) = match parent {
+            Some(parent) => {
+                let n = savepoints.fetch_add(1, Ordering::Relaxed) + 1;
+                (Some(format!("sp_{}", n)), Some(parent.state.clone()))
+            }
+            None => (None, None),
+        };
+
+        let stmt = match &savepoint {
+            Some(name) => format!("SAVEPOINT {}", name),
+            None => "BEGIN".to_string(),
+        };
+        batch_execute(&conn, &stmt).await?;
+
+        let start_id = source.as_deref().map(|source| {
+            tracer
+                .tracer
+                .db_transaction_start(protocol::DBTransactionStartData { source })
+        });
+
+        Ok(Self {
+            conn,
+            tracer,
+            source,
+            start_id,
+            savepoint,
+            savepoints,
+            state: Arc::new(TxState {
+                done: AtomicBool::new(false),
+                parent: parent_state,
+            }),
+            rt: tokio::runtime::Handle::current(),
+        })
+    }
+
+    pub async fn query_raw<P, I>(
+        &self,
+        query: &str,
+        params: I,
+        source: Option<&model::Request>,
+    ) -> Result<Cursor, Error>
+    where
+        P: BorrowToSql,
+        I: IntoIterator<Item = P>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        self.tracer
+            .trace(source, self.start_id, query, || async {
+                if self.state.is_done() {
+                    return Err(Error::TransactionDone);
+                }
+                let guard = self.conn.read().await;
+                let Some(conn) = guard.as_ref() else {
+                    return Err(Error::Closed);
+                };
+                conn.query_raw(query, params).await.map_err(Error::from)
+            })
+            .await
+    }
+
+    /// Creates a savepoint within the transaction, returned as a nested transaction.
+    /// Committing it releases the savepoint, and rolling it back
+    /// rolls back to the savepoint without ending the enclosing transaction.
+    pub async fn savepoint(&self) -> Result<Transaction, Error> {
+        if self.state.is_done() {
+            return Err(Error::TransactionDone);
+        }
+        Transaction::begin(
+            self.conn.clone(),
+            self.tracer.clone(),
+            self.source.clone(),
+            self.savepoints.clone(),
+            Some(self),
+        )
+        .await
+    }
+
+    pub async fn commit(&self) -> Result<(), Error> {
+        self.finish(true).await
+    }
+
+    pub async fn rollback(&self) -> Result<(), Error> {
+        self.finish(false).await
+    }
+
+    async fn finish(&self, commit: bool) -> Result<(), Error> {
+        if !self.state.complete() {
+            return Err(Error::TransactionDone);
+        }
+        let stmt = end_statement(self.savepoint.as_deref(), commit);
+        let result = batch_execute(&self.conn, &stmt).await;
+        self.trace_end(commit, result.as_ref().err());
+        result
+    }
+
+    fn trace_end(&self, commit: bool, error: Option<&Error>) {
+        if let (Some(start_id), Some(source)) = (self.start_id, self.source.as_deref()) {
+            self.tracer
+                .tracer
+                .db_transaction_end(protocol::DBTransactionEndData {
+                    start_id,
+                    source,
+                    commit,
+                    error,
+                });
+        }
+    }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        if !self.state.complete() {
+            return;
+        }
+
+        let conn = self.conn.clone();
+        let tracer = self.tracer.tracer.clone();
+        let source = self.source.take();
+        let start_id = self.start_id;
+        let stmt = end_statement(self.savepoint.as_deref(), false);
+        self.rt.spawn(async move {
+            let result = batch_execute(&conn, &stmt).await;
+            if let Err(err) = &result {
+                log::error!("unable to roll back dropped transaction: {}", err);
+            }
+            if let (Some(start_id), Some(source)) = (start_id, source.as_deref()) {
+                tracer.db_transaction_end(protocol::DBTransactionEndData {
+                    start_id,
+                    source,
+                    commit: false,
+                    error: result.as_ref().err(),
+                });
+            }
+        });
+    }
+}
+
+/// Returns the statement that ends a transaction or savepoint.
+fn end_statement(savepoint: Option<&str>, commit: bool) -> String {
+    match (savepoint, commit) {
+        (None, true) => "COMMIT".to_string(),
+        (None, false) => "ROLLBACK".to_string(),
+        (Some(name), true) => format!("RELEASE SAVEPOINT {}", name),
+        (Some(name), false) => format!("ROLLBACK TO SAVEPOINT {}", name),
+    }
+}
+
+async fn batch_execute(conn: &SharedConn, stmt: &str) -> Result<(), Error> {
+    let guard = conn.read().await;
+    let Some(conn) = guard.as_ref() else {
+        return Err(Error::Closed);
+    };
+    conn.batch_execute(stmt).await.map_err(Error::from)
 }
 
 #[derive(Debug, Clone)]
-struct QueryTracer(Tracer);
+struct QueryTracer {
+    tracer: Tracer,
+    db_name: std::sync::Arc<str>,
+}
 
 impl QueryTracer {
     async fn trace<F, Fut>(
         &self,
         source: Option<&model::Request>,
+        tx_start_id: Option<TraceEventId>,
         query: &str,
         exec: F,
     ) -> Result<Cursor, Error>
@@ -211,19 +576,23 @@ impl QueryTracer {
         F: FnOnce() -> Fut,
         Fut: Future<Output = Result<tokio_postgres::RowStream, Error>>,
     {
+        let start = tokio::time::Instant::now();
         let start_id = if let Some(source) = source {
-            let id = self
-                .0
-                .db_query_start(protocol::DBQueryStartData { source, query });
+            let id = self.tracer.db_query_start(protocol::DBQueryStartData {
+                source,
+                query,
+                tx_start_id,
+            });
             Some(id)
         } else {
             None
         };
 
         let result = exec().await;
+        crate::metrics::record_db_query(&self.db_name, result.is_ok(), start.elapsed());
 
         if let Some(start_id) = start_id {
-            self.0.db_query_end(protocol::DBQueryEndData {
+            self.tracer.db_query_end(protocol::DBQueryEndData {
                 start_id,
                 source: source.unwrap(),
                 error: result.as_ref().err(),
@@ -236,3 +605,36 @@ impl QueryTracer {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_end_statement() {
+        assert_eq!(end_statement(None, true), "COMMIT");
+        assert_eq!(end_statement(None, false), "ROLLBACK");
+        assert_eq!(end_statement(Some("sp_1"), true), "RELEASE SAVEPOINT sp_1");
+        assert_eq!(
+            end_statement(Some("sp_1"), false),
+            "ROLLBACK TO SAVEPOINT sp_1"
+        );
+    }
+
+    #[test]
+    fn test_tx_state() {
+        let parent = Arc::new(TxState::default());
+        let savepoint = TxState {
+            done: AtomicBool::new(false),
+            parent: Some(parent.clone()),
+        };
+
+        assert!(!savepoint.is_done());
+        assert!(parent.complete());
+        assert!(!parent.complete());
+
+        // Completing the enclosing transaction completes its savepoints.
+        assert!(savepoint.is_done());
+        assert!(!savepoint.complete());
+    }
+}
@@ -1,22 +1,26 @@
 use anyhow::Context;
 use std::borrow::Cow;
 use std::collections::HashMap;
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 use tokio_postgres::proxy;
 
 use tokio_postgres::proxy::{AcceptConn, AuthMethod, ClientBouncer, RejectConn};
 
+use crate::encore::parser::meta::v1 as meta;
 use crate::encore::runtime::v1 as pb;
 use crate::names::EncoreName;
-use crate::secrets;
+use crate::sqldb::migrate::{self, MigrateMode, MigrationSource};
 use crate::sqldb::Pool;
 use crate::trace::Tracer;
+use crate::{health, secrets, shutdown};
 
 pub struct Manager {
     databases: Arc<HashMap<EncoreName, Arc<DatabaseImpl>>>,
     proxy_port: u16,
     listener: Mutex<Option<std::net::TcpListener>>,
     runtime: tokio::runtime::Handle,
+    shutdown: shutdown::Tracker,
 }
 
 pub struct ManagerConfig<'a> {
@@ -25,6 +29,7 @@ pub struct ManagerConfig<'a> {
     pub secrets: &'a secrets::Manager,
     pub tracer: Tracer,
     pub runtime: tokio::runtime::Handle,
+    pub shutdown: shutdown::Tracker,
 }
 
 impl ManagerConfig<'_> {
@@ -52,6 +57,7 @@ impl ManagerConfig<'_> {
             proxy_port,
             runtime: self.runtime,
             listener: Mutex::new(Some(listener)),
+            shutdown: self.shutdown,
         })
     }
 }
@@ -77,6 +83,10 @@ impl Manager {
 
         let listener = self.listener.lock().unwrap().take();
         let runtime = self.runtime.clone();
+
+        // Keep accepting connections until the shutdown has completed,
+        // since shutdown hooks may still need to query the database.
+        let stop = self.shutdown.completed();
         self.runtime.spawn(async move {
             let listener = listener.context("sqldb server already started")?;
             listener
@@ -93,7 +103,10 @@ impl Manager {
             log::debug!(addr=addr; "encore runtime database proxy listening for incoming requests");
 
             loop {
-                let (stream, _) = listener.accept().await?;
+                let (stream, _) = tokio::select! {
+                    res = listener.accept() => res?,
+                    _ = stop.cancelled() => return Ok(()),
+                };
                 let mgr = manager.clone();
                 runtime.spawn(mgr.handle_conn(stream));
             }
@@ -101,6 +114,51 @@ impl Manager {
     }
 }
 
+impl Manager {
+    /// Returns readiness checks that verify each database can be queried.
+    pub fn readiness_checks(&self) -> Vec<health::Check> {
+        self.databases
+            .values()
+            .map(|db| {
+                let db = db.clone();
+                health::Check::new(format!("sqldb.{}", db.name), move || {
+                    let db = db.clone();
+                    Box::pin(async move { db.ping().await })
+                })
+            })
+            .collect()
+    }
+
+    /// Applies the pending migrations of the databases configured for
+    /// this process, or verifies them depending on the mode.
+    ///
+    /// Migration paths are resolved relative to `app_root`.
+    pub async fn migrate(
+        &self,
+        md: &meta::Data,
+        app_root: &Path,
+        mode: MigrateMode,
+    ) -> anyhow::Result<()> {
+        for db_meta in &md.sql_databases {
+            let Some(db) = self.databases.get(db_meta.name.as_str()) else {
+                continue;
+            };
+            let Some(src) = MigrationSource::from_meta(app_root, db_meta) else {
+                continue;
+            };
+
+            let applied = db
+                .migrate(&src, mode)
+                .await
+                .with_context(|| format!("unable to migrate database {}", db_meta.name))?;
+            if applied > 0 {
+                log::info!("database {}: applied {} migrations", db_meta.name, applied);
+            }
+        }
+        Ok(())
+    }
+}
+
 pub trait Database: Send + Sync {
     // The name of the database.
     fn name(&self) -> &EncoreName;
@@ -108,6 +166,7 @@ pub trait Database: Send + Sync {
     fn pool_config(&self) -> anyhow::Result<PoolConfig>;
     fn config(&self) -> anyhow::Result<&tokio_postgres::Config>;
     fn tls(&self) -> anyhow::Result<&postgres_native_tls::MakeTlsConnector>;
+    fn replicas(&self) -> anyhow::Result<&[ReplicaConfig]>;
     fn new_pool(&self) -> anyhow::Result<Pool>;
 
     /// Returns the connection string for connecting to this database via the proxy.
@@ -121,15 +180,28 @@ pub struct DatabaseImpl {
     tls: postgres_native_tls::MakeTlsConnector,
     proxy_conn_string: String,
     tracer: Tracer,
+    replicas: Vec<ReplicaConfig>,
 
     min_conns: u32,
     max_conns: u32,
+    route_reads_to_replicas: bool,
 }
 
 #[derive(Debug, Clone)]
 pub struct PoolConfig {
     pub min_conns: u32,
     pub max_conns: u32,
+
+    /// Whether read-only queries are routed to the read replicas.
+    pub route_reads_to_replicas: bool,
+}
+
+/// The configuration for connecting to a read replica of a database.
+#[derive(Clone)]
+pub struct ReplicaConfig {
+    pub config: tokio_postgres::Config,
+    pub tls: postgres_native_tls::MakeTlsConnector,
+    pub pool: PoolConfig,
 }
 
 impl Database for DatabaseImpl {
@@ -141,6 +213,7 @@ impl Database for DatabaseImpl {
         Ok(PoolConfig {
             min_conns: self.min_conns,
             max_conns: self.max_conns,
+            route_reads_to_replicas: self.route_reads_to_replicas,
         })
     }
 
@@ -152,6 +225,10 @@ impl Database for DatabaseImpl {
         Ok(&self.tls)
     }
 
+    fn replicas(&self) -> anyhow::Result<&[ReplicaConfig]> {
+        Ok(&self.replicas)
+    }
+
     fn new_pool(&self) -> anyhow::Result<Pool> {
         Pool::new(self, self.tracer.clone())
     }
@@ -161,6 +238,42 @@ impl Database for DatabaseImpl {
     }
 }
 
+impl DatabaseImpl {
+    async fn ping(&self) -> anyhow::Result<()> {
+        // Use a dedicated connection, so the check doesn't depend on the state of any pool.
+        let (client, conn) = self
+            .config
+            .connect(self.tls.clone())
+            .await
+            .context("unable to connect to database")?;
+        let conn = tokio::spawn(conn);
+
+        let result = client
+            .simple_query("SELECT 1")
+            .await
+            .map(|_| ())
+            .context("unable to query database");
+        drop(client);
+        _ = conn.await;
+        result
+    }
+
+    async fn migrate(&self, src: &MigrationSource, mode: MigrateMode) -> anyhow::Result<usize> {
+        // Use a dedicated connection, as the migration lock is held by the session.
+        let (client, conn) = self
+            .config
+            .connect(self.tls.clone())
+            .await
+            .context("unable to connect to database")?;
+        let conn = tokio::spawn(conn);
+
+        let result = migrate::run(&client, &self.name, src, mode).await;
+        drop(client);
+        _ = conn.await;
+        result
+    }
+}
+
 struct NoopDatabase {
     name: EncoreName,
     proxy_conn_string: String,
@@ -183,6 +296,10 @@ impl Database for NoopDatabase {
         anyhow::bail!("this database is not configured for use by this process")
     }
 
+    fn replicas(&self) -> anyhow::Result<&[ReplicaConfig]> {
+        anyhow::bail!("this database is not configured for use by this process")
+    }
+
     fn new_pool(&self) -> anyhow::Result<Pool> {
         anyhow::bail!("this database is not configured for use by this process")
     }
@@ -252,16 +369,21 @@ fn databases_from_cfg(
         // Get the primary server.
         let server = c
             .servers
-            .into_iter()
+            .iter()
             .find(|s| s.kind() == pb::ServerKind::Primary);
         let Some(server) = server else {
             log::warn!("no primary server found for cluster {}, skipping", c.rid);
             continue;
         };
+        let replica_servers: Vec<_> = c
+            .servers
+            .iter()
+            .filter(|s| s.kind() == pb::ServerKind::ReadReplica)
+            .collect();
 
         for db in c.databases {
             // Get the read-write pool for this db.
-            let pool = db.conn_pools.into_iter().find(|p| !p.is_readonly);
+            let pool = db.conn_pools.iter().find(|p| !p.is_readonly);
             let Some(pool) = pool else {
                 log::warn!(
                     "no read-write pool found for database {}, skipping",
@@ -270,99 +392,26 @@ fn databases_from_cfg(
                 continue;
             };
 
-            // Get the role to authenticate with.
-            let role = creds
-                .sql_roles
-                .iter()
-                .find(|r| r.rid == pool.role_rid)
-                .with_context(|| {
-                    format!(
-                        "no role found with rid {} for database {}",
-                        pool.role_rid, db.encore_name
-                    )
-                })?;
-
-            let mut config = tokio_postgres::Config::new();
-
-            // Add host/port configuration
-            if server.host.starts_with('/') {
-                // Unix socket
-                config.host(&server.host);
-            } else if let Some((host, port)) = server.host.split_once(':') {
-                config.host(host);
-                config.port(port.parse::<u16>().context("invalid port")?);
-            } else {
-                config.host(&server.host);
-                config.port(5432);
-            }
-
-            config.user(&role.username);
-            if let Some(password) = &role.password {
-                let sec = secrets.load(password.clone());
-                let password = sec.get().context("failed to resolve password")?;
-                config.password(password);
-            }
-
-            config.dbname(&db.cloud_name);
-            config.application_name("encore");
-
-            let mut tls_builder = native_tls::TlsConnector::builder();
-            if let Some(tls_config) = &server.tls_config {
-                if let Some(server_ca_cert) = &tls_config.server_ca_cert {
-                    let cert = native_tls::Certificate::from_pem(server_ca_cert.as_bytes())
-                        .context("unable to parse server ca certificate")?;
-                    tls_builder.add_root_certificate(cert);
-                    config.ssl_mode(tokio_postgres::config::SslMode::Require);
-                } else {
-                    config.ssl_mode(tokio_postgres::config::SslMode::Prefer);
-                }
-
-                if tls_config.disable_tls_hostname_verification {
-                    tls_builder.danger_accept_invalid_hostnames(true);
-                }
-                if tls_config.disable_ca_validation {
-                    tls_builder.danger_accept_invalid_certs(true);
-                }
-            } else {
-                config.ssl_mode(tokio_postgres::config::SslMode::Disable);
-            }
-
-            if let Some(client_cert_rid) = &role.client_cert_rid {
-                // Add a client certificate.
-                let client_cert = creds
-                    .client_certs
-                    .iter()
-                    .find(|c| c.rid == *client_cert_rid)
-                    .with_context(|| {
-                        format!(
-                            "no client certificate found with rid {} for database {}",
-                            client_cert_rid, db.encore_name
-                        )
-                    })?;
-
-                // Parse the client key secret.
-                let client_key = client_cert
-                    .key
-                    .as_ref()
-                    .context("client certificate has no key")?;
-                let client_key = secrets.load(client_key.clone());
-                let client_key = client_key.get().context("failed to resolve client key")?;
-
-                let client_key = convert_client_key_if_necessary(client_key)
-                    .context("failed to convert client key to PKCS#8")?;
-                let identity = native_tls::Identity::from_pkcs8(
-                    client_cert.cert.as_bytes(),
-                    client_key.as_ref(),
-                )
-                .context("failed to parse client certificate")?;
-                tls_builder.identity(identity);
+            let (config, tls) = conn_config(server, &db, pool, creds, secrets)?;
+
+            // Read replicas use the read-only pool if there is one,
+            // and the read-write pool otherwise.
+            let replica_pool = db.conn_pools.iter().find(|p| p.is_readonly).unwrap_or(pool);
+            let mut replicas = Vec::with_capacity(replica_servers.len());
+            for replica in &replica_servers {
+                let (config, tls) = conn_config(replica, &db, replica_pool, creds, secrets)
+                    .with_context(|| format!("read replica {}", replica.rid))?;
+                replicas.push(ReplicaConfig {
+                    config,
+                    tls,
+                    pool: PoolConfig {
+                        min_conns: replica_pool.min_connections as u32,
+                        max_conns: replica_pool.max_connections as u32,
+                        route_reads_to_replicas: false,
+                    },
+                });
             }
 
-            let tls = tls_builder
-                .build()
-                .context("failed to build TLS connector")?;
-            let tls = postgres_native_tls::MakeTlsConnector::new(tls);
-
             let proxy_conn_string = proxy_conn_string(&db.encore_name, proxy_port);
 
             let name: EncoreName = db.encore_name.into();
@@ -374,9 +423,11 @@ fn databases_from_cfg(
                     tls,
                     proxy_conn_string,
                     tracer: tracer.clone(),
+                    replicas,
 
                     min_conns: pool.min_connections as u32,
                     max_conns: pool.max_connections as u32,
+                    route_reads_to_replicas: db.route_reads_to_replicas,
                 }),
             );
         }
@@ -385,6 +436,111 @@ fn databases_from_cfg(
     Ok(databases)
 }
 
+/// Computes the configuration for connecting to a database on the given server.
+fn conn_config(
+    server: &pb::SqlServer,
+    db: &pb::SqlDatabase,
+    pool: &pb::SqlConnectionPool,
+    creds: &pb::infrastructure::Credentials,
+    secrets: &secrets::Manager,
+) -> anyhow::Result<(
+    tokio_postgres::Config,
+    postgres_native_tls::MakeTlsConnector,
+)> {
+    // Get the role to authenticate with.
+    let role = creds
+        .sql_roles
+        .iter()
+        .find(|r| r.rid == pool.role_rid)
+        .with_context(|| {
+            format!(
+                "no role found with rid {} for database {}",
+                pool.role_rid, db.encore_name
+            )
+        })?;
+
+    let mut config = tokio_postgres::Config::new();
+
+    // Add host/port configuration
+    if server.host.starts_with('/') {
+        // Unix socket
+        config.host(&server.host);
+    } else if let Some((host, port)) = server.host.split_once(':') {
+        config.host(host);
+        config.port(port.parse::<u16>().context("invalid port")?);
+    } else {
+        config.host(&server.host);
+        config.port(5432);
+    }
+
+    config.user(&role.username);
+    if let Some(password) = &role.password {
+        let sec = secrets.load(password.clone());
+        let password = sec.get().context("failed to resolve password")?;
+        config.password(password);
+    }
+
+    config.dbname(&db.cloud_name);
+    config.application_name("encore");
+
+    let mut tls_builder = native_tls::TlsConnector::builder();
+    if let Some(tls_config) = &server.tls_config {
+        if let Some(server_ca_cert) = &tls_config.server_ca_cert {
+            let cert = native_tls::Certificate::from_pem(server_ca_cert.as_bytes())
+                .context("unable to parse server ca certificate")?;
+            tls_builder.add_root_certificate(cert);
+            config.ssl_mode(tokio_postgres::config::SslMode::Require);
+        } else {
+            config.ssl_mode(tokio_postgres::config::SslMode::Prefer);
+        }
+
+        if tls_config.disable_tls_hostname_verification {
+            tls_builder.danger_accept_invalid_hostnames(true);
+        }
+        if tls_config.disable_ca_validation {
+            tls_builder.danger_accept_invalid_certs(true);
+        }
+    } else {
+        config.ssl_mode(tokio_postgres::config::SslMode::Disable);
+    }
+
+    if let Some(client_cert_rid) = &role.client_cert_rid {
+        // Add a client certificate.
+        let client_cert = creds
+            .client_certs
+            .iter()
+            .find(|c| c.rid == *client_cert_rid)
+            .with_context(|| {
+                format!(
+                    "no client certificate found with rid {} for database {}",
+                    client_cert_rid, db.encore_name
+                )
+            })?;
+
+        // Parse the client key secret.
+        let client_key = client_cert
+            .key
+            .as_ref()
+            .context("client certificate has no key")?;
+        let client_key = secrets.load(client_key.clone());
+        let client_key = client_key.get().context("failed to resolve client key")?;
+
+        let client_key = convert_client_key_if_necessary(client_key)
+            .context("failed to convert client key to PKCS#8")?;
+        let identity =
+            native_tls::Identity::from_pkcs8(client_cert.cert.as_bytes(), client_key.as_ref())
+                .context("failed to parse client certificate")?;
+        tls_builder.identity(identity);
+    }
+
+    let tls = tls_builder
+        .build()
+        .context("failed to build TLS connector")?;
+    let tls = postgres_native_tls::MakeTlsConnector::new(tls);
+
+    Ok((config, tls))
+}
+
 /// Converts the client key from PKCS#1 to PKCS#8 if necessary.
 fn convert_client_key_if_necessary(pem: &[u8]) -> anyhow::Result<Cow<'_, [u8]>> {
     let Ok(pem_str) = std::str::from_utf8(pem) else {
@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use crate::encore::parser::meta::v1 as meta;
+
+/// The table applied migrations are tracked in.
+///
+/// The layout matches the one used by the Encore CLI, so databases
+/// migrated by either can be migrated by the other.
+const MIGRATIONS_TABLE: &str = "schema_migrations";
+
+/// The migrations of a database, as discovered by the parser.
+#[derive(Debug)]
+pub struct MigrationSource {
+    dir: PathBuf,
+    migrations: Vec<Migration>,
+
+    /// Whether migrations may be applied out of order,
+    /// in which case each applied version is tracked separately.
+    non_sequential: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Migration {
+    number: u64,
+    file_name: String,
+}
+
+impl MigrationSource {
+    /// Returns the migrations of the given database, relative to the app root.
+    /// Returns None if the database has no migrations.
+    pub fn from_meta(app_root: &Path, db: &meta::SqlDatabase) -> Option<Self> {
+        let rel_path = db.migration_rel_path.as_deref()?;
+        if db.migrations.is_empty() {
+            return None;
+        }
+
+        let mut migrations: Vec<_> = db
+            .migrations
+            .iter()
+            .map(|m| Migration {
+                number: m.number,
+                file_name: m.filename.clone(),
+            })
+            .collect();
+        migrations.sort_by_key(|m| m.number);
+
+        Some(Self {
+            dir: app_root.join(rel_path),
+            migrations,
+            non_sequential: db.allow_non_sequential_migrations,
+        })
+    }
+
+    fn read(&self, migration: &Migration) -> anyhow::Result<String> {
+        let path = self.dir.join(&migration.file_name);
+        std::fs::read_to_string(&path)
+            .with_context(|| format!("unable to read migration {}", path.display()))
+    }
+}
+
+/// What to do with pending migrations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrateMode {
+    /// Apply pending migrations.
+    Apply,
+    /// Only verify that no migration is dirty.
+    Verify,
+}
+
+/// Applies the pending migrations to a database using the given connection,
+/// or verifies them depending on the mode. Returns the number of migrations applied.
+///
+/// Concurrent migrations of the same database are serialized with an advisory lock.
+/// Fails if a previous migration failed part-way and left the database dirty.
+pub(super) async fn run(
+    client: &tokio_postgres::Client,
+    db_name: &str,
+    src: &MigrationSource,
+    mode: MigrateMode,
+) -> anyhow::Result<usize> {
+    let lock_id = advisory_lock_id(db_name);
+    client
+        .execute("SELECT pg_advisory_lock($1)", &[&lock_id])
+        .await
+        .context("unable to acquire migration lock")?;
+
+    let result = run_locked(client, db_name, src, mode).await;
+
+    if let Err(err) = client
+        .execute("SELECT pg_advisory_unlock($1)", &[&lock_id])
+        .await
+    {
+        log::warn!(
+            "database {}: unable to release migration lock: {}",
+            db_name,
+            err
+        );
+    }
+    result
+}
+
+async fn run_locked(
+    client: &tokio_postgres::Client,
+    db_name: &str,
+    src: &MigrationSource,
+    mode: MigrateMode,
+) -> anyhow::Result<usize> {
+    let has_table = match mode {
+        MigrateMode::Apply => {
+            client
+                .batch_execute(&format!(
+                    "CREATE TABLE IF NOT EXISTS {} (version bigint NOT NULL PRIMARY KEY, dirty boolean NOT NULL)",
+                    MIGRATIONS_TABLE
+                ))
+                .await
+                .context("unable to create migrations table")?;
+            true
+        }
+        // Verifying must not modify the database, so the
+        // table is only read if it has already been created.
+        MigrateMode::Verify => client
+            .query_one("SELECT to_regclass($1) IS NOT NULL", &[&MIGRATIONS_TABLE])
+            .await
+            .context("unable to look up migrations table")?
+            .get::<_, bool>(0),
+    };
+
+    let applied: HashMap<u64, bool> = if has_table {
+        client
+            .query(
+                &format!(
+                    "SELECT version, dirty FROM {} ORDER BY version",
+                    MIGRATIONS_TABLE
+                ),
+                &[],
+            )
+            .await
+            .context("unable to load applied migrations")?
+            .into_iter()
+            .map(|row| (row.get::<_, i64>(0) as u64, row.get::<_, bool>(1)))
+            .collect()
+    } else {
+        HashMap::new()
+    };
+
+    if let Some(version) = dirty_version(&applied) {
+        anyhow::bail!(
+            "database {} is dirty: migration {} failed part-way. \
+            Fix the database schema and clear the dirty flag in the {} table before restarting",
+            db_name,
+            version,
+            MIGRATIONS_TABLE
+        );
+    }
+
+    let pending = pending(src, &applied);
+    if mode == MigrateMode::Verify {
+        if !pending.is_empty() {
+            log::warn!(
+                "database {} has {} pending migrations",
+                db_name,
+                pending.len()
+            );
+        }
+        return Ok(0);
+    }
+
+    for migration in &pending {
+        let sql = src.read(migration)?;
+        log::info!(
+            "database {}: applying migration {}",
+            db_name,
+            migration.file_name
+        );
+
+        set_version(client, src.non_sequential, migration.number, true).await?;
+        client
+            .batch_execute(&sql)
+            .await
+            .with_context(|| format!("migration {} failed", migration.file_name))?;
+        set_version(client, src.non_sequential, migration.number, false).await?;
+    }
+
+    Ok(pending.len())
+}
+
+/// Records the given version in the migrations table.
+///
+/// For sequential migrations the table holds only the latest version,
+/// otherwise it holds one row per applied version.
+async fn set_version(
+    client: &tokio_postgres::Client,
+    non_sequential: bool,
+    version: u64,
+    dirty: bool,
+) -> anyhow::Result<()> {
+    let stmt = if non_sequential {
+        format!(
+            "INSERT INTO {table} (version, dirty) VALUES ({version}, {dirty}) \
+            ON CONFLICT (version) DO UPDATE SET dirty = {dirty}",
+            table = MIGRATIONS_TABLE,
+        )
+    } else {
+        format!(
+            "BEGIN; TRUNCATE {table}; INSERT INTO {table} (version, dirty) VALUES ({version}, {dirty}); COMMIT;",
+            table = MIGRATIONS_TABLE,
+        )
+    };
+    client
+        .batch_execute(&stmt)
+        .await
+        .context("unable to update migration version")
+}
+
+fn dirty_version(applied: &HashMap<u64, bool>) -> Option<u64> {
+    applied
+        .iter()
+        .filter(|(_, &dirty)| dirty)
+        .map(|(&version, _)| version)
+        .min()
+}
+
+/// Returns the migrations that have yet to be applied, in order.
+fn pending(src: &MigrationSource, applied: &HashMap<u64, bool>) -> Vec<Migration> {
+    if src.non_sequential {
+        src.migrations
+            .iter()
+            .filter(|m| !applied.contains_key(&m.number))
+            .cloned()
+            .collect()
+    } else {
+        let current = applied.keys().max().copied();
+        src.migrations
+            .iter()
+            .filter(|m| current.map_or(true, |current| m.number > current))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Computes the advisory lock id for migrating the given database,
+/// using FNV-1a so it's stable across processes.
+fn advisory_lock_id(db_name: &str) -> i64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in "encore-migrate:".bytes().chain(db_name.bytes()) {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(numbers: &[u64], non_sequential: bool) -> MigrationSource {
+        MigrationSource {
+            dir: PathBuf::new(),
+            migrations: numbers
+                .iter()
+                .map(|&number| Migration {
+                    number,
+                    file_name: format!("{}_migration.up.sql", number),
+                })
+                .collect(),
+            non_sequential,
+        }
+    }
+
+    fn numbers(migrations: Vec<Migration>) -> Vec<u64> {
+        migrations.into_iter().map(|m| m.number).collect()
+    }
+
+    #[test]
+    fn test_pending_sequential() {
+        let src = source(&[1, 2, 3], false);
+        assert_eq!(numbers(pending(&src, &HashMap::new())), vec![1, 2, 3]);
+        assert_eq!(
+            numbers(pending(&src, &HashMap::from([(2, false)]))),
+            vec![3]
+        );
+        assert!(pending(&src, &HashMap::from([(3, false)])).is_empty());
+    }
+
+    #[test]
+    fn test_pending_non_sequential() {
+        let src = source(&[1, 2, 3], true);
+        let applied = HashMap::from([(1, false), (3, false)]);
+        assert_eq!(numbers(pending(&src, &applied)), vec![2]);
+    }
+
+    #[test]
+    fn test_dirty_version() {
+        assert_eq!(dirty_version(&HashMap::from([(1, false)])), None);
+        assert_eq!(
+            dirty_version(&HashMap::from([(1, false), (4, true), (3, true)])),
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn test_advisory_lock_id() {
+        assert_eq!(advisory_lock_id("foo"), advisory_lock_id("foo"));
+        assert_ne!(advisory_lock_id("foo"), advisory_lock_id("bar"));
+    }
+}
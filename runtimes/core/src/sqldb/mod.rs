@@ -1,7 +1,10 @@
 mod client;
 mod manager;
+mod migrate;
+mod replica;
 mod val;
 
-pub use client::{Connection, Cursor, Pool, Row};
-pub use manager::{Database, DatabaseImpl, Manager, ManagerConfig};
+pub use client::{Connection, Cursor, Pool, ReadReplica, Row, Transaction};
+pub use manager::{Database, DatabaseImpl, Manager, ManagerConfig, PoolConfig, ReplicaConfig};
+pub use migrate::MigrateMode;
 pub use val::RowValue;
@@ -0,0 +1,262 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use bb8::{PooledConnection, RunError};
+
+use crate::sqldb::client::{Error, Mgr};
+
+/// How long to wait for a connection to a replica
+/// before considering it unavailable.
+pub(super) const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait before first probing an ejected replica.
+const INITIAL_PROBE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The maximum time between probes of an ejected replica.
+const MAX_PROBE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Words that make a SELECT query unsafe to run on a replica.
+const WRITE_WORDS: &[&str] = &["into", "update", "share", "nextval", "setval"];
+
+/// The read replicas of a database.
+///
+/// Replicas that can't be reached are ejected and probed in the
+/// background until they are reachable again.
+pub(super) struct Replicas {
+    db_name: Arc<str>,
+    pools: Vec<ReplicaPool>,
+    next: AtomicUsize,
+}
+
+struct ReplicaPool {
+    pool: bb8::Pool<Mgr>,
+    healthy: AtomicBool,
+}
+
+impl Replicas {
+    pub fn new(db_name: Arc<str>, pools: Vec<bb8::Pool<Mgr>>) -> Self {
+        Self {
+            db_name,
+            pools: pools
+                .into_iter()
+                .map(|pool| ReplicaPool {
+                    pool,
+                    healthy: AtomicBool::new(true),
+                })
+                .collect(),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Gets a connection to a healthy replica, round-robin.
+    /// Returns the index of the replica alongside the connection,
+    /// or None if no replica is available, in which case
+    /// the query should fall back to the primary.
+    pub async fn get(self: &Arc<Self>) -> Option<(usize, PooledConnection<'_, Mgr>)> {
+        for idx in round_robin(&self.next, self.pools.len()) {
+            let replica = &self.pools[idx];
+            if !replica.healthy.load(Ordering::Relaxed) {
+                continue;
+            }
+            match replica.pool.get().await {
+                Ok(conn) => return Some((idx, conn)),
+                // The pool is exhausted or slow to connect, which doesn't
+                // mean the replica is down. Try the next one instead.
+                Err(RunError::TimedOut) => {}
+                Err(RunError::User(err)) => self.eject(idx, &err),
+            }
+        }
+        None
+    }
+
+    /// Reports an error from a query on the given replica,
+    /// ejecting the replica if its connection was lost.
+    pub fn report_error(self: &Arc<Self>, idx: usize, err: &tokio_postgres::Error) {
+        if err.is_closed() {
+            self.eject(idx, err);
+        }
+    }
+
+    fn eject(self: &Arc<Self>, idx: usize, err: &dyn std::fmt::Display) {
+        if !self.pools[idx].healthy.swap(false, Ordering::AcqRel) {
+            // Already ejected and being probed.
+            return;
+        }
+
+        log::warn!(
+            "database {}: read replica {} unavailable, falling back to primary: {}",
+            self.db_name,
+            idx,
+            err
+        );
+        tokio::spawn(probe(Arc::downgrade(self), idx));
+    }
+}
+
+/// Awaits a query running on a replica. If the replica's connection
+/// was lost the error is reported and the query is retried once on
+/// the primary, as it may not have reached the replica at all.
+pub(super) async fn or_primary<T>(
+    replica: impl Future<Output = Result<T, tokio_postgres::Error>>,
+    report_error: impl FnOnce(&tokio_postgres::Error),
+    primary: impl Future<Output = Result<T, Error>>,
+) -> Result<T, Error> {
+    match replica.await {
+        Ok(val) => Ok(val),
+        Err(err) => {
+            report_error(&err);
+            if err.is_closed() {
+                primary.await
+            } else {
+                Err(Error::DB(err))
+            }
+        }
+    }
+}
+
+/// Probes an ejected replica until it is reachable again,
+/// or until the replicas are dropped.
+async fn probe(replicas: Weak<Replicas>, idx: usize) {
+    let mut interval = INITIAL_PROBE_INTERVAL;
+    loop {
+        tokio::time::sleep(interval).await;
+        let Some(replicas) = replicas.upgrade() else {
+            return;
+        };
+
+        let replica = &replicas.pools[idx];
+        let check = async {
+            let conn = replica.pool.get().await.ok()?;
+            conn.simple_query("SELECT 1").await.ok()
+        };
+        if let Ok(Some(_)) = tokio::time::timeout(CONNECT_TIMEOUT, check).await {
+            log::info!(
+                "database {}: read replica {} is available again",
+                replicas.db_name,
+                idx
+            );
+            replica.healthy.store(true, Ordering::Release);
+            return;
+        }
+
+        interval = (interval * 2).min(MAX_PROBE_INTERVAL);
+    }
+}
+
+/// Returns the indices of all `n` replicas, starting
+/// from the next one in round-robin order.
+fn round_robin(next: &AtomicUsize, n: usize) -> impl Iterator<Item = usize> {
+    let start = if n > 0 {
+        next.fetch_add(1, Ordering::Relaxed)
+    } else {
+        0
+    };
+    (0..n).map(move |i| (start + i) % n)
+}
+
+/// Reports whether a query is a plain SELECT that can safely run on a read replica.
+///
+/// The check is conservative: locking reads, SELECT INTO, sequence
+/// manipulation and WITH queries (which may modify data) are not considered read-only.
+pub(super) fn is_read_only(query: &str) -> bool {
+    let mut words = skip_leading_comments(query)
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .filter(|w| !w.is_empty());
+
+    match words.next() {
+        Some(first) if first.eq_ignore_ascii_case("select") => {}
+        _ => return false,
+    }
+    !words.any(|w| WRITE_WORDS.iter().any(|ww| w.eq_ignore_ascii_case(ww)))
+}
+
+fn skip_leading_comments(mut query: &str) -> &str {
+    loop {
+        query = query.trim_start();
+        if let Some(rest) = query.strip_prefix("--") {
+            query = rest.split_once('\n').map_or("", |(_, rest)| rest);
+        } else if let Some(rest) = query.strip_prefix("/*") {
+            query = rest.split_once("*/").map_or("", |(_, rest)| rest);
+        } else {
+            return query;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_read_only() {
+        for query in [
+            "SELECT 1",
+            "  select id from users where email = $1",
+            "-- fetch the user\nSELECT * FROM users",
+            "/* report */ SELECT count(*) FROM orders",
+        ] {
+            assert!(is_read_only(query), "{query}");
+        }
+
+        for query in [
+            "INSERT INTO users (id) VALUES ($1)",
+            "UPDATE users SET name = $1",
+            "SELECT * FROM users FOR UPDATE",
+            "SELECT * FROM users FOR KEY SHARE",
+            "SELECT * INTO backup FROM users",
+            "SELECT nextval('user_ids')",
+            "WITH deleted AS (DELETE FROM users RETURNING *) SELECT * FROM deleted",
+            "-- SELECT\nDELETE FROM users",
+            "",
+        ] {
+            assert!(!is_read_only(query), "{query}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_or_primary() {
+        let primary = || async { Ok::<_, Error>("primary") };
+
+        // Successful and failed queries on the replica are returned as is.
+        let result = or_primary(async { Ok("replica") }, |_| panic!(), primary()).await;
+        assert_eq!(result.unwrap(), "replica");
+
+        let reported = AtomicBool::new(false);
+        let result = or_primary(
+            async { Err(tokio_postgres::Error::__private_api_timeout()) },
+            |_| reported.store(true, Ordering::Relaxed),
+            primary(),
+        )
+        .await;
+        assert!(matches!(result, Err(Error::DB(_))));
+        assert!(reported.load(Ordering::Relaxed));
+
+        // Queries on a closed replica connection are retried on the primary.
+        let reported = AtomicBool::new(false);
+        let result = or_primary(
+            async { Err(tokio_postgres::Error::__private_api_closed()) },
+            |err| reported.store(err.is_closed(), Ordering::Relaxed),
+            primary(),
+        )
+        .await;
+        assert_eq!(result.unwrap(), "primary");
+        assert!(reported.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let next = AtomicUsize::new(0);
+        assert_eq!(round_robin(&next, 0).count(), 0);
+
+        let picks: Vec<_> = (0..4)
+            .map(|_| round_robin(&next, 3).next().unwrap())
+            .collect();
+        assert_eq!(picks, vec![0, 1, 2, 0]);
+
+        // Every replica is tried once, starting from the next one.
+        let order: Vec<_> = round_robin(&next, 3).collect();
+        assert_eq!(order, vec![1, 2, 0]);
+    }
+}
@@ -1,7 +1,12 @@
 mod eventbuf;
 mod log;
+mod otlp;
 pub mod protocol;
 mod time_anchor;
 
 pub use log::{streaming_tracer, ReporterConfig};
+pub use otlp::{
+    otlp_exporter, parse_headers as parse_otlp_headers, AnyValue as OtlpValue,
+    Exporter as OtlpExporter, ExporterConfig as OtlpExporterConfig, KeyValue as OtlpAttr,
+};
 pub use protocol::Tracer;
@@ -0,0 +1,653 @@
+//! Exports traces as OpenTelemetry spans over OTLP/HTTP, using the JSON encoding.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use serde::Serialize;
+
+use crate::encore::runtime::v1 as pb;
+use crate::model::{self, SpanId, SpanKey, TraceEventId, TraceId};
+
+/// The maximum number of spans sent in a single export request.
+const MAX_BATCH_SIZE: usize = 512;
+
+/// How often finished spans are exported.
+const EXPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The maximum number of finished spans waiting to be exported.
+/// Spans finishing while the queue is full are dropped.
+const MAX_QUEUED_SPANS: usize = 8192;
+
+/// The maximum number of spans that may be in progress at once.
+/// Guards against unbounded growth if spans are never ended.
+const MAX_OPEN_SPANS: usize = 10_000;
+
+/// How many of the oldest open spans are evicted when `MAX_OPEN_SPANS` is reached.
+/// Evicting in batches keeps the cost of finding the oldest spans low.
+const EVICT_OPEN_SPANS: usize = MAX_OPEN_SPANS / 10;
+
+pub struct ExporterConfig {
+    /// The OTLP/HTTP traces endpoint, e.g. "http://localhost:4318/v1/traces".
+    pub endpoint: reqwest::Url,
+
+    /// Headers to send with each export request.
+    pub headers: reqwest::header::HeaderMap,
+
+    /// The fraction of traces to export, between [0, 1].
+    pub sampling_rate: f64,
+
+    /// Attributes describing the deployment, added to the resource of every span.
+    pub resource_attrs: Vec<KeyValue>,
+}
+
+impl ExporterConfig {
+    /// Parses the exporter configuration from the runtime config.
+    /// The resource attributes are left empty.
+    pub fn from_provider(
+        provider: &pb::tracing_provider::OtlpTracingProvider,
+    ) -> anyhow::Result<Self> {
+        let endpoint = reqwest::Url::parse(&provider.endpoint)
+            .with_context(|| format!("invalid otlp endpoint {}", provider.endpoint))?;
+
+        Ok(Self {
+            endpoint,
+            headers: parse_headers(&provider.headers)?,
+            sampling_rate: provider.sampling_rate.unwrap_or(1.0).clamp(0.0, 1.0),
+            resource_attrs: Vec::new(),
+        })
+    }
+}
+
+/// Parses the headers to send with each OTLP export request.
+pub fn parse_headers(
+    headers: &HashMap<String, String>,
+) -> anyhow::Result<reqwest::header::HeaderMap> {
+    let mut map = reqwest::header::HeaderMap::new();
+    for (key, value) in headers {
+        let name = reqwest::header::HeaderName::from_bytes(key.as_bytes())
+            .with_context(|| format!("invalid otlp header name {}", key))?;
+        let value = reqwest::header::HeaderValue::from_str(value)
+            .with_context(|| format!("invalid value for otlp header {}", key))?;
+        map.insert(name, value);
+    }
+    Ok(map)
+}
+
+/// Records spans as they start and end, and queues finished spans for export.
+#[derive(Debug, Clone)]
+pub struct SpanRecorder {
+    inner: Arc<RecorderInner>,
+}
+
+#[derive(Debug)]
+struct RecorderInner {
+    sampling_rate: f64,
+    open: Mutex<HashMap<SpanRef, OpenSpan>>,
+    tx: tokio::sync::mpsc::Sender<FinishedSpan>,
+    dropped: AtomicU64,
+}
+
+/// Identifies an in-progress span.
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+enum SpanRef {
+    /// A request span, identified by its Encore span.
+    Request(SpanKey),
+    /// A span for an operation made by a request, identified by its start event.
+    Event(TraceEventId),
+}
+
+#[derive(Debug)]
+struct OpenSpan {
+    service: String,
+    trace_id: TraceId,
+    span_id: SpanId,
+    parent_span_id: Option<SpanId>,
+    name: String,
+    kind: SpanKind,
+    start: SystemTime,
+    attrs: Vec<KeyValue>,
+}
+
+#[derive(Debug)]
+struct FinishedSpan {
+    service: String,
+    span: Span,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(i32)]
+pub enum SpanKind {
+    Server = 2,
+    Client = 3,
+    Producer = 4,
+    Consumer = 5,
+}
+
+pub fn otlp_exporter(
+    http_client: reqwest::Client,
+    config: ExporterConfig,
+) -> (SpanRecorder, Exporter) {
+    let (tx, rx) = tokio::sync::mpsc::channel(MAX_QUEUED_SPANS);
+    let recorder = SpanRecorder {
+        inner: Arc::new(RecorderInner {
+            sampling_rate: config.sampling_rate,
+            open: Mutex::new(HashMap::new()),
+            tx,
+            dropped: AtomicU64::new(0),
+        }),
+    };
+    let exporter = Exporter {
+        rx,
+        http_client,
+        recorder: recorder.inner.clone(),
+        endpoint: config.endpoint,
+        headers: config.headers,
+        resource_attrs: config.resource_attrs,
+    };
+    (recorder, exporter)
+}
+
+impl SpanRecorder {
+    pub(super) fn request_start(&self, req: &model::Request) {
+        if !is_sampled(&req.span.0, self.inner.sampling_rate) {
+            return;
+        }
+
+        let (service, name, kind, attrs) = match &req.data {
+            model::RequestData::RPC(rpc) => (
+                rpc.endpoint.name.service(),
+                rpc.endpoint.name.to_string(),
+                SpanKind::Server,
+                vec![
+                    KeyValue::str("http.request.method", rpc.method.as_str()),
+                    KeyValue::str("url.path", &rpc.path),
+                ],
+            ),
+            model::RequestData::Stream(data) => (
+                data.endpoint.name.service(),
+                data.endpoint.name.to_string(),
+                SpanKind::Server,
+                vec![KeyValue::str("url.path", &data.path)],
+            ),
+            model::RequestData::Auth(auth) => (
+                auth.auth_handler.service(),
+                auth.auth_handler.to_string(),
+                SpanKind::Server,
+                vec![],
+            ),
+            model::RequestData::PubSub(msg) => (
+                msg.service.as_ref(),
+                format!("{} process", msg.topic),
+                SpanKind::Consumer,
+                vec![
+                    KeyValue::str("messaging.destination.name", &msg.topic),
+                    KeyValue::str("messaging.consumer.group.name", &msg.subscription),
+                    KeyValue::str("messaging.message.id", &msg.message_id),
+                    KeyValue::int("messaging.message.delivery_attempt", msg.attempt as i64),
+                ],
+            ),
+        };
+
+        self.open(
+            SpanRef::Request(req.span),
+            OpenSpan {
+                service: service.to_string(),
+                trace_id: req.span.0,
+                span_id: req.span.1,
+                parent_span_id: req.parent_span.map(|sp| sp.1),
+                name,
+                kind,
+                start: req.start_time,
+                attrs,
+            },
+        );
+    }
+
+    pub(super) fn request_end(&self, resp: &model::Response) {
+        let (err, attrs) = match &resp.data {
+            model::ResponseData::RPC(rpc) => (
+                rpc.error.as_ref(),
+                vec![KeyValue::int(
+                    "http.response.status_code",
+                    rpc.status_code as i64,
+                )],
+            ),
+            model::ResponseData::Auth(res) => (res.as_ref().err(), vec![]),
+            model::ResponseData::PubSub(res) => (res.as_ref().err(), vec![]),
+        };
+        self.close(
+            SpanRef::Request(resp.request.span),
+            err.map(|e| e.to_string()),
+            attrs,
+        );
+    }
+
+    /// Starts a span for an operation made while processing `source`.
+    /// The span is only recorded if the request span is.
+    pub(super) fn child_start(
+        &self,
+        id: TraceEventId,
+        source: &model::Request,
+        name: String,
+        kind: SpanKind,
+        attrs: Vec<KeyValue>,
+    ) {
+        let mut open = self.inner.open.lock().unwrap();
+        let Some(parent) = open.get(&SpanRef::Request(source.span)) else {
+            return;
+        };
+        let span = OpenSpan {
+            service: parent.service.clone(),
+            trace_id: source.span.0,
+            span_id: SpanId::generate(),
+            parent_span_id: Some(source.span.1),
+            name,
+            kind,
+            start: SystemTime::now(),
+            attrs,
+        };
+        insert_open(&mut open, SpanRef::Event(id), span);
+    }
+
+    /// Sets an attribute on a span started with `child_start`,
+    /// replacing any existing attribute with the same key.
+    pub(super) fn child_attr(&self, id: TraceEventId, attr: KeyValue) {
+        let mut open = self.inner.open.lock().unwrap();
+        let Some(span) = open.get_mut(&SpanRef::Event(id)) else {
+            return;
+        };
+        span.attrs.retain(|kv| kv.key != attr.key);
+        span.attrs.push(attr);
+    }
+
+    /// Ends a span started with `child_start`.
+    pub(super) fn child_end(&self, id: TraceEventId, err: Option<String>) {
+        self.close(SpanRef::Event(id), err, vec![]);
+    }
+
+    fn open(&self, key: SpanRef, span: OpenSpan) {
+        let mut open = self.inner.open.lock().unwrap();
+        insert_open(&mut open, key, span);
+    }
+
+    fn close(&self, key: SpanRef, err: Option<String>, extra_attrs: Vec<KeyValue>) {
+        let Some(span) = self.inner.open.lock().unwrap().remove(&key) else {
+            return;
+        };
+
+        let mut attrs = span.attrs;
+        attrs.extend(extra_attrs);
+        let status = match err {
+            Some(message) => Status { code: 2, message },
+            None => Status::default(),
+        };
+
+        let finished = FinishedSpan {
+            service: span.service,
+            span: Span {
+                trace_id: span.trace_id.serialize_std(),
+                span_id: span.span_id.serialize_std(),
+                parent_span_id: span
+                    .parent_span_id
+                    .map(|id| id.serialize_std())
+                    .unwrap_or_default(),
+                name: span.name,
+                kind: span.kind as i32,
+                start_time_unix_nano: unix_nanos(span.start),
+                end_time_unix_nano: unix_nanos(SystemTime::now()),
+                attributes: attrs,
+                status,
+            },
+        };
+
+        if self.inner.tx.try_send(finished).is_err() {
+            self.inner.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Sends finished spans to the OTLP endpoint.
+#[must_use]
+pub struct Exporter {
+    rx: tokio::sync::mpsc::Receiver<FinishedSpan>,
+    http_client: reqwest::Client,
+    recorder: Arc<RecorderInner>,
+    endpoint: reqwest::Url,
+    headers: reqwest::header::HeaderMap,
+    resource_attrs: Vec<KeyValue>,
+}
+
+impl Exporter {
+    pub async fn start_exporting(mut self) {
+        let mut ticker = tokio::time::interval(EXPORT_INTERVAL);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut batch = Vec::with_capacity(MAX_BATCH_SIZE);
+
+        loop {
+            tokio::select! {
+                span = self.rx.recv() => {
+                    let Some(span) = span else {
+                        // All recorders have been dropped.
+                        self.export(std::mem::take(&mut batch)).await;
+                        return;
+                    };
+                    batch.push(span);
+                    if batch.len() >= MAX_BATCH_SIZE {
+                        self.export(std::mem::take(&mut batch)).await;
+                    }
+                }
+                _ = ticker.tick() => {
+                    self.export(std::mem::take(&mut batch)).await;
+                }
+            }
+        }
+    }
+
+    async fn export(&self, batch: Vec<FinishedSpan>) {
+        let dropped = self.recorder.dropped.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            log::warn!("dropped {} spans: the OTLP export queue is full", dropped);
+        }
+        if batch.is_empty() {
+            return;
+        }
+
+        let req = export_request(&self.resource_attrs, batch);
+        let result = self
+            .http_client
+            .post(self.endpoint.clone())
+            .headers(self.headers.clone())
+            .json(&req)
+            .send()
+            .await;
+        match result {
+            Ok(resp) if !resp.status().is_success() => {
+                let status = resp.status();
+                let body = resp.text().await.unwrap_or_default();
+                log::error!("failed to export spans: HTTP {}: {}", status, body);
+            }
+            Err(err) => {
+                log::error!("failed to export spans: {}", err);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Groups the spans by service, as each service is a separate OpenTelemetry resource.
+fn export_request(resource_attrs: &[KeyValue], batch: Vec<FinishedSpan>) -> ExportRequest {
+    let mut by_service: HashMap<String, Vec<Span>> = HashMap::new();
+    for finished in batch {
+        by_service
+            .entry(finished.service)
+            .or_default()
+            .push(finished.span);
+    }
+
+    let resource_spans = by_service
+        .into_iter()
+        .map(|(service, spans)| {
+            let mut attributes = vec![KeyValue::str("service.name", &service)];
+            attributes.extend(resource_attrs.iter().cloned());
+            ResourceSpans {
+                resource: Resource { attributes },
+                scope_spans: vec![ScopeSpans {
+                    scope: Scope {
+                        name: "encore",
+                        version: env!("CARGO_PKG_VERSION"),
+                    },
+                    spans,
+                }],
+            }
+        })
+        .collect();
+    ExportRequest { resource_spans }
+}
+
+/// Reports whether a trace should be recorded, given the sampling rate.
+/// The decision is derived from the trace id so all services agree on it.
+fn is_sampled(trace_id: &TraceId, rate: f64) -> bool {
+    if rate >= 1.0 {
+        return true;
+    } else if rate <= 0.0 {
+        return false;
+    }
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&trace_id.0[8..]);
+    let n = u64::from_be_bytes(bytes);
+    (n as f64) < rate * (u64::MAX as f64)
+}
+
+fn unix_nanos(t: SystemTime) -> String {
+    t.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default()
+        .to_string()
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportRequest {
+    resource_spans: Vec<ResourceSpans>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ResourceSpans {
+    resource: Resource,
+    scope_spans: Vec<ScopeSpans>,
+}
+
+#[derive(Debug, Serialize)]
+struct Resource {
+    attributes: Vec<KeyValue>,
+}
+
+#[derive(Debug, Serialize)]
+struct ScopeSpans {
+    scope: Scope,
+    spans: Vec<Span>,
+}
+
+#[derive(Debug, Serialize)]
+struct Scope {
+    name: &'static str,
+    version: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Span {
+    trace_id: String,
+    span_id: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    parent_span_id: String,
+    name: String,
+    kind: i32,
+    start_time_unix_nano: String,
+    end_time_unix_nano: String,
+    attributes: Vec<KeyValue>,
+    status: Status,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Status {
+    code: i32,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyValue {
+    key: String,
+    value: AnyValue,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AnyValue {
+    StringValue(String),
+    // int64 values are encoded as strings in OTLP/JSON.
+    IntValue(String),
+    BoolValue(bool),
+    DoubleValue(f64),
+}
+
+impl KeyValue {
+    pub fn str(key: &str, value: &str) -> Self {
+        Self {
+            key: key.to_string(),
+            value: AnyValue::StringValue(value.to_string()),
+        }
+    }
+
+    pub fn int(key: &str, value: i64) -> Self {
+        Self {
+            key: key.to_string(),
+            value: AnyValue::IntValue(value.to_string()),
+        }
+    }
+
+    pub fn json(key: &str, value: &serde_json::Value) -> Self {
+        Self {
+            key: key.to_string(),
+            value: AnyValue::json(value),
+        }
+    }
+}
+
+impl AnyValue {
+    /// Converts a JSON value. Arrays and objects are encoded as JSON strings.
+    pub fn json(value: &serde_json::Value) -> Self {
+        match value {
+            serde_json::Value::String(s) => AnyValue::StringValue(s.clone()),
+            serde_json::Value::Bool(b) => AnyValue::BoolValue(*b),
+            serde_json::Value::Number(n) => match (n.as_i64(), n.as_f64()) {
+                (Some(i), _) => AnyValue::IntValue(i.to_string()),
+                (None, Some(f)) => AnyValue::DoubleValue(f),
+                (None, None) => AnyValue::StringValue(n.to_string()),
+            },
+            other => AnyValue::StringValue(other.to_string()),
+        }
+    }
+}
+
+/// Inserts an open span, making room for it by evicting the oldest
+/// open spans if there are too many. Spans that are never ended would
+/// otherwise keep new spans from being recorded.
+fn insert_open(open: &mut HashMap<SpanRef, OpenSpan>, key: SpanRef, span: OpenSpan) {
+    if open.len() >= MAX_OPEN_SPANS && !open.contains_key(&key) {
+        let evicted = evict_oldest(open, EVICT_OPEN_SPANS);
+        log::warn!(
+            "too many open spans, dropped the {} oldest spans that were never ended",
+            evicted
+        );
+    }
+    open.insert(key, span);
+}
+
+/// Removes the `n` longest open spans, along with any other spans
+/// started at the same time as the last of them.
+/// Returns the number of spans removed.
+fn evict_oldest(open: &mut HashMap<SpanRef, OpenSpan>, n: usize) -> usize {
+    if n == 0 || open.is_empty() {
+        return 0;
+    }
+
+    let mut starts: Vec<SystemTime> = open.values().map(|span| span.start).collect();
+    let n = n.min(starts.len());
+    let (_, cutoff, _) = starts.select_nth_unstable(n - 1);
+    let cutoff = *cutoff;
+
+    let before = open.len();
+    open.retain(|_, span| span.start > cutoff);
+    before - open.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_sampled() {
+        let low = TraceId([0; 16]);
+        let high = TraceId([0xff; 16]);
+        assert!(is_sampled(&low, 1.0));
+        assert!(is_sampled(&high, 1.0));
+        assert!(!is_sampled(&low, 0.0));
+        assert!(is_sampled(&low, 0.5));
+        assert!(!is_sampled(&high, 0.5));
+    }
+
+    #[test]
+    fn test_insert_open_evicts_oldest() {
+        let epoch = SystemTime::now();
+        let span = |i: u64| OpenSpan {
+            service: "svc".to_string(),
+            trace_id: TraceId([1; 16]),
+            span_id: SpanId::generate(),
+            parent_span_id: None,
+            name: "op".to_string(),
+            kind: SpanKind::Client,
+            start: epoch + Duration::from_secs(i),
+            attrs: vec![],
+        };
+
+        let mut open = HashMap::new();
+        for i in 0..MAX_OPEN_SPANS as u64 {
+            insert_open(&mut open, SpanRef::Event(TraceEventId(i)), span(i));
+        }
+        assert_eq!(open.len(), MAX_OPEN_SPANS);
+
+        // New spans are still recorded, at the expense of the oldest ones.
+        let new = SpanRef::Event(TraceEventId(MAX_OPEN_SPANS as u64));
+        insert_open(&mut open, new, span(MAX_OPEN_SPANS as u64));
+        assert!(open.contains_key(&new));
+        assert_eq!(open.len(), MAX_OPEN_SPANS - EVICT_OPEN_SPANS + 1);
+        assert!(!open.contains_key(&SpanRef::Event(TraceEventId(0))));
+        let last_evicted = EVICT_OPEN_SPANS as u64 - 1;
+        assert!(!open.contains_key(&SpanRef::Event(TraceEventId(last_evicted))));
+        assert!(open.contains_key(&SpanRef::Event(TraceEventId(last_evicted + 1))));
+    }
+
+    #[test]
+    fn test_export_request_encoding() {
+        let span = Span {
+            trace_id: TraceId([1; 16]).serialize_std(),
+            span_id: SpanId([2; 8]).serialize_std(),
+            parent_span_id: String::new(),
+            name: "svc.Endpoint".to_string(),
+            kind: SpanKind::Server as i32,
+            start_time_unix_nano: "1".to_string(),
+            end_time_unix_nano: "2".to_string(),
+            attributes: vec![KeyValue::int("http.response.status_code", 200)],
+            status: Status::default(),
+        };
+        let req = export_request(
+            &[KeyValue::str("deployment.environment", "prod")],
+            vec![FinishedSpan {
+                service: "svc".to_string(),
+                span,
+            }],
+        );
+
+        let json = serde_json::to_value(&req).unwrap();
+        let resource_spans = &json["resourceSpans"][0];
+        assert_eq!(
+            resource_spans["resource"]["attributes"][0],
+            serde_json::json!({"key": "service.name", "value": {"stringValue": "svc"}})
+        );
+        let span = &resource_spans["scopeSpans"][0]["spans"][0];
+        assert_eq!(span["traceId"], "01010101010101010101010101010101");
+        assert_eq!(span["spanId"], "0202020202020202");
+        assert!(span.get("parentSpanId").is_none());
+        assert_eq!(span["kind"], 2);
+        assert_eq!(
+            span["attributes"][0]["value"],
+            serde_json::json!({"intValue": "200"})
+        );
+    }
+}
@@ -6,8 +6,10 @@ use std::sync::atomic::{AtomicU64, Ordering};
 use crate::api::reqauth::meta::HeaderValueExt;
 use crate::api::{self, PValue};
 use crate::model::{LogField, LogFieldValue, Request, TraceEventId};
+use crate::names::EndpointName;
 use crate::trace::eventbuf::EventBuffer;
 use crate::trace::log::TraceEvent;
+use crate::trace::otlp::{KeyValue, SpanKind, SpanRecorder};
 use crate::{model, objects, EncoreName};
 
 /// Represents a type of trace event.
@@ -56,17 +58,84 @@ static EVENT_ID: AtomicU64 = AtomicU64::new(1);
 #[derive(Debug, Clone)]
 pub struct Tracer {
     tx: Option<tokio::sync::mpsc::UnboundedSender<TraceEvent>>,
+
+    /// Records the events as OpenTelemetry spans, if an OTLP exporter is configured.
+    otlp: Option<SpanRecorder>,
 }
 
 pub static TRACE_VERSION: u16 = 14;
 
 impl Tracer {
     pub(super) fn new(tx: tokio::sync::mpsc::UnboundedSender<TraceEvent>) -> Self {
-        Self { tx: Some(tx) }
+        Self {
+            tx: Some(tx),
+            otlp: None,
+        }
     }
 
     pub fn noop() -> Self {
-        Self { tx: None }
+        Self {
+            tx: None,
+            otlp: None,
+        }
+    }
+
+    /// Returns a tracer that also records events as OpenTelemetry spans.
+    pub fn with_otlp(self, recorder: SpanRecorder) -> Self {
+        Self {
+            otlp: Some(recorder),
+            ..self
+        }
+    }
+
+    /// Reports whether events are exported to an OpenTelemetry collector.
+    pub fn otlp_enabled(&self) -> bool {
+        self.otlp.is_some()
+    }
+
+    fn otlp_child_start(
+        &self,
+        id: TraceEventId,
+        source: &Request,
+        name: impl FnOnce() -> String,
+        kind: SpanKind,
+        attrs: impl FnOnce() -> Vec<KeyValue>,
+    ) {
+        if let Some(otlp) = &self.otlp {
+            otlp.child_start(id, source, name(), kind, attrs());
+        }
+    }
+
+    fn otlp_bucket_start(
+        &self,
+        id: TraceEventId,
+        source: &Request,
+        operation: &str,
+        bucket: &EncoreName,
+        object: Option<&str>,
+    ) {
+        self.otlp_child_start(
+            id,
+            source,
+            || format!("bucket {}", operation),
+            SpanKind::Client,
+            || {
+                let mut attrs = vec![
+                    KeyValue::str("encore.bucket.operation", operation),
+                    KeyValue::str("encore.bucket.name", bucket),
+                ];
+                if let Some(object) = object {
+                    attrs.push(KeyValue::str("encore.bucket.object", object));
+                }
+                attrs
+            },
+        );
+    }
+
+    fn otlp_child_end<E: std::fmt::Display>(&self, id: TraceEventId, err: Option<&E>) {
+        if let Some(otlp) = &self.otlp {
+            otlp.child_end(id, err.map(|e| e.to_string()));
+        }
     }
 }
 
@@ -251,6 +320,9 @@ impl Tracer {
         };
 
         _ = self.send(event_type, req.span, eb);
+        if let Some(otlp) = &self.otlp {
+            otlp.request_start(req);
+        }
     }
 
     #[inline]
@@ -326,6 +398,9 @@ impl Tracer {
         };
 
         _ = self.send(event_type, req.span, eb);
+        if let Some(otlp) = &self.otlp {
+            otlp.request_end(resp);
+        }
     }
 }
 
@@ -345,7 +420,21 @@ impl Tracer {
         eb.str(endpoint);
         eb.nyi_stack_pcs();
 
-        Some(self.send(EventType::RPCCallStart, source.span, eb))
+        let id = self.send(EventType::RPCCallStart, source.span, eb);
+        self.otlp_child_start(
+            id,
+            source,
+            || call.target.to_string(),
+            SpanKind::Client,
+            || {
+                vec![
+                    KeyValue::str("rpc.system", "encore"),
+                    KeyValue::str("rpc.service", service),
+                    KeyValue::str("rpc.method", endpoint),
+                ]
+            },
+        );
+        Some(id)
     }
 
     #[inline]
@@ -369,6 +458,53 @@ impl Tracer {
         eb.api_err_with_legacy_stack(err);
 
         _ = self.send(EventType::RPCCallEnd, source.span, eb);
+        self.otlp_child_end(start_event_id, err);
+    }
+
+    /// Records that an API call failed and is about to be retried.
+    /// The retry is recorded as a log message on the calling span.
+    pub fn rpc_call_retry(
+        &self,
+        source: &Request,
+        start_event_id: TraceEventId,
+        target: &EndpointName,
+        attempt: u32,
+        err: &api::Error,
+        backoff: std::time::Duration,
+    ) {
+        let endpoint = target.to_string();
+        let code = err.code.to_string();
+        let fields = [
+            LogField {
+                key: "endpoint",
+                value: LogFieldValue::String(&endpoint),
+            },
+            LogField {
+                key: "attempt",
+                value: LogFieldValue::U64(attempt as u64),
+            },
+            LogField {
+                key: "code",
+                value: LogFieldValue::String(&code),
+            },
+            LogField {
+                key: "backoff_ms",
+                value: LogFieldValue::U64(backoff.as_millis() as u64),
+            },
+        ];
+        self.log_message(LogMessageData {
+            source: Some(source),
+            msg: "retrying api call",
+            level: log::Level::Warn,
+            fields: Some(fields.into_iter()),
+        });
+
+        if let Some(otlp) = &self.otlp {
+            otlp.child_attr(
+                start_event_id,
+                KeyValue::int("encore.rpc.attempts", attempt as i64 + 1),
+            );
+        }
     }
 }
 
@@ -397,7 +533,15 @@ impl Tracer {
         eb.byte_string(data.payload);
         eb.nyi_stack_pcs();
 
-        self.send(EventType::PubsubPublishStart, data.source.span, eb)
+        let id = self.send(EventType::PubsubPublishStart, data.source.span, eb);
+        self.otlp_child_start(
+            id,
+            data.source,
+            || format!("{} publish", data.topic),
+            SpanKind::Producer,
+            || vec![KeyValue::str("messaging.destination.name", data.topic)],
+        );
+        id
     }
 
     #[inline]
@@ -412,12 +556,59 @@ impl Tracer {
         eb.err_with_legacy_stack(data.result.as_ref().err());
 
         _ = self.send(EventType::PubsubPublishEnd, data.source.span, eb);
+        self.otlp_child_end(data.start_id, data.result.as_ref().err());
+    }
+}
+
+pub struct DBTransactionStartData<'a> {
+    pub source: &'a Request,
+}
+
+pub struct DBTransactionEndData<'a, E> {
+    pub start_id: TraceEventId,
+    pub source: &'a Request,
+    pub commit: bool,
+    pub error: Option<&'a E>,
+}
+
+impl Tracer {
+    #[inline]
+    pub fn db_transaction_start(&self, data: DBTransactionStartData) -> TraceEventId {
+        let mut eb = BasicEventData {
+            correlation_event_id: None,
+            extra_space: 32,
+        }
+        .into_eb();
+
+        eb.nyi_stack_pcs();
+
+        self.send(EventType::DBTransactionStart, data.source.span, eb)
+    }
+
+    #[inline]
+    pub fn db_transaction_end<E>(&self, data: DBTransactionEndData<E>)
+    where
+        E: std::fmt::Display,
+    {
+        let mut eb = BasicEventData {
+            correlation_event_id: Some(data.start_id),
+            extra_space: 4 + 4 + 8,
+        }
+        .into_eb();
+
+        eb.bool(data.commit);
+        eb.nyi_stack_pcs();
+        eb.err_with_legacy_stack(data.error);
+
+        _ = self.send(EventType::DBTransactionEnd, data.source.span, eb);
     }
 }
 
 pub struct DBQueryStartData<'a> {
     pub source: &'a Request,
     pub query: &'a str,
+    /// The start event of the transaction the query runs in, if any.
+    pub tx_start_id: Option<TraceEventId>,
 }
 
 pub struct DBQueryEndData<'a, E> {
@@ -430,7 +621,7 @@ impl Tracer {
     #[inline]
     pub fn db_query_start(&self, data: DBQueryStartData) -> TraceEventId {
         let mut eb = BasicEventData {
-            correlation_event_id: None,
+            correlation_event_id: data.tx_start_id,
             extra_space: 4 + 4 + data.query.len() + 32,
         }
         .into_eb();
@@ -438,7 +629,20 @@ impl Tracer {
         eb.str(data.query);
         eb.nyi_stack_pcs();
 
-        self.send(EventType::DBQueryStart, data.source.span, eb)
+        let id = self.send(EventType::DBQueryStart, data.source.span, eb);
+        self.otlp_child_start(
+            id,
+            data.source,
+            || "db query".to_string(),
+            SpanKind::Client,
+            || {
+                vec![
+                    KeyValue::str("db.system", "postgresql"),
+                    KeyValue::str("db.query.text", data.query),
+                ]
+            },
+        );
+        id
     }
 
     #[inline]
@@ -455,6 +659,84 @@ impl Tracer {
         eb.err_with_legacy_stack(data.error);
 
         _ = self.send(EventType::DBQueryEnd, data.source.span, eb);
+        self.otlp_child_end(data.start_id, data.error);
+    }
+}
+
+pub struct CacheCallStartData<'a, K> {
+    pub source: &'a Request,
+    pub operation: &'a str,
+    pub is_write: bool,
+    pub keys: K,
+}
+
+pub struct CacheCallEndData<'a, E> {
+    pub start_id: TraceEventId,
+    pub source: &'a Request,
+    pub result: CacheCallResult<'a, E>,
+}
+
+pub enum CacheCallResult<'a, E> {
+    Ok,
+    NoSuchKey,
+    Conflict,
+    Err(&'a E),
+}
+
+impl Tracer {
+    #[inline]
+    pub fn cache_call_start<'a, K>(&self, data: CacheCallStartData<'a, K>) -> TraceEventId
+    where
+        K: ExactSizeIterator<Item = &'a str>,
+    {
+        let mut eb = BasicEventData {
+            correlation_event_id: None,
+            extra_space: 4 + 4 + 8 + data.operation.len() + data.keys.len() * 16,
+        }
+        .into_eb();
+
+        eb.str(data.operation);
+        eb.bool(data.is_write);
+        eb.nyi_stack_pcs();
+        eb.uvarint(data.keys.len() as u64);
+        for key in data.keys {
+            eb.str(key);
+        }
+
+        self.send(EventType::CacheCallStart, data.source.span, eb)
+    }
+
+    #[inline]
+    pub fn cache_call_end<E>(&self, data: CacheCallEndData<E>)
+    where
+        E: std::fmt::Display,
+    {
+        let mut eb = BasicEventData {
+            correlation_event_id: Some(data.start_id),
+            extra_space: 4 + 4 + 8,
+        }
+        .into_eb();
+
+        match data.result {
+            CacheCallResult::Ok => {
+                eb.byte(1);
+                eb.err_with_legacy_stack::<E>(None);
+            }
+            CacheCallResult::NoSuchKey => {
+                eb.byte(2);
+                eb.err_with_legacy_stack::<E>(None);
+            }
+            CacheCallResult::Conflict => {
+                eb.byte(3);
+                eb.err_with_legacy_stack::<E>(None);
+            }
+            CacheCallResult::Err(err) => {
+                eb.byte(4);
+                eb.err_with_legacy_stack(Some(err));
+            }
+        }
+
+        _ = self.send(EventType::CacheCallEnd, data.source.span, eb);
     }
 }
 
@@ -518,7 +800,9 @@ impl Tracer {
         eb.bucket_object_attrs(&data.attrs);
         eb.nyi_stack_pcs();
 
-        self.send(EventType::BucketObjectUploadStart, data.source.span, eb)
+        let id = self.send(EventType::BucketObjectUploadStart, data.source.span, eb);
+        self.otlp_bucket_start(id, data.source, "upload", data.bucket, Some(data.object));
+        id
     }
 
     #[inline]
@@ -532,6 +816,11 @@ impl Tracer {
         }
         .into_eb();
 
+        let err = match &data.result {
+            BucketObjectUploadEndResult::Err(err) => Some(*err),
+            _ => None,
+        };
+
         match data.result {
             BucketObjectUploadEndResult::Success { size, version } => {
                 eb.uvarint(size);
@@ -545,6 +834,7 @@ impl Tracer {
         }
 
         _ = self.send(EventType::BucketObjectUploadEnd, data.source.span, eb);
+        self.otlp_child_end(data.start_id, err);
     }
 }
 
@@ -580,7 +870,9 @@ impl Tracer {
         eb.opt_str(data.version);
         eb.nyi_stack_pcs();
 
-        self.send(EventType::BucketObjectDownloadStart, data.source.span, eb)
+        let id = self.send(EventType::BucketObjectDownloadStart, data.source.span, eb);
+        self.otlp_bucket_start(id, data.source, "download", data.bucket, Some(data.object));
+        id
     }
 
     #[inline]
@@ -594,6 +886,11 @@ impl Tracer {
         }
         .into_eb();
 
+        let err = match &data.result {
+            BucketObjectDownloadEndResult::Err(err) => Some(*err),
+            _ => None,
+        };
+
         match data.result {
             BucketObjectDownloadEndResult::Success { size } => {
                 eb.uvarint(size);
@@ -606,6 +903,7 @@ impl Tracer {
         }
 
         _ = self.send(EventType::BucketObjectDownloadEnd, data.source.span, eb);
+        self.otlp_child_end(data.start_id, err);
     }
 }
 
@@ -654,7 +952,9 @@ impl Tracer {
             eb.opt_str(obj.version);
         }
 
-        self.send(EventType::BucketDeleteObjectsStart, data.source.span, eb)
+        let id = self.send(EventType::BucketDeleteObjectsStart, data.source.span, eb);
+        self.otlp_bucket_start(id, data.source, "delete", data.bucket, None);
+        id
     }
 
     #[inline]
@@ -668,6 +968,11 @@ impl Tracer {
         }
         .into_eb();
 
+        let err = match &data.result {
+            BucketDeleteObjectsEndResult::Err(err) => Some(*err),
+            _ => None,
+        };
+
         match data.result {
             BucketDeleteObjectsEndResult::Success => {
                 eb.err_with_legacy_stack::<E>(None);
@@ -678,6 +983,7 @@ impl Tracer {
         }
 
         _ = self.send(EventType::BucketDeleteObjectsEnd, data.source.span, eb);
+        self.otlp_child_end(data.start_id, err);
     }
 }
 
@@ -715,7 +1021,9 @@ impl Tracer {
         eb.opt_str(data.prefix);
         eb.nyi_stack_pcs();
 
-        self.send(EventType::BucketListObjectsStart, data.source.span, eb)
+        let id = self.send(EventType::BucketListObjectsStart, data.source.span, eb);
+        self.otlp_bucket_start(id, data.source, "list", data.bucket, None);
+        id
     }
 
     #[inline]
@@ -729,6 +1037,11 @@ impl Tracer {
         }
         .into_eb();
 
+        let err = match &data.result {
+            BucketListObjectsEndResult::Err(err) => Some(*err),
+            _ => None,
+        };
+
         match data.result {
             BucketListObjectsEndResult::Success { observed, has_more } => {
                 eb.err_with_legacy_stack::<E>(None);
@@ -743,6 +1056,7 @@ impl Tracer {
         }
 
         _ = self.send(EventType::BucketListObjectsEnd, data.source.span, eb);
+        self.otlp_child_end(data.start_id, err);
     }
 }
 
@@ -778,7 +1092,9 @@ impl Tracer {
         eb.opt_str(data.version);
         eb.nyi_stack_pcs();
 
-        self.send(EventType::BucketObjectGetAttrsStart, data.source.span, eb)
+        let id = self.send(EventType::BucketObjectGetAttrsStart, data.source.span, eb);
+        self.otlp_bucket_start(id, data.source, "get_attrs", data.bucket, Some(data.object));
+        id
     }
 
     #[inline]
@@ -792,6 +1108,11 @@ impl Tracer {
         }
         .into_eb();
 
+        let err = match &data.result {
+            BucketObjectGetAttrsEndResult::Err(err) => Some(*err),
+            _ => None,
+        };
+
         match data.result {
             BucketObjectGetAttrsEndResult::Success(attrs) => {
                 eb.err_with_legacy_stack::<E>(None);
@@ -803,6 +1124,7 @@ impl Tracer {
         }
 
         _ = self.send(EventType::BucketObjectGetAttrsEnd, data.source.span, eb);
+        self.otlp_child_end(data.start_id, err);
     }
 }
 
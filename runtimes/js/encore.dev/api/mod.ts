@@ -6,6 +6,7 @@ import { RawResponse } from "./mod";
 import { RawRequest } from "./mod";
 import { InternalHandlerResponse } from "../internal/appinit/mod";
 import { IterableSocket, IterableStream, Sink } from "./stream";
+import { DurationString } from "../internal/types/mod";
 export { RawRequest, RawResponse } from "../internal/api/node_http";
 
 export type Method =
@@ -80,12 +81,38 @@ export interface APIOptions {
    **/
   bodyLimit?: number | null;
 
+  /**
+   * The maximum time a request to this endpoint may take, such as "30s".
+   * When it expires the handler is canceled and the caller receives
+   * a `deadline_exceeded` error.
+   *
+   * The deadline is propagated to API calls made by the handler,
+   * which fail with `deadline_exceeded` once it expires.
+   *
+   * If left unspecified there is no timeout.
+   */
+  timeout?: DurationString;
+
   /**
    * Tags to filter endpoints when generating clients and in middlewares.
    */
   tags?: string[];
 }
 
+/**
+ * Options for calling an API endpoint, passed as the second argument
+ * when calling an endpoint through the generated service clients.
+ */
+export interface CallOpts {
+  /**
+   * The maximum time to wait for the call to complete, in milliseconds.
+   * When it expires the call fails with a `deadline_exceeded` error.
+   *
+   * The call is also bounded by the deadline of the request making the call, if any.
+   */
+  timeout?: number;
+}
+
 export interface StreamOptions {
   /**
    * The request path to match for this endpoint.
@@ -1,14 +1,18 @@
 import * as runtime from "../runtime/mod";
 import { getCurrentRequest } from "../reqtrack/mod";
 import { APIError, ErrCode } from "../../api/error";
+import type { CallOpts } from "../../api/mod";
 
 export async function apiCall(
   service: string,
   endpoint: string,
-  data: any
+  data: any,
+  opts?: CallOpts
 ): Promise<any> {
   const source = getCurrentRequest();
-  const resp = await runtime.RT.apiCall(service, endpoint, data, source);
+  const resp = await runtime.RT.apiCall(service, endpoint, data, source, {
+    timeoutMs: opts?.timeout
+  });
 
   // Convert any call error to our APIError type.
   // We do this here because NAPI doesn't have great support
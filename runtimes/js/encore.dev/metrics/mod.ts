@@ -0,0 +1,60 @@
+import * as runtime from "../internal/runtime/mod";
+
+export interface MetricConfig {
+  /**
+   * A description of the metric, included when exporting it.
+   */
+  help?: string;
+}
+
+/**
+ * Labels to attach to a metric measurement.
+ * Each distinct combination of label values is tracked as a separate series,
+ * so avoid labels with unbounded values (like user ids).
+ */
+export type Labels = Record<string, string>;
+
+/**
+ * A counter is a metric that only ever increases,
+ * such as the number of orders placed.
+ */
+export class Counter<L extends Labels = Labels> {
+  private impl: runtime.Counter;
+
+  constructor(name: string, cfg?: MetricConfig) {
+    this.impl = runtime.RT.metricCounter(name, cfg?.help);
+  }
+
+  /**
+   * Increments the counter by the given value, which defaults to 1.
+   */
+  increment(value: number = 1, labels?: L): void {
+    this.impl.increment(value, labels);
+  }
+}
+
+/**
+ * A gauge is a metric that can go up and down,
+ * such as the number of items in a queue.
+ */
+export class Gauge<L extends Labels = Labels> {
+  private impl: runtime.Gauge;
+
+  constructor(name: string, cfg?: MetricConfig) {
+    this.impl = runtime.RT.metricGauge(name, cfg?.help);
+  }
+
+  /**
+   * Sets the gauge to the given value.
+   */
+  set(value: number, labels?: L): void {
+    this.impl.set(value, labels);
+  }
+
+  /**
+   * Adds the given delta to the gauge. Use a negative delta to decrease it.
+   */
+  add(delta: number, labels?: L): void {
+    this.impl.add(delta, labels);
+  }
+}
@@ -12,6 +12,7 @@ export { currentRequest } from "./req_meta";
 export type {
   APICallMeta,
   APIDesc,
+  DeadLetterMeta,
   Method,
   PubSubMessageMeta,
   RequestMeta,
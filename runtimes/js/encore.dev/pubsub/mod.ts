@@ -1,8 +1,17 @@
-export { Topic } from "./topic";
-export type { TopicConfig, DeliveryGuarantee } from "./topic";
+export { Topic, PublishBatchError } from "./topic";
+export type {
+  TopicConfig,
+  DeliveryGuarantee,
+  PublishBatchResult
+} from "./topic";
 
-export { Subscription } from "./subscription";
-export type { SubscriptionConfig } from "./subscription";
+export {
+  Subscription,
+  redrive,
+  waitForPendingMessages,
+  drainPendingMessages
+} from "./subscription";
+export type { SubscriptionConfig, PendingMessage } from "./subscription";
 
 /**
  * Attribute represents a field on a message that should be sent
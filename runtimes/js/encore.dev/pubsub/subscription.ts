@@ -1,4 +1,7 @@
-import { setCurrentRequest } from "../internal/reqtrack/mod";
+import {
+  getCurrentRequest,
+  setCurrentRequest
+} from "../internal/reqtrack/mod";
 import { DurationString } from "../internal/types/mod";
 import { Topic } from "./topic";
 import * as runtime from "../internal/runtime/mod";
@@ -95,6 +98,73 @@ export interface SubscriptionConfig<Msg> {
    * the subscriber returns an error
    */
   retryPolicy?: RetryPolicy;
+
+  /**
+   * DeadLetterTopic is the topic that messages are forwarded to
+   * once the retry policy's MaxRetries has been reached.
+   * It must have the same message type as the subscription's topic.
+   *
+   * The forwarded message describes the failure in its attributes,
+   * which subscribers of the dead letter topic can read using
+   * `currentRequest().deadLetter`. Use `redrive()` to publish
+   * the message to the original subscription again.
+   *
+   * If not set, messages are dropped once retries are exhausted.
+   */
+  deadLetterTopic?: Topic<Msg>;
+}
+
+/**
+ * Redrive republishes the dead-lettered message currently being processed
+ * to the subscription it was dead-lettered from. Other subscriptions
+ * of the original topic do not receive the message again.
+ *
+ * It must be called from a subscription handler of a dead letter topic,
+ * and returns the id of the republished message.
+ */
+export async function redrive(): Promise<string> {
+  const source = getCurrentRequest();
+  if (!source) {
+    throw new Error("redrive must be called from a subscription handler");
+  }
+  return runtime.RT.pubsubRedrive(source);
+}
+
+/**
+ * waitForPendingMessages resolves once the messages published in tests
+ * have been processed by their subscriptions, including any retries.
+ *
+ * It only applies to topics delivered in-process, which is the case
+ * for topics without infrastructure configured when running tests.
+ */
+export async function waitForPendingMessages(): Promise<void> {
+  return runtime.RT.pubsubWaitForPendingMessages();
+}
+
+/**
+ * PendingMessage is a message published in tests that has yet
+ * to be delivered to one of the topic's subscriptions.
+ */
+export interface PendingMessage {
+  topic: string;
+  subscription: string;
+  id: string;
+  deliveryAttempt: number;
+  /** The message payload, if it's valid JSON. */
+  payload?: unknown;
+  attributes: Record<string, string>;
+}
+
+/**
+ * drainPendingMessages removes and returns the messages published in tests
+ * that have yet to be delivered to their subscriptions, so they're never
+ * delivered. Use it to inspect the published messages without running
+ * the subscription handlers.
+ *
+ * Like waitForPendingMessages, it only applies to topics delivered in-process.
+ */
+export function drainPendingMessages(): PendingMessage[] {
+  return runtime.RT.pubsubDrainPendingMessages();
 }
 
 /**
@@ -21,6 +21,53 @@ export class Topic<Msg extends object> {
     const source = getCurrentRequest();
     return this.impl.publish(msg, source);
   }
+
+  /**
+   * publishBatch publishes multiple messages in as few requests as the
+   * provider allows, and returns the message ids in the same order.
+   *
+   * If any of the messages fails to publish it throws a PublishBatchError,
+   * which reports which of the messages were published.
+   */
+  public async publishBatch(msgs: Msg[]): Promise<string[]> {
+    const source = getCurrentRequest();
+    const results = await this.impl.publishBatch(msgs, source);
+    if (results.some(isFailure)) {
+      throw new PublishBatchError(results);
+    }
+    return results.map((r) => r.id!);
+  }
+}
+
+/**
+ * The result of publishing a message as part of a batch.
+ * Either the id of the published message, or why it wasn't published.
+ */
+export type PublishBatchResult = { id: string } | { error: string };
+
+/**
+ * PublishBatchError is thrown by publishBatch when some of the messages
+ * failed to publish. Messages that were published are not rolled back.
+ */
+export class PublishBatchError extends Error {
+  /** The result of each message, in the order they were given. */
+  public readonly results: PublishBatchResult[];
+
+  constructor(results: runtime.PublishBatchResult[]) {
+    const failed = results.filter(isFailure);
+    super(
+      `failed to publish ${failed.length} of ${results.length} messages: ` +
+        failed[0].error
+    );
+    this.name = "PublishBatchError";
+    this.results = results.map((r) =>
+      isFailure(r) ? { error: r.error! } : { id: r.id! }
+    );
+  }
+}
+
+function isFailure(result: runtime.PublishBatchResult): boolean {
+  return result.error !== undefined && result.error !== null;
 }
 
 /**
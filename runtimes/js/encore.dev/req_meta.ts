@@ -114,6 +114,33 @@ export interface PubSubMessageMeta {
    * The parsed request payload, as expected by the application code.
    */
   parsedPayload?: Record<string, any>;
+
+  /**
+   * Set if the message was forwarded to a dead letter topic
+   * after exhausting the retries of another subscription.
+   */
+  deadLetter?: DeadLetterMeta;
+}
+
+/** Describes why a Pub/Sub message was forwarded to a dead letter topic. */
+export interface DeadLetterMeta {
+  /** The topic the message was originally published to. */
+  topic: string;
+
+  /** The subscription that failed to process the message. */
+  subscription: string;
+
+  /** The id of the original message. */
+  messageId: string;
+
+  /** The number of delivery attempts made before giving up. */
+  attempts: number;
+
+  /** The error code returned by the final attempt. */
+  errorCode: string;
+
+  /** The error message returned by the final attempt. */
+  errorMessage: string;
 }
 
 /** Provides information about the active trace. */
@@ -194,7 +221,8 @@ export function currentRequest(): RequestMeta | undefined {
       subscription: meta.pubsubMessage.subscription,
       messageId: meta.pubsubMessage.id,
       deliveryAttempt: meta.pubsubMessage.deliveryAttempt,
-      parsedPayload: meta.pubsubMessage.parsedPayload
+      parsedPayload: meta.pubsubMessage.parsedPayload,
+      deadLetter: meta.pubsubMessage.deadLetter ?? undefined
     };
     return { ...base, ...msg };
   } else {
@@ -0,0 +1,39 @@
+import * as runtime from "../internal/runtime/mod";
+
+/**
+ * Describes the progress of a graceful shutdown.
+ */
+export interface ShutdownProgress {
+  /**
+   * The time by which the shutdown hook is expected to have completed.
+   */
+  deadline: Date;
+
+  /**
+   * An abort signal that is aborted when the deadline is reached.
+   */
+  signal: AbortSignal;
+}
+
+/**
+ * A function that is called when the application is shutting down.
+ */
+export type ShutdownHook = (progress: ShutdownProgress) => void | Promise<void>;
+
+/**
+ * Registers a hook to run when the application gracefully shuts down.
+ *
+ * Shutdown hooks run after the application has stopped accepting new requests
+ * and Pub/Sub messages, and outstanding requests have completed or been canceled.
+ * The process exits once all hooks have completed, or when the
+ * graceful shutdown window closes, whichever happens first.
+ */
+export function onShutdown(hook: ShutdownHook): void {
+  runtime.RT.onShutdown((deadlineMs: number) => {
+    const remaining = Math.max(0, deadlineMs - Date.now());
+    return hook({
+      deadline: new Date(deadlineMs),
+      signal: AbortSignal.timeout(remaining)
+    });
+  });
+}
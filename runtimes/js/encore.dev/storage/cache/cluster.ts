@@ -0,0 +1,48 @@
+import * as runtime from "../../internal/runtime/mod";
+import { StringLiteral } from "../../internal/utils/constraints";
+
+/**
+ * The Redis eviction policy to use when the cache is full.
+ * See https://redis.io/docs/reference/eviction/ for details.
+ */
+export type EvictionPolicy =
+  | "noeviction"
+  | "allkeys-lru"
+  | "allkeys-lfu"
+  | "allkeys-random"
+  | "volatile-lru"
+  | "volatile-lfu"
+  | "volatile-ttl"
+  | "volatile-random";
+
+export interface CacheClusterConfig {
+  /**
+   * The eviction policy to use when the cache is full.
+   * Defaults to "allkeys-lru" if unset.
+   */
+  evictionPolicy?: EvictionPolicy;
+}
+
+/**
+ * Defines a new cache cluster infrastructure resource.
+ * Keyspaces are defined on top of a cluster to store data in it.
+ */
+export class CacheCluster {
+  impl: runtime.CacheCluster;
+
+  /**
+   * Creates a new cache cluster with the given name and configuration.
+   */
+  // eslint-disable-next-line @typescript-eslint/no-unused-vars
+  constructor(name: string, cfg?: CacheClusterConfig) {
+    this.impl = runtime.RT.cacheCluster(name);
+  }
+
+  /**
+   * Reference an existing cache cluster by name.
+   * To create a new cache cluster, use `new CacheCluster(...)` instead.
+   */
+  static named<name extends string>(name: StringLiteral<name>): CacheCluster {
+    return new CacheCluster(name, {});
+  }
+}
@@ -0,0 +1,86 @@
+import * as runtime from "../../internal/runtime/mod";
+
+export class CacheError extends Error {
+  constructor(msg: string) {
+    // extending errors causes issues after you construct them, unless you apply the following fixes
+    super(msg);
+
+    // set error name as constructor name, make it not enumerable to keep native Error behavior
+    // https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Operators/new.target#new.target_in_constructors
+    Object.defineProperty(this, "name", {
+      value: "CacheError",
+      enumerable: false,
+      configurable: true
+    });
+
+    // Fix the prototype chain, capture stack trace.
+    Object.setPrototypeOf(this, CacheError.prototype);
+    Error.captureStackTrace(this, this.constructor);
+  }
+}
+
+/**
+ * Thrown when a cache key does not exist.
+ */
+export class CacheMiss extends CacheError {
+  constructor(msg: string) {
+    // extending errors causes issues after you construct them, unless you apply the following fixes
+    super(msg);
+
+    // set error name as constructor name, make it not enumerable to keep native Error behavior
+    // https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Operators/new.target#new.target_in_constructors
+    Object.defineProperty(this, "name", {
+      value: "CacheMiss",
+      enumerable: false,
+      configurable: true
+    });
+
+    // Fix the prototype chain, capture stack trace.
+    Object.setPrototypeOf(this, CacheMiss.prototype);
+    Error.captureStackTrace(this, this.constructor);
+  }
+}
+
+/**
+ * Thrown when a cache key already exists and the operation
+ * requires that it does not.
+ */
+export class CacheKeyExists extends CacheError {
+  constructor(msg: string) {
+    // extending errors causes issues after you construct them, unless you apply the following fixes
+    super(msg);
+
+    // set error name as constructor name, make it not enumerable to keep native Error behavior
+    // https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Operators/new.target#new.target_in_constructors
+    Object.defineProperty(this, "name", {
+      value: "CacheKeyExists",
+      enumerable: false,
+      configurable: true
+    });
+
+    // Fix the prototype chain, capture stack trace.
+    Object.setPrototypeOf(this, CacheKeyExists.prototype);
+    Error.captureStackTrace(this, this.constructor);
+  }
+}
+
+export function unwrapErr<T>(val: T | runtime.TypedCacheError): T {
+  if (val instanceof runtime.TypedCacheError) {
+    switch (val.kind) {
+      case runtime.CacheErrorKind.Miss:
+        throw new CacheMiss(val.message);
+      case runtime.CacheErrorKind.KeyExists:
+        throw new CacheKeyExists(val.message);
+      default:
+        throw new CacheError(val.message);
+    }
+  }
+
+  return val;
+}
+
+export function checkErr(err: runtime.TypedCacheError | null | undefined): void {
+  if (err) {
+    unwrapErr(err);
+  }
+}
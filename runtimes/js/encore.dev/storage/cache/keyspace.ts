@@ -0,0 +1,463 @@
+import { getCurrentRequest } from "../../internal/reqtrack/mod";
+import * as runtime from "../../internal/runtime/mod";
+import { CacheCluster } from "./cluster";
+import { checkErr, unwrapErr } from "./error";
+
+/**
+ * Describes when a cache entry expires.
+ * Use the `expireIn`, `expireAt`, `neverExpire` and `keepTTL` helpers to create one.
+ */
+export type Expiry = runtime.ExpiryOptions;
+
+/** Expire the entry after the given number of milliseconds. */
+export function expireIn(ms: number): Expiry {
+  return { ttlMs: ms };
+}
+
+/** Expire the entry at the given point in time. */
+export function expireAt(date: Date): Expiry {
+  return { expireAtMs: date.getTime() };
+}
+
+/** Never expire the entry. */
+export const neverExpire: Expiry = {};
+
+/** Keep the existing expiry of the entry, if any. */
+export const keepTTL: Expiry = { keepTtl: true };
+
+export interface KeyspaceConfig {
+  /**
+   * The pattern used to compute the cache key, such as "user/:id".
+   * Each parameter must correspond to the key itself (named "key")
+   * for basic key types, or to a field of the key for object keys.
+   */
+  keyPattern: string;
+
+  /**
+   * The default expiry for entries written to the keyspace.
+   * Must be one of `expireIn(...)`, `expireAt(...)`, `neverExpire` or `keepTTL`.
+   * Defaults to never expiring if unset.
+   */
+  defaultExpiry?: Expiry;
+}
+
+export interface WriteOptions {
+  /**
+   * The expiry to use for the write, overriding the keyspace default.
+   */
+  expiry?: Expiry;
+}
+
+abstract class KeyspaceBase<K> {
+  protected readonly cluster: runtime.CacheCluster;
+  protected readonly cfg: KeyspaceConfig;
+  private readonly segments: string[];
+
+  constructor(cluster: CacheCluster, cfg: KeyspaceConfig) {
+    this.cluster = cluster.impl;
+    this.cfg = cfg;
+    this.segments = cfg.keyPattern.split("/");
+  }
+
+  /**
+   * Computes the cache key for the given key value
+   * by substituting it into the key pattern.
+   */
+  protected key(key: K): string {
+    return this.segments
+      .map((seg) => {
+        if (!seg.startsWith(":")) {
+          return seg;
+        }
+        const name = seg.substring(1);
+        const val =
+          typeof key === "object" && key !== null
+            ? (key as Record<string, unknown>)[name]
+            : key;
+        return encodeURIComponent(String(val));
+      })
+      .join("/");
+  }
+
+  protected expiry(options?: WriteOptions): Expiry | undefined {
+    return options?.expiry ?? this.cfg.defaultExpiry;
+  }
+
+  /**
+   * Deletes the given keys, returning the number of keys that existed.
+   */
+  async delete(...keys: K[]): Promise<number> {
+    const source = getCurrentRequest();
+    const res = await this.cluster.delete(
+      keys.map((k) => this.key(k)),
+      source
+    );
+    return unwrapErr(res);
+  }
+
+  /**
+   * Updates the expiry of the given key.
+   * Throws CacheMiss if the key does not exist.
+   */
+  async expire(key: K, expiry: Expiry): Promise<void> {
+    const source = getCurrentRequest();
+    checkErr(await this.cluster.expire(this.key(key), expiry, source));
+  }
+}
+
+abstract class ValueKeyspace<K, V> extends KeyspaceBase<K> {
+  protected abstract encode(val: V): Buffer;
+  protected abstract decode(buf: Buffer): V;
+
+  /**
+   * Returns the value stored at the given key.
+   * Throws CacheMiss if the key does not exist.
+   */
+  async get(key: K): Promise<V> {
+    const source = getCurrentRequest();
+    const res = await this.cluster.get(this.key(key), source);
+    return this.decode(unwrapErr(res));
+  }
+
+  /**
+   * Returns the values stored at the given keys,
+   * with undefined for keys that do not exist.
+   */
+  async multiGet(...keys: K[]): Promise<(V | undefined)[]> {
+    const source = getCurrentRequest();
+    const res = await this.cluster.getMulti(
+      keys.map((k) => this.key(k)),
+      source
+    );
+    return unwrapErr(res).map((v) => (v ? this.decode(v) : undefined));
+  }
+
+  /**
+   * Stores the value at the given key, overwriting any existing value.
+   */
+  async set(key: K, value: V, options?: WriteOptions): Promise<void> {
+    await this.write(key, value, runtime.SetMode.Always, options);
+  }
+
+  /**
+   * Stores the value at the given key if it does not already exist.
+   * Throws CacheKeyExists if it does.
+   */
+  async setIfNotExists(key: K, value: V, options?: WriteOptions): Promise<void> {
+    await this.write(key, value, runtime.SetMode.IfNotExists, options);
+  }
+
+  /**
+   * Replaces the value at the given key if it already exists.
+   * Throws CacheMiss if it does not.
+   */
+  async replace(key: K, value: V, options?: WriteOptions): Promise<void> {
+    await this.write(key, value, runtime.SetMode.IfExists, options);
+  }
+
+  /**
+   * Stores the value at the given key and returns the previous value, if any.
+   */
+  async getAndSet(key: K, value: V, options?: WriteOptions): Promise<V | undefined> {
+    const source = getCurrentRequest();
+    const res = await this.cluster.getAndSet(
+      this.key(key),
+      this.encode(value),
+      this.expiry(options),
+      source
+    );
+    const prev = unwrapErr(res);
+    return prev ? this.decode(prev) : undefined;
+  }
+
+  /**
+   * Deletes the given key and returns the value it held.
+   * Throws CacheMiss if the key does not exist.
+   */
+  async getAndDelete(key: K): Promise<V> {
+    const source = getCurrentRequest();
+    const res = await this.cluster.getAndDelete(this.key(key), source);
+    return this.decode(unwrapErr(res));
+  }
+
+  private async write(
+    key: K,
+    value: V,
+    mode: runtime.SetMode,
+    options?: WriteOptions
+  ): Promise<void> {
+    const source = getCurrentRequest();
+    checkErr(
+      await this.cluster.set(
+        this.key(key),
+        this.encode(value),
+        { mode, expiry: this.expiry(options) },
+        source
+      )
+    );
+  }
+}
+
+/**
+ * A keyspace storing string values.
+ */
+export class StringKeyspace<K> extends ValueKeyspace<K, string> {
+  // eslint-disable-next-line @typescript-eslint/no-useless-constructor
+  constructor(cluster: CacheCluster, cfg: KeyspaceConfig) {
+    super(cluster, cfg);
+  }
+
+  protected encode(val: string): Buffer {
+    return Buffer.from(val, "utf8");
+  }
+
+  protected decode(buf: Buffer): string {
+    return buf.toString("utf8");
+  }
+}
+
+/**
+ * A keyspace storing integer values.
+ */
+export class IntKeyspace<K> extends ValueKeyspace<K, number> {
+  // eslint-disable-next-line @typescript-eslint/no-useless-constructor
+  constructor(cluster: CacheCluster, cfg: KeyspaceConfig) {
+    super(cluster, cfg);
+  }
+
+  protected encode(val: number): Buffer {
+    return Buffer.from(Math.trunc(val).toString(), "utf8");
+  }
+
+  protected decode(buf: Buffer): number {
+    return parseInt(buf.toString("utf8"), 10);
+  }
+
+  /**
+   * Increments the value at the given key by delta, returning the new value.
+   * A missing key is treated as zero.
+   */
+  async increment(key: K, delta: number = 1): Promise<number> {
+    const source = getCurrentRequest();
+    return unwrapErr(await this.cluster.incrBy(this.key(key), delta, source));
+  }
+
+  /**
+   * Decrements the value at the given key by delta, returning the new value.
+   * A missing key is treated as zero.
+   */
+  async decrement(key: K, delta: number = 1): Promise<number> {
+    return this.increment(key, -delta);
+  }
+}
+
+/**
+ * A keyspace storing floating-point values.
+ */
+export class FloatKeyspace<K> extends ValueKeyspace<K, number> {
+  // eslint-disable-next-line @typescript-eslint/no-useless-constructor
+  constructor(cluster: CacheCluster, cfg: KeyspaceConfig) {
+    super(cluster, cfg);
+  }
+
+  protected encode(val: number): Buffer {
+    return Buffer.from(val.toString(), "utf8");
+  }
+
+  protected decode(buf: Buffer): number {
+    return parseFloat(buf.toString("utf8"));
+  }
+
+  /**
+   * Increments the value at the given key by delta, returning the new value.
+   * A missing key is treated as zero.
+   */
+  async increment(key: K, delta: number = 1): Promise<number> {
+    const source = getCurrentRequest();
+    return unwrapErr(
+      await this.cluster.incrByFloat(this.key(key), delta, source)
+    );
+  }
+
+  /**
+   * Decrements the value at the given key by delta, returning the new value.
+   * A missing key is treated as zero.
+   */
+  async decrement(key: K, delta: number = 1): Promise<number> {
+    return this.increment(key, -delta);
+  }
+}
+
+/**
+ * A keyspace storing arbitrary JSON-serializable values.
+ */
+export class StructKeyspace<K, V> extends ValueKeyspace<K, V> {
+  // eslint-disable-next-line @typescript-eslint/no-useless-constructor
+  constructor(cluster: CacheCluster, cfg: KeyspaceConfig) {
+    super(cluster, cfg);
+  }
+
+  protected encode(val: V): Buffer {
+    return Buffer.from(JSON.stringify(val), "utf8");
+  }
+
+  protected decode(buf: Buffer): V {
+    return JSON.parse(buf.toString("utf8"));
+  }
+}
+
+abstract class ListKeyspace<K, V> extends KeyspaceBase<K> {
+  protected abstract encode(val: V): Buffer;
+  protected abstract decode(buf: Buffer): V;
+
+  /**
+   * Pushes values to the start of the list, returning the new length.
+   */
+  async pushLeft(key: K, ...values: V[]): Promise<number> {
+    return this.push(key, runtime.ListEnd.Left, values);
+  }
+
+  /**
+   * Pushes values to the end of the list, returning the new length.
+   */
+  async pushRight(key: K, ...values: V[]): Promise<number> {
+    return this.push(key, runtime.ListEnd.Right, values);
+  }
+
+  /**
+   * Removes and returns the first value in the list.
+   * Throws CacheMiss if the list is empty.
+   */
+  async popLeft(key: K): Promise<V> {
+    return this.pop(key, runtime.ListEnd.Left);
+  }
+
+  /**
+   * Removes and returns the last value in the list.
+   * Throws CacheMiss if the list is empty.
+   */
+  async popRight(key: K): Promise<V> {
+    return this.pop(key, runtime.ListEnd.Right);
+  }
+
+  /**
+   * Returns all the values in the list.
+   */
+  async items(key: K): Promise<V[]> {
+    return this.getRange(key, 0, -1);
+  }
+
+  /**
+   * Returns the values between the start and stop indices, inclusive.
+   * Negative indices count from the end of the list.
+   */
+  async getRange(key: K, start: number, stop: number): Promise<V[]> {
+    const source = getCurrentRequest();
+    const res = await this.cluster.listRange(this.key(key), start, stop, source);
+    return unwrapErr(res).map((v) => this.decode(v));
+  }
+
+  /**
+   * Returns the value at the given index.
+   * Throws CacheMiss if the index is out of range.
+   */
+  async get(key: K, index: number): Promise<V> {
+    const source = getCurrentRequest();
+    const res = await this.cluster.listGet(this.key(key), index, source);
+    return this.decode(unwrapErr(res));
+  }
+
+  /**
+   * Updates the value at the given index.
+   */
+  async set(key: K, index: number, value: V): Promise<void> {
+    const source = getCurrentRequest();
+    checkErr(
+      await this.cluster.listSet(this.key(key), index, this.encode(value), source)
+    );
+  }
+
+  /**
+   * Trims the list to the values between the start and stop indices, inclusive.
+   */
+  async trim(key: K, start: number, stop: number): Promise<void> {
+    const source = getCurrentRequest();
+    checkErr(await this.cluster.listTrim(this.key(key), start, stop, source));
+  }
+
+  /**
+   * Returns the length of the list, or 0 if it does not exist.
+   */
+  async len(key: K): Promise<number> {
+    const source = getCurrentRequest();
+    return unwrapErr(await this.cluster.listLen(this.key(key), source));
+  }
+
+  /**
+   * Removes up to count occurrences of the value from the list,
+   * returning the number of removed values. A count of 0 removes all of them.
+   */
+  async remove(key: K, count: number, value: V): Promise<number> {
+    const source = getCurrentRequest();
+    const res = await this.cluster.listRemove(
+      this.key(key),
+      count,
+      this.encode(value),
+      source
+    );
+    return unwrapErr(res);
+  }
+
+  private async push(key: K, end: runtime.ListEnd, values: V[]): Promise<number> {
+    const source = getCurrentRequest();
+    const res = await this.cluster.push(
+      this.key(key),
+      end,
+      values.map((v) => this.encode(v)),
+      source
+    );
+    return unwrapErr(res);
+  }
+
+  private async pop(key: K, end: runtime.ListEnd): Promise<V> {
+    const source = getCurrentRequest();
+    const res = await this.cluster.pop(this.key(key), end, source);
+    return this.decode(unwrapErr(res));
+  }
+}
+
+/**
+ * A keyspace storing lists of strings.
+ */
+export class StringListKeyspace<K> extends ListKeyspace<K, string> {
+  // eslint-disable-next-line @typescript-eslint/no-useless-constructor
+  constructor(cluster: CacheCluster, cfg: KeyspaceConfig) {
+    super(cluster, cfg);
+  }
+
+  protected encode(val: string): Buffer {
+    return Buffer.from(val, "utf8");
+  }
+
+  protected decode(buf: Buffer): string {
+    return buf.toString("utf8");
+  }
+}
+
+/**
+ * A keyspace storing lists of numbers.
+ */
+export class NumberListKeyspace<K> extends ListKeyspace<K, number> {
+  // eslint-disable-next-line @typescript-eslint/no-useless-constructor
+  constructor(cluster: CacheCluster, cfg: KeyspaceConfig) {
+    super(cluster, cfg);
+  }
+
+  protected encode(val: number): Buffer {
+    return Buffer.from(val.toString(), "utf8");
+  }
+
+  protected decode(buf: Buffer): number {
+    return parseFloat(buf.toString("utf8"));
+  }
+}
@@ -0,0 +1,16 @@
+export { CacheCluster } from "./cluster";
+export type { CacheClusterConfig, EvictionPolicy } from "./cluster";
+export {
+  StringKeyspace,
+  IntKeyspace,
+  FloatKeyspace,
+  StringListKeyspace,
+  NumberListKeyspace,
+  StructKeyspace,
+  expireIn,
+  expireAt,
+  neverExpire,
+  keepTTL
+} from "./keyspace";
+export type { KeyspaceConfig, Expiry, WriteOptions } from "./keyspace";
+export { CacheError, CacheMiss, CacheKeyExists } from "./error";
@@ -67,6 +67,18 @@ export class SQLDatabase {
     return this.impl.connString();
   }
 
+  /**
+   * Returns a handle for querying the database's read replicas.
+   *
+   * Queries made through it are sent to a healthy read replica,
+   * falling back to the primary if no replica is available.
+   * Replicas may lag behind the primary, so use it for queries
+   * that can tolerate slightly stale data, like reporting.
+   */
+  replica(): ReadReplica {
+    return new ReadReplica(this.impl.replica());
+  }
+
   /**
    * query queries the database using a template string, replacing your placeholders in the template
    * with parametrised values without risking SQL injections.
@@ -303,6 +315,213 @@ export class SQLDatabase {
     const impl = await this.impl.acquire();
     return new Connection(impl);
   }
+
+  /**
+   * Begins a new transaction on a connection from the database pool.
+   *
+   * The transaction must be ended with `commit` or `rollback`.
+   * If it is garbage-collected before then, it is rolled back.
+   * @returns a new transaction
+   */
+  async begin(): Promise<Transaction> {
+    const source = getCurrentRequest();
+    const impl = await this.impl.begin(source);
+    return new Transaction(impl);
+  }
+}
+
+/**
+ * Represents the read replicas of a database.
+ */
+export class ReadReplica {
+  private readonly impl: runtime.SQLDatabase;
+
+  constructor(impl: runtime.SQLDatabase) {
+    this.impl = impl;
+  }
+
+  /**
+   * query queries the database using a template string, replacing your placeholders in the template
+   * with parametrised values without risking SQL injections.
+   *
+   * It returns an async generator, that allows iterating over the results
+   * in a streaming fashion using `for await`.
+   *
+   * @example
+   *
+   * const email = "foo@example.com";
+   * const result = database.query`SELECT id FROM users WHERE email=${email}`
+   *
+   * This produces the query: "SELECT id FROM users WHERE email=$1".
+   */
+  // eslint-disable-next-line @typescript-eslint/no-explicit-any
+  async *query<T extends Row = Record<string, any>>(
+    strings: TemplateStringsArray,
+    ...params: Primitive[]
+  ): AsyncGenerator<T> {
+    const query = buildQuery(strings, params);
+    const args = buildQueryArgs(params);
+    const source = getCurrentRequest();
+    const cursor = await this.impl.query(query, args, source);
+    while (true) {
+      const row = await cursor.next();
+      if (row === null) {
+        break;
+      }
+      yield row.values() as T;
+    }
+  }
+
+  /**
+   * rawQuery queries the database using a raw parametrised SQL query and parameters.
+   *
+   * It returns an async generator, that allows iterating over the results
+   * in a streaming fashion using `for await`.
+   *
+   * @example
+   * const query = "SELECT id FROM users WHERE email=$1";
+   * const email = "foo@example.com";
+   * for await (const row of database.rawQuery(query, email)) {
+   *   console.log(row);
+   * }
+   *
+   * @param query - The raw SQL query string.
+   * @param params - The parameters to be used in the query.
+   * @returns An async generator that yields rows from the query result.
+   */
+  async *rawQuery<T extends Row = Record<string, any>>(
+    query: string,
+    ...params: Primitive[]
+  ): AsyncGenerator<T> {
+    const args = buildQueryArgs(params);
+    const source = getCurrentRequest();
+    const result = await this.impl.query(query, args, source);
+    while (true) {
+      const row = await result.next();
+      if (row === null) {
+        break;
+      }
+
+      yield row.values() as T;
+    }
+  }
+
+  /**
+   * queryAll queries the database using a template string, replacing your placeholders in the template
+   * with parametrised values without risking SQL injections.
+   *
+   * It returns an array of all results.
+   *
+   * @example
+   *
+   * const email = "foo@example.com";
+   * const result = database.queryAll`SELECT id FROM users WHERE email=${email}`
+   *
+   * This produces the query: "SELECT id FROM users WHERE email=$1".
+   */
+  // eslint-disable-next-line @typescript-eslint/no-explicit-any
+  async queryAll<T extends Row = Record<string, any>>(
+    strings: TemplateStringsArray,
+    ...params: Primitive[]
+  ): Promise<T[]> {
+    const query = buildQuery(strings, params);
+    const args = buildQueryArgs(params);
+    const source = getCurrentRequest();
+    const cursor = await this.impl.query(query, args, source);
+    const result: T[] = [];
+    while (true) {
+      const row = await cursor.next();
+      if (row === null) {
+        break;
+      }
+      result.push(row.values() as T);
+    }
+
+    return result;
+  }
+
+  /**
+   * rawQueryAll queries the database using a raw parametrised SQL query and parameters.
+   *
+   * It returns an array of all results.
+   *
+   * @example
+   *
+   * const query = "SELECT id FROM users WHERE email=$1";
+   * const email = "foo@example.com";
+   * const rows = await database.rawQueryAll(query, email);
+   */
+  // eslint-disable-next-line @typescript-eslint/no-explicit-any
+  async rawQueryAll<T extends Row = Record<string, any>>(
+    query: string,
+    ...params: Primitive[]
+  ): Promise<T[]> {
+    const args = buildQueryArgs(params);
+    const source = getCurrentRequest();
+    const cursor = await this.impl.query(query, args, source);
+    const result: T[] = [];
+    while (true) {
+      const row = await cursor.next();
+      if (row === null) {
+        break;
+      }
+      result.push(row.values() as T);
+    }
+
+    return result;
+  }
+
+  /**
+   * queryRow is like query but returns only a single row.
+   * If the query selects no rows it returns null.
+   * Otherwise it returns the first row and discards the rest.
+   *
+   * @example
+   * const email = "foo@example.com";
+   * const result = database.queryRow`SELECT id FROM users WHERE email=${email}`
+   */
+  // eslint-disable-next-line @typescript-eslint/no-explicit-any
+  async queryRow<T extends Row = Record<string, any>>(
+    strings: TemplateStringsArray,
+    ...params: Primitive[]
+  ): Promise<T | null> {
+    const query = buildQuery(strings, params);
+    const args = buildQueryArgs(params);
+    const source = getCurrentRequest();
+    const result = await this.impl.query(query, args, source);
+    while (true) {
+      const row = await result.next();
+      return row ? (row.values() as T) : null;
+    }
+  }
+
+  /**
+   * rawQueryRow is like rawQuery but returns only a single row.
+   * If the query selects no rows, it returns null.
+   * Otherwise, it returns the first row and discards the rest.
+   *
+   * @example
+   * const query = "SELECT id FROM users WHERE email=$1";
+   * const email = "foo@example.com";
+   * const result = await database.rawQueryRow(query, email);
+   * console.log(result);
+   *
+   * @param query - The raw SQL query string.
+   * @param params - The parameters to be used in the query.
+   * @returns A promise that resolves to a single row or null.
+   */
+  async rawQueryRow<T extends Row = Record<string, any>>(
+    query: string,
+    ...params: Primitive[]
+  ): Promise<T | null> {
+    const args = buildQueryArgs(params);
+    const source = getCurrentRequest();
+    const result = await this.impl.query(query, args, source);
+    while (true) {
+      const row = await result.next();
+      return row ? (row.values() as T) : null;
+    }
+  }
 }
 
 /**
@@ -322,6 +541,286 @@ export class Connection {
     await this.impl.close();
   }
 
+  /**
+   * Begins a new transaction on the connection.
+   *
+   * The transaction must be ended with `commit` or `rollback`.
+   * If it is garbage-collected before then, it is rolled back.
+   * @returns a new transaction
+   */
+  async begin(): Promise<Transaction> {
+    const source = getCurrentRequest();
+    const impl = await this.impl.begin(source);
+    return new Transaction(impl);
+  }
+
+  /**
+   * query queries the database using a template string, replacing your placeholders in the template
+   * with parametrised values without risking SQL injections.
+   *
+   * It returns an async generator, that allows iterating over the results
+   * in a streaming fashion using `for await`.
+   *
+   * @example
+   *
+   * const email = "foo@example.com";
+   * const result = database.query`SELECT id FROM users WHERE email=${email}`
+   *
+   * This produces the query: "SELECT id FROM users WHERE email=$1".
+   */
+  // eslint-disable-next-line @typescript-eslint/no-explicit-any
+  async *query<T extends Row = Record<string, any>>(
+    strings: TemplateStringsArray,
+    ...params: Primitive[]
+  ): AsyncGenerator<T> {
+    const query = buildQuery(strings, params);
+    const args = buildQueryArgs(params);
+    const source = getCurrentRequest();
+    const cursor = await this.impl.query(query, args, source);
+    while (true) {
+      const row = await cursor.next();
+      if (row === null) {
+        break;
+      }
+      yield row.values() as T;
+    }
+  }
+
+  /**
+   * rawQuery queries the database using a raw parametrised SQL query and parameters.
+   *
+   * It returns an async generator, that allows iterating over the results
+   * in a streaming fashion using `for await`.
+   *
+   * @example
+   * const query = "SELECT id FROM users WHERE email=$1";
+   * const email = "foo@example.com";
+   * for await (const row of database.rawQuery(query, email)) {
+   *   console.log(row);
+   * }
+   *
+   * @param query - The raw SQL query string.
+   * @param params - The parameters to be used in the query.
+   * @returns An async generator that yields rows from the query result.
+   */
+  async *rawQuery<T extends Row = Record<string, any>>(
+    query: string,
+    ...params: Primitive[]
+  ): AsyncGenerator<T> {
+    const args = buildQueryArgs(params);
+    const source = getCurrentRequest();
+    const result = await this.impl.query(query, args, source);
+    while (true) {
+      const row = await result.next();
+      if (row === null) {
+        break;
+      }
+
+      yield row.values() as T;
+    }
+  }
+
+  /**
+   * queryAll queries the database using a template string, replacing your placeholders in the template
+   * with parametrised values without risking SQL injections.
+   *
+   * It returns an array of all results.
+   *
+   * @example
+   *
+   * const email = "foo@example.com";
+   * const result = database.queryAll`SELECT id FROM users WHERE email=${email}`
+   *
+   * This produces the query: "SELECT id FROM users WHERE email=$1".
+   */
+  // eslint-disable-next-line @typescript-eslint/no-explicit-any
+  async queryAll<T extends Row = Record<string, any>>(
+    strings: TemplateStringsArray,
+    ...params: Primitive[]
+  ): Promise<T[]> {
+    const query = buildQuery(strings, params);
+    const args = buildQueryArgs(params);
+    const source = getCurrentRequest();
+    const cursor = await this.impl.query(query, args, source);
+    const result: T[] = [];
+    while (true) {
+      const row = await cursor.next();
+      if (row === null) {
+        break;
+      }
+      result.push(row.values() as T);
+    }
+
+    return result;
+  }
+
+  /**
+   * rawQueryAll queries the database using a raw parametrised SQL query and parameters.
+   *
+   * It returns an array of all results.
+   *
+   * @example
+   *
+   * const query = "SELECT id FROM users WHERE email=$1";
+   * const email = "foo@example.com";
+   * const rows = await database.rawQueryAll(query, email);
+   */
+  // eslint-disable-next-line @typescript-eslint/no-explicit-any
+  async rawQueryAll<T extends Row = Record<string, any>>(
+    query: string,
+    ...params: Primitive[]
+  ): Promise<T[]> {
+    const args = buildQueryArgs(params);
+    const source = getCurrentRequest();
+    const cursor = await this.impl.query(query, args, source);
+    const result: T[] = [];
+    while (true) {
+      const row = await cursor.next();
+      if (row === null) {
+        break;
+      }
+      result.push(row.values() as T);
+    }
+
+    return result;
+  }
+
+  /**
+   * queryRow is like query but returns only a single row.
+   * If the query selects no rows it returns null.
+   * Otherwise it returns the first row and discards the rest.
+   *
+   * @example
+   * const email = "foo@example.com";
+   * const result = database.queryRow`SELECT id FROM users WHERE email=${email}`
+   */
+  // eslint-disable-next-line @typescript-eslint/no-explicit-any
+  async queryRow<T extends Row = Record<string, any>>(
+    strings: TemplateStringsArray,
+    ...params: Primitive[]
+  ): Promise<T | null> {
+    const query = buildQuery(strings, params);
+    const args = buildQueryArgs(params);
+    const source = getCurrentRequest();
+    const result = await this.impl.query(query, args, source);
+    while (true) {
+      const row = await result.next();
+      return row ? (row.values() as T) : null;
+    }
+  }
+
+  /**
+   * rawQueryRow is like rawQuery but returns only a single row.
+   * If the query selects no rows, it returns null.
+   * Otherwise, it returns the first row and discards the rest.
+   *
+   * @example
+   * const query = "SELECT id FROM users WHERE email=$1";
+   * const email = "foo@example.com";
+   * const result = await database.rawQueryRow(query, email);
+   * console.log(result);
+   *
+   * @param query - The raw SQL query string.
+   * @param params - The parameters to be used in the query.
+   * @returns A promise that resolves to a single row or null.
+   */
+  async rawQueryRow<T extends Row = Record<string, any>>(
+    query: string,
+    ...params: Primitive[]
+  ): Promise<T | null> {
+    const args = buildQueryArgs(params);
+    const source = getCurrentRequest();
+    const result = await this.impl.query(query, args, source);
+    while (true) {
+      const row = await result.next();
+      return row ? (row.values() as T) : null;
+    }
+  }
+
+  /**
+   * exec executes a query without returning any rows.
+   *
+   * @example
+   * const email = "foo@example.com";
+   * const result = database.exec`DELETE FROM users WHERE email=${email}`
+   */
+  async exec(
+    strings: TemplateStringsArray,
+    ...params: Primitive[]
+  ): Promise<void> {
+    const query = buildQuery(strings, params);
+    const args = buildQueryArgs(params);
+    const source = getCurrentRequest();
+
+    // Need to await the cursor to process any errors from things like
+    // unique constraint violations.
+    let cur = await this.impl.query(query, args, source);
+    await cur.next();
+  }
+
+  /**
+   * rawExec executes a query without returning any rows.
+   *
+   * @example
+   * const query = "DELETE FROM users WHERE email=$1";
+   * const email = "foo@example.com";
+   * await database.rawExec(query, email);
+   *
+   * @param query - The raw SQL query string.
+   * @param params - The parameters to be used in the query.
+   * @returns A promise that resolves when the query has been executed.
+   */
+  async rawExec(query: string, ...params: Primitive[]): Promise<void> {
+    const args = buildQueryArgs(params);
+    const source = getCurrentRequest();
+
+    // Need to await the cursor to process any errors from things like
+    // unique constraint violations.
+    let cur = await this.impl.query(query, args, source);
+    await cur.next();
+  }
+}
+
+/**
+ * Represents a database transaction, or a savepoint within one.
+ */
+export class Transaction {
+  private readonly impl: runtime.SQLTransaction;
+
+  constructor(impl: runtime.SQLTransaction) {
+    this.impl = impl;
+  }
+
+  /**
+   * Commits the transaction.
+   * For a savepoint this releases the savepoint.
+   */
+  async commit() {
+    await this.impl.commit();
+  }
+
+  /**
+   * Rolls back the transaction.
+   * For a savepoint this rolls back to the savepoint,
+   * leaving the enclosing transaction in progress.
+   */
+  async rollback() {
+    await this.impl.rollback();
+  }
+
+  /**
+   * Creates a savepoint within the transaction.
+   *
+   * The savepoint is itself a transaction: committing it releases
+   * the savepoint and rolling it back undoes the changes made since
+   * it was created.
+   * @returns a new nested transaction
+   */
+  async savepoint(): Promise<Transaction> {
+    const impl = await this.impl.savepoint();
+    return new Transaction(impl);
+  }
+
   /**
    * query queries the database using a template string, replacing your placeholders in the template
    * with parametrised values without risking SQL injections.
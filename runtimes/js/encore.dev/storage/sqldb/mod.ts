@@ -1,2 +1,2 @@
-export { SQLDatabase } from "./database";
+export { SQLDatabase, ReadReplica, Connection, Transaction } from "./database";
 export type { SQLDatabaseConfig, Row as ResultRow } from "./database";
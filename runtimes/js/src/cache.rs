@@ -0,0 +1,395 @@
+use encore_runtime_core::cache as core;
+use napi::bindgen_prelude::Buffer;
+use napi_derive::napi;
+use std::time::{Duration, SystemTime};
+
+use crate::api::Request;
+
+#[napi]
+pub struct CacheCluster {
+    cluster: core::Cluster,
+}
+
+#[napi]
+impl CacheCluster {
+    pub(crate) fn new(cluster: core::Cluster) -> Self {
+        Self { cluster }
+    }
+
+    #[napi]
+    pub async fn get(
+        &self,
+        key: String,
+        source: Option<&Request>,
+    ) -> napi::Either<Buffer, TypedCacheError> {
+        let source = source.map(|s| s.inner.clone());
+        match self.cluster.get(key, source.as_deref()).await {
+            Ok(val) => napi::Either::A(val.into()),
+            Err(err) => napi::Either::B(err.into()),
+        }
+    }
+
+    #[napi]
+    pub async fn get_multi(
+        &self,
+        keys: Vec<String>,
+        source: Option<&Request>,
+    ) -> napi::Either<Vec<Option<Buffer>>, TypedCacheError> {
+        let source = source.map(|s| s.inner.clone());
+        match self.cluster.get_multi(keys, source.as_deref()).await {
+            Ok(vals) => napi::Either::A(vals.into_iter().map(|v| v.map(Buffer::from)).collect()),
+            Err(err) => napi::Either::B(err.into()),
+        }
+    }
+
+    #[napi]
+    pub async fn set(
+        &self,
+        key: String,
+        value: Buffer,
+        options: Option<SetOptions>,
+        source: Option<&Request>,
+    ) -> Option<TypedCacheError> {
+        let options = options.unwrap_or_default().into();
+        let source = source.map(|s| s.inner.clone());
+        match self
+            .cluster
+            .set(key, value.to_vec(), options, source.as_deref())
+            .await
+        {
+            Ok(()) => None,
+            Err(err) => Some(err.into()),
+        }
+    }
+
+    #[napi]
+    pub async fn get_and_set(
+        &self,
+        key: String,
+        value: Buffer,
+        options: Option<ExpiryOptions>,
+        source: Option<&Request>,
+    ) -> napi::Either<Option<Buffer>, TypedCacheError> {
+        let expiry = options.unwrap_or_default().into();
+        let source = source.map(|s| s.inner.clone());
+        match self
+            .cluster
+            .get_and_set(key, value.to_vec(), expiry, source.as_deref())
+            .await
+        {
+            Ok(prev) => napi::Either::A(prev.map(Buffer::from)),
+            Err(err) => napi::Either::B(err.into()),
+        }
+    }
+
+    #[napi]
+    pub async fn get_and_delete(
+        &self,
+        key: String,
+        source: Option<&Request>,
+    ) -> napi::Either<Buffer, TypedCacheError> {
+        let source = source.map(|s| s.inner.clone());
+        match self.cluster.get_and_delete(key, source.as_deref()).await {
+            Ok(val) => napi::Either::A(val.into()),
+            Err(err) => napi::Either::B(err.into()),
+        }
+    }
+
+    #[napi]
+    pub async fn delete(
+        &self,
+        keys: Vec<String>,
+        source: Option<&Request>,
+    ) -> napi::Either<i64, TypedCacheError> {
+        let source = source.map(|s| s.inner.clone());
+        match self.cluster.delete(keys, source.as_deref()).await {
+            Ok(n) => napi::Either::A(n as i64),
+            Err(err) => napi::Either::B(err.into()),
+        }
+    }
+
+    #[napi]
+    pub async fn incr_by(
+        &self,
+        key: String,
+        delta: i64,
+        source: Option<&Request>,
+    ) -> napi::Either<i64, TypedCacheError> {
+        let source = source.map(|s| s.inner.clone());
+        match self.cluster.incr_by(key, delta, source.as_deref()).await {
+            Ok(n) => napi::Either::A(n),
+            Err(err) => napi::Either::B(err.into()),
+        }
+    }
+
+    #[napi]
+    pub async fn incr_by_float(
+        &self,
+        key: String,
+        delta: f64,
+        source: Option<&Request>,
+    ) -> napi::Either<f64, TypedCacheError> {
+        let source = source.map(|s| s.inner.clone());
+        match self
+            .cluster
+            .incr_by_float(key, delta, source.as_deref())
+            .await
+        {
+            Ok(n) => napi::Either::A(n),
+            Err(err) => napi::Either::B(err.into()),
+        }
+    }
+
+    #[napi]
+    pub async fn expire(
+        &self,
+        key: String,
+        options: ExpiryOptions,
+        source: Option<&Request>,
+    ) -> Option<TypedCacheError> {
+        let source = source.map(|s| s.inner.clone());
+        match self
+            .cluster
+            .expire(key, options.into(), source.as_deref())
+            .await
+        {
+            Ok(()) => None,
+            Err(err) => Some(err.into()),
+        }
+    }
+
+    #[napi]
+    pub async fn push(
+        &self,
+        key: String,
+        end: ListEnd,
+        values: Vec<Buffer>,
+        source: Option<&Request>,
+    ) -> napi::Either<i64, TypedCacheError> {
+        let values = values.iter().map(|v| v.to_vec()).collect();
+        let source = source.map(|s| s.inner.clone());
+        match self
+            .cluster
+            .push(key, end.into(), values, source.as_deref())
+            .await
+        {
+            Ok(n) => napi::Either::A(n as i64),
+            Err(err) => napi::Either::B(err.into()),
+        }
+    }
+
+    #[napi]
+    pub async fn pop(
+        &self,
+        key: String,
+        end: ListEnd,
+        source: Option<&Request>,
+    ) -> napi::Either<Buffer, TypedCacheError> {
+        let source = source.map(|s| s.inner.clone());
+        match self.cluster.pop(key, end.into(), source.as_deref()).await {
+            Ok(val) => napi::Either::A(val.into()),
+            Err(err) => napi::Either::B(err.into()),
+        }
+    }
+
+    #[napi]
+    pub async fn list_range(
+        &self,
+        key: String,
+        start: i64,
+        stop: i64,
+        source: Option<&Request>,
+    ) -> napi::Either<Vec<Buffer>, TypedCacheError> {
+        let source = source.map(|s| s.inner.clone());
+        match self
+            .cluster
+            .list_range(key, start, stop, source.as_deref())
+            .await
+        {
+            Ok(vals) => napi::Either::A(vals.into_iter().map(Buffer::from).collect()),
+            Err(err) => napi::Either::B(err.into()),
+        }
+    }
+
+    #[napi]
+    pub async fn list_len(
+        &self,
+        key: String,
+        source: Option<&Request>,
+    ) -> napi::Either<i64, TypedCacheError> {
+        let source = source.map(|s| s.inner.clone());
+        match self.cluster.list_len(key, source.as_deref()).await {
+            Ok(n) => napi::Either::A(n as i64),
+            Err(err) => napi::Either::B(err.into()),
+        }
+    }
+
+    #[napi]
+    pub async fn list_get(
+        &self,
+        key: String,
+        index: i64,
+        source: Option<&Request>,
+    ) -> napi::Either<Buffer, TypedCacheError> {
+        let source = source.map(|s| s.inner.clone());
+        match self.cluster.list_get(key, index, source.as_deref()).await {
+            Ok(val) => napi::Either::A(val.into()),
+            Err(err) => napi::Either::B(err.into()),
+        }
+    }
+
+    #[napi]
+    pub async fn list_set(
+        &self,
+        key: String,
+        index: i64,
+        value: Buffer,
+        source: Option<&Request>,
+    ) -> Option<TypedCacheError> {
+        let source = source.map(|s| s.inner.clone());
+        match self
+            .cluster
+            .list_set(key, index, value.to_vec(), source.as_deref())
+            .await
+        {
+            Ok(()) => None,
+            Err(err) => Some(err.into()),
+        }
+    }
+
+    #[napi]
+    pub async fn list_trim(
+        &self,
+        key: String,
+        start: i64,
+        stop: i64,
+        source: Option<&Request>,
+    ) -> Option<TypedCacheError> {
+        let source = source.map(|s| s.inner.clone());
+        match self
+            .cluster
+            .list_trim(key, start, stop, source.as_deref())
+            .await
+        {
+            Ok(()) => None,
+            Err(err) => Some(err.into()),
+        }
+    }
+
+    #[napi]
+    pub async fn list_remove(
+        &self,
+        key: String,
+        count: i64,
+        value: Buffer,
+        source: Option<&Request>,
+    ) -> napi::Either<i64, TypedCacheError> {
+        let source = source.map(|s| s.inner.clone());
+        match self
+            .cluster
+            .list_remove(key, count, value.to_vec(), source.as_deref())
+            .await
+        {
+            Ok(n) => napi::Either::A(n as i64),
+            Err(err) => napi::Either::B(err.into()),
+        }
+    }
+}
+
+#[napi]
+pub enum ListEnd {
+    Left,
+    Right,
+}
+
+impl From<ListEnd> for core::ListEnd {
+    fn from(value: ListEnd) -> Self {
+        match value {
+            ListEnd::Left => core::ListEnd::Left,
+            ListEnd::Right => core::ListEnd::Right,
+        }
+    }
+}
+
+#[napi]
+pub enum SetMode {
+    Always,
+    IfNotExists,
+    IfExists,
+}
+
+/// Describes when a cache entry expires.
+/// If no field is set the entry never expires.
+#[napi(object)]
+#[derive(Debug, Default)]
+pub struct ExpiryOptions {
+    /// Expire the entry after this many milliseconds.
+    pub ttl_ms: Option<f64>,
+    /// Expire the entry at this unix timestamp, in milliseconds.
+    pub expire_at_ms: Option<f64>,
+    /// Keep the existing expiry of the entry.
+    pub keep_ttl: Option<bool>,
+}
+
+impl From<ExpiryOptions> for core::Expiry {
+    fn from(value: ExpiryOptions) -> Self {
+        if let Some(ms) = value.ttl_ms {
+            core::Expiry::In(Duration::from_millis(ms.max(0.0) as u64))
+        } else if let Some(ms) = value.expire_at_ms {
+            core::Expiry::At(SystemTime::UNIX_EPOCH + Duration::from_millis(ms.max(0.0) as u64))
+        } else if value.keep_ttl.unwrap_or(false) {
+            core::Expiry::KeepTtl
+        } else {
+            core::Expiry::Never
+        }
+    }
+}
+
+#[napi(object)]
+#[derive(Default)]
+pub struct SetOptions {
+    pub mode: Option<SetMode>,
+    pub expiry: Option<ExpiryOptions>,
+}
+
+impl From<SetOptions> for core::SetOptions {
+    fn from(value: SetOptions) -> Self {
+        Self {
+            mode: match value.mode {
+                None | Some(SetMode::Always) => core::SetMode::Always,
+                Some(SetMode::IfNotExists) => core::SetMode::IfNotExists,
+                Some(SetMode::IfExists) => core::SetMode::IfExists,
+            },
+            expiry: value.expiry.unwrap_or_default().into(),
+        }
+    }
+}
+
+#[napi]
+pub enum CacheErrorKind {
+    Miss,
+    KeyExists,
+    Unconfigured,
+    Other,
+}
+
+#[napi]
+pub struct TypedCacheError {
+    pub kind: CacheErrorKind,
+    pub message: String,
+}
+
+impl From<core::Error> for TypedCacheError {
+    fn from(value: core::Error) -> Self {
+        let kind = match &value {
+            core::Error::Miss => CacheErrorKind::Miss,
+            core::Error::KeyExists => CacheErrorKind::KeyExists,
+            core::Error::Unconfigured => CacheErrorKind::Unconfigured,
+            core::Error::Redis(_) => CacheErrorKind::Other,
+        };
+        Self {
+            kind,
+            message: value.to_string(),
+        }
+    }
+}
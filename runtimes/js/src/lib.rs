@@ -1,11 +1,13 @@
 #![deny(clippy::all)]
 
 pub mod api;
+pub mod cache;
 mod error;
 mod gateway;
 mod headers;
 mod log;
 mod meta;
+pub mod metrics;
 mod napi_util;
 pub mod objects;
 pub mod pubsub;
@@ -14,6 +16,7 @@ mod raw_api;
 mod request_meta;
 pub mod runtime;
 mod secret;
+mod shutdown;
 mod sqldb;
 mod stream;
 mod threadsafe_function;
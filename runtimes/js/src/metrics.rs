@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+
+use encore_runtime_core::metrics::{self as core, Labels, MetricKind};
+use napi::{Error, Status};
+use napi_derive::napi;
+
+/// A user-defined counter metric.
+#[napi]
+pub struct Counter {
+    name: String,
+}
+
+#[napi]
+impl Counter {
+    pub(crate) fn new(name: String, help: Option<String>) -> napi::Result<Self> {
+        register(&name, MetricKind::Counter, help)?;
+        Ok(Self { name })
+    }
+
+    /// Increments the counter by the given value (defaulting to 1).
+    #[napi]
+    pub fn increment(&self, value: Option<f64>, labels: Option<HashMap<String, String>>) {
+        let counter = core::registry().counter(&self.name, to_labels(labels));
+        counter.add(value.unwrap_or(1.0));
+    }
+}
+
+/// A user-defined gauge metric.
+#[napi]
+pub struct Gauge {
+    name: String,
+}
+
+#[napi]
+impl Gauge {
+    pub(crate) fn new(name: String, help: Option<String>) -> napi::Result<Self> {
+        register(&name, MetricKind::Gauge, help)?;
+        Ok(Self { name })
+    }
+
+    /// Sets the gauge to the given value.
+    #[napi]
+    pub fn set(&self, value: f64, labels: Option<HashMap<String, String>>) {
+        core::registry()
+            .gauge(&self.name, to_labels(labels))
+            .set(value);
+    }
+
+    /// Adds the given delta (which may be negative) to the gauge.
+    #[napi]
+    pub fn add(&self, delta: f64, labels: Option<HashMap<String, String>>) {
+        core::registry()
+            .gauge(&self.name, to_labels(labels))
+            .add(delta);
+    }
+}
+
+fn register(name: &str, kind: MetricKind, help: Option<String>) -> napi::Result<()> {
+    if !is_valid_name(name) {
+        return Err(Error::new(
+            Status::InvalidArg,
+            format!("invalid metric name {:?}", name),
+        ));
+    } else if name.starts_with("e_") {
+        return Err(Error::new(
+            Status::InvalidArg,
+            format!("metric name {:?} uses the reserved prefix \"e_\"", name),
+        ));
+    }
+    core::registry().describe(name, kind, help.as_deref().unwrap_or_default());
+    Ok(())
+}
+
+/// Reports whether the name is a valid Prometheus metric name.
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' || c == ':' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+}
+
+fn to_labels(labels: Option<HashMap<String, String>>) -> Labels {
+    labels.map(Labels::new).unwrap_or_default()
+}
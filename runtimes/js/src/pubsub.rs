@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
@@ -15,6 +16,40 @@ use crate::napi_util::{await_promise, PromiseHandler};
 use crate::pvalue::parse_pvalues;
 use crate::threadsafe_function::{ThreadSafeCallContext, ThreadsafeFunction};
 
+/// The result of publishing a message as part of a batch.
+/// Exactly one of `id` and `error` is set.
+#[napi(object)]
+pub struct PublishBatchResult {
+    pub id: Option<String>,
+    pub error: Option<String>,
+}
+
+/// A message published to an in-memory topic that has yet
+/// to be delivered to one of its subscriptions.
+#[napi(object)]
+pub struct PendingPubSubMessage {
+    pub topic: String,
+    pub subscription: String,
+    pub id: String,
+    pub delivery_attempt: u32,
+    /// The message payload, if it's valid JSON.
+    pub payload: Option<serde_json::Value>,
+    pub attributes: HashMap<String, String>,
+}
+
+impl From<pubsub::PendingMessage> for PendingPubSubMessage {
+    fn from(msg: pubsub::PendingMessage) -> Self {
+        Self {
+            topic: msg.subscription.topic.to_string(),
+            subscription: msg.subscription.subscription.to_string(),
+            id: msg.id,
+            delivery_attempt: msg.attempt,
+            payload: serde_json::from_slice(&msg.data.raw_body).ok(),
+            attributes: msg.data.attrs,
+        }
+    }
+}
+
 #[napi]
 pub struct PubSubTopic {
     topic: TopicObj,
@@ -54,6 +89,52 @@ impl PubSubTopic {
 
         env.spawn_future(fut)
     }
+
+    /// Publishes a batch of messages, resolving to the result of each in the same order.
+    #[napi(ts_return_type = "Promise<PublishBatchResult[]>")]
+    pub fn publish_batch(
+        &self,
+        env: Env,
+        bodies: Vec<JsUnknown>,
+        source: Option<&Request>,
+    ) -> napi::Result<JsObject> {
+        let mut payloads = Vec::with_capacity(bodies.len());
+        for body in bodies {
+            let Some(payload) = parse_pvalues(body).context("failed to parse payload")? else {
+                return Err(Error::new(
+                    Status::InvalidArg,
+                    "no message payload provided",
+                ));
+            };
+            payloads.push(payload);
+        }
+
+        let source = source.map(|s| s.inner.clone());
+        let fut = self.topic.publish_batch(payloads, source);
+        let fut = async move {
+            let results = fut.await.map_err(|e| {
+                Error::new(
+                    Status::GenericFailure,
+                    format!("failed to publish batch: {}", e),
+                )
+            })?;
+            Ok(results
+                .into_iter()
+                .map(|result| match result {
+                    Ok(id) => PublishBatchResult {
+                        id: Some(id),
+                        error: None,
+                    },
+                    Err(err) => PublishBatchResult {
+                        id: None,
+                        error: Some(format!("{:#}", err)),
+                    },
+                })
+                .collect::<Vec<_>>())
+        };
+
+        env.spawn_future(fut)
+    }
 }
 
 #[napi(object)]
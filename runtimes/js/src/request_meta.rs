@@ -72,6 +72,14 @@ pub fn meta(req: &model::Request) -> Result<RequestMeta, serde_json::Error> {
                 published_at: msg.published.to_rfc3339_opts(SecondsFormat::Secs, true),
                 delivery_attempt: msg.attempt,
                 parsed_payload: msg.parsed_payload.as_ref().map(|pv| PVals(pv.clone())),
+                dead_letter: msg.dead_letter.as_ref().map(|dl| DeadLetterData {
+                    topic: dl.topic.to_string(),
+                    subscription: dl.subscription.to_string(),
+                    message_id: dl.message_id.clone(),
+                    attempts: dl.attempts,
+                    error_code: dl.error_code.clone(),
+                    error_message: dl.error_message.clone(),
+                }),
             };
             (None, Some(pubsub_message))
         }
@@ -131,6 +139,17 @@ pub struct PubSubMessageData {
     pub published_at: String,
     pub delivery_attempt: u32,
     pub parsed_payload: Option<PVals>,
+    pub dead_letter: Option<DeadLetterData>,
+}
+
+#[napi(object)]
+pub struct DeadLetterData {
+    pub topic: String,
+    pub subscription: String,
+    pub message_id: String,
+    pub attempts: u32,
+    pub error_code: String,
+    pub error_message: String,
 }
 
 #[napi(object)]
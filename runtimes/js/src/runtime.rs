@@ -1,15 +1,18 @@
 use crate::api::{new_api_handler, APIRoute, Request};
 use crate::gateway::{Gateway, GatewayConfig};
 use crate::log::Logger;
-use crate::pubsub::{PubSubSubscription, PubSubSubscriptionConfig, PubSubTopic};
+use crate::pubsub::{
+    PendingPubSubMessage, PubSubSubscription, PubSubSubscriptionConfig, PubSubTopic,
+};
 use crate::pvalue::{parse_pvalues, PVals};
 use crate::secret::Secret;
+use crate::shutdown::JSShutdownHook;
 use crate::sqldb::SQLDatabase;
-use crate::{meta, objects, websocket_api};
+use crate::{cache, meta, metrics, objects, websocket_api};
 use encore_runtime_core::api::PValues;
 use encore_runtime_core::pubsub::SubName;
 use encore_runtime_core::{api, EncoreName, EndpointName};
-use napi::{bindgen_prelude::*, JsObject};
+use napi::{bindgen_prelude::*, JsFunction, JsObject};
 use napi::{Error, JsUnknown, Status};
 use napi_derive::napi;
 use std::future::Future;
@@ -30,21 +33,56 @@ pub struct RuntimeOptions {
     pub test_mode: Option<bool>,
 }
 
+#[napi(object)]
+#[derive(Default)]
+pub struct CallOptions {
+    /// The maximum time to wait for the call to complete, in milliseconds.
+    pub timeout_ms: Option<f64>,
+}
+
+impl From<CallOptions> for api::call::CallOpts {
+    fn from(value: CallOptions) -> Self {
+        Self {
+            timeout: value
+                .timeout_ms
+                .filter(|ms| ms.is_finite() && *ms >= 0.0)
+                .map(|ms| std::time::Duration::from_secs_f64(ms / 1000.0)),
+        }
+    }
+}
+
 fn init_runtime(test_mode: bool) -> napi::Result<encore_runtime_core::Runtime> {
     // Initialize logging.
     encore_runtime_core::log::init();
 
-    encore_runtime_core::Runtime::builder()
+    let builder = encore_runtime_core::Runtime::builder()
         .with_test_mode(test_mode)
         .with_meta_autodetect()
-        .with_runtime_config_from_env()
-        .build()
-        .map_err(|e| {
-            Error::new(
-                Status::GenericFailure,
-                format!("failed to initialize runtime: {:?}", e),
-            )
-        })
+        .with_runtime_config_from_env();
+
+    // When started by the supervisor's migrate subcommand,
+    // apply the migrations and exit without running the app.
+    if encore_runtime_core::migrate_only() {
+        let code = match builder.migrate() {
+            Ok(()) => {
+                ::log::info!("database migrations completed");
+                0
+            }
+            Err(err) => {
+                ::log::error!("database migrations failed: {:?}", err);
+                1
+            }
+        };
+        ::log::logger().flush();
+        std::process::exit(code);
+    }
+
+    builder.build().map_err(|e| {
+        Error::new(
+            Status::GenericFailure,
+            format!("failed to initialize runtime: {:?}", e),
+        )
+    })
 }
 
 #[napi]
@@ -109,6 +147,45 @@ impl Runtime {
         Ok(PubSubTopic::new(topic))
     }
 
+    /// Republishes the dead-lettered message being processed by `source`
+    /// to the subscription it was dead-lettered from.
+    #[napi(ts_return_type = "Promise<string>")]
+    pub fn pubsub_redrive(&self, env: Env, source: &Request) -> napi::Result<JsObject> {
+        let fut = self.runtime.pubsub().redrive(source.inner.clone());
+        let fut = async move {
+            fut.await.map_err(|e| {
+                Error::new(
+                    Status::GenericFailure,
+                    format!("failed to redrive message: {}", e),
+                )
+            })
+        };
+        env.spawn_future(fut)
+    }
+
+    /// Waits until the messages published to in-memory topics, as used in tests,
+    /// have been processed by their subscriptions.
+    #[napi(ts_return_type = "Promise<void>")]
+    pub fn pubsub_wait_for_pending_messages(&self, env: Env) -> napi::Result<JsObject> {
+        let fut = self.runtime.pubsub().wait_for_pending_messages();
+        env.spawn_future(async move {
+            fut.await;
+            Ok(())
+        })
+    }
+
+    /// Removes and returns the messages published to in-memory topics, as used in tests,
+    /// that have yet to be delivered to their subscriptions.
+    #[napi]
+    pub fn pubsub_drain_pending_messages(&self) -> Vec<PendingPubSubMessage> {
+        self.runtime
+            .pubsub()
+            .drain_pending_messages()
+            .into_iter()
+            .map(PendingPubSubMessage::from)
+            .collect()
+    }
+
     #[napi]
     pub fn bucket(&self, encore_name: String) -> napi::Result<objects::Bucket> {
         let bkt = self
@@ -119,6 +196,26 @@ impl Runtime {
         Ok(objects::Bucket::new(bkt))
     }
 
+    #[napi]
+    pub fn cache_cluster(&self, encore_name: String) -> cache::CacheCluster {
+        let cluster = self.runtime.cache().cluster(encore_name.into());
+        cache::CacheCluster::new(cluster)
+    }
+
+    #[napi]
+    pub fn metric_counter(
+        &self,
+        name: String,
+        help: Option<String>,
+    ) -> napi::Result<metrics::Counter> {
+        metrics::Counter::new(name, help)
+    }
+
+    #[napi]
+    pub fn metric_gauge(&self, name: String, help: Option<String>) -> napi::Result<metrics::Gauge> {
+        metrics::Gauge::new(name, help)
+    }
+
     #[napi]
     pub fn gateway(
         &self,
@@ -192,6 +289,20 @@ impl Runtime {
         Ok(())
     }
 
+    /// Registers a hook to run when the runtime gracefully shuts down.
+    /// The hook is called with the deadline (in milliseconds since the Unix epoch)
+    /// by which it is expected to have completed.
+    #[napi]
+    pub fn on_shutdown(
+        &self,
+        env: Env,
+        #[napi(ts_arg_type = "(deadlineMs: number) => void | Promise<void>")] hook: JsFunction,
+    ) -> napi::Result<()> {
+        let hook = JSShutdownHook::new(env, hook)?;
+        self.runtime.shutdown().register_hook(Arc::new(hook));
+        Ok(())
+    }
+
     #[napi]
     pub fn secret(&self, encore_name: String) -> Option<Secret> {
         self.runtime
@@ -208,14 +319,16 @@ impl Runtime {
         endpoint: String,
         payload: Option<JsUnknown>,
         source: Option<&Request>,
+        opts: Option<CallOptions>,
     ) -> napi::Result<JsObject> {
         let payload = match payload {
             Some(payload) => parse_pvalues(payload)?,
             None => None,
         };
         let endpoint = encore_runtime_core::EndpointName::new(service, endpoint);
+        let opts = opts.unwrap_or_default().into();
 
-        let fut = self.do_api_call(endpoint, payload, source);
+        let fut = self.do_api_call(endpoint, payload, source, opts);
         let fut = async move {
             let res: napi::Result<Either<Option<PValues>, APICallError>> = match fut.await {
                 Ok(data) => Ok(Either::A(data)),
@@ -237,9 +350,10 @@ impl Runtime {
         endpoint: EndpointName,
         payload: Option<PValues>,
         source: Option<&'a Request>,
+        opts: api::call::CallOpts,
     ) -> impl Future<Output = api::APIResult<Option<PValues>>> + 'static {
         let source = source.map(|s| s.inner.clone());
-        let fut = self.runtime.api().call(endpoint, payload, source);
+        let fut = self.runtime.api().call(endpoint, payload, source, opts);
 
         async move {
             let data = fut.await?;
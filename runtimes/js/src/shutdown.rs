@@ -0,0 +1,91 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::time::UNIX_EPOCH;
+
+use encore_runtime_core::shutdown;
+use napi::{Env, JsFunction, JsUnknown, NapiRaw};
+
+use crate::error::coerce_to_api_error;
+use crate::napi_util::{await_promise, PromiseHandler};
+use crate::threadsafe_function::{
+    ThreadSafeCallContext, ThreadsafeFunction, ThreadsafeFunctionCallMode,
+};
+
+struct HookCall {
+    /// The deadline for the hook, in milliseconds since the Unix epoch.
+    deadline_ms: f64,
+    tx: tokio::sync::mpsc::UnboundedSender<()>,
+}
+
+/// A shutdown hook implemented in JavaScript.
+pub struct JSShutdownHook {
+    hook: ThreadsafeFunction<HookCall>,
+}
+
+impl JSShutdownHook {
+    pub fn new(env: Env, hook: JsFunction) -> napi::Result<Self> {
+        let hook = ThreadsafeFunction::create(
+            env.raw(),
+            // SAFETY: `hook` is a valid JS function.
+            unsafe { hook.raw() },
+            0,
+            call_on_js_thread,
+        )?;
+        Ok(Self { hook })
+    }
+}
+
+impl shutdown::Hook for JSShutdownHook {
+    fn run(&self, progress: shutdown::Progress) -> Pin<Box<dyn Future<Output = ()> + Send + '_>> {
+        Box::pin(async move {
+            let deadline_ms = progress
+                .deadline
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as f64)
+                .unwrap_or_default();
+
+            let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+            self.hook.call(
+                HookCall { deadline_ms, tx },
+                ThreadsafeFunctionCallMode::Blocking,
+            );
+
+            // Errors are logged by the promise handler.
+            rx.recv().await;
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct HookPromiseHandler;
+
+impl PromiseHandler for HookPromiseHandler {
+    type Output = ();
+
+    fn resolve(&self, _: Env, _: Option<JsUnknown>) -> Self::Output {}
+
+    fn reject(&self, env: Env, val: JsUnknown) -> Self::Output {
+        let err = coerce_to_api_error(env, val).unwrap_or_else(|err| err);
+        ::log::error!("shutdown hook failed: {}", err);
+    }
+
+    fn error(&self, _: Env, err: napi::Error) -> Self::Output {
+        ::log::error!("shutdown hook failed: {}", err);
+    }
+}
+
+fn call_on_js_thread(ctx: ThreadSafeCallContext<HookCall>) -> napi::Result<()> {
+    let handler = HookPromiseHandler;
+    let deadline = ctx.env.create_double(ctx.value.deadline_ms)?;
+    match ctx.callback.unwrap().call(None, &[deadline]) {
+        Ok(result) => {
+            await_promise(ctx.env, result, ctx.value.tx.clone(), handler);
+            Ok(())
+        }
+        Err(err) => {
+            handler.error(ctx.env, err);
+            _ = ctx.value.tx.send(());
+            Ok(())
+        }
+    }
+}
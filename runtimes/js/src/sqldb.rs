@@ -11,7 +11,10 @@ use std::sync::{Arc, OnceLock};
 #[napi]
 pub struct SQLDatabase {
     db: Arc<dyn sqldb::Database>,
-    pool: OnceLock<Marc<napi::Result<sqldb::Pool>>>,
+    pool: Arc<OnceLock<Marc<napi::Result<sqldb::Pool>>>>,
+
+    /// Whether queries are sent to the database's read replicas.
+    replica: bool,
 }
 
 #[napi]
@@ -51,7 +54,19 @@ impl SQLDatabase {
     pub(crate) fn new(db: Arc<dyn sqldb::Database>) -> Self {
         Self {
             db,
-            pool: OnceLock::new(),
+            pool: Arc::new(OnceLock::new()),
+            replica: false,
+        }
+    }
+
+    /// Returns a handle to the database that sends queries to its read replicas,
+    /// falling back to the primary if no replica is available.
+    #[napi]
+    pub fn replica(&self) -> SQLDatabase {
+        Self {
+            db: self.db.clone(),
+            pool: self.pool.clone(),
+            replica: true,
         }
     }
 
@@ -70,11 +85,7 @@ impl SQLDatabase {
     ) -> napi::Result<Cursor> {
         let values: Vec<_> = args.values.lock().unwrap().drain(..).collect();
         let source = source.map(|s| s.inner.as_ref());
-        let stream = self
-            .pool()?
-            .query_raw(&query, values, source)
-            .await
-            .map_err(|e| napi::Error::new(napi::Status::GenericFailure, e.to_string()))?;
+        let stream = self.query_raw(&query, values, source).await?;
         Ok(Cursor {
             stream: tokio::sync::Mutex::new(stream),
         })
@@ -89,11 +100,7 @@ impl SQLDatabase {
     ) -> napi::Result<Option<Row>> {
         let values: Vec<_> = args.values.lock().unwrap().drain(..).collect();
         let source = source.map(|s| s.inner.as_ref());
-        let mut stream = self
-            .pool()?
-            .query_raw(&query, values, source)
-            .await
-            .map_err(|e| napi::Error::new(napi::Status::GenericFailure, e.to_string()))?;
+        let mut stream = self.query_raw(&query, values, source).await?;
         let row = stream
             .next()
             .await
@@ -102,6 +109,21 @@ impl SQLDatabase {
         Ok(row.map(|row| Row { row }))
     }
 
+    async fn query_raw(
+        &self,
+        query: &str,
+        values: Vec<sqldb::RowValue>,
+        source: Option<&encore_runtime_core::model::Request>,
+    ) -> napi::Result<sqldb::Cursor> {
+        let pool = self.pool()?;
+        let result = if self.replica {
+            pool.replica().query_raw(query, values, source).await
+        } else {
+            pool.query_raw(query, values, source).await
+        };
+        result.map_err(|e| napi::Error::new(napi::Status::GenericFailure, e.to_string()))
+    }
+
     fn pool(&self) -> napi::Result<&sqldb::Pool> {
         match self.pool_marc().as_ref() {
             Ok(pool) => Ok(pool),
@@ -175,6 +197,13 @@ impl SQLDatabase {
             inner: Arc::new(conn),
         })
     }
+
+    #[napi]
+    pub async fn begin(&self, source: Option<&Request>) -> napi::Result<SQLTransaction> {
+        let source = source.map(|s| s.inner.clone());
+        let tx = self.pool()?.begin(source).await.map_err(to_napi_err)?;
+        Ok(SQLTransaction { inner: tx })
+    }
 }
 
 #[napi]
@@ -189,6 +218,13 @@ impl SQLConn {
         self.inner.close().await
     }
 
+    #[napi]
+    pub async fn begin(&self, source: Option<&Request>) -> napi::Result<SQLTransaction> {
+        let source = source.map(|s| s.inner.clone());
+        let tx = self.inner.begin(source).await.map_err(to_napi_err)?;
+        Ok(SQLTransaction { inner: tx })
+    }
+
     #[napi]
     pub async fn query(
         &self,
@@ -231,6 +267,69 @@ impl SQLConn {
     }
 }
 
+/// A transaction, or a savepoint within one.
+/// It is rolled back when garbage-collected unless committed or rolled back.
+#[napi]
+pub struct SQLTransaction {
+    inner: sqldb::Transaction,
+}
+
+#[napi]
+impl SQLTransaction {
+    #[napi]
+    pub async fn commit(&self) -> napi::Result<()> {
+        self.inner.commit().await.map_err(to_napi_err)
+    }
+
+    #[napi]
+    pub async fn rollback(&self) -> napi::Result<()> {
+        self.inner.rollback().await.map_err(to_napi_err)
+    }
+
+    #[napi]
+    pub async fn savepoint(&self) -> napi::Result<SQLTransaction> {
+        let tx = self.inner.savepoint().await.map_err(to_napi_err)?;
+        Ok(SQLTransaction { inner: tx })
+    }
+
+    #[napi]
+    pub async fn query(
+        &self,
+        query: String,
+        args: &QueryArgs,
+        source: Option<&Request>,
+    ) -> napi::Result<Cursor> {
+        let values: Vec<_> = args.values.lock().unwrap().drain(..).collect();
+        let source = source.map(|s| s.inner.as_ref());
+        let stream = self
+            .inner
+            .query_raw(&query, values, source)
+            .await
+            .map_err(to_napi_err)?;
+        Ok(Cursor {
+            stream: tokio::sync::Mutex::new(stream),
+        })
+    }
+
+    #[napi]
+    pub async fn query_row(
+        &self,
+        query: String,
+        args: &QueryArgs,
+        source: Option<&Request>,
+    ) -> napi::Result<Option<Row>> {
+        let values: Vec<_> = args.values.lock().unwrap().drain(..).collect();
+        let source = source.map(|s| s.inner.as_ref());
+        let mut stream = self
+            .inner
+            .query_raw(&query, values, source)
+            .await
+            .map_err(to_napi_err)?;
+        let row = stream.next().await.transpose().map_err(to_napi_err)?;
+        Ok(row.map(|row| Row { row }))
+    }
+}
+
 fn to_napi_err<E: Display>(e: E) -> napi::Error {
     napi::Error::new(napi::Status::GenericFailure, e.to_string())
 }
@@ -4,6 +4,7 @@ use encore_supervisor::supervisor::Supervisor;
 use std::env;
 use std::net::IpAddr;
 use std::net::Ipv4Addr;
+use tokio::signal::unix::{signal, SignalKind};
 use tokio_util::sync::CancellationToken;
 
 #[tokio::main]
@@ -19,6 +20,27 @@ pub async fn main() {
         .and_then(|p| p.parse::<u16>().ok())
         .unwrap_or(8080);
 
+    // The migrate subcommand applies the database migrations and exits.
+    if env::args().nth(1).as_deref() == Some("migrate") {
+        let procs = config::create_migrate_process_configs(&supervisor_config, exposed_port)
+            .expect("Failed to create processes for migrations");
+        for proc in procs {
+            match proc.run_to_completion().await {
+                Ok(status) if status.success() => {}
+                Ok(status) => {
+                    log::error!("Database migrations failed: {}", status);
+                    std::process::exit(status.code().unwrap_or(1));
+                }
+                Err(e) => {
+                    log::error!("Failed to run database migrations: {:?}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        log::info!("Database migrations applied.");
+        return;
+    }
+
     if supervisor_config.hosted_gateways.is_empty() && supervisor_config.hosted_services.len() > 1 {
         panic!("Cannot run supervisor with no gateways and multiple services.");
     }
@@ -46,7 +68,7 @@ pub async fn main() {
                 vec![],
                 *service_port,
                 &service_ports,
-                &supervisor_config.binary_config,
+                &supervisor_config,
             )
             .expect("Failed to create process for service"),
         );
@@ -58,10 +80,10 @@ pub async fn main() {
         procs.push(
             config::create_process_config(
                 vec![],
-                supervisor_config.hosted_gateways,
+                supervisor_config.hosted_gateways.clone(),
                 port,
                 &service_ports,
-                &supervisor_config.binary_config,
+                &supervisor_config,
             )
             .expect("Failed to create process for gateways"),
         );
@@ -73,7 +95,17 @@ pub async fn main() {
 
     let sv = Supervisor::new(procs);
     let supervisor_token = root_token.child_token();
-    handles.push(tokio::spawn(sv.supervise(supervisor_token)));
+    let supervisor_handle = {
+        // If a process failure is escalated, shut everything down.
+        let root_token = root_token.clone();
+        tokio::spawn(async move {
+            let res = sv.supervise(supervisor_token).await;
+            if res.is_err() {
+                root_token.cancel();
+            }
+            res
+        })
+    };
 
     if use_proxy {
         let proxy = proxy::GatewayProxy::new(
@@ -86,13 +118,15 @@ pub async fn main() {
         handles.push(tokio::spawn(proxy_fut));
     }
 
-    // Spawn a task to listen for SIGINT or SIGTERM and cancel the root token
+    // Spawn a task to listen for SIGINT or SIGTERM and cancel the root token,
+    // which stops all processes in parallel using their stop signal.
     tokio::spawn(async move {
-        // Wait for SIGINT or SIGTERM
-        tokio::signal::ctrl_c()
-            .await
-            .expect("Failed to listen for ctrl+c");
-        log::info!("Received shutdown signal. Initiating graceful shutdown...");
+        let mut sigterm = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+        let mut sigint = signal(SignalKind::interrupt()).expect("Failed to listen for SIGINT");
+        tokio::select! {
+            _ = sigterm.recv() => log::info!("Received SIGTERM. Initiating graceful shutdown..."),
+            _ = sigint.recv() => log::info!("Received SIGINT. Initiating graceful shutdown..."),
+        }
         root_token.cancel();
     });
 
@@ -105,5 +139,20 @@ pub async fn main() {
             log::error!("Error while shutting down process: {:?}", e);
         }
     }
+
+    let failed = match supervisor_handle.await {
+        Ok(Ok(())) => false,
+        Ok(Err(e)) => {
+            log::error!("Supervisor failed: {:?}", e);
+            true
+        }
+        Err(e) => {
+            log::error!("Error while shutting down supervisor: {:?}", e);
+            true
+        }
+    };
     log::info!("All processes have exited. Shutting down.");
+    if failed {
+        std::process::exit(1);
+    }
 }
@@ -1,12 +1,11 @@
-use crate::supervisor::Process;
-use anyhow::{Context, Result};
+use crate::supervisor::{self, HealthProbe, Process, RestartMode, RestartPolicy, StopPolicy};
+use anyhow::{bail, Context, Result};
 use base64::Engine;
 use prost::Message;
 use runtime::v1 as runtimepb;
 use std::io::Read;
 use std::time::Duration;
 use std::{collections::HashMap, env, fs::File};
-use tokio_retry::strategy::ExponentialBackoff;
 
 pub mod runtime {
     pub mod v1 {
@@ -16,11 +15,12 @@ pub mod runtime {
 
 // loads the binary config and the runtime config and merges them into a supervisor config
 pub fn load_supervisor_config() -> Result<SupervisorConfig> {
-    let (services, gateways) = load_hosted_processes()?;
+    let hosted = load_hosted_processes()?;
     Ok(SupervisorConfig {
         binary_config: load_binary_config()?,
-        hosted_services: services,
-        hosted_gateways: gateways,
+        hosted_services: hosted.services,
+        hosted_gateways: hosted.gateways,
+        graceful_shutdown: hosted.graceful_shutdown,
     })
 }
 
@@ -43,9 +43,17 @@ fn load_binary_config() -> Result<BinaryConfig> {
     serde_json::from_str(&contents).map_err(|e| anyhow::anyhow!(e))
 }
 
+// The parts of the runtime config the supervisor needs to start and stop processes.
+struct HostedProcesses {
+    services: Vec<String>,
+    gateways: Vec<String>,
+    // The total time the processes are allowed to take to shut down gracefully.
+    graceful_shutdown: Option<Duration>,
+}
+
 // attempts to read the encore runtime config either as proto or json. Extracts and returns the
-// hosted services and gateways.
-fn load_hosted_processes() -> Result<(Vec<String>, Vec<String>)> {
+// hosted services and gateways, and the graceful shutdown timing.
+fn load_hosted_processes() -> Result<HostedProcesses> {
     let infra_cfg_path = env::var("ENCORE_INFRA_CONFIG_PATH");
     if let Ok(cfg) = infra_cfg_path {
         let mut file = File::open(cfg).context("Failed to open ENCORE_INFRA_CONFIG_PATH")?;
@@ -53,7 +61,14 @@ fn load_hosted_processes() -> Result<(Vec<String>, Vec<String>)> {
         file.read_to_string(&mut contents)?;
         let config: InfraConfig = serde_json::from_str(contents.as_str())
             .context("Failed to parse InfraConfig as JSON")?;
-        return Ok((config.hosted_services, config.hosted_gateways));
+        return Ok(HostedProcesses {
+            services: config.hosted_services,
+            gateways: config.hosted_gateways,
+            graceful_shutdown: config
+                .graceful_shutdown
+                .and_then(|gs| gs.total)
+                .map(|secs| Duration::from_secs(secs.max(0) as u64)),
+        });
     }
 
     // Read and decode the runtime config bytes from the environment variable
@@ -92,13 +107,13 @@ fn load_hosted_processes() -> Result<(Vec<String>, Vec<String>)> {
                 .resources
                 .context("Resources not found in Infrastructure")?
                 .gateways;
-            Ok((
-                deployment
+            Ok(HostedProcesses {
+                services: deployment
                     .hosted_services
                     .iter()
                     .map(|s| s.name.clone())
                     .collect(),
-                deployment
+                gateways: deployment
                     .hosted_gateways
                     .iter()
                     .map(|rid| {
@@ -110,16 +125,32 @@ fn load_hosted_processes() -> Result<(Vec<String>, Vec<String>)> {
                             .clone())
                     })
                     .collect::<Result<Vec<String>>>()?,
-            ))
+                graceful_shutdown: deployment
+                    .graceful_shutdown
+                    .and_then(|gs| gs.total)
+                    .and_then(|d| Duration::try_from(d).ok()),
+            })
         }
         Err(_) => {
             // If protobuf decoding fails, try JSON decoding
             let config: RuntimeConfig = serde_json::from_slice(&runtime_config)
                 .context("Failed to parse RuntimeConfig as JSON")?;
-            Ok((
-                config.hosted_services,
-                config.gateways.iter().map(|g| g.name.clone()).collect(),
-            ))
+            let graceful_shutdown = match config.graceful_shutdown.and_then(|gs| gs.total) {
+                Some(total) => Some(
+                    total
+                        .to_duration()
+                        .context("invalid graceful_shutdown.total in RuntimeConfig")?,
+                ),
+                None => config
+                    .shutdown_timeout
+                    .filter(|t| *t > 0)
+                    .map(Duration::from_nanos),
+            };
+            Ok(HostedProcesses {
+                services: config.hosted_services,
+                gateways: config.gateways.iter().map(|g| g.name.clone()).collect(),
+                graceful_shutdown,
+            })
         }
     }
 }
@@ -130,13 +161,11 @@ pub fn create_process_config(
     gateways: Vec<String>,
     port: u16,
     service_ports: &HashMap<String, u16>,
-    cfg: &BinaryConfig,
+    cfg: &SupervisorConfig,
 ) -> Result<Process> {
-    // Append all supervisor environment variables
-    let mut env = std::env::vars().collect::<HashMap<String, String>>();
-
     // Find a process config that contains all the services and gateways
     let binary_config = cfg
+        .binary_config
         .procs
         .iter()
         .find(|p| {
@@ -148,6 +177,21 @@ pub fn create_process_config(
             services, gateways
         ))?;
 
+    proc_process_config(binary_config, services, gateways, port, service_ports, cfg)
+}
+
+// Create a process config for running the given binary with a set of its services and gateways
+fn proc_process_config(
+    binary_config: &Proc,
+    services: Vec<String>,
+    gateways: Vec<String>,
+    port: u16,
+    service_ports: &HashMap<String, u16>,
+    cfg: &SupervisorConfig,
+) -> Result<Process> {
+    // Append all supervisor environment variables
+    let mut env = std::env::vars().collect::<HashMap<String, String>>();
+
     // Add proc-specific environment variables
     env.extend(binary_config.env.iter().map(|e| {
         let parts: Vec<&str> = e.splitn(2, '=').collect();
@@ -173,8 +217,6 @@ pub fn create_process_config(
         ),
     ]);
 
-    let policy = ExponentialBackoff::from_millis(100).max_delay(Duration::from_millis(1000));
-
     Ok(Process {
         name: binary_config.id.clone(),
         program: binary_config
@@ -185,10 +227,43 @@ pub fn create_process_config(
         args: binary_config.command[1..].to_vec(),
         env: env.into_iter().collect(),
         cwd: std::env::current_dir().context("Failed to get current directory")?,
-        restart_policy: Box::new(policy),
+        restart_policy: binary_config.restart.policy(),
+        health_probe: binary_config
+            .health_probe
+            .as_ref()
+            .map(|probe| probe.probe(port)),
+        stop_policy: binary_config.stop.policy(cfg.graceful_shutdown)?,
     })
 }
 
+// Create the process configs for applying the database migrations, one per binary,
+// since each binary only knows about the databases used by the services it implements.
+// Migrations that another binary has already applied are skipped.
+pub fn create_migrate_process_configs(cfg: &SupervisorConfig, port: u16) -> Result<Vec<Process>> {
+    if cfg.binary_config.procs.is_empty() {
+        bail!("no procs configured");
+    }
+    cfg.binary_config
+        .procs
+        .iter()
+        .map(|proc| {
+            let mut process = proc_process_config(
+                proc,
+                proc.services.clone(),
+                proc.gateways.clone(),
+                port,
+                &HashMap::new(),
+                cfg,
+            )?;
+            process.name = format!("{}-migrate", proc.id);
+            process
+                .env
+                .push(("ENCORE_SQLDB_MIGRATE_ONLY".to_string(), "1".to_string()));
+            Ok(process)
+        })
+        .collect()
+}
+
 // Supervisor config is the config bundled with the supervisor binary
 // It contains the list of available binaries and which services and gateways they implement
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -196,6 +271,9 @@ pub struct SupervisorConfig {
     pub binary_config: BinaryConfig,
     pub hosted_services: Vec<String>,
     pub hosted_gateways: Vec<String>,
+    // The total time processes are allowed to take to shut down gracefully,
+    // as configured in the runtime config.
+    pub graceful_shutdown: Option<Duration>,
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -204,6 +282,13 @@ pub struct InfraConfig {
     pub hosted_services: Vec<String>,
     #[serde(default)]
     pub hosted_gateways: Vec<String>,
+    pub graceful_shutdown: Option<InfraGracefulShutdown>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct InfraGracefulShutdown {
+    // The total shutdown time, in seconds.
+    pub total: Option<i32>,
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -218,6 +303,119 @@ pub struct Proc {
     env: Vec<String>,
     services: Vec<String>,
     gateways: Vec<String>,
+    #[serde(default)]
+    restart: RestartConfig,
+    #[serde(default)]
+    health_probe: Option<HealthProbeConfig>,
+    #[serde(default)]
+    stop: StopConfig,
+}
+
+// Stop config configures how a proc is stopped. By default it's sent SIGTERM,
+// and killed if it's still running once the graceful shutdown has had time to complete.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct StopConfig {
+    signal: Option<String>,
+    timeout_secs: Option<u64>,
+}
+
+impl StopConfig {
+    fn policy(&self, graceful_shutdown: Option<Duration>) -> Result<StopPolicy> {
+        let mut policy = match graceful_shutdown {
+            Some(total) => StopPolicy::for_graceful_shutdown(total),
+            None => StopPolicy::default(),
+        };
+        if let Some(name) = &self.signal {
+            policy.signal = supervisor::parse_signal(name)
+                .with_context(|| format!("unsupported stop signal {}", name))?;
+        }
+        if let Some(secs) = self.timeout_secs {
+            policy.timeout = Duration::from_secs(secs);
+        }
+        Ok(policy)
+    }
+}
+
+// Restart config configures when and how quickly a proc is restarted after it exits.
+// Unset fields use the defaults of the supervisor's restart policy.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct RestartConfig {
+    #[serde(default)]
+    policy: RestartMode,
+    initial_backoff_ms: Option<u64>,
+    max_backoff_ms: Option<u64>,
+    max_failures: Option<u32>,
+    failure_window_secs: Option<u64>,
+}
+
+impl RestartConfig {
+    fn policy(&self) -> RestartPolicy {
+        let defaults = RestartPolicy::default();
+        RestartPolicy {
+            mode: self.policy,
+            initial_backoff: self
+                .initial_backoff_ms
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.initial_backoff),
+            max_backoff: self
+                .max_backoff_ms
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.max_backoff),
+            max_failures: self.max_failures.unwrap_or(defaults.max_failures),
+            failure_window: self
+                .failure_window_secs
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.failure_window),
+        }
+    }
+}
+
+// Health probe config configures an HTTP probe of a proc, which is restarted
+// when it fails `failure_threshold` probes in a row.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct HealthProbeConfig {
+    #[serde(default = "default_probe_path")]
+    path: String,
+    #[serde(default = "default_probe_initial_delay_secs")]
+    initial_delay_secs: u64,
+    #[serde(default = "default_probe_interval_secs")]
+    interval_secs: u64,
+    #[serde(default = "default_probe_timeout_secs")]
+    timeout_secs: u64,
+    #[serde(default = "default_probe_failure_threshold")]
+    failure_threshold: u32,
+}
+
+fn default_probe_path() -> String {
+    "/__encore/livez".to_string()
+}
+
+fn default_probe_initial_delay_secs() -> u64 {
+    10
+}
+
+fn default_probe_interval_secs() -> u64 {
+    10
+}
+
+fn default_probe_timeout_secs() -> u64 {
+    2
+}
+
+fn default_probe_failure_threshold() -> u32 {
+    3
+}
+
+impl HealthProbeConfig {
+    fn probe(&self, port: u16) -> HealthProbe {
+        HealthProbe {
+            url: format!("http://127.0.0.1:{}{}", port, self.path),
+            initial_delay: Duration::from_secs(self.initial_delay_secs),
+            interval: Duration::from_secs(self.interval_secs),
+            timeout: Duration::from_secs(self.timeout_secs),
+            failure_threshold: self.failure_threshold.max(1),
+        }
+    }
 }
 
 // Process config is the config for a given process
@@ -239,9 +437,72 @@ struct RuntimeConfig {
     pub hosted_services: Vec<String>,
     #[serde(default)]
     pub gateways: Vec<GatewayConfig>,
+    // The deprecated total shutdown time, in nanoseconds.
+    pub shutdown_timeout: Option<u64>,
+    pub graceful_shutdown: Option<GracefulShutdownConfig>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct GracefulShutdownConfig {
+    // The total shutdown time.
+    pub total: Option<JsonDuration>,
+}
+
+// A duration in the protojson format, such as "1.5s".
+// Plain numbers are read as nanoseconds, as written by older configs.
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+enum JsonDuration {
+    Nanos(u64),
+    Proto(String),
+}
+
+impl JsonDuration {
+    fn to_duration(&self) -> Result<Duration> {
+        match self {
+            JsonDuration::Nanos(nanos) => Ok(Duration::from_nanos(*nanos)),
+            JsonDuration::Proto(s) => {
+                let secs = s
+                    .strip_suffix('s')
+                    .with_context(|| format!("duration {:?} must end with 's'", s))?;
+                let secs: f64 = secs
+                    .parse()
+                    .with_context(|| format!("invalid duration {:?}", s))?;
+                Duration::try_from_secs_f64(secs)
+                    .with_context(|| format!("invalid duration {:?}", s))
+            }
+        }
+    }
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
 struct GatewayConfig {
     pub name: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_duration() {
+        let parse = |json: &str| {
+            serde_json::from_str::<GracefulShutdownConfig>(json)
+                .unwrap()
+                .total
+                .unwrap()
+                .to_duration()
+        };
+        assert_eq!(parse(r#"{"total": "5s"}"#).unwrap(), Duration::from_secs(5));
+        assert_eq!(
+            parse(r#"{"total": "1.500s"}"#).unwrap(),
+            Duration::from_millis(1500)
+        );
+        assert_eq!(
+            parse(r#"{"total": 2000000000}"#).unwrap(),
+            Duration::from_secs(2)
+        );
+        assert!(parse(r#"{"total": "5"}"#).is_err());
+        assert!(parse(r#"{"total": "-5s"}"#).is_err());
+    }
+}
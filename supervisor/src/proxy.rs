@@ -90,12 +90,19 @@ impl GatewayProxy {
         }
     }
 
-    // concurrently calls /__encore/healthz for all services. Returns "unhealthy" if any of them
-    // does not return "ok".
-    pub async fn health_check(&self) -> Result<HealthzResponse> {
+    // concurrently calls the given health endpoint (such as /__encore/healthz) for all services.
+    // Returns "unhealthy" if any of them does not return "ok".
+    // The authorization header, if any, is forwarded so that authorized
+    // requests get the detailed readiness check results.
+    pub async fn health_check(
+        &self,
+        path: &str,
+        authorization: Option<String>,
+    ) -> Result<HealthzResponse> {
         let handles = self.services.clone().into_iter().map(|(svc, port)| {
             let client = self.client.clone();
-            let url = format!("http://127.0.0.1:{}/__encore/healthz", port);
+            let url = format!("http://127.0.0.1:{}{}", port, path);
+            let authorization = authorization.clone();
             tokio::spawn(async move {
                 let err_resp = || HealthzResponse {
                     code: "unhealthy".to_string(),
@@ -113,25 +120,27 @@ impl GatewayProxy {
                     },
                 };
 
-                match client
-                    .get(url.as_str())
-                    .send()
-                    .await
-                    .context("failed to get url")
-                    .and_then(|r| {
-                        if r.status().is_success() {
-                            Ok(r)
-                        } else {
-                            Err(anyhow::anyhow!("Unsuccessful request"))
-                        }
-                    }) {
+                let mut req = client.get(url.as_str());
+                if let Some(authorization) = authorization {
+                    req = req.header("authorization", authorization);
+                }
+
+                // Unsuccessful responses, such as failed readiness checks,
+                // still report the results of the checks in their body.
+                match req.send().await.context("failed to get url") {
                     Ok(res) => {
+                        let success = res.status().is_success();
                         match res
                             .json::<HealthzResponse>()
                             .await
                             .context("Failed to parse response body")
                         {
-                            Ok(res) => res,
+                            Ok(mut res) => {
+                                if !success && res.code == "ok" {
+                                    res.code = "unhealthy".to_string();
+                                }
+                                res
+                            }
                             Err(_) => err_resp(),
                         }
                     }
@@ -182,9 +191,19 @@ impl ProxyHttp for GatewayProxy {
     where
         Self::CTX: Send + Sync,
     {
-        if session.req_header().uri.path() == "/__encore/healthz" {
+        let path = session.req_header().uri.path().to_string();
+        if matches!(
+            path.as_str(),
+            "/__encore/healthz" | "/__encore/livez" | "/__encore/readyz"
+        ) {
+            let authorization = session
+                .req_header()
+                .headers
+                .get("authorization")
+                .and_then(|v| v.to_str().ok())
+                .map(String::from);
             let healthz_resp = self
-                .health_check()
+                .health_check(&path, authorization)
                 .await
                 .or_err(ErrorType::HTTPStatus(503), "failed to run health check")?;
             let healthz_bytes: Vec<u8> = serde_json::to_vec(&healthz_resp)
@@ -3,9 +3,21 @@
 //! The supervisor ensures all services (and gateways) hosted
 //! by the Encore deployment are started and running.
 
-use std::{ffi::OsStr, io, os::unix::ffi::OsStrExt, path::PathBuf, time::Duration};
+use std::{
+    collections::VecDeque,
+    ffi::OsStr,
+    fmt::Display,
+    io,
+    os::unix::{ffi::OsStrExt, process::ExitStatusExt},
+    path::PathBuf,
+    process::ExitStatus,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 use tokio::process::{Child, Command};
-use tokio_retry::Retry;
 use tokio_util::{sync::CancellationToken, task::TaskTracker};
 
 /// The supervisor.
@@ -23,20 +35,31 @@ impl Supervisor {
     /// Runs the supervisor.
     ///
     /// It returns when all processes have exited, due to either
-    /// crashing or cancellation.
-    pub async fn supervise(self, token: CancellationToken) {
+    /// cancellation or a process failure being escalated. When a failure
+    /// is escalated all other processes are stopped, and an error is returned.
+    pub async fn supervise(self, token: CancellationToken) -> anyhow::Result<()> {
         let tracker = TaskTracker::new();
+        let escalated = Arc::new(AtomicBool::new(false));
 
         for p in self.procs {
             let tok = token.clone();
+            let escalated = escalated.clone();
             tracker.spawn(async move {
-                let _ = p.run(tok.clone()).await;
-                tok.cancel();
+                if let Err(err) = p.run(tok.clone()).await {
+                    log::error!(proc = p.name.as_str(); "{}, stopping all processes", err);
+                    escalated.store(true, Ordering::Relaxed);
+                    tok.cancel();
+                }
             });
         }
 
         tracker.close();
         tracker.wait().await;
+
+        if escalated.load(Ordering::Relaxed) {
+            anyhow::bail!("one or more processes failed");
+        }
+        Ok(())
     }
 }
 
@@ -59,42 +82,159 @@ pub struct Process {
     pub env: Vec<(String, String)>,
 
     /// How to restart the process if it exits.
-    pub restart_policy: Box<dyn RestartPolicy>,
+    pub restart_policy: RestartPolicy,
+
+    /// How to check that the process is healthy, if at all.
+    pub health_probe: Option<HealthProbe>,
+
+    /// How to stop the process gracefully.
+    pub stop_policy: StopPolicy,
+}
+
+/// How a single run of a process ended.
+enum Exit {
+    /// The process exited by itself.
+    Exited(ExitStatus),
+    /// The process was killed after failing its health probe.
+    Unhealthy,
+    /// The process was stopped due to cancellation.
+    Cancelled,
 }
 
+/// A process failure that can't be handled by restarting the process.
+#[derive(Debug)]
+pub enum Escalation {
+    /// The process failed and the restart policy doesn't restart it.
+    NotRestarted,
+    /// The process failed too many times within the failure window.
+    CrashLoop { failures: usize, window: Duration },
+}
+
+impl Display for Escalation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Escalation::NotRestarted => write!(f, "process failed and is not restarted"),
+            Escalation::CrashLoop { failures, window } => write!(
+                f,
+                "process is crash looping: failed {} times within {:?}",
+                failures, window
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Escalation {}
+
 impl Process {
     /// Runs the process, waiting for it to exit.
     ///
     /// It restarts the process on exit according to the restart policy,
-    /// unless the cancellation token is canceled.
-    async fn run(&self, token: CancellationToken) -> io::Result<()> {
+    /// unless the cancellation token is canceled. It returns an error
+    /// if the process fails in a way that restarting it doesn't handle.
+    async fn run(&self, token: CancellationToken) -> Result<(), Escalation> {
         let name = self.name.as_str();
-        Retry::spawn(self.restart_policy.retries(), move || {
-            let token = token.clone();
+        let policy = &self.restart_policy;
+
+        // When recent failures happened, used for backoff and crash loop detection.
+        let mut failures: VecDeque<Instant> = VecDeque::new();
+
+        loop {
+            if token.is_cancelled() {
+                return Ok(());
+            }
 
             log::info!(proc = name; "starting process");
+            let failed = match self.run_once(&token).await {
+                Ok(Exit::Cancelled) => {
+                    log::info!(proc = name; "process stopped");
+                    return Ok(());
+                }
+                Ok(Exit::Exited(status)) => {
+                    log_exit(name, status);
+                    !status.success()
+                }
+                Ok(Exit::Unhealthy) => true,
+                Err(err) => {
+                    log::error!(proc = name; "unable to run process: {}", err);
+                    true
+                }
+            };
 
-            let res = self.run_once(token.clone());
-            async move {
-                // Wait for the process to complete.
-                let _ = res.await;
-                log::info!(proc = name; "process exited");
-                if token.is_cancelled() {
-                    Ok(())
+            if !policy.should_restart(failed) {
+                return if failed {
+                    Err(Escalation::NotRestarted)
                 } else {
-                    Err(io::Error::new(io::ErrorKind::Other, "exited"))
-                }
+                    log::info!(proc = name; "process completed, not restarting it");
+                    Ok(())
+                };
+            }
+
+            let now = Instant::now();
+            if failed {
+                failures.push_back(now);
+            }
+            while failures
+                .front()
+                .is_some_and(|t| now.duration_since(*t) > policy.failure_window)
+            {
+                failures.pop_front();
+            }
+            if failures.len() > policy.max_failures as usize {
+                return Err(Escalation::CrashLoop {
+                    failures: failures.len(),
+                    window: policy.failure_window,
+                });
+            }
+
+            let delay = policy.backoff(failures.len());
+            log::info!(proc = name, delay_ms = delay.as_millis() as u64; "restarting process");
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {},
+                _ = token.cancelled() => return Ok(()),
             }
-        })
-        .await
+        }
     }
 
-    async fn run_once(&self, token: CancellationToken) -> io::Result<()> {
+    async fn run_once(&self, token: &CancellationToken) -> io::Result<Exit> {
         // If the token is already cancelled, do nothing.
         if token.is_cancelled() {
-            return Err(io::Error::new(io::ErrorKind::Other, "canceled"));
+            return Ok(Exit::Cancelled);
+        }
+
+        let mut cmd = self.command().spawn()?;
+
+        let unhealthy = async {
+            match &self.health_probe {
+                Some(probe) => probe.wait_unhealthy(&self.name).await,
+                None => std::future::pending().await,
+            }
+        };
+
+        // Wait for the process to exit, the token to be cancelled,
+        // or the process to become unhealthy, whichever happens first.
+        tokio::select! {
+            status = cmd.wait() => status.map(Exit::Exited),
+
+            _ = token.cancelled() => {
+                kill_gracefully(&mut cmd, &self.stop_policy).await.map(|_| Exit::Cancelled)
+            },
+
+            _ = unhealthy => {
+                log::warn!(proc = self.name.as_str(); "process failed its health probe, restarting it");
+                kill_gracefully(&mut cmd, &self.stop_policy).await.map(|_| Exit::Unhealthy)
+            },
         }
+    }
+}
 
+impl Process {
+    /// Runs the process once, without restarting it, and waits for it to exit.
+    pub async fn run_to_completion(&self) -> io::Result<ExitStatus> {
+        log::info!(proc = self.name.as_str(); "starting process");
+        self.command().spawn()?.wait().await
+    }
+
+    fn command(&self) -> Command {
         let envs = self.env.iter().map(|(k, v)| {
             (
                 OsStr::from_bytes(k.as_bytes()),
@@ -102,52 +242,260 @@ impl Process {
             )
         });
 
-        let mut cmd = Command::new(&self.program)
-            .args(&self.args)
+        let mut cmd = Command::new(&self.program);
+        cmd.args(&self.args)
             .env_clear()
             .envs(envs)
-            .current_dir(&self.cwd)
-            .spawn()?;
+            .current_dir(&self.cwd);
+        cmd
+    }
+}
 
-        // Wait for the process to exit, or the token to be cancelled,
-        // whichever happens first.
-        tokio::select! {
-            status = cmd.wait() => status.map(|_| ()),
+/// Logs how a process exited, including the signal that ended it, if any.
+fn log_exit(name: &str, status: ExitStatus) {
+    if let Some(code) = status.code() {
+        if status.success() {
+            log::info!(proc = name, code = code; "process exited");
+        } else {
+            log::warn!(proc = name, code = code; "process exited with a failure");
+        }
+    } else if let Some(sig) = status.signal() {
+        log::warn!(
+            proc = name,
+            signal = sig,
+            signal_name = signal_name(sig),
+            core_dumped = status.core_dumped();
+            "process terminated by signal"
+        );
+    } else {
+        log::warn!(proc = name; "process exited: {}", status);
+    }
+}
 
-            _ = token.cancelled() => {
-                kill_gracefully(&mut cmd).await
-            },
+const SIGNALS: &[(i32, &str)] = &[
+    (libc::SIGHUP, "SIGHUP"),
+    (libc::SIGINT, "SIGINT"),
+    (libc::SIGQUIT, "SIGQUIT"),
+    (libc::SIGILL, "SIGILL"),
+    (libc::SIGTRAP, "SIGTRAP"),
+    (libc::SIGABRT, "SIGABRT"),
+    (libc::SIGBUS, "SIGBUS"),
+    (libc::SIGFPE, "SIGFPE"),
+    (libc::SIGKILL, "SIGKILL"),
+    (libc::SIGUSR1, "SIGUSR1"),
+    (libc::SIGSEGV, "SIGSEGV"),
+    (libc::SIGUSR2, "SIGUSR2"),
+    (libc::SIGPIPE, "SIGPIPE"),
+    (libc::SIGALRM, "SIGALRM"),
+    (libc::SIGTERM, "SIGTERM"),
+];
+
+fn signal_name(sig: i32) -> &'static str {
+    SIGNALS
+        .iter()
+        .find(|(num, _)| *num == sig)
+        .map(|(_, name)| *name)
+        .unwrap_or("unknown")
+}
+
+/// Parses a signal name such as "SIGTERM" or "TERM".
+pub fn parse_signal(name: &str) -> Option<i32> {
+    let name = name.to_ascii_uppercase();
+    let name = name.strip_prefix("SIG").unwrap_or(&name);
+    SIGNALS
+        .iter()
+        .find(|(_, n)| n[3..] == *name)
+        .map(|(num, _)| *num)
+}
+
+/// The default total time processes take to shut down gracefully,
+/// matching the runtime's default.
+const DEFAULT_GRACEFUL_SHUTDOWN: Duration = Duration::from_secs(5);
+
+/// The extra time a process is given to exit after its graceful shutdown
+/// deadline, before it's killed.
+const STOP_MARGIN: Duration = Duration::from_secs(2);
+
+/// How to stop a process gracefully.
+///
+/// The process is sent `signal`, and killed if it's still running after `timeout`.
+#[derive(Debug, Clone)]
+pub struct StopPolicy {
+    /// The signal that asks the process to shut down.
+    pub signal: i32,
+
+    /// How long to wait for the process to exit before killing it.
+    pub timeout: Duration,
+}
+
+impl Default for StopPolicy {
+    fn default() -> Self {
+        Self::for_graceful_shutdown(DEFAULT_GRACEFUL_SHUTDOWN)
+    }
+}
+
+impl StopPolicy {
+    /// A stop policy that gives the process time to complete a graceful
+    /// shutdown taking at most `total`.
+    pub fn for_graceful_shutdown(total: Duration) -> Self {
+        Self {
+            signal: libc::SIGTERM,
+            timeout: total + STOP_MARGIN,
+        }
+    }
+}
+
+/// When a process is restarted after it exits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartMode {
+    /// Restart the process whenever it exits.
+    #[default]
+    Always,
+    /// Restart the process only if it fails.
+    OnFailure,
+    /// Never restart the process.
+    Never,
+}
+
+/// How to restart a process after it exits.
+///
+/// Restarts are delayed by an exponential backoff based on the number of recent failures.
+/// If the process fails more than `max_failures` times within `failure_window`
+/// it is considered to be crash looping, and the failure is escalated by
+/// stopping all supervised processes.
+#[derive(Debug, Clone)]
+pub struct RestartPolicy {
+    pub mode: RestartMode,
+
+    /// The delay before restarting the process after the first failure.
+    pub initial_backoff: Duration,
+
+    /// The maximum delay before restarting the process.
+    pub max_backoff: Duration,
+
+    /// The number of failures within the failure window that are tolerated.
+    pub max_failures: u32,
+
+    /// How long failures are remembered.
+    pub failure_window: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            mode: RestartMode::Always,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            max_failures: 5,
+            failure_window: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// Reports whether a process that exited should be restarted.
+    pub fn should_restart(&self, failed: bool) -> bool {
+        match self.mode {
+            RestartMode::Always => true,
+            RestartMode::OnFailure => failed,
+            RestartMode::Never => false,
         }
     }
+
+    /// The delay before restarting a process, given the number of recent failures.
+    pub fn backoff(&self, failures: usize) -> Duration {
+        if failures == 0 {
+            return self.initial_backoff.min(self.max_backoff);
+        }
+        let exp = (failures - 1).min(31) as u32;
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(exp))
+            .min(self.max_backoff)
+    }
+}
+
+/// An HTTP health probe, used to restart processes that stop responding.
+#[derive(Debug, Clone)]
+pub struct HealthProbe {
+    /// The URL to probe. The process is healthy if it responds with a 2xx status.
+    pub url: String,
+
+    /// How long to wait after starting the process before probing it.
+    pub initial_delay: Duration,
+
+    /// How often to probe the process.
+    pub interval: Duration,
+
+    /// How long to wait for a response to a probe.
+    pub timeout: Duration,
+
+    /// The number of consecutive failed probes after which the process is unhealthy.
+    pub failure_threshold: u32,
+}
+
+impl HealthProbe {
+    /// Probes the process until it's unhealthy.
+    async fn wait_unhealthy(&self, name: &str) {
+        let client = reqwest::Client::new();
+        tokio::time::sleep(self.initial_delay).await;
+
+        let mut consecutive_failures = 0;
+        loop {
+            match self.probe(&client).await {
+                Ok(()) => consecutive_failures = 0,
+                Err(err) => {
+                    consecutive_failures += 1;
+                    log::warn!(
+                        proc = name,
+                        failures = consecutive_failures;
+                        "health probe failed: {:#}",
+                        err
+                    );
+                    if consecutive_failures >= self.failure_threshold {
+                        return;
+                    }
+                }
+            }
+            tokio::time::sleep(self.interval).await;
+        }
+    }
+
+    async fn probe(&self, client: &reqwest::Client) -> anyhow::Result<()> {
+        let resp = client.get(&self.url).timeout(self.timeout).send().await?;
+        if !resp.status().is_success() {
+            anyhow::bail!("unhealthy status {}", resp.status());
+        }
+        Ok(())
+    }
 }
 
 /// Attempts to kill a child process gracefully.
-async fn kill_gracefully(child: &mut Child) -> io::Result<()> {
-    do_kill_gracefully(child).await
+async fn kill_gracefully(child: &mut Child, policy: &StopPolicy) -> io::Result<()> {
+    do_kill_gracefully(child, policy).await
 }
 
 #[cfg(target_os = "windows")]
-async fn do_kill_gracefully(child: &mut Child) -> io::Result<()> {
+async fn do_kill_gracefully(child: &mut Child, _policy: &StopPolicy) -> io::Result<()> {
     child.kill().await
 }
 
 #[cfg(not(target_os = "windows"))]
-async fn do_kill_gracefully(child: &mut Child) -> io::Result<()> {
-    use std::time::Duration;
+async fn do_kill_gracefully(child: &mut Child, policy: &StopPolicy) -> io::Result<()> {
     if let Some(pid) = child.id() {
-        for (sig, wait) in [
-            (libc::SIGINT, Duration::from_secs(2)),
-            (libc::SIGTERM, Duration::from_secs(2)),
-        ] {
-            unsafe {
-                libc::kill(pid as i32, sig);
-            }
+        unsafe {
+            libc::kill(pid as i32, policy.signal);
+        }
 
-            tokio::select! {
-                _ = child.wait() => return Ok(()),
-                _ = tokio::time::sleep(wait) => {
-                    // Still running, escalate.
-                }
+        tokio::select! {
+            _ = child.wait() => return Ok(()),
+            _ = tokio::time::sleep(policy.timeout) => {
+                // Still running, escalate.
+                log::warn!(
+                    proc_pid = pid,
+                    timeout_ms = policy.timeout.as_millis() as u64;
+                    "process did not exit in time, killing it"
+                );
             }
         }
     }
@@ -156,15 +504,31 @@ async fn do_kill_gracefully(child: &mut Child) -> io::Result<()> {
     child.kill().await
 }
 
-pub trait RestartPolicy: Send + Sync + 'static {
-    fn retries(&self) -> Box<dyn Iterator<Item = Duration> + Send + 'static>;
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restart_policy() {
+        let policy = RestartPolicy {
+            mode: RestartMode::OnFailure,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+            ..Default::default()
+        };
+        assert!(policy.should_restart(true));
+        assert!(!policy.should_restart(false));
+
+        let backoffs: Vec<_> = (0..6).map(|n| policy.backoff(n).as_millis()).collect();
+        assert_eq!(backoffs, vec![100, 100, 200, 400, 500, 500]);
+        assert_eq!(policy.backoff(1000), Duration::from_millis(500));
+    }
 
-impl<T> RestartPolicy for T
-where
-    T: Iterator<Item = Duration> + Clone + Send + Sync + 'static,
-{
-    fn retries(&self) -> Box<dyn Iterator<Item = Duration> + Send + 'static> {
-        Box::new(self.clone())
+    #[test]
+    fn test_parse_signal() {
+        assert_eq!(parse_signal("SIGTERM"), Some(libc::SIGTERM));
+        assert_eq!(parse_signal("int"), Some(libc::SIGINT));
+        assert_eq!(parse_signal("SIGBOGUS"), None);
+        assert_eq!(signal_name(libc::SIGKILL), "SIGKILL");
     }
 }
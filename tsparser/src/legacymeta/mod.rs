@@ -10,7 +10,9 @@ use crate::parser::parser::{ParseContext, ParseResult, Service};
 use crate::parser::resourceparser::bind::{Bind, BindKind};
 use crate::parser::resources::apis::{authhandler, gateway};
 use crate::parser::resources::infra::cron::CronJobSchedule;
-use crate::parser::resources::infra::{cron, objects, pubsub_subscription, pubsub_topic, sqldb};
+use crate::parser::resources::infra::{
+    cache, cron, objects, pubsub_subscription, pubsub_topic, sqldb,
+};
 use crate::parser::resources::Resource;
 use crate::parser::types::validation;
 use crate::parser::types::{Object, ObjectId};
@@ -102,12 +104,16 @@ impl MetaBuilder<'_> {
 
             // Depends on auth handler objects
             Gateway((&'a Bind, &'a gateway::Gateway)),
+
+            // Depends on cache cluster objects
+            CacheKeyspace((&'a Bind, &'a cache::Keyspace)),
         }
 
         let mut dependent: Vec<Dependent> = Vec::new();
         let mut topic_idx: HashMap<ObjectId, usize> = HashMap::new();
         let mut endpoint_idx: HashMap<ObjectId, (usize, usize)> = HashMap::new();
         let mut topic_by_name: HashMap<String, usize> = HashMap::new();
+        let mut cache_cluster_idx: HashMap<ObjectId, usize> = HashMap::new();
 
         let mut auth_handlers: HashMap<ObjectId, Rc<authhandler::AuthHandler>> = HashMap::new();
 
@@ -194,6 +200,7 @@ impl MetaBuilder<'_> {
                         loc: Some(loc_from_range(self.app_root, &self.pc.file_set, ep.range)?),
                         allow_unauthenticated: !ep.require_auth,
                         body_limit: ep.body_limit,
+                        timeout: ep.timeout.map(|t| t.as_nanos() as i64),
                         expose: {
                             let mut map = HashMap::new();
                             if ep.expose {
@@ -251,6 +258,19 @@ impl MetaBuilder<'_> {
                     topic_by_name.insert(topic.name.clone(), idx);
                 }
 
+                Resource::CacheCluster(cluster) => {
+                    let idx = self.data.cache_clusters.len();
+                    self.data.cache_clusters.push(v1::CacheCluster {
+                        name: cluster.name.clone(),
+                        doc: cluster.doc.clone().unwrap_or_default(),
+                        keyspaces: vec![], // filled in later
+                        eviction_policy: cluster.eviction_policy.clone(),
+                    });
+                    if let Some(obj) = &b.object {
+                        cache_cluster_idx.insert(obj.id, idx);
+                    }
+                }
+
                 Resource::Secret(secret) => {
                     let service = self.service_for_range(&secret.range).ok_or(
                         secret
@@ -271,7 +291,6 @@ impl MetaBuilder<'_> {
                 }
 
                 // Dependent resources
-                // TODO: Include Cache Keyspace here too.
                 Resource::PubSubSubscription(sub) => {
                     dependent.push(Dependent::PubSubSubscription((b, sub)));
                 }
@@ -281,6 +300,9 @@ impl MetaBuilder<'_> {
                 Resource::Gateway(gw) => {
                     dependent.push(Dependent::Gateway((b, gw)));
                 }
+                Resource::CacheKeyspace(ks) => {
+                    dependent.push(Dependent::CacheKeyspace((b, ks)));
+                }
             }
         }
 
@@ -293,12 +315,27 @@ impl MetaBuilder<'_> {
         for r in &dependent {
             match r {
                 Dependent::PubSubSubscription((b, sub)) => {
-                    let topic_idx = topic_idx
+                    let sub_topic_idx = topic_idx
                         .get(&sub.topic.id)
                         .ok_or_else(|| sub.topic.parse_err("topic not found"))?
                         .to_owned();
-                    let result = self.pubsub_subscription(b, sub)?;
-                    let topic = &mut self.data.pubsub_topics[topic_idx];
+                    let dead_letter_topic = match &sub.dead_letter_topic {
+                        None => None,
+                        Some(dlq) => {
+                            let idx = topic_idx
+                                .get(&dlq.id)
+                                .ok_or_else(|| dlq.parse_err("dead letter topic not found"))?
+                                .to_owned();
+                            if idx == sub_topic_idx {
+                                return Err(dlq.parse_err(
+                                    "a subscription cannot use its own topic as dead letter topic",
+                                ));
+                            }
+                            Some(self.data.pubsub_topics[idx].name.clone())
+                        }
+                    };
+                    let result = self.pubsub_subscription(b, sub, dead_letter_topic)?;
+                    let topic = &mut self.data.pubsub_topics[sub_topic_idx];
                     topic.subscriptions.push(result);
                 }
 
@@ -327,6 +364,16 @@ impl MetaBuilder<'_> {
                     self.data.cron_jobs.push(result);
                 }
 
+                Dependent::CacheKeyspace((_b, ks)) => {
+                    let cluster_idx = cache_cluster_idx
+                        .get(&ks.cluster.id)
+                        .ok_or_else(|| ks.cluster.parse_err("cache cluster not found"))?
+                        .to_owned();
+                    let result = self.cache_keyspace(ks)?;
+                    let cluster = &mut self.data.cache_clusters[cluster_idx];
+                    cluster.keyspaces.push(result);
+                }
+
                 Dependent::Gateway((_b, gw)) => {
                     let auth_handler = if let Some(auth_handler) = &gw.auth_handler {
                         let Some(ah) = auth_handlers.get(&auth_handler.id) else {
@@ -583,6 +630,34 @@ impl MetaBuilder<'_> {
         Ok(self.data)
     }
 
+    fn cache_keyspace(&mut self, ks: &cache::Keyspace) -> PResult<v1::cache_cluster::Keyspace> {
+        let service = self.service_for_range(&ks.range).ok_or(
+            ks.range
+                .parse_err("cache keyspaces must be defined within a service"),
+        )?;
+        let service = service.name.clone();
+
+        let key_type = self.schema.typ(&ks.key_type).map_err(|e| {
+            ks.key_type
+                .parse_err(format!("could not resolve key type: {}", e))
+        })?;
+        let value_type = self.schema.typ(&ks.value_type).map_err(|e| {
+            ks.value_type
+                .parse_err(format!("could not resolve value type: {}", e))
+        })?;
+
+        let mut path_pattern = ks.path.to_meta();
+        path_pattern.r#type = v1::path::Type::CacheKeyspace as i32;
+
+        Ok(v1::cache_cluster::Keyspace {
+            key_type: Some(key_type),
+            value_type: Some(value_type),
+            service,
+            doc: ks.doc.clone().unwrap_or_default(),
+            path_pattern: Some(path_pattern),
+        })
+    }
+
     fn pubsub_topic(&mut self, topic: &pubsub_topic::Topic) -> PResult<v1::PubSubTopic> {
         use pubsub_topic::DeliveryGuarantee;
         let message_type = self.schema.typ(&topic.message_type).map_err(|e| {
@@ -608,6 +683,7 @@ impl MetaBuilder<'_> {
         &self,
         bind: &Bind,
         sub: &pubsub_subscription::Subscription,
+        dead_letter_topic: Option<String>,
     ) -> PResult<v1::pub_sub_topic::Subscription> {
         let service_name = self
             .service_for_range(&bind.range.unwrap_or(sub.range))
@@ -629,6 +705,7 @@ impl MetaBuilder<'_> {
                 max_backoff: sub.config.max_retry_backoff.as_nanos() as i64,
                 max_retries: sub.config.max_retries as i64,
             }),
+            dead_letter_topic,
         })
     }
 
@@ -43,6 +43,10 @@ pub struct Endpoint {
     /// None means no limit.
     pub body_limit: Option<u64>,
 
+    /// The maximum time a request may take.
+    /// None means no timeout.
+    pub timeout: Option<std::time::Duration>,
+
     pub streaming_request: bool,
     pub streaming_response: bool,
     pub static_assets: Option<StaticAssets>,
@@ -371,6 +375,14 @@ pub const ENDPOINT_PARSER: ResourceParser = ResourceParser {
                 None => Some(2 * 1024 * 1024),
             };
 
+            if let Some(timeout) = &cfg.timeout {
+                if streaming_request || streaming_response {
+                    timeout.err("timeout is not supported for streaming endpoints");
+                } else if timeout.is_zero() {
+                    timeout.err("timeout must be positive");
+                }
+            }
+
             let resource = Resource::APIEndpoint(Lrc::new(Endpoint {
                 range: r.range,
                 name: r.endpoint_name,
@@ -384,6 +396,7 @@ pub const ENDPOINT_PARSER: ResourceParser = ResourceParser {
                 streaming_response,
                 static_assets,
                 body_limit,
+                timeout: cfg.timeout.map(|t| t.take()),
                 encoding,
                 tags: cfg.tags.unwrap_or_default(),
             }));
@@ -481,6 +494,7 @@ struct EndpointConfig {
     expose: Option<bool>,
     auth: Option<bool>,
     bodyLimit: Option<Nullable<u64>>,
+    timeout: Option<Sp<std::time::Duration>>,
     tags: Option<Vec<String>>,
 
     // For static assets.
@@ -0,0 +1,468 @@
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use litparser_derive::LitParser;
+use swc_common::sync::Lrc;
+use swc_common::{Span, Spanned};
+use swc_ecma_ast as ast;
+
+use litparser::{report_and_continue, LitParser, ParseResult, Sp, ToParseErr};
+
+use crate::parser::module_loader::Module;
+use crate::parser::resourceparser::bind::{BindData, BindKind, ResourceOrPath};
+use crate::parser::resourceparser::paths::PkgPath;
+use crate::parser::resourceparser::resource_parser::ResourceParser;
+use crate::parser::resources::parseutil::{
+    extract_bind_name, extract_type_param, iter_references, NamedClassResourceOptionalConfig,
+    ReferenceParser, TrackedNames,
+};
+use crate::parser::resources::Resource;
+use crate::parser::respath::{self, Path, Segment, ValueType};
+use crate::parser::types::{Basic, FieldName, Object, Type};
+use crate::parser::Range;
+use crate::span_err::ErrReporter;
+
+#[derive(Debug, Clone)]
+pub struct CacheCluster {
+    pub name: String,
+    pub doc: Option<String>,
+    pub eviction_policy: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Keyspace {
+    pub range: Range,
+    pub cluster: Sp<Rc<Object>>,
+    pub doc: Option<String>,
+    pub key_type: Sp<Type>,
+    pub value_type: Sp<Type>,
+    pub path: Path,
+}
+
+#[derive(Debug, LitParser)]
+#[allow(non_snake_case)]
+struct DecodedClusterConfig {
+    evictionPolicy: Option<Sp<String>>,
+}
+
+const DEFAULT_EVICTION_POLICY: &str = "allkeys-lru";
+
+const EVICTION_POLICIES: &[&str] = &[
+    "noeviction",
+    "allkeys-lru",
+    "allkeys-lfu",
+    "allkeys-random",
+    "volatile-lru",
+    "volatile-lfu",
+    "volatile-ttl",
+    "volatile-random",
+];
+
+impl DecodedClusterConfig {
+    fn eviction_policy(&self) -> ParseResult<String> {
+        let Some(policy) = &self.evictionPolicy else {
+            return Ok(DEFAULT_EVICTION_POLICY.to_string());
+        };
+
+        if EVICTION_POLICIES.contains(&policy.as_str()) {
+            Ok(policy.to_string())
+        } else {
+            Err(policy.parse_err(format!(
+                "invalid eviction policy, must be one of: {}",
+                EVICTION_POLICIES.join(", ")
+            )))
+        }
+    }
+}
+
+pub const CACHE_CLUSTER_PARSER: ResourceParser = ResourceParser {
+    name: "cache_cluster",
+    interesting_pkgs: &[PkgPath("encore.dev/storage/cache")],
+
+    run: |pass| {
+        let names = TrackedNames::new(&[("encore.dev/storage/cache", "CacheCluster")]);
+        let module = pass.module.clone();
+
+        type Res = NamedClassResourceOptionalConfig<DecodedClusterConfig>;
+        for r in iter_references::<Res>(&module, &names) {
+            let r = report_and_continue!(r);
+            let object = match &r.bind_name {
+                None => None,
+                Some(id) => pass
+                    .type_checker
+                    .resolve_obj(pass.module.clone(), &ast::Expr::Ident(id.clone())),
+            };
+
+            let eviction_policy = match &r.config {
+                Some(cfg) => report_and_continue!(cfg.eviction_policy()),
+                None => DEFAULT_EVICTION_POLICY.to_string(),
+            };
+
+            let resource = Resource::CacheCluster(Lrc::new(CacheCluster {
+                name: r.resource_name.to_owned(),
+                doc: r.doc_comment,
+                eviction_policy,
+            }));
+            pass.add_resource(resource.clone());
+            pass.add_bind(BindData {
+                range: r.range,
+                resource: ResourceOrPath::Resource(resource),
+                object,
+                kind: BindKind::Create,
+                ident: r.bind_name,
+            });
+        }
+    },
+};
+
+/// The keyspace classes provided by `encore.dev/storage/cache`,
+/// along with the value type they store.
+#[derive(Debug, Clone, Copy)]
+enum KeyspaceKind {
+    String,
+    Int,
+    Float,
+    StringList,
+    NumberList,
+    Struct,
+}
+
+impl KeyspaceKind {
+    const ALL: &'static [(&'static str, KeyspaceKind)] = &[
+        ("StringKeyspace", KeyspaceKind::String),
+        ("IntKeyspace", KeyspaceKind::Int),
+        ("FloatKeyspace", KeyspaceKind::Float),
+        ("StringListKeyspace", KeyspaceKind::StringList),
+        ("NumberListKeyspace", KeyspaceKind::NumberList),
+        ("StructKeyspace", KeyspaceKind::Struct),
+    ];
+
+    /// The value type implied by the keyspace class, if any.
+    /// Struct keyspaces take the value type as a type parameter instead.
+    fn implicit_value_type(&self) -> Option<Basic> {
+        match self {
+            KeyspaceKind::String | KeyspaceKind::StringList => Some(Basic::String),
+            KeyspaceKind::Int | KeyspaceKind::Float | KeyspaceKind::NumberList => {
+                Some(Basic::Number)
+            }
+            KeyspaceKind::Struct => None,
+        }
+    }
+}
+
+#[derive(Debug, LitParser)]
+#[allow(non_snake_case)]
+struct DecodedKeyspaceConfig {
+    keyPattern: Sp<String>,
+    defaultExpiry: Option<ast::Expr>,
+}
+
+impl DecodedKeyspaceConfig {
+    /// Checks that the default expiry, if any, is created using one of the
+    /// expiry helpers, since it's applied by the runtime on every write.
+    fn validate_default_expiry(&self) -> ParseResult<()> {
+        let Some(expr) = &self.defaultExpiry else {
+            return Ok(());
+        };
+
+        let valid = match expr {
+            ast::Expr::Call(call) => match &call.callee {
+                ast::Callee::Expr(callee) => {
+                    matches!(expr_name(callee), Some("expireIn" | "expireAt"))
+                }
+                _ => false,
+            },
+            expr => matches!(expr_name(expr), Some("neverExpire" | "keepTTL")),
+        };
+
+        if valid {
+            Ok(())
+        } else {
+            Err(expr.span().parse_err(
+                "defaultExpiry must be one of expireIn(...), expireAt(...), neverExpire or keepTTL",
+            ))
+        }
+    }
+}
+
+/// Returns the name an identifier or member expression (such as `cache.expireIn`) refers to.
+fn expr_name(expr: &ast::Expr) -> Option<&str> {
+    match expr {
+        ast::Expr::Ident(id) => Some(&*id.sym),
+        ast::Expr::Member(member) => match &member.prop {
+            ast::MemberProp::Ident(id) => Some(&*id.sym),
+            _ => None,
+        },
+        ast::Expr::Paren(paren) => expr_name(&paren.expr),
+        _ => None,
+    }
+}
+
+pub const KEYSPACE_PARSER: ResourceParser = ResourceParser {
+    name: "cache_keyspace",
+    interesting_pkgs: &[PkgPath("encore.dev/storage/cache")],
+
+    run: |pass| {
+        let module = pass.module.clone();
+
+        for (class_name, kind) in KeyspaceKind::ALL {
+            let tracked = [("encore.dev/storage/cache", *class_name)];
+            let names = TrackedNames::new(&tracked);
+
+            for r in iter_references::<KeyspaceDefinition>(&module, &names) {
+                let r = report_and_continue!(r);
+                let object = match &r.bind_name {
+                    None => None,
+                    Some(id) => pass
+                        .type_checker
+                        .resolve_obj(pass.module.clone(), &ast::Expr::Ident(id.clone())),
+                };
+
+                let Some(cluster) = pass
+                    .type_checker
+                    .resolve_obj(pass.module.clone(), &r.cluster)
+                else {
+                    r.cluster.err("cannot resolve cache cluster reference");
+                    continue;
+                };
+
+                let key_type = pass
+                    .type_checker
+                    .resolve_type(pass.module.clone(), &r.key_type);
+
+                let value_type = match (kind.implicit_value_type(), &r.value_type) {
+                    (Some(basic), _) => Sp::new(r.range.to_span(), Type::Basic(basic)),
+                    (None, Some(value_type)) => pass
+                        .type_checker
+                        .resolve_type(pass.module.clone(), value_type),
+                    (None, None) => {
+                        r.range.err("missing value type parameter");
+                        continue;
+                    }
+                };
+
+                report_and_continue!(r.config.validate_default_expiry());
+
+                let underlying = pass.type_checker.underlying(module.id, key_type.get());
+                let path = report_and_continue!(parse_key_pattern(
+                    &r.config.keyPattern,
+                    Sp::new(key_type.span(), &underlying),
+                ));
+
+                let resource = Resource::CacheKeyspace(Lrc::new(Keyspace {
+                    range: r.range,
+                    cluster: Sp::new(r.cluster.span(), cluster),
+                    doc: r.doc_comment,
+                    key_type,
+                    value_type,
+                    path,
+                }));
+                pass.add_resource(resource.clone());
+                pass.add_bind(BindData {
+                    range: r.range,
+                    resource: ResourceOrPath::Resource(resource),
+                    object,
+                    kind: BindKind::Create,
+                    ident: r.bind_name,
+                });
+            }
+        }
+    },
+};
+
+/// Parses and type-checks a keyspace key pattern against the key type.
+fn parse_key_pattern(pattern: &Sp<String>, key_type: Sp<&Type>) -> ParseResult<Path> {
+    let path = Path::parse(
+        pattern.span(),
+        pattern.as_str(),
+        respath::ParseOptions {
+            allow_wildcard: false,
+            allow_fallback: false,
+            prefix_slash: false,
+        },
+    )
+    .map_err(|err| pattern.parse_err(err.error.to_string()))?;
+
+    if let Some(Segment::Literal(lit)) = path.segments.first().map(|s| s.get()) {
+        if lit == "__encore" {
+            return Err(pattern.parse_err("use of reserved prefix '__encore' in key pattern"));
+        }
+    }
+
+    let span = key_type.span();
+    let key_type = key_type.take();
+    let mut segments = Vec::with_capacity(path.segments.len());
+
+    match key_type {
+        Type::Basic(basic) => {
+            let value_type = key_value_type(span, key_type)?;
+            let mut found = false;
+            for seg in path.segments {
+                let (seg_span, seg) = seg.split();
+                let seg = match seg {
+                    Segment::Param { name, .. } => {
+                        if name != "key" {
+                            return Err(seg_span.parse_err(format!(
+                                "key pattern parameter must be named 'key' for {} keys",
+                                basic_name(basic)
+                            )));
+                        }
+                        found = true;
+                        Segment::Param {
+                            name,
+                            value_type,
+                            validation: None,
+                        }
+                    }
+                    seg => seg,
+                };
+                segments.push(Sp::new(seg_span, seg));
+            }
+            if !found {
+                return Err(pattern.parse_err("key pattern must contain the parameter ':key'"));
+            }
+        }
+
+        Type::Interface(iface) => {
+            let mut unused = HashSet::new();
+            for field in &iface.fields {
+                match &field.name {
+                    FieldName::String(name) => {
+                        unused.insert(name.as_str());
+                    }
+                    FieldName::Symbol(_) => {
+                        return Err(field
+                            .range
+                            .parse_err("symbol fields are not supported in cache keys"));
+                    }
+                }
+            }
+
+            for seg in path.segments {
+                let (seg_span, seg) = seg.split();
+                let seg = match seg {
+                    Segment::Param { name, .. } => {
+                        let Some(field) = iface.fields.iter().find(|f| f.name.eq_str(&name)) else {
+                            return Err(seg_span.parse_err(format!(
+                                "key pattern parameter '{}' not found in key type",
+                                name
+                            )));
+                        };
+                        if field.optional {
+                            return Err(field
+                                .range
+                                .parse_err("cache key fields cannot be optional"));
+                        }
+                        let value_type = key_value_type(field.range.to_span(), &field.typ)?;
+                        unused.remove(name.as_str());
+                        Segment::Param {
+                            name,
+                            value_type,
+                            validation: None,
+                        }
+                    }
+                    seg => seg,
+                };
+                segments.push(Sp::new(seg_span, seg));
+            }
+
+            if !unused.is_empty() {
+                let mut unused: Vec<_> = unused.into_iter().collect();
+                unused.sort();
+                return Err(pattern.parse_err(format!(
+                    "key type fields not used in key pattern: {}",
+                    unused.join(", ")
+                )));
+            }
+        }
+
+        _ => {
+            return Err(span.parse_err(
+                "cache key type must be string, number, boolean or an interface of those",
+            ));
+        }
+    }
+
+    Ok(Path {
+        span: path.span,
+        segments,
+    })
+}
+
+/// Resolves the path value type for a key (or key field) type.
+fn key_value_type(span: Span, typ: &Type) -> ParseResult<ValueType> {
+    match typ {
+        Type::Basic(Basic::String) => Ok(ValueType::String),
+        Type::Basic(Basic::Number | Basic::BigInt) => Ok(ValueType::Int),
+        Type::Basic(Basic::Boolean) => Ok(ValueType::Bool),
+        _ => Err(span.parse_err("unsupported cache key type: must be string, number or boolean")),
+    }
+}
+
+fn basic_name(basic: &Basic) -> &'static str {
+    match basic {
+        Basic::String => "string",
+        Basic::Number => "number",
+        Basic::BigInt => "bigint",
+        Basic::Boolean => "boolean",
+        _ => "builtin",
+    }
+}
+
+#[derive(Debug)]
+struct KeyspaceDefinition {
+    pub range: Range,
+    pub cluster: ast::Expr,
+    pub config: DecodedKeyspaceConfig,
+    pub doc_comment: Option<String>,
+    pub bind_name: Option<ast::Ident>,
+    pub key_type: ast::TsType,
+    pub value_type: Option<ast::TsType>,
+}
+
+impl ReferenceParser for KeyspaceDefinition {
+    fn parse_resource_reference(
+        module: &Module,
+        path: &swc_ecma_visit::AstNodePath,
+    ) -> ParseResult<Option<Self>> {
+        for node in path.iter().rev() {
+            if let swc_ecma_visit::AstParentNodeRef::NewExpr(
+                expr,
+                swc_ecma_visit::fields::NewExprField::Callee,
+            ) = node
+            {
+                let Some(args) = &expr.args else {
+                    return Err(expr.span.parse_err("missing constructor arguments"));
+                };
+                let (Some(cluster), Some(config)) = (args.first(), args.get(1)) else {
+                    return Err(expr
+                        .span
+                        .parse_err("expected cache cluster and keyspace config"));
+                };
+                if let Some(spread) = cluster.spread.as_ref() {
+                    return Err(spread.parse_err("cannot use ... for cache cluster reference"));
+                }
+
+                let Some(key_type) = extract_type_param(expr.type_args.as_deref(), 0) else {
+                    return Err(expr.span.parse_err("missing key type parameter"));
+                };
+                let value_type = extract_type_param(expr.type_args.as_deref(), 1);
+
+                let doc_comment = module.preceding_comments(expr.span.lo.into());
+                let bind_name = extract_bind_name(path)?;
+                let config = DecodedKeyspaceConfig::parse_lit(&config.expr)?;
+
+                return Ok(Some(Self {
+                    range: expr.span.into(),
+                    cluster: cluster.expr.as_ref().clone(),
+                    config,
+                    doc_comment,
+                    bind_name,
+                    key_type: key_type.to_owned(),
+                    value_type: value_type.map(|t| t.to_owned()),
+                }));
+            }
+        }
+        Ok(None)
+    }
+}
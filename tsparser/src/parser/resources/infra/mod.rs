@@ -1,3 +1,4 @@
+pub mod cache;
 pub mod cron;
 pub mod objects;
 pub mod pubsub_subscription;
@@ -23,6 +23,9 @@ pub struct Subscription {
     pub name: String,
     pub doc: Option<String>,
     pub config: SubscriptionConfig,
+
+    /// The topic messages are forwarded to once retries are exhausted.
+    pub dead_letter_topic: Option<Sp<Rc<Object>>>,
 }
 
 #[derive(Debug, Clone)]
@@ -45,6 +48,7 @@ struct DecodedSubscriptionConfig {
     ackDeadline: Option<std::time::Duration>,
     messageRetention: Option<std::time::Duration>,
     retryPolicy: Option<DecodedRetryPolicy>,
+    deadLetterTopic: Option<ast::Expr>,
 }
 
 #[allow(non_snake_case)]
@@ -86,6 +90,17 @@ pub const SUBSCRIPTION_PARSER: ResourceParser = ResourceParser {
                 continue;
             };
 
+            let dead_letter_topic = match &r.config.deadLetterTopic {
+                None => None,
+                Some(expr) => {
+                    let Some(obj) = pass.type_checker.resolve_obj(pass.module.clone(), expr) else {
+                        expr.err("cannot resolve dead letter topic reference");
+                        continue;
+                    };
+                    Some(Sp::new(expr.span(), obj))
+                }
+            };
+
             let resource = Resource::PubSubSubscription(Lrc::new(Subscription {
                 range: r.range,
                 topic: Sp::new(topic_expr.expr.span(), topic),
@@ -120,6 +135,7 @@ pub const SUBSCRIPTION_PARSER: ResourceParser = ResourceParser {
                         .unwrap_or(100),
                     max_concurrency: r.config.maxConcurrency,
                 },
+                dead_letter_topic,
             }));
             pass.add_resource(resource.clone());
             pass.add_bind(BindData {
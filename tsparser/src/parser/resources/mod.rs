@@ -7,6 +7,7 @@ use crate::parser::resources::apis::api::ENDPOINT_PARSER;
 use crate::parser::resources::apis::authhandler::AUTHHANDLER_PARSER;
 use crate::parser::resources::apis::gateway::GATEWAY_PARSER;
 use crate::parser::resources::apis::service::SERVICE_PARSER;
+use crate::parser::resources::infra::cache::{CACHE_CLUSTER_PARSER, KEYSPACE_PARSER};
 use crate::parser::resources::infra::cron::CRON_PARSER;
 use crate::parser::resources::infra::objects::OBJECTS_PARSER;
 use crate::parser::resources::infra::pubsub_subscription::SUBSCRIPTION_PARSER;
@@ -30,6 +31,8 @@ pub enum Resource {
     PubSubTopic(Lrc<infra::pubsub_topic::Topic>),
     PubSubSubscription(Lrc<infra::pubsub_subscription::Subscription>),
     CronJob(Lrc<infra::cron::CronJob>),
+    CacheCluster(Lrc<infra::cache::CacheCluster>),
+    CacheKeyspace(Lrc<infra::cache::Keyspace>),
     Secret(Lrc<infra::secret::Secret>),
 }
 
@@ -57,6 +60,8 @@ impl Display for Resource {
             Resource::PubSubTopic(topic) => write!(f, "PubSubTopic({})", topic.name),
             Resource::PubSubSubscription(sub) => write!(f, "PubSubSubscription({})", sub.name),
             Resource::CronJob(cron) => write!(f, "CronJob({})", cron.name),
+            Resource::CacheCluster(cluster) => write!(f, "CacheCluster({})", cluster.name),
+            Resource::CacheKeyspace(ks) => write!(f, "CacheKeyspace({})", ks.path),
             Resource::Secret(secret) => write!(f, "Secret({})", secret.name),
             Resource::Service(svc) => write!(f, "Service({})", svc.name),
         }
@@ -75,5 +80,7 @@ pub static DEFAULT_RESOURCE_PARSERS: &[&ResourceParser] = &[
     &TOPIC_PARSER,
     &SUBSCRIPTION_PARSER,
     &CRON_PARSER,
+    &CACHE_CLUSTER_PARSER,
+    &KEYSPACE_PARSER,
     &SECRET_PARSER,
 ];
@@ -539,6 +539,7 @@ export const Bar = 5;
                 raw: false,
                 require_auth: false,
                 body_limit: None,
+                timeout: None,
                 encoding: EndpointEncoding {
                     span: DUMMY_SP,
                     default_method: Method::Post,
@@ -636,6 +637,7 @@ export const Bar = 5;
                 raw: false,
                 require_auth: false,
                 body_limit: None,
+                timeout: None,
                 encoding: EndpointEncoding {
                     span: DUMMY_SP,
                     default_method: Method::Post,
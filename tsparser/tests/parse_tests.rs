@@ -1,6 +1,8 @@
 use std::fs;
+use std::io::Write;
 use std::path::Path;
 use std::rc::Rc;
+use std::sync::{Arc, Mutex};
 
 use anyhow::Result;
 use common::js_runtime_path;
@@ -32,8 +34,64 @@ fn test_parser() {
     });
 }
 
+/// Parses each archive in testdata/errors and checks that parsing fails
+/// with an error containing the contents of the archive's `error.txt` file.
+#[test]
+fn test_parser_errors() {
+    glob!("testdata/errors/*.txt", |path| {
+        let input = fs::read_to_string(path).unwrap();
+        let ar = txtar::from_str(&input);
+        let expected = ar
+            .files
+            .iter()
+            .find(|f| f.name == Path::new("error.txt"))
+            .map(|f| f.data.trim().to_string())
+            .expect("missing error.txt");
+
+        let tmp_dir = TempDir::new("parse").unwrap();
+        ar.materialize(&tmp_dir).unwrap();
+
+        let output = CapturedOutput::default();
+        let cm: Rc<SourceMap> = Default::default();
+        let errs = Rc::new(Handler::with_emitter_writer(
+            Box::new(output.clone()),
+            Some(cm.clone()),
+        ));
+        let result = parse_txtar_with(tmp_dir.path(), cm, errs);
+
+        let output = output.to_string();
+        assert!(result.is_err(), "expected parse error in {:?}", path);
+        assert!(
+            output.contains(&expected),
+            "expected error {:?} in {:?}, got:\n{}",
+            expected,
+            path,
+            output,
+        );
+    });
+}
+
+/// Collects the errors emitted while parsing.
+#[derive(Clone, Default)]
+struct CapturedOutput(Arc<Mutex<Vec<u8>>>);
+
+impl Write for CapturedOutput {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for CapturedOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&String::from_utf8_lossy(&self.0.lock().unwrap()))
+    }
+}
+
 fn parse_txtar(app_root: &Path) -> Result<app::AppDesc> {
-    let globals = Globals::new();
     let cm: Rc<SourceMap> = Default::default();
     let errs = Rc::new(Handler::with_tty_emitter(
         swc_common::errors::ColorConfig::Auto,
@@ -41,6 +99,11 @@ fn parse_txtar(app_root: &Path) -> Result<app::AppDesc> {
         false,
         Some(cm.clone()),
     ));
+    parse_txtar_with(app_root, cm, errs)
+}
+
+fn parse_txtar_with(app_root: &Path, cm: Rc<SourceMap>, errs: Rc<Handler>) -> Result<app::AppDesc> {
+    let globals = Globals::new();
 
     GLOBALS.set(&globals, || -> Result<app::AppDesc> {
         HANDLER.set(&errs, || -> Result<app::AppDesc> {